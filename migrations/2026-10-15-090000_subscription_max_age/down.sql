@@ -0,0 +1,2 @@
+ALTER TABLE users_subscriptions
+    DROP COLUMN max_age_hours;
@@ -0,0 +1,2 @@
+ALTER TABLE users_subscriptions
+    ADD COLUMN max_age_hours integer;
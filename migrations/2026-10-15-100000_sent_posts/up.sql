@@ -0,0 +1,8 @@
+CREATE TABLE sent_posts
+(
+    subscription_id integer     NOT NULL REFERENCES users_subscriptions (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    post_id         varchar(16) NOT NULL,
+    sent_at         varchar(32) NOT NULL,
+
+    PRIMARY KEY (subscription_id, post_id)
+)
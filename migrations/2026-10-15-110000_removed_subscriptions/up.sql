@@ -0,0 +1,12 @@
+CREATE TABLE removed_subscriptions
+(
+    id            integer PRIMARY KEY AUTOINCREMENT NOT NULL,
+    user_id       varchar(20)                       NOT NULL,
+    subreddit     varchar(255)                      NOT NULL,
+    send_on       integer                           NOT NULL,
+    send_at       integer                           NOT NULL,
+    settings      text                              NOT NULL,
+    removed_at    varchar(32)                       NOT NULL,
+
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE ON UPDATE CASCADE
+)
@@ -0,0 +1,5 @@
+ALTER TABLE users_subscriptions
+    DROP COLUMN post_limit;
+
+ALTER TABLE users
+    DROP COLUMN default_post_limit;
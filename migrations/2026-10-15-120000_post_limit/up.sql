@@ -0,0 +1,5 @@
+ALTER TABLE users
+    ADD COLUMN default_post_limit integer;
+
+ALTER TABLE users_subscriptions
+    ADD COLUMN post_limit integer NOT NULL DEFAULT 10;
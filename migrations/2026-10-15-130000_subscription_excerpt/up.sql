@@ -0,0 +1,2 @@
+ALTER TABLE users_subscriptions
+    ADD COLUMN include_excerpt boolean NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+ALTER TABLE users_subscriptions
+    ADD COLUMN empty_streak integer NOT NULL DEFAULT 0;
@@ -0,0 +1,5 @@
+ALTER TABLE users_subscriptions
+    ADD COLUMN target_chat_id text NOT NULL DEFAULT '';
+
+UPDATE users_subscriptions
+SET target_chat_id = user_id;
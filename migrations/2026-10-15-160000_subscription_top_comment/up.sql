@@ -0,0 +1,2 @@
+ALTER TABLE users_subscriptions
+    ADD COLUMN include_top_comment boolean NOT NULL DEFAULT 0;
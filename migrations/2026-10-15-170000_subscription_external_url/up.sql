@@ -0,0 +1,2 @@
+ALTER TABLE users_subscriptions
+    ADD COLUMN prefer_external_url boolean NOT NULL DEFAULT 0;
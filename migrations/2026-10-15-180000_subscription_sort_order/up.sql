@@ -0,0 +1,2 @@
+ALTER TABLE users_subscriptions
+    ADD COLUMN sort_order integer NOT NULL DEFAULT 0;
@@ -0,0 +1,4 @@
+ALTER TABLE users_subscriptions
+    DROP COLUMN error_text;
+ALTER TABLE users_subscriptions
+    DROP COLUMN empty_text;
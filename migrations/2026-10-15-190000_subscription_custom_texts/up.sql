@@ -0,0 +1,4 @@
+ALTER TABLE users_subscriptions
+    ADD COLUMN empty_text text;
+ALTER TABLE users_subscriptions
+    ADD COLUMN error_text text;
@@ -0,0 +1,2 @@
+ALTER TABLE users_subscriptions
+    ADD COLUMN as_poll boolean NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+ALTER TABLE users_subscriptions
+    DROP COLUMN tag;
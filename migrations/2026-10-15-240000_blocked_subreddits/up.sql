@@ -0,0 +1,5 @@
+CREATE TABLE blocked_subreddits
+(
+    subreddit  varchar(64) PRIMARY KEY NOT NULL,
+    blocked_at varchar(32)             NOT NULL
+)
@@ -0,0 +1,2 @@
+ALTER TABLE users
+    ADD COLUMN combined_digest boolean NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+ALTER TABLE users_subscriptions
+    DROP COLUMN posts_per_message;
@@ -0,0 +1,2 @@
+ALTER TABLE users_subscriptions
+    ADD COLUMN posts_per_message integer;
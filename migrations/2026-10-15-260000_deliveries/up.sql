@@ -0,0 +1,9 @@
+CREATE TABLE deliveries
+(
+    id              integer PRIMARY KEY AUTOINCREMENT NOT NULL,
+    subscription_id integer                           NOT NULL,
+    sent_at         varchar(32)                       NOT NULL,
+    post_count      integer                           NOT NULL,
+
+    FOREIGN KEY (subscription_id) REFERENCES users_subscriptions (id) ON DELETE CASCADE ON UPDATE CASCADE
+)
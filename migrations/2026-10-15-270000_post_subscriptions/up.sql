@@ -0,0 +1,12 @@
+CREATE TABLE post_subscriptions
+(
+    id              integer PRIMARY KEY AUTOINCREMENT NOT NULL,
+    user_id         varchar(20)                       NOT NULL,
+    permalink       varchar(256)                      NOT NULL,
+    created_at      varchar(32)                       NOT NULL,
+    last_checked_at varchar(32)                       NOT NULL,
+
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE UNIQUE INDEX idx_post_subscriptions ON post_subscriptions (user_id, permalink);
@@ -0,0 +1,2 @@
+ALTER TABLE users_subscriptions
+    ADD COLUMN recurring boolean NOT NULL DEFAULT 1;
@@ -0,0 +1,4 @@
+ALTER TABLE users_subscriptions
+    DROP COLUMN last_message_id;
+ALTER TABLE users_subscriptions
+    DROP COLUMN edit_in_place;
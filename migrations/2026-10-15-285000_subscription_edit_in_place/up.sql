@@ -0,0 +1,4 @@
+ALTER TABLE users_subscriptions
+    ADD COLUMN edit_in_place boolean NOT NULL DEFAULT 0;
+ALTER TABLE users_subscriptions
+    ADD COLUMN last_message_id text;
@@ -0,0 +1,8 @@
+CREATE TABLE feedback
+(
+    id         integer PRIMARY KEY AUTOINCREMENT NOT NULL,
+    user_id    varchar(20)                       NOT NULL,
+    text       text                              NOT NULL,
+    created_at varchar(32)                       NOT NULL,
+    delivered  boolean                           NOT NULL DEFAULT 0
+);
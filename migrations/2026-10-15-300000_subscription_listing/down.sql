@@ -0,0 +1,4 @@
+ALTER TABLE users_subscriptions
+    DROP COLUMN timeframe;
+ALTER TABLE users_subscriptions
+    DROP COLUMN sort;
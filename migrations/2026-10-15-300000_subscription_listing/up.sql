@@ -0,0 +1,4 @@
+ALTER TABLE users_subscriptions
+    ADD COLUMN sort varchar(16) NOT NULL DEFAULT 'top';
+ALTER TABLE users_subscriptions
+    ADD COLUMN timeframe varchar(8) NOT NULL DEFAULT 'week';
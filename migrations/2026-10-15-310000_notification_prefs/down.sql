@@ -0,0 +1,8 @@
+ALTER TABLE users_subscriptions
+    DROP COLUMN link_preview;
+ALTER TABLE users_subscriptions
+    DROP COLUMN disable_notification;
+ALTER TABLE users
+    DROP COLUMN link_preview;
+ALTER TABLE users
+    DROP COLUMN disable_notification;
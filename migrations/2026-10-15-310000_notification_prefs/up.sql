@@ -0,0 +1,8 @@
+ALTER TABLE users
+    ADD COLUMN disable_notification boolean NOT NULL DEFAULT 0;
+ALTER TABLE users
+    ADD COLUMN link_preview boolean NOT NULL DEFAULT 0;
+ALTER TABLE users_subscriptions
+    ADD COLUMN disable_notification boolean NOT NULL DEFAULT 0;
+ALTER TABLE users_subscriptions
+    ADD COLUMN link_preview boolean NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+ALTER TABLE feedback
+    DROP COLUMN category;
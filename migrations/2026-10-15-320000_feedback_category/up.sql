@@ -0,0 +1,2 @@
+ALTER TABLE feedback
+    ADD COLUMN category varchar(32) NOT NULL DEFAULT 'Other';
@@ -0,0 +1,2 @@
+ALTER TABLE users_subscriptions
+    ADD COLUMN link_style varchar(16) NOT NULL DEFAULT 'permalink';
@@ -0,0 +1,2 @@
+ALTER TABLE users_subscriptions
+    ADD COLUMN protect_content boolean NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+ALTER TABLE users_subscriptions
+    ADD COLUMN since_last_sent boolean NOT NULL DEFAULT 0;
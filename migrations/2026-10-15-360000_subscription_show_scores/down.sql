@@ -0,0 +1,2 @@
+ALTER TABLE users_subscriptions
+    DROP COLUMN show_scores;
@@ -0,0 +1,2 @@
+ALTER TABLE users_subscriptions
+    ADD COLUMN show_scores boolean NOT NULL DEFAULT 0;
@@ -1,140 +1,382 @@
-use futures::StreamExt;
+use std::convert::TryFrom;
+use std::time::Instant;
+
 use log::{error, info, warn};
-use telegram_bot::{Api, MessageKind, MessageOrChannelPost, UpdateKind};
+use serde::de::DeserializeOwned;
+use tokio::time::{sleep, Duration};
 
 use crate::bot::commands::{
-    feedback, help, send_now, start, stop, subscribe, subscriptions, unsubscribe,
+    about, backup, blocklist, chats, clear_dialog, combined, custom_text, debug_post, diag,
+    edit_in_place, excerpt, external, feedback, find_command, help, history, inactive, links,
+    locale, migrate, next, normalize_command, once, parse_command, photos, poll, popular,
+    post_limit, posts_per_message, prefs, protect, prune, purge_inactive, rate_limit, rename,
+    render_test, render_test_user, reorder, reset_dialogs, resubscribe, retarget, scores, send_new,
+    send_now, show_dialog, since_last_sent, stale, start, stats, stop, subscribe, subscriptions,
+    tag, timezone, title_length, top_comment, unsubscribe, unwatch_post, watch_post, Command,
+    DEEP_LINK_SUBSCRIBE_PREFIX,
+};
+use crate::bot::dialogs::{
+    deliver_pending_feedback, parse_callback_data, Dialog, DialogStep, Feedback, Reorder, Stop,
+    Subscribe, Unsubscribe,
 };
-use crate::bot::dialogs::{Dialog, Feedback, Subscribe, Unsubscribe};
 use crate::bot::error::BotError;
+use crate::bot::throttle::{CommandThrottle, Verdict};
 use crate::db::client::DbClient;
-use crate::reddit::client::RedditClient;
+use crate::db::models::DialogEntity;
+use crate::reddit::client::{Listing, RedditClient, SubredditInfo};
 use crate::telegram::client::TelegramClient;
-use crate::telegram::types::Message;
+use crate::telegram::helpers::{escape_html, next_offset};
+use crate::telegram::types::{InlineQuery, InlineQueryResultArticle, Message, ParseMode, Update};
 
 const ERROR_TEXT: &str = r#"
 Looks like I'm having a technical glitch. Something went wrong.
 If the issues persist, open an issue on github (https://github.com/aldis-ameriks/reddit-bot) or you can also send feedback via /feedback command.
 "#;
 
-pub async fn init_bot(token: &str, bot_name: &str, database_url: &str, author_id: &str) {
-    let db = DbClient::new(&database_url);
-    let api = Api::new(&token);
-    let reddit_client = RedditClient::new();
+fn build_error_text(author_id: &str) -> String {
+    format!(
+        "{}You can also <a href=\"tg://user?id={}\">contact the author</a> directly.\n",
+        escape_html(ERROR_TEXT),
+        escape_html(author_id)
+    )
+}
+
+/// Commands answered for anyone, everything else is only answered for the author.
+const PUBLIC_COMMANDS: [&str; 2] = ["/about", "/popular"];
+
+const EXPIRED_BUTTON_TEXT: &str = "This button has expired";
+const THROTTLED_TEXT: &str = "You're doing that too much, slow down.";
+
+/// Window `commands_per_minute` is counted over.
+const THROTTLE_WINDOW_SECS: u64 = 60;
+
+/// Subreddits suggested for an inline query and how long telegram may cache them.
+const INLINE_QUERY_LIMIT: u32 = 10;
+const INLINE_QUERY_CACHE_SECS: u32 = 300;
+
+const POLL_TIMEOUT_SECS: u64 = 60;
+const POLL_ERROR_DELAY_SECS: u64 = 5;
+
+/// How often feedback that couldn't be forwarded to the author is retried.
+const FEEDBACK_RETRY_SECS: u64 = 600;
+
+/// Settings shared by every update the bot handles.
+pub struct BotSettings {
+    /// Appended to the end of every digest sent on demand.
+    pub footer: Option<String>,
+    /// Uptime reported by /about.
+    pub started_at: Instant,
+    /// Listing of new subscriptions unless the user picks another one.
+    pub listing: Listing,
+    /// Commands a single user may send per minute, 0 lifts the limit. Non authors are only counted
+    /// for the public commands, anything else is turned away before.
+    pub commands_per_minute: u32,
+}
+
+pub async fn init_bot(
+    token: &str,
+    database_url: &str,
+    author_id: &str,
+    bot_name: &str,
+    settings: &BotSettings,
+    reddit_client: &RedditClient,
+) -> Result<(), BotError> {
+    let db = DbClient::new(database_url);
     let telegram_client = TelegramClient::new(token.to_string());
+    let error_text = build_error_text(author_id);
+    let throttle = CommandThrottle::new(
+        settings.commands_per_minute as usize,
+        Duration::from_secs(THROTTLE_WINDOW_SECS),
+    );
 
-    let handle_message_closure = |data: String, user_id: String, is_mentioned: bool| {
+    let handle_message_closure = |update: ParsedUpdate| {
         handle_message(
             &db,
             &telegram_client,
-            &reddit_client,
+            reddit_client,
             author_id,
-            data,
-            user_id,
-            is_mentioned,
+            settings,
+            &throttle,
+            update,
         )
     };
 
-    let mut stream = api.stream();
-    while let Some(update) = stream.next().await {
-        if let Ok(update) = update {
-            match update.kind {
-                UpdateKind::Message(message) => {
-                    if let MessageKind::Text { data, .. } = message.kind {
-                        let user_id = message.from.id.to_string();
-                        if let Err(e) = handle_message_closure(data, user_id.clone(), true).await {
-                            error!("error handling message: {}", e);
-                            telegram_client
-                                .send_message(&Message {
-                                    chat_id: &user_id,
-                                    text: ERROR_TEXT,
-                                    ..Default::default()
-                                })
-                                .await
-                                .ok();
-                        }
-                    }
-                }
-                UpdateKind::CallbackQuery(query) => {
-                    if query.message.is_none() {
-                        warn!("empty message in callback query");
-                        continue;
-                    }
-
-                    if query.data.is_none() {
-                        warn!("empty data in callback query");
-                        continue;
-                    }
+    let mut offset = 0;
+    let mut feedback_retried_at = Instant::now();
+    loop {
+        if feedback_retried_at.elapsed() >= Duration::from_secs(FEEDBACK_RETRY_SECS) {
+            feedback_retried_at = Instant::now();
+            if let Err(e) = deliver_pending_feedback(&telegram_client, &db, author_id).await {
+                error!("error delivering pending feedback: {}", e);
+            }
+        }
 
-                    let message = query.message.unwrap();
-                    let data = query.data.unwrap();
-                    let user_id;
+        let updates = match telegram_client.get_updates(offset, POLL_TIMEOUT_SECS).await {
+            Ok(updates) => updates,
+            Err(e) => {
+                error!("error getting updates: {}", e);
+                sleep(Duration::from_secs(POLL_ERROR_DELAY_SECS)).await;
+                continue;
+            }
+        };
 
-                    match message {
-                        MessageOrChannelPost::Message(message) => {
-                            user_id = message.chat.id().to_string();
-                        }
-                        MessageOrChannelPost::ChannelPost(post) => {
-                            user_id = post.chat.id.to_string();
-                        }
-                    }
+        // Acknowledge the batch so it's not received again on the next poll.
+        offset = next_offset(&updates, offset);
 
-                    if let Err(e) = handle_message_closure(data, user_id.clone(), true).await {
-                        error!("error handling message in callback query: {}", e);
-                        telegram_client
-                            .send_message(&Message {
-                                chat_id: &user_id,
-                                text: ERROR_TEXT,
-                                ..Default::default()
-                            })
-                            .await
-                            .ok();
-                    }
+        for update in updates {
+            if let Some(query) = &update.inline_query {
+                let result = handle_inline_query(
+                    &telegram_client,
+                    reddit_client,
+                    author_id,
+                    bot_name,
+                    query,
+                )
+                .await;
+                if let Err(e) = result {
+                    error!("error handling inline query: {}", e);
                 }
-                UpdateKind::ChannelPost(post) => {
-                    if let MessageKind::Text { data, .. } = post.kind {
-                        let mut parsed_data = data;
-                        let mut is_mentioned = false;
-                        // If message ends with bot_name. Replace bot_name with empty string.
-                        if parsed_data.ends_with(bot_name) {
-                            parsed_data = parsed_data.replace(&format!("@{}", bot_name), "");
-                            is_mentioned = true;
-                        }
+                continue;
+            }
 
-                        let user_id = post.chat.id.to_string();
-                        if let Err(e) =
-                            handle_message_closure(parsed_data, user_id.clone(), is_mentioned).await
-                        {
-                            error!("error handling channel post: {}", e);
-                            telegram_client
-                                .send_message(&Message {
-                                    chat_id: &user_id,
-                                    text: ERROR_TEXT,
-                                    ..Default::default()
-                                })
-                                .await
-                                .ok();
-                        }
-                    }
+            if let Some(update) = parse_update(update, bot_name) {
+                let user_id = update.user_id.clone();
+                if let Err(e) = handle_message_closure(update).await {
+                    error!("error handling update: {}", e);
+                    telegram_client
+                        .send_message(&Message {
+                            chat_id: &user_id,
+                            text: &error_text,
+                            parse_mode: Some(ParseMode::Html),
+                            ..Default::default()
+                        })
+                        .await
+                        .ok();
                 }
-                _ => {}
             }
         }
     }
 }
 
-async fn handle_message(
-    db: &DbClient,
+/// Answers `@bot <query>` with matching subreddits, each shared with a link that subscribes to it.
+async fn handle_inline_query(
     telegram_client: &TelegramClient,
     reddit_client: &RedditClient,
     author_id: &str,
-    payload: String,
+    bot_name: &str,
+    query: &InlineQuery,
+) -> Result<(), BotError> {
+    let user_id = query.from.id.to_string();
+    info!(
+        "received inline query from: {}, query: {}",
+        user_id, query.query
+    );
+
+    if user_id != author_id {
+        warn!(
+            "non author ({}) attempted to interact with the bot",
+            user_id
+        );
+        return Ok(());
+    }
+
+    let text = query.query.trim();
+    let results = if text.is_empty() {
+        vec![]
+    } else {
+        reddit_client
+            .search_subreddits(text, INLINE_QUERY_LIMIT)
+            .await?
+            .iter()
+            .map(|subreddit| build_inline_result(bot_name, subreddit))
+            .collect()
+    };
+
+    telegram_client
+        .answer_inline_query(&query.id, &results, INLINE_QUERY_CACHE_SECS)
+        .await?;
+    Ok(())
+}
+
+fn build_inline_result(bot_name: &str, subreddit: &SubredditInfo) -> InlineQueryResultArticle {
+    let message_text = format!(
+        "r/{} - {}\nGet its weekly top posts: https://t.me/{}?start={}{}",
+        subreddit.name, subreddit.title, bot_name, DEEP_LINK_SUBSCRIBE_PREFIX, subreddit.name
+    );
+    InlineQueryResultArticle::new(
+        &subreddit.name,
+        &format!("r/{}", subreddit.name),
+        &format!(
+            "{} - {} subscribers",
+            subreddit.title, subreddit.subscribers
+        ),
+        &message_text,
+    )
+}
+
+/// Verifies the token before the bot starts and, with `notify_author`, that the author can be
+/// messaged. Returns the bot's own username, used to detect mentions in channel posts.
+pub async fn self_check(
+    telegram_client: &TelegramClient,
+    author_id: &str,
+    notify_author: bool,
+) -> Result<String, BotError> {
+    let me = telegram_client
+        .get_me()
+        .await
+        .map_err(|err| BotError::Config(format!("failed to verify the Telegram token: {}", err)))?;
+    let bot_name = me.username.unwrap_or_default();
+    info!("running as @{}", bot_name);
+
+    if notify_author {
+        telegram_client
+            .send_message(&Message {
+                chat_id: author_id,
+                text: &format!("Bot started as @{}", bot_name),
+                ..Default::default()
+            })
+            .await
+            .map_err(|err| {
+                BotError::Config(format!(
+                    "failed to message the author ({}): {}",
+                    author_id, err
+                ))
+            })?;
+    }
+
+    Ok(bot_name)
+}
+
+#[derive(Debug, PartialEq)]
+struct ParsedUpdate {
+    data: String,
     user_id: String,
     is_mentioned: bool,
+    is_private: bool,
+    callback_query_id: Option<String>,
+}
+
+/// Extracts the payload, user id and whether the bot was addressed from an update.
+fn parse_update(update: Update, bot_name: &str) -> Option<ParsedUpdate> {
+    if let Some(message) = update.message {
+        let data = message.text?;
+        let user_id = match message.from {
+            Some(from) => from.id.to_string(),
+            None => message.chat.id.to_string(),
+        };
+        return Some(ParsedUpdate {
+            data,
+            user_id,
+            is_mentioned: true,
+            is_private: message.chat.type_ == "private",
+            callback_query_id: None,
+        });
+    }
+
+    if let Some(query) = update.callback_query {
+        if query.message.is_none() {
+            warn!("empty message in callback query");
+            return None;
+        }
+
+        if query.data.is_none() {
+            warn!("empty data in callback query");
+            return None;
+        }
+
+        return Some(ParsedUpdate {
+            data: query.data.unwrap(),
+            user_id: query.message.unwrap().chat.id.to_string(),
+            is_mentioned: true,
+            is_private: false,
+            callback_query_id: Some(query.id),
+        });
+    }
+
+    if let Some(post) = update.channel_post {
+        let mut parsed_data = post.text?;
+        let mut is_mentioned = false;
+        // If message ends with a mention of the bot, strip the mention.
+        let mention = format!("@{}", bot_name);
+        if !bot_name.is_empty() && parsed_data.ends_with(&mention) {
+            parsed_data = parsed_data.replace(&mention, "");
+            is_mentioned = true;
+        }
+
+        return Some(ParsedUpdate {
+            data: parsed_data,
+            user_id: post.chat.id.to_string(),
+            is_mentioned,
+            is_private: false,
+            callback_query_id: None,
+        });
+    }
+
+    None
+}
+
+/// Payload of a button press that belongs to the user's current dialog step, `None` for buttons
+/// left over from earlier steps or finished dialogs.
+fn current_callback_payload(
+    db: &DbClient,
+    user_id: &str,
+    data: &str,
+) -> Result<Option<String>, BotError> {
+    let (command, step, payload) = match parse_callback_data(data) {
+        Some(parts) => parts,
+        None => return Ok(None),
+    };
+    let payload = match db.get_users_dialog_opt(user_id)? {
+        Some(dialog) if dialog.command == command && dialog.step == step => {
+            Some(payload.to_string())
+        }
+        _ => None,
+    };
+    Ok(payload)
+}
+
+/// Dialog stored for the user, one that can't be restored is dropped and treated as no dialog.
+fn restore_dialog<T>(db: &DbClient, dialog: DialogEntity) -> Result<Option<Dialog<T>>, BotError>
+where
+    T: std::hash::Hash + std::cmp::Eq + DialogStep + DeserializeOwned + std::str::FromStr,
+{
+    let user_id = dialog.user_id.clone();
+    match Dialog::try_from(dialog) {
+        Ok(dialog) => Ok(Some(dialog)),
+        Err(err) => {
+            error!("dropping corrupt dialog of user {}: {}", user_id, err);
+            db.delete_dialog(&user_id)?;
+            Ok(None)
+        }
+    }
+}
+
+async fn handle_message(
+    db: &DbClient,
+    telegram_client: &TelegramClient,
+    reddit_client: &RedditClient,
+    author_id: &str,
+    settings: &BotSettings,
+    throttle: &CommandThrottle,
+    update: ParsedUpdate,
 ) -> Result<(), BotError> {
+    let ParsedUpdate {
+        data: payload,
+        user_id,
+        is_mentioned,
+        is_private,
+        callback_query_id,
+    } = update;
     info!("received message from: {}, message: {}", user_id, payload);
 
-    if user_id != author_id {
+    // Plain command words count too, in a private chat "about" is the same as "/about".
+    let command = match normalize_command(&payload) {
+        Some(normalized) if is_private => normalized,
+        _ => payload.clone(),
+    };
+    if user_id != author_id
+        && (callback_query_id.is_some() || !PUBLIC_COMMANDS.contains(&parse_command(&command).0))
+    {
         warn!(
             "non author ({}) attempted to interact with the bot",
             user_id
@@ -142,39 +384,222 @@ async fn handle_message(
         return Ok(());
     }
 
-    // TODO: Extract commands as enum
-    match payload.as_ref() {
-        "/start" => start(&telegram_client, &db, &user_id).await?,
-        "/stop" => stop(&telegram_client, &db, &user_id).await?,
-        "/subscribe" => subscribe(&telegram_client, &db, &reddit_client, &user_id).await?,
-        "/unsubscribe" => unsubscribe(&telegram_client, &db, &user_id).await?,
-        "/subscriptions" => subscriptions(&telegram_client, &db, &user_id).await?,
-        "/feedback" => feedback(&telegram_client, &db, author_id, &user_id).await?,
-        "/sendnow" => send_now(&telegram_client, &db, &reddit_client, &user_id).await?,
-        "/help" => help(&telegram_client, &user_id).await?,
+    match throttle.check(&user_id, Instant::now()) {
+        Verdict::Allowed => {}
+        verdict => {
+            info!("throttling commands from: {}", user_id);
+            // Button presses are always answered, otherwise the button keeps loading.
+            if let Some(callback_query_id) = &callback_query_id {
+                telegram_client
+                    .answer_callback_query(callback_query_id, THROTTLED_TEXT, false)
+                    .await?;
+            } else if verdict == Verdict::Throttled {
+                telegram_client
+                    .send_message(&Message {
+                        chat_id: &user_id,
+                        text: THROTTLED_TEXT,
+                        ..Default::default()
+                    })
+                    .await?;
+            }
+            return Ok(());
+        }
+    }
+
+    let payload = match callback_query_id {
+        Some(callback_query_id) => match current_callback_payload(db, &user_id, &payload)? {
+            Some(payload) => payload,
+            None => {
+                info!("ignoring stale callback from: {}", user_id);
+                telegram_client
+                    .answer_callback_query(&callback_query_id, EXPIRED_BUTTON_TEXT, true)
+                    .await?;
+                return Ok(());
+            }
+        },
+        None => payload,
+    };
+
+    // Plain command words are only treated as commands in private chats outside of dialogs.
+    let payload = match normalize_command(&payload) {
+        Some(normalized) if is_private && db.get_users_dialog_opt(&user_id)?.is_none() => {
+            normalized
+        }
+        _ => payload,
+    };
+    let (command, args) = parse_command(&payload);
+
+    if let Some(command) = find_command(command) {
+        match command {
+            Command::Start => {
+                start(
+                    telegram_client,
+                    db,
+                    reddit_client,
+                    &user_id,
+                    args,
+                    &settings.listing,
+                )
+                .await?
+            }
+            Command::Stop => stop(telegram_client, db, &user_id).await?,
+            Command::Subscribe => {
+                subscribe(
+                    telegram_client,
+                    db,
+                    reddit_client,
+                    &user_id,
+                    args,
+                    &settings.listing,
+                )
+                .await?
+            }
+            Command::Once => {
+                once(
+                    telegram_client,
+                    db,
+                    reddit_client,
+                    &user_id,
+                    args,
+                    &settings.listing,
+                )
+                .await?
+            }
+            Command::Unsubscribe => unsubscribe(telegram_client, db, &user_id).await?,
+            Command::Resubscribe => resubscribe(telegram_client, db, &user_id).await?,
+            Command::Subscriptions => subscriptions(telegram_client, db, &user_id).await?,
+            Command::Reorder => reorder(telegram_client, db, &user_id).await?,
+            Command::Next => next(telegram_client, db, &user_id).await?,
+            Command::History => history(telegram_client, db, &user_id, args).await?,
+            Command::WatchPost => watch_post(telegram_client, db, &user_id, args).await?,
+            Command::UnwatchPost => unwatch_post(telegram_client, db, &user_id, args).await?,
+            Command::Popular => popular(telegram_client, db, &user_id).await?,
+            Command::PostLimit => post_limit(telegram_client, db, &user_id, args).await?,
+            Command::Timezone => timezone(telegram_client, db, &user_id, args).await?,
+            Command::Locale => locale(telegram_client, db, &user_id, args).await?,
+            Command::Combined => combined(telegram_client, db, &user_id, args).await?,
+            Command::Prefs => prefs(telegram_client, db, &user_id, args).await?,
+            Command::Excerpt => excerpt(telegram_client, db, &user_id, args).await?,
+            Command::TopComment => top_comment(telegram_client, db, &user_id, args).await?,
+            Command::External => external(telegram_client, db, &user_id, args).await?,
+            Command::Links => links(telegram_client, db, &user_id, args).await?,
+            Command::Protect => protect(telegram_client, db, &user_id, args).await?,
+            Command::SinceLast => since_last_sent(telegram_client, db, &user_id, args).await?,
+            Command::Scores => scores(telegram_client, db, &user_id, args).await?,
+            Command::TitleLen => title_length(telegram_client, db, &user_id, args).await?,
+            Command::PerMessage => posts_per_message(telegram_client, db, &user_id, args).await?,
+            Command::Tag => tag(telegram_client, db, &user_id, args).await?,
+            Command::Poll => poll(telegram_client, db, &user_id, args).await?,
+            Command::Photos => photos(telegram_client, db, &user_id, args).await?,
+            Command::InPlace => edit_in_place(telegram_client, db, &user_id, args).await?,
+            Command::CustomText => custom_text(telegram_client, db, &user_id, args).await?,
+            Command::Rename => rename(telegram_client, db, reddit_client, &user_id, args).await?,
+            Command::Retarget => retarget(telegram_client, db, &user_id, args).await?,
+            Command::Feedback => feedback(telegram_client, db, author_id, &user_id).await?,
+            Command::SendNow => {
+                send_now(
+                    telegram_client,
+                    db,
+                    reddit_client,
+                    &user_id,
+                    settings.footer.as_deref(),
+                )
+                .await?
+            }
+            Command::SendNew => {
+                send_new(
+                    telegram_client,
+                    db,
+                    reddit_client,
+                    &user_id,
+                    settings.footer.as_deref(),
+                )
+                .await?
+            }
+            Command::About => about(telegram_client, db, &user_id, settings.started_at).await?,
+            Command::Help => help(telegram_client, &user_id, args).await?,
+        }
+        return Ok(());
+    }
+
+    match command {
+        "/showdialog" => show_dialog(telegram_client, db, author_id, &user_id, args).await?,
+        "/cleardialog" => clear_dialog(telegram_client, db, author_id, &user_id, args).await?,
+        "/reset_dialogs" => reset_dialogs(telegram_client, db, author_id, &user_id).await?,
+        "/chats" => chats(telegram_client, db, author_id, &user_id).await?,
+        "/blocklist" => blocklist(telegram_client, db, author_id, &user_id, args).await?,
+        "/stale" => stale(telegram_client, db, author_id, &user_id).await?,
+        "/stats" => stats(telegram_client, db, author_id, &user_id).await?,
+        "/backup" => backup(telegram_client, db, author_id, &user_id).await?,
+        "/prune" => prune(telegram_client, db, author_id, &user_id).await?,
+        "/migrate" => migrate(telegram_client, db, author_id, &user_id).await?,
+        "/inactive" => inactive(telegram_client, db, author_id, &user_id).await?,
+        "/purge_inactive" => purge_inactive(telegram_client, db, author_id, &user_id).await?,
+        "/ratelimit" => rate_limit(telegram_client, reddit_client, author_id, &user_id).await?,
+        "/debugpost" => {
+            debug_post(telegram_client, reddit_client, author_id, &user_id, args).await?
+        }
+        "/diag" => diag(telegram_client, reddit_client, author_id, &user_id).await?,
+        "/rendertest" => render_test(telegram_client, author_id, &user_id, args).await?,
+        "/rendertest_user" => {
+            render_test_user(
+                telegram_client,
+                db,
+                reddit_client,
+                author_id,
+                &user_id,
+                args,
+            )
+            .await?
+        }
         _ => {
-            if let Ok(dialog) = db.get_users_dialog(&user_id) {
+            if let Some(dialog) = db.get_users_dialog_opt(&user_id)? {
                 match dialog.command.as_str() {
-                    "/subscribe" => {
-                        let mut dialog: Dialog<Subscribe> = Dialog::from(dialog);
-                        dialog
-                            .handle_current_step(&telegram_client, &db, &reddit_client, &payload)
-                            .await?;
-                        return Ok(());
+                    "/subscribe" | "/once" => {
+                        if let Some(mut dialog) = restore_dialog::<Subscribe>(db, dialog)? {
+                            dialog
+                                .handle_current_step(
+                                    telegram_client,
+                                    db,
+                                    reddit_client,
+                                    &payload,
+                                    &settings.listing,
+                                )
+                                .await?;
+                            return Ok(());
+                        }
                     }
                     "/unsubscribe" => {
-                        let mut dialog: Dialog<Unsubscribe> = Dialog::from(dialog);
-                        dialog
-                            .handle_current_step(&telegram_client, &db, &payload)
-                            .await?;
-                        return Ok(());
+                        if let Some(mut dialog) = restore_dialog::<Unsubscribe>(db, dialog)? {
+                            dialog
+                                .handle_current_step(telegram_client, db, &payload)
+                                .await?;
+                            return Ok(());
+                        }
+                    }
+                    "/reorder" => {
+                        if let Some(mut dialog) = restore_dialog::<Reorder>(db, dialog)? {
+                            dialog
+                                .handle_current_step(telegram_client, db, &payload)
+                                .await?;
+                            return Ok(());
+                        }
+                    }
+                    "/stop" => {
+                        if let Some(mut dialog) = restore_dialog::<Stop>(db, dialog)? {
+                            dialog
+                                .handle_current_step(telegram_client, db, &payload)
+                                .await?;
+                            return Ok(());
+                        }
                     }
                     "/feedback" => {
-                        let mut dialog: Dialog<Feedback> = Dialog::from(dialog);
-                        dialog
-                            .handle_current_step(&telegram_client, &db, author_id, &payload)
-                            .await?;
-                        return Ok(());
+                        if let Some(mut dialog) = restore_dialog::<Feedback>(db, dialog)? {
+                            dialog
+                                .handle_current_step(telegram_client, db, author_id, &payload)
+                                .await?;
+                            return Ok(());
+                        }
                     }
                     _ => {}
                 }
@@ -193,3 +618,498 @@ async fn handle_message(
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use diesel::Connection;
+    use mockito::{mock, server_url, Matcher};
+    use serial_test::serial;
+
+    use super::*;
+    use crate::db::test_helpers::setup_test_db;
+    use crate::telegram::test_helpers::{mock_send_message_not_called, mock_send_message_success};
+    use crate::telegram::types::User;
+
+    #[test]
+    fn error_text_mentions_author() {
+        let text = build_error_text("123");
+        assert!(text.starts_with(ERROR_TEXT));
+        assert!(text.ends_with(
+            "You can also <a href=\"tg://user?id=123\">contact the author</a> directly.\n"
+        ));
+
+        let text = build_error_text("<123>");
+        assert!(text.contains("tg://user?id=&lt;123&gt;"));
+    }
+
+    fn settings() -> BotSettings {
+        BotSettings {
+            footer: None,
+            started_at: Instant::now(),
+            listing: Listing::default(),
+            commands_per_minute: 0,
+        }
+    }
+
+    fn parse(json: &str) -> Option<ParsedUpdate> {
+        parse_update(serde_json::from_str(json).unwrap(), "reddit_bot")
+    }
+
+    fn parsed(data: &str, user_id: &str, is_mentioned: bool, is_private: bool) -> ParsedUpdate {
+        ParsedUpdate {
+            data: data.to_string(),
+            user_id: user_id.to_string(),
+            is_mentioned,
+            is_private,
+            callback_query_id: None,
+        }
+    }
+
+    #[test]
+    fn parse_update_message() {
+        let result = parse(
+            r#"{"update_id":1,"message":{"message_id":2,"from":{"id":123,"is_bot":false,"first_name":"Name"},"chat":{"id":123,"type":"private"},"date":1581200384,"text":"/start"}}"#,
+        );
+        assert_eq!(result, Some(parsed("/start", "123", true, true)));
+
+        let result = parse(
+            r#"{"update_id":1,"message":{"message_id":2,"from":{"id":123,"is_bot":false,"first_name":"Name"},"chat":{"id":123,"type":"private"},"date":1581200384}}"#,
+        );
+        assert_eq!(result, None);
+
+        let result = parse(
+            r#"{"update_id":1,"message":{"message_id":2,"from":{"id":123,"is_bot":false,"first_name":"Name"},"chat":{"id":-200,"title":"Group","type":"group"},"date":1581200384,"text":"subscribe"}}"#,
+        );
+        assert_eq!(result, Some(parsed("subscribe", "123", true, false)));
+    }
+
+    #[test]
+    fn parse_update_callback_query() {
+        let result = parse(
+            r#"{"update_id":1,"callback_query":{"id":"9","from":{"id":123,"is_bot":false,"first_name":"Name"},"message":{"message_id":2,"chat":{"id":456,"type":"private"},"date":1581200384,"text":"Select subreddit"},"data":"rust"}}"#,
+        );
+        assert_eq!(
+            result,
+            Some(ParsedUpdate {
+                callback_query_id: Some("9".to_string()),
+                ..parsed("rust", "456", true, false)
+            })
+        );
+
+        let result = parse(
+            r#"{"update_id":1,"callback_query":{"id":"9","from":{"id":123,"is_bot":false,"first_name":"Name"},"data":"rust"}}"#,
+        );
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn parse_update_channel_post() {
+        let result = parse(
+            r#"{"update_id":1,"channel_post":{"message_id":2,"chat":{"id":-100,"type":"channel"},"date":1581200384,"text":"/sendnow@reddit_bot"}}"#,
+        );
+        assert_eq!(result, Some(parsed("/sendnow", "-100", true, false)));
+
+        let result = parse(
+            r#"{"update_id":1,"channel_post":{"message_id":2,"chat":{"id":-100,"type":"channel"},"date":1581200384,"text":"hello"}}"#,
+        );
+        assert_eq!(result, Some(parsed("hello", "-100", false, false)));
+
+        let result = parse(
+            r#"{"update_id":1,"channel_post":{"message_id":2,"chat":{"id":-100,"type":"channel"},"date":1581200384,"text":"/sendnow@other_reddit_bot"}}"#,
+        );
+        assert_eq!(
+            result,
+            Some(parsed("/sendnow@other_reddit_bot", "-100", false, false))
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn current_callback_payload_matches_step() {
+        let db = setup_test_db();
+        db.create_user("123").unwrap();
+        let mut dialog = Dialog::<Subscribe>::new("123".to_string());
+        dialog.current_step = Subscribe::Weekday;
+        db.insert_or_update_dialog(&dialog.into()).unwrap();
+
+        let result = current_callback_payload(&db, "123", "/subscribe:Weekday:0").unwrap();
+        assert_eq!(result, Some("0".to_string()));
+
+        // Buttons from an earlier step or another dialog.
+        assert_eq!(
+            current_callback_payload(&db, "123", "/subscribe:Time:12").unwrap(),
+            None
+        );
+        assert_eq!(
+            current_callback_payload(&db, "123", "/unsubscribe:Subreddit:rust").unwrap(),
+            None
+        );
+        // Buttons sent before the callback data was tagged.
+        assert_eq!(current_callback_payload(&db, "123", "0").unwrap(), None);
+        // No dialog in progress.
+        assert_eq!(
+            current_callback_payload(&db, "456", "/subscribe:Weekday:0").unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn handle_message_rejects_stale_callback() {
+        let _m = mock("POST", "/bottoken/answerCallbackQuery")
+            .match_body("callback_query_id=9&text=This+button+has+expired&show_alert=true")
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":true}"#)
+            .expect(1)
+            .create();
+        let _m2 = mock_send_message_not_called("token");
+        let db = setup_test_db();
+        db.create_user("123").unwrap();
+        let mut dialog = Dialog::<Unsubscribe>::new("123".to_string());
+        dialog.current_step = Unsubscribe::Subreddit;
+        db.insert_or_update_dialog(&dialog.into()).unwrap();
+        let telegram_client = TelegramClient::new_with("token".to_string(), server_url());
+        let reddit_client = RedditClient::new();
+
+        let update = ParsedUpdate {
+            callback_query_id: Some("9".to_string()),
+            ..parsed("/subscribe:Weekday:0", "123", true, false)
+        };
+        handle_message(
+            &db,
+            &telegram_client,
+            &reddit_client,
+            "123",
+            &settings(),
+            &CommandThrottle::new(0, Duration::from_secs(60)),
+            update,
+        )
+        .await
+        .unwrap();
+        _m.assert();
+        _m2.assert();
+        assert_eq!(db.get_users_dialog("123").unwrap().step, "Subreddit");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn handle_message_database_error() {
+        let _m = mock_send_message_not_called("token");
+        let db = setup_test_db();
+        db.create_user("123").unwrap();
+        db.conn.execute("DROP TABLE dialogs").unwrap();
+        let telegram_client = TelegramClient::new_with("token".to_string(), server_url());
+        let reddit_client = RedditClient::new();
+
+        let update = parsed("rust", "123", true, true);
+        let result = handle_message(
+            &db,
+            &telegram_client,
+            &reddit_client,
+            "123",
+            &settings(),
+            &CommandThrottle::new(0, Duration::from_secs(60)),
+            update,
+        )
+        .await;
+        assert!(result.is_err());
+        _m.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn handle_message_drops_corrupt_dialog() {
+        let _m = mock_send_message_not_called("token");
+        let db = setup_test_db();
+        db.create_user("123").unwrap();
+        db.insert_or_update_dialog(&DialogEntity {
+            user_id: "123".to_string(),
+            command: "/subscribe".to_string(),
+            step: "Subreddit".to_string(),
+            data: "{not json".to_string(),
+        })
+        .unwrap();
+        let telegram_client = TelegramClient::new_with("token".to_string(), server_url());
+        let reddit_client = RedditClient::new();
+
+        let update = parsed("rust", "123", false, true);
+        handle_message(
+            &db,
+            &telegram_client,
+            &reddit_client,
+            "123",
+            &settings(),
+            &CommandThrottle::new(0, Duration::from_secs(60)),
+            update,
+        )
+        .await
+        .unwrap();
+        _m.assert();
+        assert_eq!(db.get_users_dialog_opt("123").unwrap(), None);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn handle_message_throttles_rapid_commands() {
+        let message = Message {
+            chat_id: "123",
+            text: THROTTLED_TEXT,
+            ..Default::default()
+        };
+        let _m = mock_send_message_success("token", &message);
+        let _m2 = mock("POST", "/bottoken/answerCallbackQuery")
+            .match_body("callback_query_id=9&text=You%27re+doing+that+too+much%2C+slow+down.&show_alert=false")
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":true}"#)
+            .expect(1)
+            .create();
+        let db = setup_test_db();
+        db.create_user("123").unwrap();
+        let telegram_client = TelegramClient::new_with("token".to_string(), server_url());
+        let reddit_client = RedditClient::new();
+        let throttle = CommandThrottle::new(2, Duration::from_secs(60));
+
+        let callback = ParsedUpdate {
+            callback_query_id: Some("9".to_string()),
+            ..parsed("/subscribe:Weekday:0", "123", true, false)
+        };
+        let updates = vec![
+            parsed("rust", "123", false, true),
+            parsed("rust", "123", false, true),
+            parsed("rust", "123", false, true),
+            parsed("rust", "123", false, true),
+            callback,
+        ];
+        for update in updates {
+            handle_message(
+                &db,
+                &telegram_client,
+                &reddit_client,
+                "123",
+                &settings(),
+                &throttle,
+                update,
+            )
+            .await
+            .unwrap();
+        }
+        // Only the first throttled message is answered, button presses always are.
+        _m.assert();
+        _m2.assert();
+    }
+
+    fn inline_query(user_id: i64, query: &str) -> InlineQuery {
+        InlineQuery {
+            id: "42".to_string(),
+            from: User {
+                id: user_id,
+                is_bot: false,
+                first_name: "Name".to_string(),
+                username: None,
+            },
+            query: query.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn handle_inline_query_success() {
+        let _m = mock("GET", "/subreddits/search.json")
+            .match_query(Matcher::UrlEncoded("q".to_string(), "rust".to_string()))
+            .with_status(200)
+            .with_body(r#"{"kind": "Listing", "data": {"children": [{"kind": "t5", "data": {"display_name": "rust", "title": "Rust", "subscribers": 300000}}]}}"#)
+            .create();
+        let _m2 = mock("POST", "/bottoken/answerInlineQuery")
+            .match_body(Matcher::Regex(
+                r#""description":"Rust - 300000 subscribers".*https://t.me/reddit_bot\?start=sub_rust"#
+                    .to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":true}"#)
+            .create();
+        let telegram_client = TelegramClient::new_with("token".to_string(), server_url());
+        let reddit_client = RedditClient::new_with(&server_url());
+
+        handle_inline_query(
+            &telegram_client,
+            &reddit_client,
+            "123",
+            "reddit_bot",
+            &inline_query(123, " rust "),
+        )
+        .await
+        .unwrap();
+        _m.assert();
+        _m2.assert();
+    }
+
+    #[tokio::test]
+    async fn handle_inline_query_non_author() {
+        let _m = mock("GET", "/subreddits/search.json")
+            .match_query(Matcher::Any)
+            .expect(0)
+            .create();
+        let _m2 = mock("POST", "/bottoken/answerInlineQuery")
+            .expect(0)
+            .create();
+        let telegram_client = TelegramClient::new_with("token".to_string(), server_url());
+        let reddit_client = RedditClient::new_with(&server_url());
+
+        handle_inline_query(
+            &telegram_client,
+            &reddit_client,
+            "123",
+            "reddit_bot",
+            &inline_query(456, "rust"),
+        )
+        .await
+        .unwrap();
+        _m.assert();
+        _m2.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn handle_message_public_commands() {
+        let _m = mock("POST", "/bottoken/sendMessage")
+            .match_body(Matcher::Regex(
+                r#""chat_id":"456".*Tracking 0 subreddits"#.to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .expect(1)
+            .create();
+        let _m2 = mock("POST", "/bottoken/sendMessage")
+            .match_body(Matcher::Regex(
+                r#""chat_id":"456".*Nobody has subscribed to anything yet\."#.to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":692}}"#)
+            .expect(1)
+            .create();
+        let db = setup_test_db();
+        let telegram_client = TelegramClient::new_with("token".to_string(), server_url());
+        let reddit_client = RedditClient::new();
+        let throttle = CommandThrottle::new(0, Duration::from_secs(60));
+
+        let callback = ParsedUpdate {
+            callback_query_id: Some("9".to_string()),
+            ..parsed("/about", "456", true, false)
+        };
+        let updates = vec![
+            parsed("/about", "456", true, true),
+            parsed("/popular", "456", true, true),
+            parsed("/stop", "456", true, true),
+            callback,
+        ];
+        for update in updates {
+            handle_message(
+                &db,
+                &telegram_client,
+                &reddit_client,
+                "123",
+                &settings(),
+                &throttle,
+                update,
+            )
+            .await
+            .unwrap();
+        }
+        _m.assert();
+        _m2.assert();
+        assert_eq!(db.get_users_dialog_opt("456").unwrap(), None);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn handle_message_public_plain_command() {
+        let _m = mock("POST", "/bottoken/sendMessage")
+            .match_body(Matcher::Regex(
+                r#""chat_id":"456".*Tracking 0 subreddits"#.to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .expect(1)
+            .create();
+        let db = setup_test_db();
+        let telegram_client = TelegramClient::new_with("token".to_string(), server_url());
+        let reddit_client = RedditClient::new();
+        let throttle = CommandThrottle::new(0, Duration::from_secs(60));
+
+        // Plain words are only commands in private chats.
+        let updates = vec![
+            parsed("about", "456", true, true),
+            parsed("about", "456", true, false),
+        ];
+        for update in updates {
+            handle_message(
+                &db,
+                &telegram_client,
+                &reddit_client,
+                "123",
+                &settings(),
+                &throttle,
+                update,
+            )
+            .await
+            .unwrap();
+        }
+        _m.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn self_check_success() {
+        let _m = mock("GET", "/bottoken/getMe")
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"id":414141,"is_bot":true,"first_name":"Bot","username":"reddit_bot"}}"#)
+            .create();
+        let _m2 = mock_send_message_not_called("token");
+        let telegram_client = TelegramClient::new_with("token".to_string(), server_url());
+
+        let result = self_check(&telegram_client, "123", false).await.unwrap();
+        assert_eq!(result, "reddit_bot");
+        _m.assert();
+        _m2.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn self_check_notifies_author() {
+        let _m = mock("GET", "/bottoken/getMe")
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"id":414141,"is_bot":true,"first_name":"Bot","username":"reddit_bot"}}"#)
+            .create();
+        let _m2 = mock_send_message_success(
+            "token",
+            &Message {
+                chat_id: "123",
+                text: "Bot started as @reddit_bot",
+                ..Default::default()
+            },
+        );
+        let telegram_client = TelegramClient::new_with("token".to_string(), server_url());
+
+        let result = self_check(&telegram_client, "123", true).await.unwrap();
+        assert_eq!(result, "reddit_bot");
+        _m.assert();
+        _m2.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn self_check_invalid_token() {
+        let _m = mock("GET", "/bottoken/getMe")
+            .with_status(401)
+            .with_body("Unauthorized")
+            .create();
+        let telegram_client = TelegramClient::new_with("token".to_string(), server_url());
+
+        let result = self_check(&telegram_client, "123", true).await.unwrap_err();
+        assert!(matches!(result, BotError::Config(_)));
+        assert_eq!(
+            result.to_string(),
+            "invalid configuration: failed to verify the Telegram token: Unauthorized"
+        );
+        _m.assert();
+    }
+}
@@ -1,89 +1,511 @@
+use chrono::{DateTime, Utc, Weekday};
+use chrono_tz::Tz;
 use diesel::result::DatabaseErrorKind;
-use diesel::result::Error::DatabaseError;
+use diesel::result::Error::{DatabaseError, DeserializationError, NotFound};
+use lazy_static::lazy_static;
 use log::{error, info, warn};
+use num::traits::FromPrimitive;
+use reqwest::Url;
+use std::fs;
+use std::path::Path;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use crate::bot::dialogs::{Dialog, Feedback, Subscribe, Unsubscribe};
+use crate::bot::dialogs::{
+    parse_quick_subscribe, parse_subreddit, Dialog, Feedback, Reorder, Stop, Subscribe,
+    Unsubscribe, QUICK_SUBSCRIBE_USAGE,
+};
 use crate::bot::error::BotError;
-use crate::db::client::DbClient;
-use crate::reddit::client::RedditClient;
-use crate::task::task::process_subscription;
-use crate::telegram::client::TelegramClient;
+use crate::db::client::{
+    subscription_limit_text, DbClient, DEFAULT_POST_LIMIT, MAX_SUBSCRIPTIONS,
+    POST_SUBSCRIPTION_TTL_DAYS,
+};
+use crate::db::models::{parse_timezone, Subscription, SubscriptionChanges, SubscriptionSettings};
+use crate::reddit::client::{Listing, RedditClient};
+use crate::reddit::post::Post;
+use crate::task::digest::{build_digest, format_number, LinkStyle, RenderOptions};
+use crate::task::task::{
+    fetch_posts, process_subscription, report_fetch_error, Outcome, FETCH_RETRY_DELAY,
+};
+use crate::telegram::messenger::Messenger;
 use crate::telegram::types::Message;
 
-const HELP_TEXT: &str = r#"
-You can send me these commands:
-/start
-/stop
-/subscribe
-/unsubscribe
-/subscriptions
-/sendnow
-/feedback
-/help
+/// Commands every user can send, see `COMMANDS`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Start,
+    Stop,
+    Subscribe,
+    Once,
+    Unsubscribe,
+    Resubscribe,
+    Subscriptions,
+    Reorder,
+    Next,
+    History,
+    WatchPost,
+    UnwatchPost,
+    Popular,
+    PostLimit,
+    Timezone,
+    Locale,
+    Combined,
+    Prefs,
+    Excerpt,
+    TopComment,
+    External,
+    Links,
+    Protect,
+    SinceLast,
+    Scores,
+    Poll,
+    Photos,
+    InPlace,
+    TitleLen,
+    PerMessage,
+    Tag,
+    CustomText,
+    Rename,
+    Retarget,
+    SendNow,
+    SendNew,
+    Feedback,
+    About,
+    Help,
+}
+
+/// Every command users can send along with its name and the description and examples shown by
+/// `/help <command>`, in the order /help lists them. Plain command words and `handle_message` go
+/// by it as well.
+const COMMANDS: [(Command, &str, &str); 39] = [
+    (
+        Command::Start,
+        "start",
+        "/start\nRegisters you with the bot. Needs to be called once before setting up subscriptions. Links like t.me/<bot>?start=sub_rust register you and start subscribing to the subreddit right away.",
+    ),
+    (
+        Command::Stop,
+        "stop",
+        "/stop\nDeletes your user along with all of your subscriptions, after asking for confirmation.",
+    ),
+    (
+        Command::Subscribe,
+        "subscribe",
+        "/subscribe [subreddits] [sort] [timeframe] [posts] [day] [hour]\nSubscribes to posts of one or more subreddits. Sort (top, hot, new, rising, controversial) and timeframe (hour, day, week, month, year, all) default to top of the week unless the bot is configured otherwise. Without arguments I'll ask for the subreddits, day and time step by step, anything missing from the arguments is asked for as well.\n\nExamples:\n/subscribe\n/subscribe rust\n/subscribe rust golang 5 sun\n/subscribe rust top week 10 sun 12",
+    ),
+    (
+        Command::Once,
+        "once",
+        "/once [subreddits] [sort] [timeframe] [posts] [day] [hour]\nSame as /subscribe, but only a single digest is sent and the subscription is removed afterwards.\n\nExamples:\n/once\n/once rust sun 12",
+    ),
+    (
+        Command::Unsubscribe,
+        "unsubscribe",
+        "/unsubscribe\nShows your subscriptions to pick the one to remove.",
+    ),
+    (
+        Command::Resubscribe,
+        "resubscribe",
+        "/resubscribe\nRestores the most recently removed subscription. Removed subscriptions are kept for 24 hours.",
+    ),
+    (
+        Command::Subscriptions,
+        "subscriptions",
+        "/subscriptions\nLists your subscriptions along with their schedule.",
+    ),
+    (
+        Command::Reorder,
+        "reorder",
+        "/reorder\nChanges the order your subscriptions are listed and sent in. Pick a subreddit and move it up or down.",
+    ),
+    (
+        Command::Next,
+        "next",
+        "/next\nShows your upcoming digests ordered by the time they will be sent.",
+    ),
+    (
+        Command::History,
+        "history",
+        "/history <subreddit>\nShows how many digests of a subscription were sent and when the latest ones went out.\n\nExample:\n/history rust",
+    ),
+    (
+        Command::WatchPost,
+        "watchpost",
+        "/watchpost <link>\nSends new top comments of a reddit thread as they come in, for a week.\n\nExample:\n/watchpost https://www.reddit.com/r/rust/comments/fbenua/a_halfhour_to_learn_rust/",
+    ),
+    (
+        Command::UnwatchPost,
+        "unwatchpost",
+        "/unwatchpost <link>\nStops sending new comments of a thread watched with /watchpost.\n\nExample:\n/unwatchpost https://www.reddit.com/r/rust/comments/fbenua/a_halfhour_to_learn_rust/",
+    ),
+    (
+        Command::Popular,
+        "popular",
+        "/popular\nLists the 10 subreddits with the most subscribers among all users of the bot.",
+    ),
+    (
+        Command::PostLimit,
+        "postlimit",
+        "/postlimit [1-100|reset]\nShows or changes the number of posts that new subscriptions include.\n\nExamples:\n/postlimit\n/postlimit 5\n/postlimit reset",
+    ),
+    (
+        Command::Timezone,
+        "timezone",
+        "/timezone [name]\nShows or changes the timezone the day and hour of your subscriptions are in, UTC by default. Use the name of a city in your timezone.\n\nExamples:\n/timezone\n/timezone Europe/Riga\n/timezone America/New_York",
+    ),
+    (
+        Command::Locale,
+        "locale",
+        "/locale [language|reset]\nShows or changes the language the scores in your digests are formatted for, e.g. 12.345 in German. Scores are shown with /scores.\n\nExamples:\n/locale\n/locale de\n/locale reset",
+    ),
+    (
+        Command::Combined,
+        "combined",
+        "/combined <on|off>\nMerges the digests that are due at the same time into a single message, grouped by subreddit. Digests delivered to other chats are still sent separately.\n\nExample:\n/combined on",
+    ),
+    (
+        Command::Prefs,
+        "prefs",
+        "/prefs [silent|preview] [on|off]\nShows or changes whether new subscriptions are sent without a notification sound and with a preview of the first link. Existing subscriptions keep their settings.\n\nExamples:\n/prefs\n/prefs silent on\n/prefs preview off",
+    ),
+    (
+        Command::Excerpt,
+        "excerpt",
+        "/excerpt <subreddit> <on|off>\nIncludes a short excerpt of text posts in the digest.\n\nExample:\n/excerpt rust on",
+    ),
+    (
+        Command::TopComment,
+        "topcomment",
+        "/topcomment <subreddit> <on|off>\nIncludes the top comment under the first posts of the digest.\n\nExample:\n/topcomment rust on",
+    ),
+    (
+        Command::External,
+        "external",
+        "/external <subreddit> <on|off>\nLinks posts to the article they share instead of the reddit thread.\n\nExample:\n/external worldnews on",
+    ),
+    (
+        Command::Links,
+        "links",
+        "/links <subreddit> <permalink|short|np>\nAdds a second link under each post of the digest: short for the redd.it link, np for the no-participation np.reddit.com link. permalink shows only the link to the comments again.\n\nExample:\n/links rust short",
+    ),
+    (
+        Command::Protect,
+        "protect",
+        "/protect <subreddit> <on|off>\nPrevents the digest from being forwarded or saved, e.g. for digests shared in a channel.\n\nExample:\n/protect rust on",
+    ),
+    (
+        Command::SinceLast,
+        "sincelast",
+        "/sincelast <subreddit> <on|off>\nSends the newest posts since the previous digest instead of the top posts, up to the post limit of the subscription.\n\nExample:\n/sincelast rust on",
+    ),
+    (
+        Command::Scores,
+        "scores",
+        "/scores <subreddit> <on|off>\nShows the score of each post next to its title, formatted for the language set with /locale.\n\nExample:\n/scores rust on",
+    ),
+    (
+        Command::Poll,
+        "poll",
+        "/poll <subreddit> <on|off>\nSends the posts as a poll of which one to read, with the post titles as options.\n\nExample:\n/poll rust on",
+    ),
+    (
+        Command::Photos,
+        "photos",
+        "/photos <subreddit> <on|off>\nSends image posts as photos instead of links in the digest. NSFW images are blurred until tapped.\n\nExample:\n/photos aww on",
+    ),
+    (
+        Command::InPlace,
+        "inplace",
+        "/inplace <subreddit> <on|off>\nEdits the previous digest instead of sending a new one, e.g. for a channel showing the current top posts. Digests longer than one message are still sent anew.\n\nExample:\n/inplace rust on",
+    ),
+    (
+        Command::TitleLen,
+        "titlelen",
+        "/titlelen <subreddit> <10-300|off>\nCuts off long titles in the digest after the given number of characters.\n\nExamples:\n/titlelen rust 80\n/titlelen rust off",
+    ),
+    (
+        Command::PerMessage,
+        "permessage",
+        "/permessage <subreddit> <1-100|off>\nSplits the digest into messages of at most the given number of posts, long messages are split regardless.\n\nExamples:\n/permessage rust 5\n/permessage rust off",
+    ),
+    (
+        Command::Tag,
+        "tag",
+        "/tag <subreddit> <emoji|off>\nStarts the digest header with an emoji or a short tag, to tell your subscriptions apart.\n\nExamples:\n/tag rust 🦀\n/tag rust off",
+    ),
+    (
+        Command::CustomText,
+        "customtext",
+        "/customtext <subreddit> <empty|error> <text|reset>\nReplaces the message sent when a digest has no posts or when posts can't be fetched. {subreddit} in the error text is replaced with the subreddit name.\n\nExamples:\n/customtext rust empty Quiet week in the crab shack.\n/customtext rust error r/{subreddit} is down, back next week!\n/customtext rust error reset",
+    ),
+    (
+        Command::Rename,
+        "rename",
+        "/rename <old_subreddit> <new_subreddit>\nMoves a subscription over to a subreddit that changed its name, keeping the schedule and options.\n\nExample:\n/rename rust rustlang",
+    ),
+    (
+        Command::Retarget,
+        "retarget",
+        "/retarget <subreddit> <chat_id>\nDelivers the digest of a subscription to another chat, e.g. a channel you manage. I need to be able to post there.\n\nExample:\n/retarget rust -1001234567890",
+    ),
+    (
+        Command::SendNow,
+        "sendnow",
+        "/sendnow\nSends the digests of all of your subscriptions right away.",
+    ),
+    (
+        Command::SendNew,
+        "sendnew",
+        "/sendnew\nSends the digests of all of your subscriptions right away, leaving out posts you have already received.",
+    ),
+    (
+        Command::Feedback,
+        "feedback",
+        "/feedback\nSends a message to the author after asking whether it's about a bug, a feature request or something else. Leave your email if you'd like to get a reply.",
+    ),
+    (
+        Command::About,
+        "about",
+        "/about\nShows how many subreddits are tracked, how long the bot has been running and where to find its source code.",
+    ),
+    (
+        Command::Help,
+        "help",
+        "/help [command]\nLists the available commands or describes a specific one.\n\nExample:\n/help subscribe",
+    ),
+];
+
+lazy_static! {
+    static ref HELP_TEXT: String = format!(
+        "\nYou can send me these commands:\n{}\n\n{}",
+        COMMANDS
+            .iter()
+            .map(|(_, name, _)| format!("/{}", name))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        HELP_FOOTER
+    );
+}
+
+const HELP_FOOTER: &str = r#"Send /help <command> to learn more about a specific command.
 
 Bot is open source and available here https://github.com/aldis-ameriks/reddit-bot. If you encounter any issues feel free to open an issue.
 Or you can also send feedback via /feedback command.
 "#;
 
+/// Start payload of deep links subscribing to a subreddit, e.g. "sub_rust".
+pub const DEEP_LINK_SUBSCRIBE_PREFIX: &str = "sub_";
+
+/// Number of latest digests listed by /history.
+const HISTORY_LIMIT: i64 = 5;
+
+/// Subscriptions not sent for this many days are listed by /stale, weekly ones should never get there.
+const STALE_AFTER_DAYS: i64 = 14;
+
+/// Users without subscriptions that joined more than this many days ago are listed by /inactive.
+const INACTIVE_AFTER_DAYS: i64 = 30;
+
+/// Number of subreddits listed by /popular.
+const POPULAR_SUBREDDITS_LIMIT: i64 = 10;
+
+/// Tags are meant for an emoji or a word, not a sentence.
+const MAX_TAG_LENGTH: usize = 16;
+
+/// Telegram rejects messages longer than 4096 characters.
+const DEBUG_POST_MAX_LENGTH: usize = 4096;
+
+/// Telegram rejects documents larger than 50 MB.
+const MAX_DOCUMENT_SIZE: u64 = 50 * 1024 * 1024;
+
+pub const BLOCKED_SUBREDDIT_TEXT: &str = "That subreddit isn't allowed on this instance.";
+
+/// Detailed description and examples of a command for `/help <command>`.
+fn command_help(command: &str) -> Option<&'static str> {
+    let name = command.trim_start_matches('/').to_lowercase();
+    COMMANDS
+        .iter()
+        .find(|(_, command_name, _)| *command_name == name)
+        .map(|(_, _, help)| *help)
+}
+
+/// The command sent as e.g. "/subscribe", None for author-only commands and anything else.
+pub fn find_command(command: &str) -> Option<Command> {
+    let name = command.strip_prefix('/')?;
+    COMMANDS
+        .iter()
+        .find(|(_, command_name, _)| *command_name == name)
+        .map(|(command, _, _)| *command)
+}
+
+/// Prepends the missing slash when the payload starts with a known command word.
+pub fn normalize_command(payload: &str) -> Option<String> {
+    let payload = payload.trim();
+    if payload.starts_with('/') {
+        return None;
+    }
+
+    let (command, args) = parse_command(payload);
+    let command = command.to_lowercase();
+    if !COMMANDS.iter().any(|(_, name, _)| *name == command) {
+        return None;
+    }
+
+    if args.is_empty() {
+        Some(format!("/{}", command))
+    } else {
+        Some(format!("/{} {}", command, args))
+    }
+}
+
+pub fn parse_command(payload: &str) -> (&str, &str) {
+    let payload = payload.trim();
+    match payload.find(char::is_whitespace) {
+        Some(index) => (&payload[..index], payload[index..].trim()),
+        None => (payload, ""),
+    }
+}
+
 pub async fn start(
-    telegram_client: &TelegramClient,
+    telegram_client: &impl Messenger,
     db: &DbClient,
+    reddit_client: &RedditClient,
     user_id: &str,
+    args: &str,
+    listing: &Listing,
 ) -> Result<(), BotError> {
     match db.create_user(user_id) {
-        Ok(_) => {
-            telegram_client
-                .send_message(&Message {
-                    chat_id: user_id,
-                    text: HELP_TEXT,
-                    ..Default::default()
-                })
-                .await?;
-            Ok(())
+        Ok(_) | Err(DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {}
+        Err(err) => return Err(BotError::DatabaseError(err)),
+    }
+
+    // Links like t.me/<bot>?start=sub_rust arrive as "/start sub_rust".
+    match args.strip_prefix(DEEP_LINK_SUBSCRIBE_PREFIX) {
+        Some(subreddit) if !subreddit.is_empty() => {
+            subscribe(
+                telegram_client,
+                db,
+                reddit_client,
+                user_id,
+                subreddit,
+                listing,
+            )
+            .await
         }
-        Err(DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+        _ => {
             telegram_client
                 .send_message(&Message {
                     chat_id: user_id,
-                    text: HELP_TEXT,
+                    text: &HELP_TEXT,
                     ..Default::default()
                 })
                 .await?;
             Ok(())
         }
-        Err(err) => Err(BotError::DatabaseError(err)),
     }
 }
 
 pub async fn stop(
-    telegram_client: &TelegramClient,
+    telegram_client: &impl Messenger,
     db: &DbClient,
     user_id: &str,
 ) -> Result<(), BotError> {
-    db.delete_user(user_id)?;
-    telegram_client
-        .send_message(&Message {
-            chat_id: user_id,
-            text: "User and subscriptions deleted",
-            ..Default::default()
-        })
-        .await?;
+    // The dialog is stored along with the user, a chat that never started has nothing to delete.
+    if !db.user_exists(user_id)? {
+        telegram_client
+            .send_message(&Message {
+                chat_id: user_id,
+                text: "There's nothing to delete.",
+                ..Default::default()
+            })
+            .await?;
+        return Ok(());
+    }
 
-    Ok(())
+    Dialog::<Stop>::new(user_id.to_string())
+        .handle_current_step(telegram_client, db, "")
+        .await
 }
 
 pub async fn subscribe(
-    telegram_client: &TelegramClient,
+    telegram_client: &impl Messenger,
     db: &DbClient,
     reddit_client: &RedditClient,
     user_id: &str,
+    args: &str,
+    listing: &Listing,
 ) -> Result<(), BotError> {
-    match Dialog::<Subscribe>::new(user_id.to_string())
-        .handle_current_step(&telegram_client, &db, &reddit_client, "")
-        .await
-    {
+    start_subscribe(
+        telegram_client,
+        db,
+        reddit_client,
+        user_id,
+        args,
+        false,
+        listing,
+    )
+    .await
+}
+
+/// Same as /subscribe, except the subscription is removed once its first digest has been sent.
+pub async fn once(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    reddit_client: &RedditClient,
+    user_id: &str,
+    args: &str,
+    listing: &Listing,
+) -> Result<(), BotError> {
+    start_subscribe(
+        telegram_client,
+        db,
+        reddit_client,
+        user_id,
+        args,
+        true,
+        listing,
+    )
+    .await
+}
+
+async fn start_subscribe(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    reddit_client: &RedditClient,
+    user_id: &str,
+    args: &str,
+    once: bool,
+    listing: &Listing,
+) -> Result<(), BotError> {
+    let mut dialog = if once {
+        Dialog::<Subscribe>::new_once(user_id.to_string())
+    } else {
+        Dialog::<Subscribe>::new(user_id.to_string())
+    };
+    let result = if args.trim().is_empty() {
+        dialog
+            .handle_current_step(telegram_client, db, reddit_client, "", listing)
+            .await
+    } else {
+        match parse_quick_subscribe(args) {
+            Ok(quick) => {
+                dialog
+                    .handle_quick(telegram_client, db, reddit_client, quick, listing)
+                    .await
+            }
+            Err(err) => {
+                telegram_client
+                    .send_message(&Message {
+                        chat_id: user_id,
+                        text: &format!("{}\n{}", err, QUICK_SUBSCRIBE_USAGE),
+                        ..Default::default()
+                    })
+                    .await?;
+                return Ok(());
+            }
+        }
+    };
+
+    match result {
         Ok(_) => Ok(()),
         Err(BotError::DatabaseError(err)) => {
             if let DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _) = err {
@@ -103,17 +525,76 @@ pub async fn subscribe(
 }
 
 pub async fn unsubscribe(
-    telegram_client: &TelegramClient,
+    telegram_client: &impl Messenger,
     db: &DbClient,
     user_id: &str,
 ) -> Result<(), BotError> {
     Dialog::<Unsubscribe>::new(user_id.to_string())
-        .handle_current_step(&telegram_client, &db, "")
+        .handle_current_step(telegram_client, db, "")
+        .await
+}
+
+pub async fn reorder(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    user_id: &str,
+) -> Result<(), BotError> {
+    Dialog::<Reorder>::new(user_id.to_string())
+        .handle_current_step(telegram_client, db, "")
         .await
 }
 
+pub async fn resubscribe(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    user_id: &str,
+) -> Result<(), BotError> {
+    let blocked = match db.get_removed_subscription(user_id)? {
+        Some(removed) => db.is_subreddit_blocked(&removed.subreddit)?,
+        None => false,
+    };
+    let text = if db.get_subscription_count(user_id)? >= MAX_SUBSCRIPTIONS {
+        subscription_limit_text()
+    } else if blocked {
+        BLOCKED_SUBREDDIT_TEXT.to_string()
+    } else {
+        match db.restore_subscription(user_id) {
+            Ok(Some(subscription)) => {
+                let day = Weekday::from_i32(subscription.send_on).ok_or_else(|| {
+                    BotError::DatabaseError(DeserializationError(
+                        format!("invalid send_on: {}", subscription.send_on).into(),
+                    ))
+                })?;
+                format!(
+                    "Resubscribed to: {}. Posts will be sent periodically on {} at around {}:00 {} time.",
+                    subscription.subreddit,
+                    day,
+                    subscription.send_at,
+                    db.get_timezone(user_id)?
+                )
+            }
+            Ok(None) => "There is nothing to restore. Removed subscriptions are kept for 24 hours."
+                .to_string(),
+            Err(DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+                "You are already subscribed to the most recently removed subreddit.".to_string()
+            }
+            Err(err) => return Err(BotError::DatabaseError(err)),
+        }
+    };
+
+    telegram_client
+        .send_message(&Message {
+            chat_id: user_id,
+            text: &text,
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(())
+}
+
 pub async fn subscriptions(
-    telegram_client: &TelegramClient,
+    telegram_client: &impl Messenger,
     db: &DbClient,
     user_id: &str,
 ) -> Result<(), BotError> {
@@ -127,9 +608,10 @@ pub async fn subscriptions(
             })
             .await?;
     } else {
+        let timezone = db.get_timezone(user_id)?;
         let text = subscriptions
             .iter()
-            .map(|subscription| format!("{}\n", subscription.subreddit))
+            .map(|subscription| format!("{}\n", subscription.summary(&timezone)))
             .collect::<String>();
         telegram_client
             .send_message(&Message {
@@ -143,72 +625,217 @@ pub async fn subscriptions(
     Ok(())
 }
 
-pub async fn feedback(
-    telegram_client: &TelegramClient,
+pub async fn next(
+    telegram_client: &impl Messenger,
     db: &DbClient,
-    author_id: &str,
     user_id: &str,
 ) -> Result<(), BotError> {
-    match Dialog::<Feedback>::new(user_id.to_string())
-        .handle_current_step(&telegram_client, &db, author_id, "")
-        .await
-    {
-        Ok(_) => Ok(()),
-        Err(BotError::DatabaseError(err)) => {
-            if let DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _) = err {
-                warn!("feedback was initiated without user");
-                telegram_client
-                    .send_message(&Message {
-                        chat_id: user_id,
-                        text: "You need to call /start before interacting with me",
-                        ..Default::default()
+    let subscriptions = db.get_user_subscriptions_with_next(user_id, Utc::now())?;
+    if subscriptions.is_empty() {
+        telegram_client
+            .send_message(&Message {
+                chat_id: user_id,
+                text: "You haven't subscribed to anything yet. Subscribe using /subscribe command.",
+                ..Default::default()
+            })
+            .await?;
+    } else {
+        let timezone = parse_timezone(&db.get_timezone(user_id)?);
+        let text = subscriptions
+            .iter()
+            .take(3)
+            .map(|(subscription, next)| {
+                format!(
+                    "{} - {} {}\n",
+                    subscription.subreddit,
+                    next.with_timezone(&timezone).format("%a, %d %b at %H:%M"),
+                    timezone.name()
+                )
+            })
+            .collect::<String>();
+        telegram_client
+            .send_message(&Message {
+                chat_id: user_id,
+                text: &format!("Your next digests:\n{}", text),
+                ..Default::default()
+            })
+            .await?;
+    }
+
+    Ok(())
+}
+
+pub async fn history(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    user_id: &str,
+    subreddit: &str,
+) -> Result<(), BotError> {
+    let text = if subreddit.is_empty() {
+        "Usage: /history <subreddit>".to_string()
+    } else {
+        match db.delivery_history(user_id, subreddit, HISTORY_LIMIT)? {
+            None => format!("You are not subscribed to {}", subreddit),
+            Some((0, _)) => format!("No digests from {} have been sent yet", subreddit),
+            Some((count, deliveries)) => {
+                let timezone = parse_timezone(&db.get_timezone(user_id)?);
+                let recent = deliveries
+                    .iter()
+                    .map(|delivery| {
+                        let sent_at = match delivery.sent_at.parse::<DateTime<Utc>>() {
+                            Ok(date) => format!(
+                                "{} {}",
+                                date.with_timezone(&timezone).format("%a, %d %b at %H:%M"),
+                                timezone.name()
+                            ),
+                            Err(_) => delivery.sent_at.clone(),
+                        };
+                        format!("{} - {} posts\n", sent_at, delivery.post_count)
                     })
-                    .await?;
+                    .collect::<String>();
+                format!(
+                    "{} digests from {} sent so far, latest:\n{}",
+                    count, subreddit, recent
+                )
             }
-            Ok(())
         }
-        Err(err) => Err(err),
+    };
+
+    telegram_client
+        .send_message(&Message {
+            chat_id: user_id,
+            text: &text,
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Reddit thread path, e.g. "/r/rust/comments/fbenua/a_halfhour_to_learn_rust/", from a link or a path.
+fn parse_permalink(input: &str) -> Option<String> {
+    let path = if input.starts_with('/') {
+        input.to_string()
+    } else {
+        let with_scheme = if input.contains("://") {
+            input.to_string()
+        } else {
+            format!("https://{}", input)
+        };
+        let url = Url::parse(&with_scheme).ok()?;
+        let host = url.host_str()?;
+        if host != "reddit.com" && !host.ends_with(".reddit.com") {
+            return None;
+        }
+        url.path().to_string()
+    };
+
+    let segments = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .take(5)
+        .collect::<Vec<&str>>();
+    match segments.as_slice() {
+        ["r", _, "comments", _, ..] => Some(format!("/{}/", segments.join("/"))),
+        _ => None,
     }
 }
 
-pub async fn send_now(
-    telegram_client: &TelegramClient,
+pub async fn watch_post(
+    telegram_client: &impl Messenger,
     db: &DbClient,
-    reddit_client: &RedditClient,
     user_id: &str,
+    link: &str,
 ) -> Result<(), BotError> {
-    let subscriptions = db.get_user_subscriptions(user_id)?;
+    let text = if link.is_empty() {
+        "Usage: /watchpost <link>".to_string()
+    } else {
+        match parse_permalink(link) {
+            None => "That doesn't look like a link to a reddit post".to_string(),
+            Some(permalink) => match db.watch_post(user_id, &permalink) {
+                Ok(_) => format!(
+                    "Watching {} for new top comments for the next {} days",
+                    permalink, POST_SUBSCRIPTION_TTL_DAYS
+                ),
+                Err(DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+                    "You are already watching this post".to_string()
+                }
+                Err(err) => return Err(BotError::DatabaseError(err)),
+            },
+        }
+    };
 
-    if subscriptions.len() == 0 {
-        telegram_client
-            .send_message(&Message {
-                chat_id: user_id,
-                text: "You haven't subscribed to anything yet. Subscribe using /subscribe command.",
-                ..Default::default()
-            })
-            .await?;
-    }
+    telegram_client
+        .send_message(&Message {
+            chat_id: user_id,
+            text: &text,
+            ..Default::default()
+        })
+        .await?;
 
-    for subscription in subscriptions {
-        match process_subscription(db, telegram_client, reddit_client, &subscription).await {
-            Ok(_) => {
-                info!("processed subscription: {:?}", &subscription);
-            }
-            Err(err) => {
-                error!("failed to process subscription: {}", err);
+    Ok(())
+}
+
+pub async fn unwatch_post(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    user_id: &str,
+    link: &str,
+) -> Result<(), BotError> {
+    let text = if link.is_empty() {
+        "Usage: /unwatchpost <link>".to_string()
+    } else {
+        match parse_permalink(link) {
+            None => "That doesn't look like a link to a reddit post".to_string(),
+            Some(permalink) => {
+                if db.delete_post_subscription(user_id, &permalink)? {
+                    format!("Stopped watching {}", permalink)
+                } else {
+                    "You are not watching this post".to_string()
+                }
             }
         }
-        thread::sleep(Duration::from_secs(30));
-    }
+    };
+
+    telegram_client
+        .send_message(&Message {
+            chat_id: user_id,
+            text: &text,
+            ..Default::default()
+        })
+        .await?;
 
     Ok(())
 }
 
-pub async fn help(telegram_client: &TelegramClient, user_id: &str) -> Result<(), BotError> {
+pub async fn popular(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    user_id: &str,
+) -> Result<(), BotError> {
+    let subreddits = db.top_subreddits(POPULAR_SUBREDDITS_LIMIT)?;
+    let text = if subreddits.is_empty() {
+        "Nobody has subscribed to anything yet.".to_string()
+    } else {
+        let list = subreddits
+            .iter()
+            .enumerate()
+            .map(|(index, (subreddit, count))| {
+                let noun = if *count == 1 {
+                    "subscriber"
+                } else {
+                    "subscribers"
+                };
+                format!("{}. {} - {} {}\n", index + 1, subreddit, count, noun)
+            })
+            .collect::<String>();
+        format!("Most subscribed subreddits:\n{}", list)
+    };
+
     telegram_client
         .send_message(&Message {
             chat_id: user_id,
-            text: HELP_TEXT,
+            text: &text,
             ..Default::default()
         })
         .await?;
@@ -216,133 +843,4637 @@ pub async fn help(telegram_client: &TelegramClient, user_id: &str) -> Result<(),
     Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use mockito::server_url;
-    use serial_test::serial;
+pub async fn post_limit(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    user_id: &str,
+    limit: &str,
+) -> Result<(), BotError> {
+    let text = if limit.is_empty() {
+        format!(
+            "New subscriptions will include {} posts. Use /postlimit <1-100> to change it or /postlimit reset to restore the default.",
+            db.get_default_post_limit(user_id)?
+                .unwrap_or(DEFAULT_POST_LIMIT)
+        )
+    } else if limit == "reset" {
+        db.set_default_post_limit(user_id, None)?;
+        format!(
+            "New subscriptions will include the default of {} posts.",
+            DEFAULT_POST_LIMIT
+        )
+    } else {
+        match limit.parse::<i32>() {
+            Ok(limit) if (1..=100).contains(&limit) => {
+                db.set_default_post_limit(user_id, Some(limit))?;
+                format!("New subscriptions will include {} posts.", limit)
+            }
+            _ => "Number of posts must be between 1 and 100.".to_string(),
+        }
+    };
 
-    use crate::db::test_helpers::{setup_test_db, setup_test_db_with};
-    use crate::telegram::test_helpers::{mock_send_message_not_called, mock_send_message_success};
+    telegram_client
+        .send_message(&Message {
+            chat_id: user_id,
+            text: &text,
+            ..Default::default()
+        })
+        .await?;
 
-    use super::*;
-    use crate::reddit::test_helpers::mock_reddit_success;
+    Ok(())
+}
+
+pub async fn timezone(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    user_id: &str,
+    timezone: &str,
+) -> Result<(), BotError> {
+    let text = if timezone.is_empty() {
+        format!(
+            "Your subscriptions are sent in {} time. Use /timezone <name> to change it, e.g. /timezone Europe/Riga.",
+            db.get_timezone(user_id)?
+        )
+    } else {
+        match timezone.parse::<Tz>() {
+            Ok(timezone) => {
+                db.set_timezone(user_id, timezone.name())?;
+                format!(
+                    "Your subscriptions will be sent in {} time.",
+                    timezone.name()
+                )
+            }
+            Err(_) => format!(
+                "Unknown timezone - {}. Use a name like Europe/Riga or America/New_York.",
+                timezone
+            ),
+        }
+    };
+
+    telegram_client
+        .send_message(&Message {
+            chat_id: user_id,
+            text: &text,
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(())
+}
+
+pub async fn locale(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    user_id: &str,
+    locale: &str,
+) -> Result<(), BotError> {
+    let text = if locale.is_empty() {
+        match db.get_locale(user_id)? {
+            Some(locale) => format!(
+                "Scores in your digests are formatted for {}, e.g. {}. Use /locale reset to go back to the default.",
+                locale,
+                format_number(12345, Some(&locale))
+            ),
+            None => "Scores in your digests are formatted like 12,345. Use /locale <language> to change it, e.g. /locale de.".to_string(),
+        }
+    } else if locale.eq_ignore_ascii_case("reset") {
+        db.set_locale(user_id, None)?;
+        "Scores in your digests will be formatted like 12,345.".to_string()
+    } else if is_language_tag(locale) {
+        db.set_locale(user_id, Some(locale))?;
+        format!(
+            "Scores in your digests will be formatted for {}, e.g. {}.",
+            locale,
+            format_number(12345, Some(locale))
+        )
+    } else {
+        format!(
+            "Unknown language - {}. Use a language code like de or pt-BR.",
+            locale
+        )
+    };
+
+    telegram_client
+        .send_message(&Message {
+            chat_id: user_id,
+            text: &text,
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Language tag like "de" or "pt-BR", only its form is checked.
+fn is_language_tag(value: &str) -> bool {
+    let mut parts = value.split(['-', '_']);
+    let language = parts.next().unwrap_or_default();
+    (2..=3).contains(&language.len())
+        && language.chars().all(|c| c.is_ascii_alphabetic())
+        && parts.all(|part| {
+            (1..=8).contains(&part.len()) && part.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+}
+
+fn parse_toggle(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "on" | "yes" | "true" => Some(true),
+        "off" | "no" | "false" => Some(false),
+        _ => None,
+    }
+}
+
+pub async fn combined(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    user_id: &str,
+    args: &str,
+) -> Result<(), BotError> {
+    let text = match parse_toggle(args) {
+        Some(true) => {
+            db.set_combined_digest(user_id, true)?;
+            "Digests due at the same time will be combined into a single message"
+        }
+        Some(false) => {
+            db.set_combined_digest(user_id, false)?;
+            "Each subscription will be sent as a separate digest"
+        }
+        None => "Usage: /combined <on|off>",
+    };
+
+    telegram_client
+        .send_message(&Message {
+            chat_id: user_id,
+            text,
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Shows or changes whether new subscriptions are sent silently and with a link preview.
+pub async fn prefs(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    user_id: &str,
+    args: &str,
+) -> Result<(), BotError> {
+    let (disable_notification, link_preview) = db.get_user_prefs(user_id)?;
+    let mut parts = args.split_whitespace();
+    let pref = parts.next().map(str::to_lowercase);
+    let value = parts.next().and_then(parse_toggle);
+
+    let text = match (pref.as_deref(), value) {
+        (None, _) => format!(
+            "New subscriptions will be sent {} and {} link preview. Use /prefs <silent|preview> <on|off> to change it.",
+            if disable_notification {
+                "silently"
+            } else {
+                "with a notification"
+            },
+            if link_preview { "with a" } else { "without a" }
+        ),
+        (Some("silent"), Some(value)) => {
+            db.update_user_prefs(user_id, value, link_preview)?;
+            if value {
+                "New subscriptions will be sent silently".to_string()
+            } else {
+                "New subscriptions will be sent with a notification".to_string()
+            }
+        }
+        (Some("preview"), Some(value)) => {
+            db.update_user_prefs(user_id, disable_notification, value)?;
+            if value {
+                "New subscriptions will be sent with a link preview".to_string()
+            } else {
+                "New subscriptions will be sent without a link preview".to_string()
+            }
+        }
+        _ => "Usage: /prefs <silent|preview> <on|off>".to_string(),
+    };
+
+    telegram_client
+        .send_message(&Message {
+            chat_id: user_id,
+            text: &text,
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Turns an option of the user's subscription on or off for `<command> <subreddit> <on|off>`,
+/// `texts` are the replies for on and off with `{subreddit}` filled in.
+async fn toggle(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    user_id: &str,
+    args: &str,
+    command: &str,
+    changes: fn(bool) -> SubscriptionChanges<'static>,
+    texts: (&str, &str),
+) -> Result<(), BotError> {
+    let (subreddit, value) = parse_command(args);
+    let text = match parse_toggle(value) {
+        Some(value) if !subreddit.is_empty() => {
+            if db.update_subscription(user_id, subreddit, changes(value))? == 0 {
+                format!("You are not subscribed to {}", subreddit)
+            } else if value {
+                texts.0.replace("{subreddit}", subreddit)
+            } else {
+                texts.1.replace("{subreddit}", subreddit)
+            }
+        }
+        _ => format!("Usage: {} <subreddit> <on|off>", command),
+    };
+
+    telegram_client
+        .send_message(&Message {
+            chat_id: user_id,
+            text: &text,
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(())
+}
+
+pub async fn excerpt(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    user_id: &str,
+    args: &str,
+) -> Result<(), BotError> {
+    toggle(
+        telegram_client,
+        db,
+        user_id,
+        args,
+        "/excerpt",
+        |include_excerpt| SubscriptionChanges {
+            include_excerpt: Some(include_excerpt),
+            ..Default::default()
+        },
+        (
+            "Text post excerpts enabled for {subreddit}",
+            "Text post excerpts disabled for {subreddit}",
+        ),
+    )
+    .await
+}
+
+pub async fn top_comment(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    user_id: &str,
+    args: &str,
+) -> Result<(), BotError> {
+    toggle(
+        telegram_client,
+        db,
+        user_id,
+        args,
+        "/topcomment",
+        |include_top_comment| SubscriptionChanges {
+            include_top_comment: Some(include_top_comment),
+            ..Default::default()
+        },
+        (
+            "Top comments enabled for {subreddit}",
+            "Top comments disabled for {subreddit}",
+        ),
+    )
+    .await
+}
+
+pub async fn external(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    user_id: &str,
+    args: &str,
+) -> Result<(), BotError> {
+    toggle(
+        telegram_client,
+        db,
+        user_id,
+        args,
+        "/external",
+        |prefer_external_url| SubscriptionChanges {
+            prefer_external_url: Some(prefer_external_url),
+            ..Default::default()
+        },
+        (
+            "Link posts in {subreddit} will point to the linked article",
+            "Link posts in {subreddit} will point to reddit",
+        ),
+    )
+    .await
+}
+
+pub async fn links(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    user_id: &str,
+    args: &str,
+) -> Result<(), BotError> {
+    let (subreddit, value) = parse_command(args);
+    let text = match value.to_lowercase().parse::<LinkStyle>() {
+        Ok(link_style) if !subreddit.is_empty() => {
+            if db.update_subscription(
+                user_id,
+                subreddit,
+                SubscriptionChanges {
+                    link_style: Some(&link_style.to_string()),
+                    ..Default::default()
+                },
+            )? == 0
+            {
+                format!("You are not subscribed to {}", subreddit)
+            } else {
+                match link_style {
+                    LinkStyle::Permalink => {
+                        format!("Posts in {} will only link to the comments", subreddit)
+                    }
+                    LinkStyle::Short => {
+                        format!("Posts in {} will include the short redd.it link", subreddit)
+                    }
+                    LinkStyle::Np => {
+                        format!("Posts in {} will include the np.reddit.com link", subreddit)
+                    }
+                }
+            }
+        }
+        _ => "Usage: /links <subreddit> <permalink|short|np>".to_string(),
+    };
+
+    telegram_client
+        .send_message(&Message {
+            chat_id: user_id,
+            text: &text,
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(())
+}
+
+pub async fn protect(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    user_id: &str,
+    args: &str,
+) -> Result<(), BotError> {
+    toggle(
+        telegram_client,
+        db,
+        user_id,
+        args,
+        "/protect",
+        |protect_content| SubscriptionChanges {
+            protect_content: Some(protect_content),
+            ..Default::default()
+        },
+        (
+            "Digests of {subreddit} can no longer be forwarded or saved",
+            "Digests of {subreddit} can be forwarded and saved",
+        ),
+    )
+    .await
+}
+
+pub async fn since_last_sent(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    user_id: &str,
+    args: &str,
+) -> Result<(), BotError> {
+    toggle(
+        telegram_client,
+        db,
+        user_id,
+        args,
+        "/sincelast",
+        |since_last_sent| SubscriptionChanges {
+            since_last_sent: Some(since_last_sent),
+            ..Default::default()
+        },
+        (
+            "Digests of {subreddit} will include the newest posts since the previous one",
+            "Digests of {subreddit} will include the top posts",
+        ),
+    )
+    .await
+}
+
+pub async fn scores(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    user_id: &str,
+    args: &str,
+) -> Result<(), BotError> {
+    toggle(
+        telegram_client,
+        db,
+        user_id,
+        args,
+        "/scores",
+        |show_scores| SubscriptionChanges {
+            show_scores: Some(show_scores),
+            ..Default::default()
+        },
+        (
+            "Digests of {subreddit} will show the score of each post",
+            "Digests of {subreddit} will not show scores",
+        ),
+    )
+    .await
+}
+
+pub async fn title_length(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    user_id: &str,
+    args: &str,
+) -> Result<(), BotError> {
+    let (subreddit, value) = parse_command(args);
+    let max_title_len = match value {
+        "off" => Some(None),
+        value => match value.parse::<i32>() {
+            Ok(len) if (10..=300).contains(&len) => Some(Some(len)),
+            _ => None,
+        },
+    };
+    let text = match max_title_len {
+        Some(max_title_len) if !subreddit.is_empty() => {
+            if db.update_subscription(
+                user_id,
+                subreddit,
+                SubscriptionChanges {
+                    max_title_len: Some(max_title_len),
+                    ..Default::default()
+                },
+            )? == 0
+            {
+                format!("You are not subscribed to {}", subreddit)
+            } else if let Some(len) = max_title_len {
+                format!(
+                    "Titles in {} will be cut off after {} characters",
+                    subreddit, len
+                )
+            } else {
+                format!("Titles in {} will be shown in full", subreddit)
+            }
+        }
+        _ => "Usage: /titlelen <subreddit> <10-300|off>".to_string(),
+    };
+
+    telegram_client
+        .send_message(&Message {
+            chat_id: user_id,
+            text: &text,
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(())
+}
+
+pub async fn posts_per_message(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    user_id: &str,
+    args: &str,
+) -> Result<(), BotError> {
+    let (subreddit, value) = parse_command(args);
+    let posts_per_message = match value {
+        "off" => Some(None),
+        value => match value.parse::<i32>() {
+            Ok(count) if (1..=100).contains(&count) => Some(Some(count)),
+            _ => None,
+        },
+    };
+    let text = match posts_per_message {
+        Some(posts_per_message) if !subreddit.is_empty() => {
+            if db.update_subscription(
+                user_id,
+                subreddit,
+                SubscriptionChanges {
+                    posts_per_message: Some(posts_per_message),
+                    ..Default::default()
+                },
+            )? == 0
+            {
+                format!("You are not subscribed to {}", subreddit)
+            } else if let Some(count) = posts_per_message {
+                format!(
+                    "Digests of {} will have at most {} posts per message",
+                    subreddit, count
+                )
+            } else {
+                format!(
+                    "Digests of {} will only be split when they get too long",
+                    subreddit
+                )
+            }
+        }
+        _ => "Usage: /permessage <subreddit> <1-100|off>".to_string(),
+    };
+
+    telegram_client
+        .send_message(&Message {
+            chat_id: user_id,
+            text: &text,
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(())
+}
+
+pub async fn tag(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    user_id: &str,
+    args: &str,
+) -> Result<(), BotError> {
+    let (subreddit, value) = parse_command(args);
+    let tag = match value {
+        "off" => Some(None),
+        value if !value.is_empty() && value.chars().count() <= MAX_TAG_LENGTH => Some(Some(value)),
+        _ => None,
+    };
+    let text = match tag {
+        Some(tag) if !subreddit.is_empty() => {
+            if db.update_subscription(
+                user_id,
+                subreddit,
+                SubscriptionChanges {
+                    tag: Some(tag),
+                    ..Default::default()
+                },
+            )? == 0
+            {
+                format!("You are not subscribed to {}", subreddit)
+            } else if let Some(tag) = tag {
+                format!("Digests from {} will start with {}", subreddit, tag)
+            } else {
+                format!("Removed the tag from {}", subreddit)
+            }
+        }
+        _ => format!(
+            "Usage: /tag <subreddit> <emoji|off>, tags can be up to {} characters",
+            MAX_TAG_LENGTH
+        ),
+    };
+
+    telegram_client
+        .send_message(&Message {
+            chat_id: user_id,
+            text: &text,
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(())
+}
+
+pub async fn poll(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    user_id: &str,
+    args: &str,
+) -> Result<(), BotError> {
+    toggle(
+        telegram_client,
+        db,
+        user_id,
+        args,
+        "/poll",
+        |as_poll| SubscriptionChanges {
+            as_poll: Some(as_poll),
+            ..Default::default()
+        },
+        (
+            "Posts from {subreddit} will be sent as a poll",
+            "Posts from {subreddit} will be sent as a list",
+        ),
+    )
+    .await
+}
+
+pub async fn photos(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    user_id: &str,
+    args: &str,
+) -> Result<(), BotError> {
+    toggle(
+        telegram_client,
+        db,
+        user_id,
+        args,
+        "/photos",
+        |as_photos| SubscriptionChanges {
+            as_photos: Some(as_photos),
+            ..Default::default()
+        },
+        (
+            "Image posts from {subreddit} will be sent as photos",
+            "Image posts from {subreddit} will be sent as links",
+        ),
+    )
+    .await
+}
+
+pub async fn edit_in_place(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    user_id: &str,
+    args: &str,
+) -> Result<(), BotError> {
+    toggle(
+        telegram_client,
+        db,
+        user_id,
+        args,
+        "/inplace",
+        |edit_in_place| SubscriptionChanges {
+            edit_in_place: Some(edit_in_place),
+            ..Default::default()
+        },
+        (
+            "Digests of {subreddit} will replace the previous one",
+            "Digests of {subreddit} will be sent as new messages",
+        ),
+    )
+    .await
+}
+
+pub async fn rename(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    reddit_client: &RedditClient,
+    user_id: &str,
+    args: &str,
+) -> Result<(), BotError> {
+    let (subreddit, new_subreddit) = parse_command(args);
+    let subreddit = parse_subreddit(subreddit).unwrap_or_default();
+    let new_subreddit = parse_subreddit(new_subreddit).unwrap_or_default();
+    let subreddits = db
+        .get_user_subscriptions(user_id)?
+        .into_iter()
+        .map(|subscription| subscription.subreddit)
+        .collect::<Vec<String>>();
+    // Subscriptions made before names were lowercased keep the case they were typed in.
+    let current = subreddits
+        .iter()
+        .find(|s| s.eq_ignore_ascii_case(&subreddit));
+    let text = match current {
+        _ if subreddit.is_empty() || new_subreddit.is_empty() => {
+            "Usage: /rename <old_subreddit> <new_subreddit>".to_string()
+        }
+        None => format!("You are not subscribed to {}", subreddit),
+        Some(current) => {
+            if subreddits
+                .iter()
+                .any(|s| s.eq_ignore_ascii_case(&new_subreddit))
+            {
+                format!("You are already subscribed to {}", new_subreddit)
+            } else if db.is_subreddit_blocked(&new_subreddit)? {
+                BLOCKED_SUBREDDIT_TEXT.to_string()
+            } else if !reddit_client.validate_subreddit(&new_subreddit).await {
+                format!("Invalid subreddit - {}", new_subreddit)
+            } else {
+                db.rename_subscription(user_id, current, &new_subreddit)?;
+                format!("Renamed {} to {}", current, new_subreddit)
+            }
+        }
+    };
+
+    telegram_client
+        .send_message(&Message {
+            chat_id: user_id,
+            text: &text,
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(())
+}
+
+pub async fn custom_text(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    user_id: &str,
+    args: &str,
+) -> Result<(), BotError> {
+    let (subreddit, rest) = parse_command(args);
+    let (kind, value) = parse_command(rest);
+    let value = match value {
+        "reset" => None,
+        value => Some(value),
+    };
+    let changes = match (kind, value) {
+        _ if subreddit.is_empty() => None,
+        (_, Some("")) => None,
+        ("empty", value) => Some(SubscriptionChanges {
+            empty_text: Some(value),
+            ..Default::default()
+        }),
+        ("error", value) => Some(SubscriptionChanges {
+            error_text: Some(value),
+            ..Default::default()
+        }),
+        _ => None,
+    };
+    let updated = match changes {
+        Some(changes) => Some(db.update_subscription(user_id, subreddit, changes)?),
+        None => None,
+    };
+    let text = match updated {
+        Some(0) => format!("You are not subscribed to {}", subreddit),
+        Some(_) if value.is_none() => {
+            format!("Restored the default {} text for {}", kind, subreddit)
+        }
+        Some(_) => format!("Updated the {} text for {}", kind, subreddit),
+        None => "Usage: /customtext <subreddit> <empty|error> <text|reset>".to_string(),
+    };
+
+    telegram_client
+        .send_message(&Message {
+            chat_id: user_id,
+            text: &text,
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(())
+}
+
+pub async fn retarget(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    user_id: &str,
+    args: &str,
+) -> Result<(), BotError> {
+    let (subreddit, target_chat_id) = parse_command(args);
+    let text = if subreddit.is_empty() || target_chat_id.is_empty() {
+        "Usage: /retarget <subreddit> <chat_id>".to_string()
+    } else if let Err(err) = telegram_client
+        .send_chat_action(target_chat_id, "typing")
+        .await
+    {
+        warn!("cannot post to chat {}: {}", target_chat_id, err);
+        format!(
+            "I can't post to {}. Make sure I'm added to that chat and allowed to post there.",
+            target_chat_id
+        )
+    } else if db.update_subscription(
+        user_id,
+        subreddit,
+        SubscriptionChanges {
+            target_chat_id: Some(target_chat_id),
+            ..Default::default()
+        },
+    )? == 0
+    {
+        format!("You are not subscribed to {}", subreddit)
+    } else {
+        format!(
+            "Digests for {} will be sent to {}",
+            subreddit, target_chat_id
+        )
+    };
+
+    telegram_client
+        .send_message(&Message {
+            chat_id: user_id,
+            text: &text,
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(())
+}
+
+pub async fn feedback(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    author_id: &str,
+    user_id: &str,
+) -> Result<(), BotError> {
+    match Dialog::<Feedback>::new(user_id.to_string())
+        .handle_current_step(telegram_client, db, author_id, "")
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(BotError::DatabaseError(err)) => {
+            if let DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _) = err {
+                warn!("feedback was initiated without user");
+                telegram_client
+                    .send_message(&Message {
+                        chat_id: user_id,
+                        text: "You need to call /start before interacting with me",
+                        ..Default::default()
+                    })
+                    .await?;
+            }
+            Ok(())
+        }
+        Err(err) => Err(err),
+    }
+}
+
+pub async fn about(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    user_id: &str,
+    started_at: Instant,
+) -> Result<(), BotError> {
+    let text = format!(
+        "Tracking {} subreddits, up for {}.\n\nBot is open source and available here https://github.com/aldis-ameriks/reddit-bot",
+        db.tracked_subreddit_count()?,
+        format_uptime(started_at.elapsed())
+    );
+    telegram_client
+        .send_message(&Message {
+            chat_id: user_id,
+            text: &text,
+            disable_web_page_preview: true,
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Uptime rounded down to minutes, e.g. "2d 3h 15m".
+fn format_uptime(uptime: Duration) -> String {
+    let minutes = uptime.as_secs() / 60;
+    format!(
+        "{}d {}h {}m",
+        minutes / (24 * 60),
+        minutes / 60 % 24,
+        minutes % 60
+    )
+}
+
+pub async fn send_now(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    reddit_client: &RedditClient,
+    user_id: &str,
+    footer: Option<&str>,
+) -> Result<(), BotError> {
+    send_digests(telegram_client, db, reddit_client, user_id, footer, false).await
+}
+
+/// Same as /sendnow, except posts already sent for a subscription are left out.
+pub async fn send_new(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    reddit_client: &RedditClient,
+    user_id: &str,
+    footer: Option<&str>,
+) -> Result<(), BotError> {
+    send_digests(telegram_client, db, reddit_client, user_id, footer, true).await
+}
+
+async fn send_digests(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    reddit_client: &RedditClient,
+    user_id: &str,
+    footer: Option<&str>,
+    only_new: bool,
+) -> Result<(), BotError> {
+    let subscriptions = db.get_user_subscriptions(user_id)?;
+
+    if subscriptions.is_empty() {
+        telegram_client
+            .send_message(&Message {
+                chat_id: user_id,
+                text: "You haven't subscribed to anything yet. Subscribe using /subscribe command.",
+                ..Default::default()
+            })
+            .await?;
+    }
+
+    for group in group_by_subreddit(subscriptions) {
+        let (subscription, duplicates) = group.split_first().unwrap();
+        if db.is_subreddit_blocked(&subscription.subreddit)? {
+            telegram_client
+                .send_message(&Message {
+                    chat_id: user_id,
+                    text: &format!(
+                        "\"{}\" isn't allowed on this instance anymore.",
+                        subscription.subreddit
+                    ),
+                    ..Default::default()
+                })
+                .await?;
+            continue;
+        }
+        match process_subscription(
+            db,
+            telegram_client,
+            reddit_client,
+            subscription,
+            footer,
+            only_new,
+        )
+        .await
+        {
+            Ok(Outcome::NothingNew) => {
+                telegram_client
+                    .send_message(&Message {
+                        chat_id: user_id,
+                        text: &format!(
+                            "Nothing new since the last digest of \"{}\".",
+                            subscription.subreddit
+                        ),
+                        ..Default::default()
+                    })
+                    .await?;
+            }
+            // Already logged, there's no one left to reply to.
+            Ok(Outcome::UnknownUser) => {}
+            Ok(Outcome::Sent) => {
+                info!("processed subscription: {:?}", subscription);
+                for duplicate in duplicates {
+                    info!(
+                        "subscription {} was sent along with {}",
+                        duplicate.id, subscription.id
+                    );
+                    db.update_last_sent(duplicate.id)?;
+                }
+            }
+            Err(err) => {
+                if !report_fetch_error(telegram_client, subscription, &err).await? {
+                    error!("failed to process subscription: {}", err);
+                }
+            }
+        }
+        thread::sleep(Duration::from_secs(30));
+    }
+
+    Ok(())
+}
+
+/// Subscriptions to the same subreddit delivered to the same chat with the same digest, e.g. "rust"
+/// and "Rust", so each digest is fetched and sent once. Groups keep the order of their first
+/// subscription.
+fn group_by_subreddit(subscriptions: Vec<Subscription>) -> Vec<Vec<Subscription>> {
+    let mut groups: Vec<Vec<Subscription>> = vec![];
+    for subscription in subscriptions {
+        let group = groups
+            .iter_mut()
+            .find(|group| same_digest(&group[0], &subscription));
+        match group {
+            Some(group) => group.push(subscription),
+            None => groups.push(vec![subscription]),
+        }
+    }
+    groups
+}
+
+/// Whether both subscriptions list and render the same posts in the same chat, so sending one of
+/// them covers the other.
+fn same_digest(a: &Subscription, b: &Subscription) -> bool {
+    let settings = |subscription: &Subscription| SubscriptionSettings {
+        // Whether the subscription is removed once sent doesn't change its digest.
+        recurring: true,
+        ..SubscriptionSettings::from(subscription)
+    };
+    a.subreddit.eq_ignore_ascii_case(&b.subreddit)
+        && settings(a) == settings(b)
+        // "Since last sent" digests start from the subscription's own last digest.
+        && (!a.since_last_sent || a.last_sent_at == b.last_sent_at)
+}
+
+pub async fn show_dialog(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    author_id: &str,
+    user_id: &str,
+    target_user_id: &str,
+) -> Result<(), BotError> {
+    if user_id != author_id {
+        warn!("non author ({}) attempted to show dialog", user_id);
+        return Ok(());
+    }
+
+    let text = if target_user_id.is_empty() {
+        let dialogs = db.get_all_dialogs()?;
+        if dialogs.is_empty() {
+            "No active dialogs".to_string()
+        } else {
+            let mut text = String::from("Active dialogs:\n");
+            for dialog in dialogs {
+                text.push_str(&format!(
+                    "{} - {} ({})\n",
+                    dialog.user_id, dialog.command, dialog.step
+                ));
+            }
+            text.push_str("Use /showdialog <user_id> to see the details.");
+            text
+        }
+    } else {
+        match db.get_users_dialog(target_user_id) {
+            Ok(dialog) => format!(
+                "Dialog for user({}):\ncommand: {}\nstep: {}\ndata: {}",
+                target_user_id, dialog.command, dialog.step, dialog.data
+            ),
+            Err(NotFound) => format!("No dialog found for user({})", target_user_id),
+            Err(err) => return Err(BotError::DatabaseError(err)),
+        }
+    };
+
+    telegram_client
+        .send_message(&Message {
+            chat_id: user_id,
+            text: &text,
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(())
+}
+
+pub async fn clear_dialog(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    author_id: &str,
+    user_id: &str,
+    target_user_id: &str,
+) -> Result<(), BotError> {
+    if user_id != author_id {
+        warn!("non author ({}) attempted to clear dialog", user_id);
+        return Ok(());
+    }
+
+    if target_user_id.is_empty() {
+        telegram_client
+            .send_message(&Message {
+                chat_id: user_id,
+                text: "Usage: /cleardialog <user_id>",
+                ..Default::default()
+            })
+            .await?;
+        return Ok(());
+    }
+
+    let text = match db.get_users_dialog(target_user_id) {
+        Ok(_) => {
+            db.delete_dialog(target_user_id)?;
+            format!("Cleared dialog for user({})", target_user_id)
+        }
+        Err(NotFound) => format!("No dialog found for user({})", target_user_id),
+        Err(err) => return Err(BotError::DatabaseError(err)),
+    };
+
+    telegram_client
+        .send_message(&Message {
+            chat_id: user_id,
+            text: &text,
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(())
+}
+
+pub async fn render_test(
+    telegram_client: &impl Messenger,
+    author_id: &str,
+    user_id: &str,
+    subreddit: &str,
+) -> Result<(), BotError> {
+    if user_id != author_id {
+        warn!("non author ({}) attempted to render test digest", user_id);
+        return Ok(());
+    }
+
+    let subreddit = if subreddit.is_empty() {
+        "rust"
+    } else {
+        subreddit
+    };
+    let subscription = Subscription {
+        user_id: user_id.to_string(),
+        subreddit: subreddit.to_string(),
+        ..Default::default()
+    };
+    let posts = (1..=3)
+        .map(|i| Post {
+            id: format!("post{}", i),
+            title: format!("Sample post #{} from r/{}", i, subreddit),
+            link: format!("https://reddit.com/r/{}/comments/post{}/", subreddit, i),
+            is_crosspost: i == 3,
+            selftext: String::new(),
+            top_comment: None,
+            external_url: None,
+            over_18: false,
+            image_url: None,
+            score: 0,
+        })
+        .collect::<Vec<Post>>();
+
+    let opts = RenderOptions::default();
+    for message in build_digest(&subscription, &posts, &opts) {
+        telegram_client
+            .send_message(&Message {
+                chat_id: user_id,
+                text: &message,
+                disable_web_page_preview: true,
+                parse_mode: opts.parse_mode,
+                ..Default::default()
+            })
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Fetches and renders the digests of all the user's subscriptions, only a report is sent to the author.
+pub async fn render_test_user(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    reddit_client: &RedditClient,
+    author_id: &str,
+    user_id: &str,
+    target_user_id: &str,
+) -> Result<(), BotError> {
+    if user_id != author_id {
+        warn!("non author ({}) attempted to render test a user", user_id);
+        return Ok(());
+    }
+
+    let text = if target_user_id.is_empty() {
+        "Usage: /rendertest_user <user_id>".to_string()
+    } else {
+        let subscriptions = db.get_user_subscriptions(target_user_id)?;
+        if subscriptions.is_empty() {
+            format!("User {} has no subscriptions", target_user_id)
+        } else {
+            let mut report = format!("Render test for user {}:\n", target_user_id);
+            for subscription in &subscriptions {
+                let line = match fetch_posts(reddit_client, subscription, FETCH_RETRY_DELAY).await {
+                    Ok(posts) => {
+                        let opts = RenderOptions::for_subscription(subscription);
+                        let messages = build_digest(subscription, &posts, &opts);
+                        format!(
+                            "{} - {} posts in {} messages",
+                            subscription.subreddit,
+                            posts.len(),
+                            messages.len()
+                        )
+                    }
+                    Err((err, attempts)) => format!(
+                        "{} - failed after {} attempts: {}",
+                        subscription.subreddit, attempts, err
+                    ),
+                };
+                report.push_str(&format!("{}\n", line));
+            }
+            report
+        }
+    };
+
+    telegram_client
+        .send_message(&Message {
+            chat_id: user_id,
+            text: &text,
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(())
+}
+
+pub async fn diag(
+    telegram_client: &impl Messenger,
+    reddit_client: &RedditClient,
+    author_id: &str,
+    user_id: &str,
+) -> Result<(), BotError> {
+    if user_id != author_id {
+        warn!("non author ({}) attempted to run diagnostics", user_id);
+        return Ok(());
+    }
+
+    let started = Instant::now();
+    let reddit = if reddit_client.validate_subreddit("announcements").await {
+        "ok".to_string()
+    } else {
+        "failed".to_string()
+    };
+    let reddit_elapsed = started.elapsed().as_millis();
+
+    let started = Instant::now();
+    let telegram = match telegram_client.get_me().await {
+        Ok(me) => format!("ok, @{}", me.username.unwrap_or_default()),
+        Err(err) => format!("failed, {}", err),
+    };
+    let telegram_elapsed = started.elapsed().as_millis();
+
+    telegram_client
+        .send_message(&Message {
+            chat_id: user_id,
+            text: &format!(
+                "Reddit: {} ({} ms)\nTelegram: {} ({} ms)",
+                reddit, reddit_elapsed, telegram, telegram_elapsed
+            ),
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(())
+}
+
+pub async fn debug_post(
+    telegram_client: &impl Messenger,
+    reddit_client: &RedditClient,
+    author_id: &str,
+    user_id: &str,
+    subreddit: &str,
+) -> Result<(), BotError> {
+    if user_id != author_id {
+        warn!("non author ({}) attempted to debug a post", user_id);
+        return Ok(());
+    }
+
+    let text = if subreddit.is_empty() {
+        "Usage: /debugpost <subreddit>".to_string()
+    } else {
+        let body = reddit_client
+            .fetch_raw(subreddit, &Listing::default(), 1)
+            .await?;
+        match body["data"]["children"]
+            .get(0)
+            .and_then(|child| child.get("data"))
+        {
+            Some(data) => serde_json::to_string_pretty(data)
+                .unwrap_or_default()
+                .chars()
+                .take(DEBUG_POST_MAX_LENGTH)
+                .collect(),
+            None => format!("No posts found in {}", subreddit),
+        }
+    };
+
+    telegram_client
+        .send_message(&Message {
+            chat_id: user_id,
+            text: &text,
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Drops the dialogs of all users, e.g. when stored dialogs no longer match the code after an update.
+pub async fn reset_dialogs(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    author_id: &str,
+    user_id: &str,
+) -> Result<(), BotError> {
+    if user_id != author_id {
+        warn!("non author ({}) attempted to reset dialogs", user_id);
+        return Ok(());
+    }
+
+    let count = db.delete_all_dialogs()?;
+    telegram_client
+        .send_message(&Message {
+            chat_id: user_id,
+            text: &format!("Reset {} dialogs", count),
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(())
+}
+
+pub async fn chats(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    author_id: &str,
+    user_id: &str,
+) -> Result<(), BotError> {
+    if user_id != author_id {
+        warn!("non author ({}) attempted to list chats", user_id);
+        return Ok(());
+    }
+
+    let bot_id = telegram_client.get_me().await?.id;
+    let mut text = String::from("Chats:\n");
+    for chat_id in db.get_target_chat_ids()? {
+        let status = match telegram_client.get_chat_member(&chat_id, bot_id).await {
+            Ok(member) if member.can_send() => member.status,
+            Ok(member) => format!("{}, cannot post", member.status),
+            Err(err) => format!("unavailable, {}", err),
+        };
+        text.push_str(&format!("{} - {}\n", chat_id, status));
+    }
+
+    telegram_client
+        .send_message(&Message {
+            chat_id: user_id,
+            text: &text,
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(())
+}
+
+pub async fn stale(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    author_id: &str,
+    user_id: &str,
+) -> Result<(), BotError> {
+    if user_id != author_id {
+        warn!(
+            "non author ({}) attempted to list stale subscriptions",
+            user_id
+        );
+        return Ok(());
+    }
+
+    let subscriptions =
+        db.get_stale_subscriptions(Utc::now() - chrono::Duration::days(STALE_AFTER_DAYS))?;
+    let text = if subscriptions.is_empty() {
+        format!("No subscriptions older than {} days", STALE_AFTER_DAYS)
+    } else {
+        // Schedules are shown in the timezone of their user.
+        let mut list = String::new();
+        for subscription in &subscriptions {
+            let timezone = db.get_timezone(&subscription.user_id)?;
+            list.push_str(&format!(
+                "{} ({}) - {}\n",
+                subscription.id,
+                subscription.user_id,
+                subscription.summary(&timezone)
+            ));
+        }
+        format!(
+            "Subscriptions not sent in {} days:\n{}",
+            STALE_AFTER_DAYS, list
+        )
+    };
+
+    telegram_client
+        .send_message(&Message {
+            chat_id: user_id,
+            text: &text,
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(())
+}
+
+pub async fn stats(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    author_id: &str,
+    user_id: &str,
+) -> Result<(), BotError> {
+    if user_id != author_id {
+        warn!("non author ({}) attempted to view stats", user_id);
+        return Ok(());
+    }
+
+    let text = format!(
+        "Users: {}\nSubscriptions: {}",
+        db.user_count()?,
+        db.subscription_count()?
+    );
+    telegram_client
+        .send_message(&Message {
+            chat_id: user_id,
+            text: &text,
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(())
+}
+
+pub async fn backup(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    author_id: &str,
+    user_id: &str,
+) -> Result<(), BotError> {
+    if user_id != author_id {
+        warn!("non author ({}) attempted to download a backup", user_id);
+        return Ok(());
+    }
+
+    let file_name = format!("reddit-bot-{}.sqlite", Utc::now().format("%Y%m%d-%H%M%S"));
+    let path = std::env::temp_dir().join(&file_name);
+    // VACUUM INTO refuses to overwrite a leftover of an earlier backup.
+    fs::remove_file(&path).ok();
+    db.snapshot(&path.to_string_lossy())?;
+    let result = send_backup(telegram_client, user_id, &file_name, &path).await;
+    fs::remove_file(&path).ok();
+    result
+}
+
+async fn send_backup(
+    telegram_client: &impl Messenger,
+    user_id: &str,
+    file_name: &str,
+    path: &Path,
+) -> Result<(), BotError> {
+    let size = fs::metadata(path)?.len();
+    if size > MAX_DOCUMENT_SIZE {
+        warn!("backup of {} bytes is too large to send", size);
+        telegram_client
+            .send_message(&Message {
+                chat_id: user_id,
+                text: &format!(
+                    "Backup is {} MB, Telegram doesn't accept documents over {} MB.",
+                    size / 1024 / 1024,
+                    MAX_DOCUMENT_SIZE / 1024 / 1024
+                ),
+                ..Default::default()
+            })
+            .await?;
+        return Ok(());
+    }
+
+    telegram_client
+        .send_document(user_id, file_name, fs::read(path)?)
+        .await?;
+    Ok(())
+}
+
+pub async fn migrate(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    author_id: &str,
+    user_id: &str,
+) -> Result<(), BotError> {
+    if user_id != author_id {
+        warn!("non author ({}) attempted to run migrations", user_id);
+        return Ok(());
+    }
+
+    let status = db.run_migrations()?;
+    telegram_client
+        .send_message(&Message {
+            chat_id: user_id,
+            text: &format!(
+                "Applied {} pending migrations, schema version: {}",
+                status.applied,
+                status.version.as_deref().unwrap_or("none")
+            ),
+            ..Default::default()
+        })
+        .await?;
+    Ok(())
+}
+
+pub async fn prune(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    author_id: &str,
+    user_id: &str,
+) -> Result<(), BotError> {
+    if user_id != author_id {
+        warn!("non author ({}) attempted to prune subscriptions", user_id);
+        return Ok(());
+    }
+
+    let count = db.prune_orphan_subscriptions()?;
+    telegram_client
+        .send_message(&Message {
+            chat_id: user_id,
+            text: &format!("Pruned {} orphan subscriptions", count),
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(())
+}
+
+pub async fn inactive(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    author_id: &str,
+    user_id: &str,
+) -> Result<(), BotError> {
+    if user_id != author_id {
+        warn!("non author ({}) attempted to list inactive users", user_id);
+        return Ok(());
+    }
+
+    let users = db.get_inactive_users(Utc::now() - chrono::Duration::days(INACTIVE_AFTER_DAYS))?;
+    let text = if users.is_empty() {
+        format!(
+            "No users without subscriptions older than {} days",
+            INACTIVE_AFTER_DAYS
+        )
+    } else {
+        let list = users
+            .iter()
+            .map(|user| format!("{} - joined {}\n", user.id, user.created_at))
+            .collect::<String>();
+        format!(
+            "Users without subscriptions for over {} days:\n{}\nRemove them with /purge_inactive",
+            INACTIVE_AFTER_DAYS, list
+        )
+    };
+
+    telegram_client
+        .send_message(&Message {
+            chat_id: user_id,
+            text: &text,
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(())
+}
+
+pub async fn purge_inactive(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    author_id: &str,
+    user_id: &str,
+) -> Result<(), BotError> {
+    if user_id != author_id {
+        warn!("non author ({}) attempted to purge inactive users", user_id);
+        return Ok(());
+    }
+
+    let count =
+        db.delete_inactive_users(Utc::now() - chrono::Duration::days(INACTIVE_AFTER_DAYS))?;
+    telegram_client
+        .send_message(&Message {
+            chat_id: user_id,
+            text: &format!("Purged {} inactive users", count),
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// `/blocklist` lists, `/blocklist add <subreddit>` and `/blocklist remove <subreddit>` change the
+/// subreddits nobody can subscribe to.
+pub async fn blocklist(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    author_id: &str,
+    user_id: &str,
+    args: &str,
+) -> Result<(), BotError> {
+    if user_id != author_id {
+        warn!("non author ({}) attempted to change the blocklist", user_id);
+        return Ok(());
+    }
+
+    let text = match parse_command(args) {
+        ("", _) => {
+            let subreddits = db.get_blocked_subreddits()?;
+            if subreddits.is_empty() {
+                "No subreddits are blocked".to_string()
+            } else {
+                format!("Blocked subreddits:\n{}", subreddits.join("\n"))
+            }
+        }
+        ("add", subreddit) if !subreddit.is_empty() => {
+            if db.block_subreddit(subreddit)? == 0 {
+                format!("{} is already blocked", subreddit)
+            } else {
+                format!("Blocked {}", subreddit)
+            }
+        }
+        ("remove", subreddit) if !subreddit.is_empty() => {
+            if db.unblock_subreddit(subreddit)? == 0 {
+                format!("{} is not blocked", subreddit)
+            } else {
+                format!("Unblocked {}", subreddit)
+            }
+        }
+        _ => "Usage: /blocklist [add|remove <subreddit>]".to_string(),
+    };
+
+    telegram_client
+        .send_message(&Message {
+            chat_id: user_id,
+            text: &text,
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Reddit rate limit as last seen by the bot, the scheduler shares its client so the digests it
+/// fetches are included.
+pub async fn rate_limit(
+    telegram_client: &impl Messenger,
+    reddit_client: &RedditClient,
+    author_id: &str,
+    user_id: &str,
+) -> Result<(), BotError> {
+    if user_id != author_id {
+        warn!("non author ({}) attempted to check rate limit", user_id);
+        return Ok(());
+    }
+
+    let text = match reddit_client.rate_limit() {
+        Some(rate_limit) => format!(
+            "Remaining requests: {}\nResets in {} seconds, as of {}",
+            rate_limit.remaining,
+            rate_limit.reset_secs,
+            rate_limit.seen_at.format("%H:%M:%S UTC")
+        ),
+        None => "No requests made to reddit yet".to_string(),
+    };
+    telegram_client
+        .send_message(&Message {
+            chat_id: user_id,
+            text: &text,
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(())
+}
+
+pub async fn help(
+    telegram_client: &impl Messenger,
+    user_id: &str,
+    command: &str,
+) -> Result<(), BotError> {
+    telegram_client
+        .send_message(&Message {
+            chat_id: user_id,
+            text: command_help(command).unwrap_or(&HELP_TEXT),
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use diesel::Connection;
+    use mockito::{mock, server_url, Matcher};
+    use serde_json::json;
+    use serial_test::serial;
+
+    use crate::db::models::DialogEntity;
+    use crate::db::test_helpers::{setup_test_db, setup_test_db_with};
+    use crate::telegram::client::TelegramClient;
+    use crate::telegram::test_helpers::{
+        mock_send_message_not_called, mock_send_message_success, RecordingMessenger, Sent,
+    };
+
+    use super::*;
+    use crate::reddit::test_helpers::mock_reddit_success;
+
+    const TOKEN: &str = "token";
+    const USER_ID: &str = "123";
+    const OTHER_USER_ID: &str = "456";
+
+    /// Summary the quick syntax ends with, nothing is saved until it's confirmed.
+    fn mock_confirm_prompt(summary: &str) -> mockito::Mock {
+        mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::Regex(format!(
+                r#""text":"{} - Confirm\?".*"callback_data":"/(subscribe|once):Confirm:confirm""#,
+                summary
+            )))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .expect(1)
+            .create()
+    }
+
+    async fn press_confirm(
+        telegram_client: &TelegramClient,
+        db: &DbClient,
+        reddit_client: &RedditClient,
+        listing: &Listing,
+    ) {
+        let mut dialog =
+            Dialog::<Subscribe>::try_from(db.get_users_dialog(USER_ID).unwrap()).unwrap();
+        dialog
+            .handle_current_step(telegram_client, db, reddit_client, "confirm", listing)
+            .await
+            .unwrap();
+    }
+
+    fn seed_subscriptions(db: &DbClient, count: i64) {
+        for i in 0..count {
+            db.subscribe(USER_ID, &format!("seeded{}", i), 0, 12)
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn parse_command_works() {
+        assert_eq!(parse_command("/start"), ("/start", ""));
+        assert_eq!(parse_command(" /start \n"), ("/start", ""));
+        assert_eq!(parse_command("/showdialog 456"), ("/showdialog", "456"));
+        assert_eq!(parse_command("/showdialog   456 "), ("/showdialog", "456"));
+        assert_eq!(parse_command("rust golang"), ("rust", "golang"));
+    }
+
+    #[test]
+    fn normalize_command_works() {
+        assert_eq!(
+            normalize_command("subscribe"),
+            Some("/subscribe".to_string())
+        );
+        assert_eq!(normalize_command(" Help \n"), Some("/help".to_string()));
+        assert_eq!(
+            normalize_command("postlimit 5"),
+            Some("/postlimit 5".to_string())
+        );
+        assert_eq!(normalize_command("/subscribe"), None);
+        assert_eq!(normalize_command("rust"), None);
+        assert_eq!(normalize_command("subscribes"), None);
+        assert_eq!(normalize_command(""), None);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn start_success() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: &HELP_TEXT,
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+
+        start(
+            &telegram_client,
+            &db_client,
+            &reddit_client,
+            USER_ID,
+            "",
+            &Listing::default(),
+        )
+        .await
+        .unwrap();
+        _m.assert();
+
+        let users = db_client.get_users().unwrap();
+        assert_eq!(users.len(), 1);
+        assert_eq!(users[0].id, USER_ID);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn start_existing_user() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: &HELP_TEXT,
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+
+        let users = db_client.get_users().unwrap();
+        assert_eq!(users.len(), 1);
+        assert_eq!(users[0].id, USER_ID);
+
+        start(
+            &telegram_client,
+            &db_client,
+            &reddit_client,
+            USER_ID,
+            "",
+            &Listing::default(),
+        )
+        .await
+        .unwrap();
+        _m.assert();
+
+        let users = db_client.get_users().unwrap();
+        assert_eq!(users.len(), 1);
+        assert_eq!(users[0].id, USER_ID);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn start_deep_link_subscribe() {
+        let url = &server_url();
+        let _m1 = mock("GET", "/r/rust").with_status(200).create();
+        let _m2 = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::Regex(
+                "On which day do you want to receive the posts\\?".to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .expect(1)
+            .create();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+
+        start(
+            &telegram_client,
+            &db_client,
+            &reddit_client,
+            USER_ID,
+            "sub_rust",
+            &Listing::default(),
+        )
+        .await
+        .unwrap();
+        _m1.assert();
+        _m2.assert();
+
+        assert_eq!(db_client.get_users().unwrap().len(), 1);
+        let dialog =
+            Dialog::<Subscribe>::try_from(db_client.get_users_dialog(USER_ID).unwrap()).unwrap();
+        assert_eq!(dialog.current_step, Subscribe::Weekday);
+        assert_eq!(dialog.data.get(&Subscribe::Subreddit).unwrap(), "rust");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn start_unknown_payload() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: &HELP_TEXT,
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+
+        start(
+            &telegram_client,
+            &db_client,
+            &reddit_client,
+            USER_ID,
+            "sub_",
+            &Listing::default(),
+        )
+        .await
+        .unwrap();
+        _m.assert();
+        assert!(db_client.get_users_dialog(USER_ID).is_err());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn start_error() {
+        let url = &server_url();
+        let _m = mock_send_message_not_called(TOKEN);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db_with(false);
+
+        let result = start(
+            &telegram_client,
+            &db_client,
+            &reddit_client,
+            USER_ID,
+            "",
+            &Listing::default(),
+        )
+        .await;
+        assert!(result.is_err());
+        _m.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn stop_success() {
+        let url = &server_url();
+        let _m1 = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::Regex(
+                r#"This will delete your user and all subscriptions. Are you sure\?.*"callback_data":"/stop:Confirm:yes".*"callback_data":"/stop:Confirm:cancel""#.to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .expect(1)
+            .create();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let users = db_client.get_users().unwrap();
+        assert_eq!(users.len(), 1);
+        assert_eq!(users[0].id, USER_ID);
+
+        stop(&telegram_client, &db_client, USER_ID).await.unwrap();
+        _m1.assert();
+
+        // Nothing is deleted until confirmed.
+        let users = db_client.get_users().unwrap();
+        assert_eq!(users.len(), 1);
+
+        let message = Message {
+            chat_id: USER_ID,
+            text: "User and subscriptions deleted",
+            ..Default::default()
+        };
+        let _m2 = mock_send_message_success(TOKEN, &message);
+        let mut dialog =
+            Dialog::<Stop>::try_from(db_client.get_users_dialog(USER_ID).unwrap()).unwrap();
+        dialog
+            .handle_current_step(&telegram_client, &db_client, "yes")
+            .await
+            .unwrap();
+
+        let users = db_client.get_users().unwrap();
+        assert_eq!(users.len(), 0);
+        assert!(db_client.get_users_dialog(USER_ID).is_err());
+        _m2.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn stop_unknown_user() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "There's nothing to delete.",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let db_client = setup_test_db();
+
+        stop(&telegram_client, &db_client, USER_ID).await.unwrap();
+        _m.assert();
+        assert!(db_client.get_users_dialog(USER_ID).is_err());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn stop_error() {
+        let url = &server_url();
+        let _m = mock_send_message_not_called(TOKEN);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let db_client = setup_test_db_with(false);
+
+        let result = stop(&telegram_client, &db_client, USER_ID).await;
+        assert!(result.is_err());
+        _m.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn subscribe_success() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Type the name of subreddit you want to subscribe to.\nMultiple subreddits are allowed, separated by whitespace or newline.",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let reddit_client = RedditClient::new_with(url);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        subscribe(
+            &telegram_client,
+            &db_client,
+            &reddit_client,
+            USER_ID,
+            "",
+            &Listing::default(),
+        )
+        .await
+        .unwrap();
+        _m.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn subscribe_quick() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Subscribed to: rust. Posts will be sent periodically on Sun at around 12:00 UTC time.",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let message = Message {
+            chat_id: USER_ID,
+            text: "You can use /sendnow to get posts now from all of your subscriptions.",
+            ..Default::default()
+        };
+        let _m2 = mock_send_message_success(TOKEN, &message);
+        let _m3 = mock("GET", "/r/rust").with_status(200).create();
+        let _m4 = mock_confirm_prompt("r/rust, top/week, 25 posts, Sundays 12:00 UTC");
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let reddit_client = RedditClient::new_with(url);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        subscribe(
+            &telegram_client,
+            &db_client,
+            &reddit_client,
+            USER_ID,
+            "rust top week 25 sun 12",
+            &Listing::default(),
+        )
+        .await
+        .unwrap();
+        _m4.assert();
+        assert!(db_client
+            .get_user_subscriptions(USER_ID)
+            .unwrap()
+            .is_empty());
+        press_confirm(
+            &telegram_client,
+            &db_client,
+            &reddit_client,
+            &Listing::default(),
+        )
+        .await;
+        _m.assert();
+        _m2.assert();
+        _m3.assert();
+
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(subscriptions[0].subreddit, "rust");
+        assert_eq!(subscriptions[0].send_on, 6);
+        assert_eq!(subscriptions[0].send_at, 12);
+        assert_eq!(subscriptions[0].post_limit, 25);
+        assert!(db_client.get_users_dialog(USER_ID).is_err());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn subscribe_instance_listing() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Subscribed to: rust. Posts will be sent periodically on Sun at around 12:00 UTC time.",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let message = Message {
+            chat_id: USER_ID,
+            text: "You can use /sendnow to get posts now from all of your subscriptions.",
+            ..Default::default()
+        };
+        let _m2 = mock_send_message_success(TOKEN, &message);
+        let _m3 = mock("GET", "/r/rust").with_status(200).create();
+        let _m4 = mock_confirm_prompt("r/rust, hot/day, 10 posts, Sundays 12:00 UTC");
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let reddit_client = RedditClient::new_with(url);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let listing = Listing::new("hot", "day").unwrap();
+
+        subscribe(
+            &telegram_client,
+            &db_client,
+            &reddit_client,
+            USER_ID,
+            "rust sun 12",
+            &listing,
+        )
+        .await
+        .unwrap();
+        _m4.assert();
+        press_confirm(&telegram_client, &db_client, &reddit_client, &listing).await;
+        _m.assert();
+        _m2.assert();
+        _m3.assert();
+
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(subscriptions[0].sort, "hot");
+        assert_eq!(subscriptions[0].timeframe, "day");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn subscribe_instance_listing_header() {
+        let url = &server_url();
+        let _m = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .create();
+        let _m2 = mock("GET", "/r/rust").with_status(200).create();
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let reddit_client = RedditClient::new_with(url);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let listing = Listing::new("new", "week").unwrap();
+
+        subscribe(
+            &telegram_client,
+            &db_client,
+            &reddit_client,
+            USER_ID,
+            "rust sun 12",
+            &listing,
+        )
+        .await
+        .unwrap();
+        press_confirm(&telegram_client, &db_client, &reddit_client, &listing).await;
+
+        let subscription = db_client.get_user_subscriptions(USER_ID).unwrap().remove(0);
+        assert_eq!(subscription.sort, "new");
+        let opts = RenderOptions::for_subscription(&subscription);
+        let result = build_digest(&subscription, &[], &opts);
+        assert_eq!(
+            result,
+            ["Popular posts from: \"rust\"\n\nNo popular posts.\n"]
+        );
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn subscribe_quick_overrides_listing() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Subscribed to: rust. Posts will be sent periodically on Sun at around 12:00 UTC time.",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let message = Message {
+            chat_id: USER_ID,
+            text: "You can use /sendnow to get posts now from all of your subscriptions.",
+            ..Default::default()
+        };
+        let _m2 = mock_send_message_success(TOKEN, &message);
+        let _m3 = mock("GET", "/r/rust").with_status(200).create();
+        let _m4 = mock_confirm_prompt("r/rust, top/month, 10 posts, Sundays 12:00 UTC");
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let reddit_client = RedditClient::new_with(url);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        subscribe(
+            &telegram_client,
+            &db_client,
+            &reddit_client,
+            USER_ID,
+            "rust top month sun 12",
+            &Listing::new("hot", "day").unwrap(),
+        )
+        .await
+        .unwrap();
+        _m4.assert();
+        press_confirm(
+            &telegram_client,
+            &db_client,
+            &reddit_client,
+            &Listing::default(),
+        )
+        .await;
+        _m.assert();
+        _m2.assert();
+        _m3.assert();
+
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(subscriptions[0].sort, "top");
+        assert_eq!(subscriptions[0].timeframe, "month");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn once_quick() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Subscribed to: rust. Posts will be sent once on Sun at around 12:00 UTC time, then the subscription is removed.",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let message = Message {
+            chat_id: USER_ID,
+            text: "You can use /sendnow to get posts now from all of your subscriptions.",
+            ..Default::default()
+        };
+        let _m2 = mock_send_message_success(TOKEN, &message);
+        let _m3 = mock("GET", "/r/rust").with_status(200).create();
+        let _m4 = mock_confirm_prompt("r/rust, top/week, 10 posts, once on Sunday 12:00 UTC");
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let reddit_client = RedditClient::new_with(url);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        once(
+            &telegram_client,
+            &db_client,
+            &reddit_client,
+            USER_ID,
+            "rust sun 12",
+            &Listing::default(),
+        )
+        .await
+        .unwrap();
+        _m4.assert();
+        press_confirm(
+            &telegram_client,
+            &db_client,
+            &reddit_client,
+            &Listing::default(),
+        )
+        .await;
+        _m.assert();
+        _m2.assert();
+        _m3.assert();
+
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(subscriptions[0].subreddit, "rust");
+        assert!(!subscriptions[0].recurring);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn subscribe_quick_limit_reached() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: &subscription_limit_text(),
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let _m2 = mock("GET", "/r/rust").with_status(200).create();
+        let _m4 = mock_confirm_prompt("r/rust, top/week, 10 posts, Sundays 12:00 UTC");
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        seed_subscriptions(&db_client, MAX_SUBSCRIPTIONS);
+        let reddit_client = RedditClient::new_with(url);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        subscribe(
+            &telegram_client,
+            &db_client,
+            &reddit_client,
+            USER_ID,
+            "rust sun 12",
+            &Listing::default(),
+        )
+        .await
+        .unwrap();
+        _m4.assert();
+        press_confirm(
+            &telegram_client,
+            &db_client,
+            &reddit_client,
+            &Listing::default(),
+        )
+        .await;
+        _m.assert();
+
+        let count = db_client.get_subscription_count(USER_ID).unwrap();
+        assert_eq!(count, MAX_SUBSCRIPTIONS);
+        assert!(db_client.get_users_dialog(USER_ID).is_err());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn subscribe_quick_partial() {
+        let url = &server_url();
+        let _m = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::Regex("At what time".to_string()))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .expect(1)
+            .create();
+        let _m2 = mock("GET", "/r/rust").with_status(200).create();
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let reddit_client = RedditClient::new_with(url);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        subscribe(
+            &telegram_client,
+            &db_client,
+            &reddit_client,
+            USER_ID,
+            "rust 5 sun",
+            &Listing::default(),
+        )
+        .await
+        .unwrap();
+        _m.assert();
+        _m2.assert();
+
+        let dialog =
+            Dialog::<Subscribe>::try_from(db_client.get_users_dialog(USER_ID).unwrap()).unwrap();
+        assert_eq!(dialog.current_step, Subscribe::TimePreset);
+        assert_eq!(dialog.data.get(&Subscribe::Subreddit).unwrap(), "rust");
+        assert_eq!(dialog.data.get(&Subscribe::Weekday).unwrap(), "6");
+        assert_eq!(dialog.options.post_limit, Some(5));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn subscribe_invalid_subreddits() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Invalid subreddits - bbb, ddd, try again",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let _m2 = mock("GET", "/r/aaa").with_status(200).create();
+        let _m3 = mock("GET", "/r/bbb").with_status(404).create();
+        let _m4 = mock("GET", "/r/ccc").with_status(200).create();
+        let _m5 = mock("GET", "/r/ddd").with_status(404).create();
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let reddit_client = RedditClient::new_with(url);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        subscribe(
+            &telegram_client,
+            &db_client,
+            &reddit_client,
+            USER_ID,
+            "ddd aaa ccc bbb",
+            &Listing::default(),
+        )
+        .await
+        .unwrap();
+        _m.assert();
+        _m2.assert();
+        _m3.assert();
+        _m4.assert();
+        _m5.assert();
+
+        let dialog =
+            Dialog::<Subscribe>::try_from(db_client.get_users_dialog(USER_ID).unwrap()).unwrap();
+        assert_eq!(dialog.current_step, Subscribe::Subreddit);
+        assert!(db_client
+            .get_user_subscriptions(USER_ID)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn subscribe_quick_invalid() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: &format!("Hour must be between 0 and 23.\n{}", QUICK_SUBSCRIBE_USAGE),
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let reddit_client = RedditClient::new_with(url);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        subscribe(
+            &telegram_client,
+            &db_client,
+            &reddit_client,
+            USER_ID,
+            "rust sun 25",
+            &Listing::default(),
+        )
+        .await
+        .unwrap();
+        _m.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn subscribe_quick_blocked() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: BLOCKED_SUBREDDIT_TEXT,
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let _m2 = mock("GET", "/r/spam").expect(0).create();
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.block_subreddit("spam").unwrap();
+        let reddit_client = RedditClient::new_with(url);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        subscribe(
+            &telegram_client,
+            &db_client,
+            &reddit_client,
+            USER_ID,
+            "spam sun 12",
+            &Listing::default(),
+        )
+        .await
+        .unwrap();
+        _m.assert();
+        _m2.assert();
+        assert!(db_client
+            .get_user_subscriptions(USER_ID)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn subscribe_without_user() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "You need to call /start before setting up subscriptions",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        let reddit_client = RedditClient::new_with(url);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        let users = db_client.get_users().unwrap();
+        assert_eq!(users.len(), 0);
+
+        subscribe(
+            &telegram_client,
+            &db_client,
+            &reddit_client,
+            USER_ID,
+            "",
+            &Listing::default(),
+        )
+        .await
+        .unwrap();
+
+        _m.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn unsubscribe_without_user() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "You have no subscriptions to unsubscribe from",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        let users = db_client.get_users().unwrap();
+        assert_eq!(users.len(), 0);
+
+        unsubscribe(&telegram_client, &db_client, USER_ID)
+            .await
+            .unwrap();
+
+        _m.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn resubscribe_success() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Resubscribed to: rust. Posts will be sent periodically on Thu at around 18:00 UTC time.",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 3, 18).unwrap();
+        db_client.unsubscribe(USER_ID, "rust").unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        resubscribe(&telegram_client, &db_client, USER_ID)
+            .await
+            .unwrap();
+        _m.assert();
+
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(subscriptions.len(), 1);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn resubscribe_blocked_subreddit() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: BLOCKED_SUBREDDIT_TEXT,
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "spam", 3, 18).unwrap();
+        db_client.unsubscribe(USER_ID, "spam").unwrap();
+        db_client.block_subreddit("spam").unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        resubscribe(&telegram_client, &db_client, USER_ID)
+            .await
+            .unwrap();
+        _m.assert();
+
+        assert!(db_client
+            .get_user_subscriptions(USER_ID)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn resubscribe_invalid_send_on() {
+        use diesel::Connection;
+
+        let url = &server_url();
+        let _m = mock_send_message_not_called(TOKEN);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 3, 18).unwrap();
+        db_client.unsubscribe(USER_ID, "rust").unwrap();
+        db_client
+            .conn
+            .execute("UPDATE removed_subscriptions SET send_on = 9")
+            .unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        let result = resubscribe(&telegram_client, &db_client, USER_ID).await;
+        assert!(matches!(
+            result,
+            Err(BotError::DatabaseError(DeserializationError(_)))
+        ));
+        _m.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn resubscribe_limit_reached() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: &subscription_limit_text(),
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 3, 18).unwrap();
+        db_client.unsubscribe(USER_ID, "rust").unwrap();
+        seed_subscriptions(&db_client, MAX_SUBSCRIPTIONS);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        resubscribe(&telegram_client, &db_client, USER_ID)
+            .await
+            .unwrap();
+        _m.assert();
+
+        let count = db_client.get_subscription_count(USER_ID).unwrap();
+        assert_eq!(count, MAX_SUBSCRIPTIONS);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn resubscribe_nothing_to_restore() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "There is nothing to restore. Removed subscriptions are kept for 24 hours.",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        resubscribe(&telegram_client, &db_client, USER_ID)
+            .await
+            .unwrap();
+        _m.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn subscriptions_success() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "You are currently subscribed to:\ngolang - Wed at 2:00 UTC, hot, never sent\nrust - Tue at 1:00 UTC, top of the week, never sent\n",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 1, 1).unwrap();
+        let golang = db_client.subscribe(USER_ID, "golang", 2, 2).unwrap();
+        db_client.set_listing(golang.id, "hot", "day").unwrap();
+        db_client
+            .conn
+            .execute("UPDATE users_subscriptions SET last_sent_at = NULL")
+            .unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        subscriptions(&telegram_client, &db_client, USER_ID)
+            .await
+            .unwrap();
+        _m.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn subscriptions_no_subscriptions() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "You haven't subscribed to anything yet. Subscribe using /subscribe command.",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        subscriptions(&telegram_client, &db_client, USER_ID)
+            .await
+            .unwrap();
+        _m.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn subscriptions_error() {
+        let url = &server_url();
+        let _m = mock_send_message_not_called(TOKEN);
+        let db_client = setup_test_db_with(false);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        let result = subscriptions(&telegram_client, &db_client, USER_ID).await;
+        assert!(result.is_err());
+        _m.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn next_success() {
+        let url = &server_url();
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let subscription = db_client.subscribe(USER_ID, "rust", 3, 18).unwrap();
+        let text = format!(
+            "Your next digests:\nrust - {}\n",
+            subscription
+                .next_send_at(Utc::now(), Tz::UTC)
+                .format("%a, %d %b at %H:%M UTC")
+        );
+        let message = Message {
+            chat_id: USER_ID,
+            text: &text,
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        next(&telegram_client, &db_client, USER_ID).await.unwrap();
+        _m.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn history_success() {
+        let url = &server_url();
+        let _m = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::Regex(
+                r"2 digests from rust sent so far, latest:\\n\w{3}, \d{2} \w{3} at \d{2}:\d{2} UTC - 0 posts\\n.* - 2 posts\\n".to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .expect(1)
+            .create();
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let subscription = db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        db_client
+            .touch_last_sent(subscription.id, &["a", "b"])
+            .unwrap();
+        db_client.touch_last_sent(subscription.id, &[]).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        history(&telegram_client, &db_client, USER_ID, "rust")
+            .await
+            .unwrap();
+        _m.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn history_empty() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "No digests from rust have been sent yet",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        history(&telegram_client, &db_client, USER_ID, "rust")
+            .await
+            .unwrap();
+        _m.assert();
+    }
+
+    #[test]
+    fn parse_permalink_works() {
+        let expected = Some("/r/rust/comments/fbenua/a_halfhour_to_learn_rust/".to_string());
+        assert_eq!(
+            parse_permalink(
+                "https://www.reddit.com/r/rust/comments/fbenua/a_halfhour_to_learn_rust/?utm_source=share"
+            ),
+            expected
+        );
+        assert_eq!(
+            parse_permalink("old.reddit.com/r/rust/comments/fbenua/a_halfhour_to_learn_rust"),
+            expected
+        );
+        assert_eq!(
+            parse_permalink("/r/rust/comments/fbenua/a_halfhour_to_learn_rust/abc123/"),
+            expected
+        );
+        assert_eq!(
+            parse_permalink("https://reddit.com/r/rust/comments/fbenua"),
+            Some("/r/rust/comments/fbenua/".to_string())
+        );
+        assert_eq!(
+            parse_permalink("https://example.com/r/rust/comments/fbenua/"),
+            None
+        );
+        assert_eq!(parse_permalink("https://www.reddit.com/r/rust/"), None);
+        assert_eq!(parse_permalink("rust"), None);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn watch_post_success() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Watching /r/rust/comments/fbenua/a_halfhour_to_learn_rust/ for new top comments for the next 7 days",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let already_watching = Message {
+            chat_id: USER_ID,
+            text: "You are already watching this post",
+            ..Default::default()
+        };
+        let _m2 = mock_send_message_success(TOKEN, &already_watching);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let link = "https://www.reddit.com/r/rust/comments/fbenua/a_halfhour_to_learn_rust/";
+
+        watch_post(&telegram_client, &db_client, USER_ID, link)
+            .await
+            .unwrap();
+        watch_post(&telegram_client, &db_client, USER_ID, link)
+            .await
+            .unwrap();
+        _m.assert();
+        _m2.assert();
+        assert_eq!(db_client.get_post_subscriptions().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn unwatch_post_success() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Stopped watching /r/rust/comments/fbenua/a_halfhour_to_learn_rust/",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let not_watching = Message {
+            chat_id: USER_ID,
+            text: "You are not watching this post",
+            ..Default::default()
+        };
+        let _m2 = mock_send_message_success(TOKEN, &not_watching);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client
+            .watch_post(USER_ID, "/r/rust/comments/fbenua/a_halfhour_to_learn_rust/")
+            .unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let link = "https://www.reddit.com/r/rust/comments/fbenua/a_halfhour_to_learn_rust/";
+
+        unwatch_post(&telegram_client, &db_client, USER_ID, link)
+            .await
+            .unwrap();
+        unwatch_post(&telegram_client, &db_client, USER_ID, link)
+            .await
+            .unwrap();
+        _m.assert();
+        _m2.assert();
+        assert!(db_client.get_post_subscriptions().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn popular_success() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text:
+                "Most subscribed subreddits:\n1. golang - 2 subscribers\n2. rust - 1 subscriber\n",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.create_user(OTHER_USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        db_client.subscribe(USER_ID, "golang", 0, 12).unwrap();
+        db_client.subscribe(OTHER_USER_ID, "golang", 0, 12).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        popular(&telegram_client, &db_client, USER_ID)
+            .await
+            .unwrap();
+        _m.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn post_limit_success() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "New subscriptions will include 5 posts.",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        post_limit(&telegram_client, &db_client, USER_ID, "5")
+            .await
+            .unwrap();
+        _m.assert();
+
+        let subscription = db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        assert_eq!(subscription.post_limit, 5);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn post_limit_invalid() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Number of posts must be between 1 and 100.",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        post_limit(&telegram_client, &db_client, USER_ID, "500")
+            .await
+            .unwrap();
+        _m.assert();
+
+        let result = db_client.get_default_post_limit(USER_ID).unwrap();
+        assert_eq!(result, None);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn timezone_success() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Your subscriptions are sent in UTC time. Use /timezone <name> to change it, e.g. /timezone Europe/Riga.",
+            ..Default::default()
+        };
+        let message2 = Message {
+            chat_id: USER_ID,
+            text: "Your subscriptions will be sent in Europe/Riga time.",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let _m2 = mock_send_message_success(TOKEN, &message2);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        timezone(&telegram_client, &db_client, USER_ID, "")
+            .await
+            .unwrap();
+        timezone(&telegram_client, &db_client, USER_ID, "Europe/Riga")
+            .await
+            .unwrap();
+        _m.assert();
+        _m2.assert();
+        assert_eq!(db_client.get_timezone(USER_ID).unwrap(), "Europe/Riga");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn timezone_invalid() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text:
+                "Unknown timezone - Mars/Olympus. Use a name like Europe/Riga or America/New_York.",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        timezone(&telegram_client, &db_client, USER_ID, "Mars/Olympus")
+            .await
+            .unwrap();
+        _m.assert();
+        assert_eq!(db_client.get_timezone(USER_ID).unwrap(), "UTC");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn locale_success() {
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let messenger = RecordingMessenger::new();
+
+        locale(&messenger, &db_client, USER_ID, "pt-BR")
+            .await
+            .unwrap();
+        assert_eq!(
+            db_client.get_locale(USER_ID).unwrap().as_deref(),
+            Some("pt-BR")
+        );
+        locale(&messenger, &db_client, USER_ID, "").await.unwrap();
+        locale(&messenger, &db_client, USER_ID, "reset")
+            .await
+            .unwrap();
+        assert_eq!(db_client.get_locale(USER_ID).unwrap(), None);
+        assert_eq!(
+            messenger.texts(),
+            [
+                "Scores in your digests will be formatted for pt-BR, e.g. 12.345.",
+                "Scores in your digests are formatted for pt-BR, e.g. 12.345. Use /locale reset to go back to the default.",
+                "Scores in your digests will be formatted like 12,345.",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn locale_invalid() {
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let messenger = RecordingMessenger::new();
+
+        locale(&messenger, &db_client, USER_ID, "german")
+            .await
+            .unwrap();
+        assert_eq!(
+            messenger.texts(),
+            ["Unknown language - german. Use a language code like de or pt-BR."]
+        );
+        assert_eq!(db_client.get_locale(USER_ID).unwrap(), None);
+    }
+
+    #[test]
+    fn is_language_tag_works() {
+        assert!(is_language_tag("de"));
+        assert!(is_language_tag("pt-BR"));
+        assert!(is_language_tag("fr_FR"));
+        assert!(!is_language_tag("german"));
+        assert!(!is_language_tag("d"));
+        assert!(!is_language_tag("de-"));
+        assert!(!is_language_tag("12"));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn next_in_timezone() {
+        let url = &server_url();
+        let _m = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::Regex(
+                r"rust - Sun, \d+ \w+ at 09:00 Asia/Tokyo".to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .expect(1)
+            .create();
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.set_timezone(USER_ID, "Asia/Tokyo").unwrap();
+        db_client.subscribe(USER_ID, "rust", 6, 9).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        next(&telegram_client, &db_client, USER_ID).await.unwrap();
+        _m.assert();
+    }
+
+    #[test]
+    fn parse_toggle_works() {
+        assert_eq!(parse_toggle("on"), Some(true));
+        assert_eq!(parse_toggle("OFF"), Some(false));
+        assert_eq!(parse_toggle("maybe"), None);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn combined_success() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Digests due at the same time will be combined into a single message",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        combined(&telegram_client, &db_client, USER_ID, "on")
+            .await
+            .unwrap();
+        _m.assert();
+
+        let (_, _, combined_digest) = db_client
+            .get_subscriptions_with_user_settings()
+            .unwrap()
+            .remove(0);
+        assert!(combined_digest);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn prefs_success() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "New subscriptions will be sent silently",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        prefs(&telegram_client, &db_client, USER_ID, "silent on")
+            .await
+            .unwrap();
+        _m.assert();
+        assert_eq!(db_client.get_user_prefs(USER_ID).unwrap(), (true, false));
+
+        let message = Message {
+            chat_id: USER_ID,
+            text: "New subscriptions will be sent silently and with a link preview. Use /prefs <silent|preview> <on|off> to change it.",
+            ..Default::default()
+        };
+        db_client.update_user_prefs(USER_ID, true, true).unwrap();
+        let _m = mock_send_message_success(TOKEN, &message);
+        prefs(&telegram_client, &db_client, USER_ID, "")
+            .await
+            .unwrap();
+        _m.assert();
+
+        let subscription = db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        assert!(subscription.disable_notification);
+        assert!(subscription.link_preview);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn prefs_invalid() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Usage: /prefs <silent|preview> <on|off>",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        prefs(&telegram_client, &db_client, USER_ID, "loud on")
+            .await
+            .unwrap();
+        _m.assert();
+        assert_eq!(db_client.get_user_prefs(USER_ID).unwrap(), (false, false));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn excerpt_success() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Text post excerpts enabled for rust",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        excerpt(&telegram_client, &db_client, USER_ID, "rust on")
+            .await
+            .unwrap();
+        _m.assert();
+
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert!(subscriptions[0].include_excerpt);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn excerpt_invalid() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Usage: /excerpt <subreddit> <on|off>",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        excerpt(&telegram_client, &db_client, USER_ID, "rust")
+            .await
+            .unwrap();
+        _m.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn top_comment_success() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Top comments enabled for rust",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        top_comment(&telegram_client, &db_client, USER_ID, "rust on")
+            .await
+            .unwrap();
+        _m.assert();
+
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert!(subscriptions[0].include_top_comment);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn external_success() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Link posts in rust will point to the linked article",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        external(&telegram_client, &db_client, USER_ID, "rust on")
+            .await
+            .unwrap();
+        _m.assert();
+
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert!(subscriptions[0].prefer_external_url);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn protect_not_subscribed() {
+        let messenger = RecordingMessenger::new();
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+
+        protect(&messenger, &db_client, USER_ID, "rust on")
+            .await
+            .unwrap();
+        assert_eq!(
+            messenger.sent(),
+            vec![Sent {
+                chat_id: USER_ID.to_string(),
+                text: "You are not subscribed to rust".to_string(),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn help_recorded() {
+        let messenger = RecordingMessenger::new();
+
+        help(&messenger, USER_ID, "nope").await.unwrap();
+        help(&messenger, USER_ID, "protect").await.unwrap();
+        let texts = messenger.texts();
+        assert_eq!(texts.len(), 2);
+        assert_eq!(texts[0], *HELP_TEXT);
+        assert!(texts[1].starts_with("/protect <subreddit> <on|off>"));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn protect_success() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Digests of rust can no longer be forwarded or saved",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        protect(&telegram_client, &db_client, USER_ID, "rust on")
+            .await
+            .unwrap();
+        _m.assert();
+
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert!(subscriptions[0].protect_content);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn since_last_sent_success() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Digests of rust will include the newest posts since the previous one",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        since_last_sent(&telegram_client, &db_client, USER_ID, "rust on")
+            .await
+            .unwrap();
+        _m.assert();
+
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert!(subscriptions[0].since_last_sent);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn scores_success() {
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        let messenger = RecordingMessenger::new();
+
+        scores(&messenger, &db_client, USER_ID, "rust on")
+            .await
+            .unwrap();
+        scores(&messenger, &db_client, USER_ID, "golang on")
+            .await
+            .unwrap();
+        assert_eq!(
+            messenger.texts(),
+            [
+                "Digests of rust will show the score of each post",
+                "You are not subscribed to golang"
+            ]
+        );
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert!(subscriptions[0].show_scores);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn links_success() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Posts in rust will include the short redd.it link",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        links(&telegram_client, &db_client, USER_ID, "rust short")
+            .await
+            .unwrap();
+        _m.assert();
+
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(subscriptions[0].link_style, "short");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn links_invalid_style() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Usage: /links <subreddit> <permalink|short|np>",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        links(&telegram_client, &db_client, USER_ID, "rust both")
+            .await
+            .unwrap();
+        _m.assert();
+
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(subscriptions[0].link_style, "permalink");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn title_length_success() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Titles in rust will be cut off after 80 characters",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        title_length(&telegram_client, &db_client, USER_ID, "rust 80")
+            .await
+            .unwrap();
+        _m.assert();
+
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(subscriptions[0].max_title_len, Some(80));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn posts_per_message_success() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Digests of rust will have at most 5 posts per message",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        posts_per_message(&telegram_client, &db_client, USER_ID, "rust 5")
+            .await
+            .unwrap();
+        _m.assert();
+
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(subscriptions[0].posts_per_message, Some(5));
+
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Digests of rust will only be split when they get too long",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+
+        posts_per_message(&telegram_client, &db_client, USER_ID, "rust off")
+            .await
+            .unwrap();
+        _m.assert();
+
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(subscriptions[0].posts_per_message, None);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn posts_per_message_invalid() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Usage: /permessage <subreddit> <1-100|off>",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        posts_per_message(&telegram_client, &db_client, USER_ID, "rust 0")
+            .await
+            .unwrap();
+        _m.assert();
+
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(subscriptions[0].posts_per_message, None);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn title_length_invalid() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Usage: /titlelen <subreddit> <10-300|off>",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        title_length(&telegram_client, &db_client, USER_ID, "rust 5")
+            .await
+            .unwrap();
+        _m.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn tag_success() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Digests from rust will start with 🦀",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        tag(&telegram_client, &db_client, USER_ID, "rust 🦀")
+            .await
+            .unwrap();
+        _m.assert();
+
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(subscriptions[0].tag, Some("🦀".to_string()));
+
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Removed the tag from rust",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+
+        tag(&telegram_client, &db_client, USER_ID, "rust off")
+            .await
+            .unwrap();
+        _m.assert();
+
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(subscriptions[0].tag, None);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn tag_too_long() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Usage: /tag <subreddit> <emoji|off>, tags can be up to 16 characters",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        tag(
+            &telegram_client,
+            &db_client,
+            USER_ID,
+            "rust this tag is way too long",
+        )
+        .await
+        .unwrap();
+        _m.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn poll_success() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Posts from rust will be sent as a poll",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        poll(&telegram_client, &db_client, USER_ID, "rust on")
+            .await
+            .unwrap();
+        _m.assert();
+
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert!(subscriptions[0].as_poll);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn photos_success() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Image posts from rust will be sent as photos",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        photos(&telegram_client, &db_client, USER_ID, "rust on")
+            .await
+            .unwrap();
+        _m.assert();
+
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert!(subscriptions[0].as_photos);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn edit_in_place_success() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Digests of rust will replace the previous one",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        edit_in_place(&telegram_client, &db_client, USER_ID, "rust on")
+            .await
+            .unwrap();
+        _m.assert();
+
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert!(subscriptions[0].edit_in_place);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn retarget_success() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Digests for rust will be sent to -100123",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let _m2 = mock("POST", format!("/bot{}/sendChatAction", TOKEN).as_str())
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":true}"#)
+            .expect(1)
+            .create();
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        retarget(&telegram_client, &db_client, USER_ID, "rust -100123")
+            .await
+            .unwrap();
+        _m.assert();
+        _m2.assert();
+
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(subscriptions[0].target_chat_id, "-100123");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn retarget_forbidden() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "I can't post to -100123. Make sure I'm added to that chat and allowed to post there.",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let _m2 = mock("POST", format!("/bot{}/sendChatAction", TOKEN).as_str())
+            .with_status(403)
+            .with_body(r#"{"ok":false,"error_code":403,"description":"Forbidden: bot is not a member of the channel chat"}"#)
+            .expect(1)
+            .create();
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        retarget(&telegram_client, &db_client, USER_ID, "rust -100123")
+            .await
+            .unwrap();
+        _m.assert();
+        _m2.assert();
+
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(subscriptions[0].target_chat_id, USER_ID);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn rename_success() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Renamed rust to rustlang",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let _m2 = mock("GET", "/r/rustlang").with_status(200).create();
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 3, 18).unwrap();
+        let reddit_client = RedditClient::new_with(url);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        rename(
+            &telegram_client,
+            &db_client,
+            &reddit_client,
+            USER_ID,
+            "r/Rust r/RustLang",
+        )
+        .await
+        .unwrap();
+        _m.assert();
+        _m2.assert();
+
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(subscriptions[0].subreddit, "rustlang");
+        assert_eq!(subscriptions[0].send_on, 3);
+        assert_eq!(subscriptions[0].send_at, 18);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn rename_invalid_subreddit() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Invalid subreddit - rustlang",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let _m2 = mock("GET", "/r/rustlang").with_status(404).create();
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 3, 18).unwrap();
+        let reddit_client = RedditClient::new_with(url);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        rename(
+            &telegram_client,
+            &db_client,
+            &reddit_client,
+            USER_ID,
+            "rust rustlang",
+        )
+        .await
+        .unwrap();
+        _m.assert();
+        _m2.assert();
+
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(subscriptions[0].subreddit, "rust");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn rename_blocked_subreddit() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: BLOCKED_SUBREDDIT_TEXT,
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let _m2 = mock("GET", "/r/spam").expect(0).create();
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 3, 18).unwrap();
+        db_client.block_subreddit("spam").unwrap();
+        let reddit_client = RedditClient::new_with(url);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        rename(
+            &telegram_client,
+            &db_client,
+            &reddit_client,
+            USER_ID,
+            "rust Spam",
+        )
+        .await
+        .unwrap();
+        _m.assert();
+        _m2.assert();
+
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(subscriptions[0].subreddit, "rust");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn rename_already_subscribed() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "You are already subscribed to golang",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let _m2 = mock("GET", "/r/golang").expect(0).create();
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 3, 18).unwrap();
+        db_client.subscribe(USER_ID, "golang", 0, 12).unwrap();
+        let reddit_client = RedditClient::new_with(url);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        rename(
+            &telegram_client,
+            &db_client,
+            &reddit_client,
+            USER_ID,
+            "rust golang",
+        )
+        .await
+        .unwrap();
+        _m.assert();
+        _m2.assert();
+
+        let subreddits = db_client
+            .get_user_subscriptions(USER_ID)
+            .unwrap()
+            .into_iter()
+            .map(|subscription| subscription.subreddit)
+            .collect::<Vec<String>>();
+        assert_eq!(subreddits, ["golang", "rust"]);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn feedback_success() {
+        let url = &server_url();
+        let _m = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::Regex("What is your feedback about\\?".to_string()))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .expect(1)
+            .create();
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        feedback(&telegram_client, &db_client, "", USER_ID)
+            .await
+            .unwrap();
+
+        _m.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn feedback_without_user() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "You need to call /start before interacting with me",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        let users = db_client.get_users().unwrap();
+        assert_eq!(users.len(), 0);
+
+        feedback(&telegram_client, &db_client, "", USER_ID)
+            .await
+            .unwrap();
+        _m.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn send_now_success() {
+        let url = &server_url();
+        let subreddit = "rust";
+        let message = Message {
+            chat_id: USER_ID,
+            text: &format!("Weekly popular posts from: \"rust\"\n\nA half-hour to learn Rust\n{}/r/rust/comments/fbenua/a_halfhour_to_learn_rust/\n\n", url),
+            disable_web_page_preview: true,
+            ..Default::default()
+        };
+        let _m1 = mock_send_message_success(TOKEN, &message);
+        let _m2 = mock_reddit_success(subreddit);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, subreddit, 1, 1).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+
+        send_now(&telegram_client, &db_client, &reddit_client, USER_ID, None)
+            .await
+            .unwrap();
+        _m1.assert();
+        _m2.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn send_now_error_text() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "r/rust is down, back next week!",
+            protect_content: true,
+            ..Default::default()
+        };
+        let _m1 = mock_send_message_success(TOKEN, &message);
+        let _m2 = mock("GET", "/r/rust/top.json?limit=10&t=week")
+            .with_status(503)
+            .with_body("<html>Service unavailable</html>")
+            .create();
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 1, 1).unwrap();
+        db_client
+            .update_subscription(
+                USER_ID,
+                "rust",
+                SubscriptionChanges {
+                    error_text: Some(Some("r/{subreddit} is down, back next week!")),
+                    protect_content: Some(true),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+
+        send_now(&telegram_client, &db_client, &reddit_client, USER_ID, None)
+            .await
+            .unwrap();
+        _m1.assert();
+        _m2.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn send_now_overlapping_subscriptions() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: &format!("Weekly popular posts from: \"Rust\"\n\nA half-hour to learn Rust\n{}/r/rust/comments/fbenua/a_halfhour_to_learn_rust/\n\n", url),
+            disable_web_page_preview: true,
+            ..Default::default()
+        };
+        let _m1 = mock_send_message_success(TOKEN, &message);
+        // The first subscription in list order is sent, "Rust" sorts before "rust".
+        let _m2 = mock_reddit_success("Rust");
+        let _m3 = mock("GET", "/r/rust/top.json?limit=10&t=week")
+            .expect(0)
+            .create();
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 1, 1).unwrap();
+        db_client.subscribe(USER_ID, "Rust", 2, 2).unwrap();
+        db_client
+            .conn
+            .execute("UPDATE users_subscriptions SET last_sent_at = NULL")
+            .unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+
+        send_now(&telegram_client, &db_client, &reddit_client, USER_ID, None)
+            .await
+            .unwrap();
+        _m1.assert();
+        _m2.assert();
+        _m3.assert();
+
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(subscriptions.len(), 2);
+        assert!(subscriptions.iter().all(|s| s.last_sent_at.is_some()));
+    }
+
+    #[test]
+    fn group_by_subreddit_works() {
+        let subscription = |id: i32, subreddit: &str, target_chat_id: &str| Subscription {
+            id,
+            subreddit: subreddit.to_string(),
+            target_chat_id: target_chat_id.to_string(),
+            ..Default::default()
+        };
+        let groups = group_by_subreddit(vec![
+            subscription(1, "rust", USER_ID),
+            subscription(2, "golang", USER_ID),
+            subscription(3, "Rust", USER_ID),
+            subscription(4, "rust", "-100"),
+        ]);
+        let ids = groups
+            .iter()
+            .map(|group| group.iter().map(|s| s.id).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![vec![1, 3], vec![2], vec![4]]);
+    }
+
+    #[test]
+    fn group_by_subreddit_different_settings() {
+        let subscription = |id: i32, timeframe: &str| Subscription {
+            id,
+            subreddit: "rust".to_string(),
+            target_chat_id: USER_ID.to_string(),
+            sort: "top".to_string(),
+            timeframe: timeframe.to_string(),
+            ..Default::default()
+        };
+        let groups = group_by_subreddit(vec![
+            subscription(1, "week"),
+            subscription(2, "month"),
+            Subscription {
+                recurring: true,
+                ..subscription(3, "week")
+            },
+        ]);
+        let ids = groups
+            .iter()
+            .map(|group| group.iter().map(|s| s.id).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![vec![1, 3], vec![2]]);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn send_now_no_subscriptions() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "You haven't subscribed to anything yet. Subscribe using /subscribe command.",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+
+        send_now(&telegram_client, &db_client, &reddit_client, USER_ID, None)
+            .await
+            .unwrap();
+        _m.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn send_new_nothing_new() {
+        let url = &server_url();
+        let _m = mock_reddit_success("rust");
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let subscription = db_client.subscribe(USER_ID, "rust", 1, 1).unwrap();
+        db_client
+            .touch_last_sent(subscription.id, &["fbenua"])
+            .unwrap();
+        let sent = db_client.get_user_subscriptions(USER_ID).unwrap().remove(0);
+        let messenger = RecordingMessenger::new();
+        let reddit_client = RedditClient::new_with(url);
+
+        send_new(&messenger, &db_client, &reddit_client, USER_ID, None)
+            .await
+            .unwrap();
+        _m.assert();
+        assert_eq!(
+            messenger.texts(),
+            vec!["Nothing new since the last digest of \"rust\".".to_string()]
+        );
+
+        let result = db_client.get_user_subscriptions(USER_ID).unwrap().remove(0);
+        assert_eq!(result.last_sent_at, sent.last_sent_at);
+        assert_eq!(result.empty_streak, 0);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn send_now_blocked_subreddit() {
+        let url = &server_url();
+        let _m = mock("GET", "/r/rust+spam/top.json?limit=10&t=week")
+            .expect(0)
+            .create();
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust+spam", 1, 1).unwrap();
+        db_client.block_subreddit("spam").unwrap();
+        let messenger = RecordingMessenger::new();
+        let reddit_client = RedditClient::new_with(url);
+
+        send_now(&messenger, &db_client, &reddit_client, USER_ID, None)
+            .await
+            .unwrap();
+        _m.assert();
+        assert_eq!(
+            messenger.texts(),
+            ["\"rust+spam\" isn't allowed on this instance anymore."]
+        );
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn help_success() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: &HELP_TEXT,
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        help(&telegram_client, USER_ID, "").await.unwrap();
+        _m.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn help_command() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "/excerpt <subreddit> <on|off>\nIncludes a short excerpt of text posts in the digest.\n\nExample:\n/excerpt rust on",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        help(&telegram_client, USER_ID, "excerpt").await.unwrap();
+        _m.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn help_unknown_command() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: &HELP_TEXT,
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        help(&telegram_client, USER_ID, "dance").await.unwrap();
+        _m.assert();
+    }
+
+    #[test]
+    fn format_uptime_works() {
+        assert_eq!(format_uptime(Duration::from_secs(59)), "0d 0h 0m");
+        assert_eq!(
+            format_uptime(Duration::from_secs(2 * 86400 + 3 * 3600 + 15 * 60 + 30)),
+            "2d 3h 15m"
+        );
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn about_success() {
+        let url = &server_url();
+        let _m = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::Regex(
+                r"Tracking 2 subreddits, up for 0d 0h 0m\.".to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .expect(1)
+            .create();
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.create_user(OTHER_USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        db_client.subscribe(OTHER_USER_ID, "rust", 0, 12).unwrap();
+        db_client.subscribe(OTHER_USER_ID, "golang", 0, 12).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        about(&telegram_client, &db_client, USER_ID, Instant::now())
+            .await
+            .unwrap();
+        _m.assert();
+    }
+
+    #[test]
+    fn command_help_covers_commands() {
+        for (command, name, help) in COMMANDS.iter() {
+            assert!(help.starts_with(&format!("/{}", name)), "{}", name);
+            assert_eq!(find_command(&format!("/{}", name)), Some(*command));
+        }
+        assert_eq!(find_command("/diag"), None);
+        assert_eq!(find_command("subscribe"), None);
+        assert!(command_help("/Subscribe")
+            .unwrap()
+            .contains("/subscribe rust top week 10 sun 12"));
+        assert_eq!(command_help("diag"), None);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn show_dialog_success() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Dialog for user(456):\ncommand: /subscribe\nstep: Subreddit\ndata: {}",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(OTHER_USER_ID).unwrap();
+        let mut dialog = Dialog::<Subscribe>::new(OTHER_USER_ID.to_string());
+        dialog.current_step = Subscribe::Subreddit;
+        db_client.insert_or_update_dialog(&dialog.into()).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        show_dialog(
+            &telegram_client,
+            &db_client,
+            USER_ID,
+            USER_ID,
+            OTHER_USER_ID,
+        )
+        .await
+        .unwrap();
+        _m.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn show_dialog_all() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Active dialogs:\n123 - /feedback (Start)\n456 - /subscribe (Weekday)\nUse /showdialog <user_id> to see the details.",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.create_user(OTHER_USER_ID).unwrap();
+        db_client
+            .insert_or_update_dialog(&DialogEntity {
+                user_id: OTHER_USER_ID.to_string(),
+                command: "/subscribe".to_string(),
+                step: "Weekday".to_string(),
+                data: "{}".to_string(),
+            })
+            .unwrap();
+        db_client
+            .insert_or_update_dialog(&DialogEntity {
+                user_id: USER_ID.to_string(),
+                command: "/feedback".to_string(),
+                step: "Start".to_string(),
+                data: "{}".to_string(),
+            })
+            .unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        show_dialog(&telegram_client, &db_client, USER_ID, USER_ID, "")
+            .await
+            .unwrap();
+        _m.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn show_dialog_no_dialog() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "No dialog found for user(456)",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        show_dialog(
+            &telegram_client,
+            &db_client,
+            USER_ID,
+            USER_ID,
+            OTHER_USER_ID,
+        )
+        .await
+        .unwrap();
+        _m.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn show_dialog_non_author() {
+        let url = &server_url();
+        let _m = mock_send_message_not_called(TOKEN);
+        let db_client = setup_test_db();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        show_dialog(
+            &telegram_client,
+            &db_client,
+            USER_ID,
+            OTHER_USER_ID,
+            OTHER_USER_ID,
+        )
+        .await
+        .unwrap();
+        _m.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn clear_dialog_success() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Cleared dialog for user(456)",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(OTHER_USER_ID).unwrap();
+        let dialog = Dialog::<Subscribe>::new(OTHER_USER_ID.to_string());
+        db_client.insert_or_update_dialog(&dialog.into()).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        clear_dialog(
+            &telegram_client,
+            &db_client,
+            USER_ID,
+            USER_ID,
+            OTHER_USER_ID,
+        )
+        .await
+        .unwrap();
+        _m.assert();
+
+        assert!(db_client.get_users_dialog(OTHER_USER_ID).is_err());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn reset_dialogs_success() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Reset 2 dialogs",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.create_user(OTHER_USER_ID).unwrap();
+        let dialog = Dialog::<Subscribe>::new(USER_ID.to_string());
+        db_client.insert_or_update_dialog(&dialog.into()).unwrap();
+        db_client
+            .insert_or_update_dialog(&DialogEntity {
+                user_id: OTHER_USER_ID.to_string(),
+                command: "/subscribe".to_string(),
+                step: "Subreddit".to_string(),
+                data: "{not json".to_string(),
+            })
+            .unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        reset_dialogs(&telegram_client, &db_client, USER_ID, USER_ID)
+            .await
+            .unwrap();
+        _m.assert();
+
+        assert!(db_client.get_users_dialog(USER_ID).is_err());
+        assert!(db_client.get_users_dialog(OTHER_USER_ID).is_err());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn clear_dialog_no_dialog() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "No dialog found for user(456)",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        clear_dialog(
+            &telegram_client,
+            &db_client,
+            USER_ID,
+            USER_ID,
+            OTHER_USER_ID,
+        )
+        .await
+        .unwrap();
+        _m.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn render_test_user_success() {
+        let url = &server_url();
+        let _m = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::Regex(
+                r"Render test for user 456:\\ngolang - failed after 1 attempts: .+\\nrust - 1 posts in 1 messages\\n".to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .expect(1)
+            .create();
+        let _m2 = mock("GET", "/r/golang/top.json?limit=10&t=week")
+            .with_status(500)
+            .with_body("internal server error")
+            .create();
+        let _m3 = mock_reddit_success("rust");
+        let db_client = setup_test_db();
+        db_client.create_user(OTHER_USER_ID).unwrap();
+        db_client.subscribe(OTHER_USER_ID, "golang", 0, 12).unwrap();
+        db_client.subscribe(OTHER_USER_ID, "rust", 0, 12).unwrap();
+        let reddit_client = RedditClient::new_with(url);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
 
-    const TOKEN: &str = "token";
-    const USER_ID: &str = "123";
+        render_test_user(
+            &telegram_client,
+            &db_client,
+            &reddit_client,
+            USER_ID,
+            USER_ID,
+            OTHER_USER_ID,
+        )
+        .await
+        .unwrap();
+        _m.assert();
+        _m2.assert();
+        _m3.assert();
+    }
 
     #[tokio::test]
     #[serial]
-    async fn start_success() {
+    async fn render_test_success() {
         let url = &server_url();
         let message = Message {
             chat_id: USER_ID,
-            text: HELP_TEXT,
+            text: "Weekly popular posts from: \"rust\"\n\nSample post #1 from r/rust\nhttps://reddit.com/r/rust/comments/post1/\n\nSample post #2 from r/rust\nhttps://reddit.com/r/rust/comments/post2/\n\nSample post #3 from r/rust (crosspost)\nhttps://reddit.com/r/rust/comments/post3/\n\n",
+            disable_web_page_preview: true,
             ..Default::default()
         };
         let _m = mock_send_message_success(TOKEN, &message);
         let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
-        let db_client = setup_test_db();
 
-        start(&telegram_client, &db_client, USER_ID).await.unwrap();
+        render_test(&telegram_client, USER_ID, USER_ID, "rust")
+            .await
+            .unwrap();
         _m.assert();
+    }
 
-        let users = db_client.get_users().unwrap();
-        assert_eq!(users.len(), 1);
-        assert_eq!(users[0].id, USER_ID);
+    #[tokio::test]
+    #[serial]
+    async fn render_test_non_author() {
+        let url = &server_url();
+        let _m = mock_send_message_not_called(TOKEN);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        render_test(&telegram_client, USER_ID, OTHER_USER_ID, "rust")
+            .await
+            .unwrap();
+        _m.assert();
     }
 
     #[tokio::test]
     #[serial]
-    async fn start_existing_user() {
+    async fn diag_success() {
         let url = &server_url();
-        let message = Message {
-            chat_id: USER_ID,
-            text: HELP_TEXT,
-            ..Default::default()
-        };
-        let _m = mock_send_message_success(TOKEN, &message);
+        let _m1 = mock("GET", "/r/announcements").with_status(200).create();
+        let _m2 = mock("GET", format!("/bot{}/getMe", TOKEN).as_str())
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"id":414141,"is_bot":true,"first_name":"Bot","username":"reddit_bot"}}"#)
+            .create();
+        let _m3 = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::Regex(
+                r"Reddit: ok \(\d+ ms\)\\nTelegram: ok, @reddit_bot \(\d+ ms\)".to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .expect(1)
+            .create();
         let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
-        let db_client = setup_test_db();
-        db_client.create_user(USER_ID).unwrap();
+        let reddit_client = RedditClient::new_with(url);
 
-        let users = db_client.get_users().unwrap();
-        assert_eq!(users.len(), 1);
-        assert_eq!(users[0].id, USER_ID);
+        diag(&telegram_client, &reddit_client, USER_ID, USER_ID)
+            .await
+            .unwrap();
+        _m1.assert();
+        _m2.assert();
+        _m3.assert();
+    }
 
-        start(&telegram_client, &db_client, USER_ID).await.unwrap();
-        _m.assert();
+    #[tokio::test]
+    #[serial]
+    async fn diag_failure() {
+        let url = &server_url();
+        let _m1 = mock("GET", "/r/announcements").with_status(500).create();
+        let _m2 = mock("GET", format!("/bot{}/getMe", TOKEN).as_str())
+            .with_status(401)
+            .with_body("Unauthorized")
+            .create();
+        let _m3 = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::Regex(
+                r"Reddit: failed \(\d+ ms\)\\nTelegram: failed, Unauthorized \(\d+ ms\)"
+                    .to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .expect(1)
+            .create();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
 
-        let users = db_client.get_users().unwrap();
-        assert_eq!(users.len(), 1);
-        assert_eq!(users[0].id, USER_ID);
+        diag(&telegram_client, &reddit_client, USER_ID, USER_ID)
+            .await
+            .unwrap();
+        _m1.assert();
+        _m2.assert();
+        _m3.assert();
     }
 
     #[tokio::test]
     #[serial]
-    async fn start_error() {
+    async fn stale_success() {
         let url = &server_url();
-        let _m = mock_send_message_not_called(TOKEN);
+        let _m = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::Regex(
+                r"Subscriptions not sent in 14 days:\\n\d+ \(456\) - golang - Mon at 12:00 UTC, top of the week, never sent\\n".to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .expect(1)
+            .create();
+        let db_client = setup_test_db();
+        db_client.create_user(OTHER_USER_ID).unwrap();
+        db_client.subscribe(OTHER_USER_ID, "rust", 0, 12).unwrap();
+        let subscription = db_client.subscribe(OTHER_USER_ID, "golang", 0, 12).unwrap();
+        db_client
+            .conn
+            .execute(&format!(
+                "UPDATE users_subscriptions SET last_sent_at = NULL WHERE id = {}",
+                subscription.id
+            ))
+            .unwrap();
         let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
-        let db_client = setup_test_db_with(false);
 
-        let result = start(&telegram_client, &db_client, USER_ID).await;
-        assert!(result.is_err());
+        stale(&telegram_client, &db_client, USER_ID, USER_ID)
+            .await
+            .unwrap();
         _m.assert();
     }
 
     #[tokio::test]
     #[serial]
-    async fn stop_success() {
+    async fn backup_success() {
         let url = &server_url();
-        let message = Message {
-            chat_id: USER_ID,
-            text: "User and subscriptions deleted",
-            ..Default::default()
-        };
-        let _m = mock_send_message_success(TOKEN, &message);
-        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let _m = mock("POST", format!("/bot{}/sendDocument", TOKEN).as_str())
+            .match_body(Matcher::Regex(
+                r#"filename="reddit-bot-\d{8}-\d{6}\.sqlite""#.to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .expect(1)
+            .create();
         let db_client = setup_test_db();
         db_client.create_user(USER_ID).unwrap();
-        let users = db_client.get_users().unwrap();
-        assert_eq!(users.len(), 1);
-        assert_eq!(users[0].id, USER_ID);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
 
-        stop(&telegram_client, &db_client, USER_ID).await.unwrap();
+        backup(&telegram_client, &db_client, USER_ID, USER_ID)
+            .await
+            .unwrap();
+        _m.assert();
+    }
 
-        let users = db_client.get_users().unwrap();
-        assert_eq!(users.len(), 0);
+    #[tokio::test]
+    #[serial]
+    async fn migrate_success() {
+        let url = &server_url();
+        let _m = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::Regex(
+                r"Applied 0 pending migrations, schema version: \d{14}".to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .expect(1)
+            .create();
+        let db_client = setup_test_db();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        migrate(&telegram_client, &db_client, USER_ID, USER_ID)
+            .await
+            .unwrap();
         _m.assert();
     }
 
     #[tokio::test]
     #[serial]
-    async fn stop_error() {
+    async fn migrate_non_author() {
         let url = &server_url();
         let _m = mock_send_message_not_called(TOKEN);
-        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
         let db_client = setup_test_db_with(false);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
 
-        let result = stop(&telegram_client, &db_client, USER_ID).await;
-        assert!(result.is_err());
+        migrate(&telegram_client, &db_client, USER_ID, OTHER_USER_ID)
+            .await
+            .unwrap();
         _m.assert();
+        // Nothing was applied on behalf of the non author.
+        assert!(db_client.run_migrations().unwrap().applied > 0);
     }
 
     #[tokio::test]
     #[serial]
-    async fn subscribe_success() {
+    async fn backup_non_author() {
         let url = &server_url();
-        let message = Message {
-            chat_id: USER_ID,
-            text: "Type the name of subreddit you want to subscribe to.\nMultiple subreddits are allowed, separated by whitespace or newline.",
-            ..Default::default()
-        };
-        let _m = mock_send_message_success(TOKEN, &message);
+        let _m = mock("POST", format!("/bot{}/sendDocument", TOKEN).as_str())
+            .expect(0)
+            .create();
         let db_client = setup_test_db();
-        db_client.create_user(USER_ID).unwrap();
-        let reddit_client = RedditClient::new_with(url);
         let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
 
-        subscribe(&telegram_client, &db_client, &reddit_client, USER_ID)
+        backup(&telegram_client, &db_client, USER_ID, OTHER_USER_ID)
             .await
             .unwrap();
         _m.assert();
@@ -350,87 +5481,154 @@ mod tests {
 
     #[tokio::test]
     #[serial]
-    async fn subscribe_without_user() {
+    async fn stats_success() {
         let url = &server_url();
         let message = Message {
             chat_id: USER_ID,
-            text: "You need to call /start before setting up subscriptions",
+            text: "Users: 2\nSubscriptions: 1",
             ..Default::default()
         };
         let _m = mock_send_message_success(TOKEN, &message);
         let db_client = setup_test_db();
-        let reddit_client = RedditClient::new_with(url);
+        db_client.create_user(USER_ID).unwrap();
+        db_client.create_user(OTHER_USER_ID).unwrap();
+        db_client.subscribe(OTHER_USER_ID, "rust", 0, 12).unwrap();
         let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
 
-        let users = db_client.get_users().unwrap();
-        assert_eq!(users.len(), 0);
-
-        subscribe(&telegram_client, &db_client, &reddit_client, USER_ID)
+        stats(&telegram_client, &db_client, USER_ID, USER_ID)
             .await
             .unwrap();
-
         _m.assert();
     }
 
     #[tokio::test]
     #[serial]
-    async fn unsubscribe_without_user() {
+    async fn chats_success() {
         let url = &server_url();
         let message = Message {
             chat_id: USER_ID,
-            text: "You have no subscriptions to unsubscribe from",
+            text: "Chats:\n-100123 - administrator, cannot post\n123 - member\n",
             ..Default::default()
         };
         let _m = mock_send_message_success(TOKEN, &message);
+        let _m2 = mock("GET", format!("/bot{}/getMe", TOKEN).as_str())
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"id":414141,"is_bot":true,"first_name":"Bot","username":"reddit_bot"}}"#)
+            .create();
+        let _m3 = mock("POST", format!("/bot{}/getChatMember", TOKEN).as_str())
+            .match_body(Matcher::Json(
+                json!({ "chat_id": "-100123", "user_id": 414141 }),
+            ))
+            .with_status(200)
+            .with_body(
+                r#"{"ok":true,"result":{"status":"administrator","can_post_messages":false}}"#,
+            )
+            .create();
+        let _m4 = mock("POST", format!("/bot{}/getChatMember", TOKEN).as_str())
+            .match_body(Matcher::Json(
+                json!({ "chat_id": USER_ID, "user_id": 414141 }),
+            ))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"status":"member"}}"#)
+            .create();
         let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.create_user(OTHER_USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        db_client.subscribe(OTHER_USER_ID, "rust", 0, 12).unwrap();
+        db_client
+            .update_subscription(
+                OTHER_USER_ID,
+                "rust",
+                SubscriptionChanges {
+                    target_chat_id: Some("-100123"),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
         let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
 
-        let users = db_client.get_users().unwrap();
-        assert_eq!(users.len(), 0);
-
-        unsubscribe(&telegram_client, &db_client, USER_ID)
+        chats(&telegram_client, &db_client, USER_ID, USER_ID)
             .await
             .unwrap();
+        _m.assert();
+        _m2.assert();
+        _m3.assert();
+        _m4.assert();
+    }
 
+    #[tokio::test]
+    #[serial]
+    async fn chats_non_author() {
+        let url = &server_url();
+        let _m = mock_send_message_not_called(TOKEN);
+        let db_client = setup_test_db();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        chats(&telegram_client, &db_client, USER_ID, OTHER_USER_ID)
+            .await
+            .unwrap();
         _m.assert();
     }
 
     #[tokio::test]
     #[serial]
-    async fn subscriptions_success() {
+    async fn custom_text_success() {
         let url = &server_url();
         let message = Message {
             chat_id: USER_ID,
-            text: "You are currently subscribed to:\nrust\n",
+            text: "Updated the empty text for rust",
             ..Default::default()
         };
         let _m = mock_send_message_success(TOKEN, &message);
         let db_client = setup_test_db();
         db_client.create_user(USER_ID).unwrap();
-        db_client.subscribe(USER_ID, "rust", 1, 1).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
         let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
 
-        subscriptions(&telegram_client, &db_client, USER_ID)
+        custom_text(
+            &telegram_client,
+            &db_client,
+            USER_ID,
+            "rust empty Quiet week in the crab shack.",
+        )
+        .await
+        .unwrap();
+        _m.assert();
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(
+            subscriptions[0].empty_text.as_deref(),
+            Some("Quiet week in the crab shack.")
+        );
+
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Restored the default empty text for rust",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        custom_text(&telegram_client, &db_client, USER_ID, "rust empty reset")
             .await
             .unwrap();
         _m.assert();
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(subscriptions[0].empty_text, None);
     }
 
     #[tokio::test]
     #[serial]
-    async fn subscriptions_no_subscriptions() {
+    async fn custom_text_usage() {
         let url = &server_url();
         let message = Message {
             chat_id: USER_ID,
-            text: "You haven't subscribed to anything yet. Subscribe using /subscribe command.",
+            text: "Usage: /customtext <subreddit> <empty|error> <text|reset>",
             ..Default::default()
         };
         let _m = mock_send_message_success(TOKEN, &message);
         let db_client = setup_test_db();
-        db_client.create_user(USER_ID).unwrap();
         let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
 
-        subscriptions(&telegram_client, &db_client, USER_ID)
+        custom_text(&telegram_client, &db_client, USER_ID, "rust header Hi")
             .await
             .unwrap();
         _m.assert();
@@ -438,120 +5636,262 @@ mod tests {
 
     #[tokio::test]
     #[serial]
-    async fn subscriptions_error() {
+    async fn debug_post_success() {
+        let url = &server_url();
+        let _m = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::Regex(r#"\\"id\\": \\"fbenua\\""#.to_string()))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .expect(1)
+            .create();
+        let _m2 = mock("GET", "/r/rust/top.json?limit=1&t=week")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"kind": "Listing", "data": {"children": [{"kind": "t3", "data": {"id": "fbenua", "title": "A half-hour to learn Rust"}}]}}"#,
+            )
+            .create();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+
+        debug_post(&telegram_client, &reddit_client, USER_ID, USER_ID, "rust")
+            .await
+            .unwrap();
+        _m.assert();
+        _m2.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn debug_post_non_author() {
         let url = &server_url();
         let _m = mock_send_message_not_called(TOKEN);
-        let db_client = setup_test_db_with(false);
         let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
 
-        let result = subscriptions(&telegram_client, &db_client, USER_ID).await;
-        assert!(result.is_err());
+        debug_post(
+            &telegram_client,
+            &reddit_client,
+            USER_ID,
+            OTHER_USER_ID,
+            "rust",
+        )
+        .await
+        .unwrap();
         _m.assert();
     }
 
     #[tokio::test]
     #[serial]
-    async fn feedback_success() {
+    async fn inactive_success() {
         let url = &server_url();
         let message = Message {
             chat_id: USER_ID,
-            text: "You can write your feedback. If you want the author to get back to you, leave your email.",
+            text: "Users without subscriptions for over 30 days:\n456 - joined 2020-03-22T12:00:05+00:00\n\nRemove them with /purge_inactive",
             ..Default::default()
         };
         let _m = mock_send_message_success(TOKEN, &message);
         let db_client = setup_test_db();
         db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        db_client.create_user(OTHER_USER_ID).unwrap();
+        db_client
+            .conn
+            .execute("UPDATE users SET created_at = '2020-03-22T12:00:05+00:00'")
+            .unwrap();
         let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
 
-        feedback(&telegram_client, &db_client, "", USER_ID)
+        inactive(&telegram_client, &db_client, USER_ID, USER_ID)
             .await
             .unwrap();
-
         _m.assert();
+        assert!(db_client.user_exists(OTHER_USER_ID).unwrap());
     }
 
     #[tokio::test]
     #[serial]
-    async fn feedback_without_user() {
+    async fn purge_inactive_success() {
         let url = &server_url();
         let message = Message {
             chat_id: USER_ID,
-            text: "You need to call /start before interacting with me",
+            text: "Purged 1 inactive users",
             ..Default::default()
         };
         let _m = mock_send_message_success(TOKEN, &message);
         let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        db_client.create_user(OTHER_USER_ID).unwrap();
+        db_client
+            .conn
+            .execute("UPDATE users SET created_at = '2020-03-22T12:00:05+00:00'")
+            .unwrap();
         let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
 
-        let users = db_client.get_users().unwrap();
-        assert_eq!(users.len(), 0);
-
-        feedback(&telegram_client, &db_client, "", USER_ID)
+        purge_inactive(&telegram_client, &db_client, USER_ID, USER_ID)
             .await
             .unwrap();
         _m.assert();
+        assert!(db_client.user_exists(USER_ID).unwrap());
+        assert!(!db_client.user_exists(OTHER_USER_ID).unwrap());
     }
 
     #[tokio::test]
     #[serial]
-    async fn send_now_success() {
+    async fn prune_success() {
         let url = &server_url();
-        let subreddit = "rust";
         let message = Message {
             chat_id: USER_ID,
-            text: &format!("Weekly popular posts from: \"rust\"\n\nA half-hour to learn Rust\n{}/r/rust/comments/fbenua/a_halfhour_to_learn_rust/\n\n", url),
-            disable_web_page_preview: true,
+            text: "Pruned 1 orphan subscriptions",
             ..Default::default()
         };
-        let _m1 = mock_send_message_success(TOKEN, &message);
-        let _m2 = mock_reddit_success(subreddit);
+        let _m = mock_send_message_success(TOKEN, &message);
         let db_client = setup_test_db();
-        db_client.create_user(USER_ID).unwrap();
-        db_client.subscribe(USER_ID, subreddit, 1, 1).unwrap();
+        db_client.create_user(OTHER_USER_ID).unwrap();
+        db_client.subscribe(OTHER_USER_ID, "rust", 0, 12).unwrap();
+        db_client.conn.execute("PRAGMA foreign_keys = OFF").unwrap();
+        db_client.delete_user(OTHER_USER_ID).unwrap();
         let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
-        let reddit_client = RedditClient::new_with(&url);
 
-        send_now(&telegram_client, &db_client, &reddit_client, USER_ID)
+        prune(&telegram_client, &db_client, USER_ID, USER_ID)
             .await
             .unwrap();
-        _m1.assert();
-        _m2.assert();
+        _m.assert();
+        assert_eq!(db_client.get_subscriptions().unwrap().len(), 0);
     }
 
     #[tokio::test]
     #[serial]
-    async fn send_now_no_subscriptions() {
+    async fn blocklist_success() {
         let url = &server_url();
+        let db_client = setup_test_db();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
         let message = Message {
             chat_id: USER_ID,
-            text: "You haven't subscribed to anything yet. Subscribe using /subscribe command.",
+            text: "Blocked Spam",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        blocklist(&telegram_client, &db_client, USER_ID, USER_ID, "add Spam")
+            .await
+            .unwrap();
+        _m.assert();
+        assert!(db_client.is_subreddit_blocked("spam").unwrap());
+
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Blocked subreddits:\nspam",
             ..Default::default()
         };
         let _m = mock_send_message_success(TOKEN, &message);
+        blocklist(&telegram_client, &db_client, USER_ID, USER_ID, "")
+            .await
+            .unwrap();
+        _m.assert();
+
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Unblocked spam",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        blocklist(
+            &telegram_client,
+            &db_client,
+            USER_ID,
+            USER_ID,
+            "remove spam",
+        )
+        .await
+        .unwrap();
+        _m.assert();
+        assert!(!db_client.is_subreddit_blocked("spam").unwrap());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn blocklist_non_author() {
+        let url = &server_url();
+        let _m = mock_send_message_not_called(TOKEN);
         let db_client = setup_test_db();
-        db_client.create_user(USER_ID).unwrap();
         let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
-        let reddit_client = RedditClient::new_with(&url);
 
-        send_now(&telegram_client, &db_client, &reddit_client, USER_ID)
+        blocklist(
+            &telegram_client,
+            &db_client,
+            USER_ID,
+            OTHER_USER_ID,
+            "add spam",
+        )
+        .await
+        .unwrap();
+        _m.assert();
+        assert!(!db_client.is_subreddit_blocked("spam").unwrap());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn rate_limit_success() {
+        let url = &server_url();
+        let _m1 = mock("GET", "/r/rust/top.json?limit=10&t=week")
+            .with_status(200)
+            .with_header("x-ratelimit-remaining", "598.0")
+            .with_header("x-ratelimit-reset", "120")
+            .with_body(r#"{"kind":"Listing","data":{"children":[]}}"#)
+            .create();
+        let _m2 = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::Regex(
+                r"Remaining requests: 598\\nResets in 120 seconds, as of \d{2}:\d{2}:\d{2} UTC"
+                    .to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .expect(1)
+            .create();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        reddit_client
+            .fetch_posts("rust", &Listing::default(), 10, None)
+            .await
+            .unwrap();
+
+        rate_limit(&telegram_client, &reddit_client, USER_ID, USER_ID)
             .await
             .unwrap();
-        _m.assert();
+        _m2.assert();
     }
 
     #[tokio::test]
     #[serial]
-    async fn help_success() {
+    async fn rate_limit_not_seen() {
         let url = &server_url();
         let message = Message {
             chat_id: USER_ID,
-            text: HELP_TEXT,
+            text: "No requests made to reddit yet",
             ..Default::default()
         };
         let _m = mock_send_message_success(TOKEN, &message);
         let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+
+        rate_limit(&telegram_client, &reddit_client, USER_ID, USER_ID)
+            .await
+            .unwrap();
+        _m.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn prune_non_author() {
+        let url = &server_url();
+        let _m = mock_send_message_not_called(TOKEN);
+        let db_client = setup_test_db();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
 
-        help(&telegram_client, USER_ID).await.unwrap();
+        prune(&telegram_client, &db_client, USER_ID, OTHER_USER_ID)
+            .await
+            .unwrap();
         _m.assert();
     }
 }
@@ -1,4 +1,4 @@
-use log::info;
+use log::{error, info};
 use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
@@ -7,15 +7,42 @@ use strum_macros::{Display, EnumString};
 use crate::bot::dialogs::Dialog;
 use crate::bot::error::BotError;
 use crate::db::client::DbClient;
-use crate::telegram::client::TelegramClient;
-use crate::telegram::types::Message;
+use crate::db::models::FeedbackEntity;
+use crate::telegram::error::TelegramError;
+use crate::telegram::helpers::build_inline_keyboard_markup;
+use crate::telegram::messenger::Messenger;
+use crate::telegram::types::{InlineKeyboardButton, Message, ReplyMarkup};
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Display, EnumString)]
 pub enum Feedback {
     Start,
+    Category,
     Input,
 }
 
+impl DialogStep for Feedback {
+    type Options = ();
+}
+
+/// Callback data and label of the feedback categories, the label is what gets stored.
+const CATEGORIES: [(&str, &str); 3] = [
+    ("bug", "Bug"),
+    ("feature", "Feature request"),
+    ("other", "Other"),
+];
+
+/// Category used when none was picked, e.g. for dialogs started before categories existed.
+const DEFAULT_CATEGORY: &str = "Other";
+
+/// Label of the pressed category button, a typed label is accepted as well.
+fn parse_category(payload: &str) -> Option<&'static str> {
+    let payload = payload.trim().to_lowercase();
+    CATEGORIES
+        .iter()
+        .find(|(key, label)| *key == payload || label.to_lowercase() == payload)
+        .map(|(_, label)| *label)
+}
+
 impl Dialog<Feedback> {
     pub fn new(user_id: String) -> Self {
         Dialog {
@@ -23,20 +50,32 @@ impl Dialog<Feedback> {
             user_id,
             current_step: Feedback::Start,
             data: HashMap::new(),
+            options: (),
         }
     }
 
     pub async fn handle_current_step(
         &mut self,
-        telegram_client: &TelegramClient,
+        telegram_client: &impl Messenger,
         db: &DbClient,
         author_id: &str,
         payload: &str,
     ) -> Result<(), BotError> {
-        self.data.insert(self.current_step, payload.to_string());
+        if !self.store_payload(telegram_client, payload).await? {
+            return Ok(());
+        }
 
         match self.current_step {
             Feedback::Start => {
+                self.current_step = Feedback::Category;
+                db.insert_or_update_dialog(&self.clone().into())?;
+                self.send_categories(telegram_client).await?;
+            }
+            Feedback::Category => {
+                if parse_category(payload).is_none() {
+                    return self.send_categories(telegram_client).await;
+                }
+
                 self.current_step = Feedback::Input;
                 db.insert_or_update_dialog(&self.clone().into())?;
 
@@ -50,20 +89,33 @@ impl Dialog<Feedback> {
             }
             Feedback::Input => {
                 let input = self.data.get(&Feedback::Input).unwrap();
-                info!("received feedback from user({}): {}", &self.user_id, input);
+                let category = self
+                    .data
+                    .get(&Feedback::Category)
+                    .and_then(|category| parse_category(category))
+                    .unwrap_or(DEFAULT_CATEGORY);
+                info!(
+                    "received feedback from user({}) [{}]: {}",
+                    &self.user_id, category, input
+                );
 
-                telegram_client
-                    .send_message(&Message {
-                        chat_id: author_id,
-                        text: &format!("Received input from user({}):\n{}", &self.user_id, input),
-                        ..Default::default()
-                    })
-                    .await?;
+                // Saved first so it's not lost when the author can't be reached.
+                let feedback = db.save_feedback(&self.user_id, category, input)?;
+                let text = match forward_feedback(telegram_client, author_id, &feedback).await {
+                    Ok(_) => {
+                        db.set_feedback_delivered(feedback.id)?;
+                        "Passed your feedback to my creator. Thanks for the input!"
+                    }
+                    Err(err) => {
+                        error!("failed to forward feedback({}): {}", feedback.id, err);
+                        "Saved your feedback; the author will see it soon."
+                    }
+                };
 
                 telegram_client
                     .send_message(&Message {
                         chat_id: &self.user_id,
-                        text: "Passed your feedback to my creator. Thanks for the input!",
+                        text,
                         ..Default::default()
                     })
                     .await?;
@@ -72,4 +124,290 @@ impl Dialog<Feedback> {
         }
         Ok(())
     }
+
+    async fn send_categories(&self, telegram_client: &impl Messenger) -> Result<(), BotError> {
+        let buttons = CATEGORIES
+            .iter()
+            .map(|(key, label)| self.button(label, key))
+            .collect::<Vec<InlineKeyboardButton>>();
+
+        let markup = build_inline_keyboard_markup(buttons, 1);
+
+        telegram_client
+            .send_message(&Message {
+                chat_id: &self.user_id,
+                text: "What is your feedback about?",
+                reply_markup: Some(&ReplyMarkup::InlineKeyboardMarkup(markup)),
+                ..Default::default()
+            })
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Retries feedback that couldn't be forwarded to the author, stops at the first failure.
+pub async fn deliver_pending_feedback(
+    telegram_client: &impl Messenger,
+    db: &DbClient,
+    author_id: &str,
+) -> Result<(), BotError> {
+    for feedback in db.get_undelivered_feedback()? {
+        forward_feedback(telegram_client, author_id, &feedback).await?;
+        db.set_feedback_delivered(feedback.id)?;
+    }
+    Ok(())
+}
+
+async fn forward_feedback(
+    telegram_client: &impl Messenger,
+    author_id: &str,
+    feedback: &FeedbackEntity,
+) -> Result<(), TelegramError> {
+    telegram_client
+        .send_message(&Message {
+            chat_id: author_id,
+            text: &format!(
+                "Received input from user({}) [{}]:\n{}",
+                feedback.user_id, feedback.category, feedback.text
+            ),
+            ..Default::default()
+        })
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::{mock, server_url, Matcher};
+    use serde_json::json;
+    use serial_test::serial;
+
+    use crate::bot::dialogs::feedback::{deliver_pending_feedback, parse_category};
+    use crate::bot::dialogs::{Dialog, DialogStep, Feedback, MAX_PAYLOAD_LENGTH};
+    use crate::db::test_helpers::setup_test_db;
+    use crate::telegram::client::TelegramClient;
+    use crate::telegram::test_helpers::mock_send_message_success;
+    use crate::telegram::types::Message;
+
+    const TOKEN: &str = "token";
+    const USER_ID: &str = "123";
+    const AUTHOR_ID: &str = "999";
+
+    fn input_dialog() -> Dialog<Feedback> {
+        let mut dialog = Dialog::<Feedback>::new(USER_ID.to_string());
+        dialog.current_step = Feedback::Input;
+        dialog
+    }
+
+    #[test]
+    fn test_parse_category() {
+        assert_eq!(parse_category("bug"), Some("Bug"));
+        assert_eq!(parse_category("Feature request"), Some("Feature request"));
+        assert_eq!(parse_category(" OTHER "), Some("Other"));
+        assert_eq!(parse_category("complaint"), None);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn category_then_input() {
+        let url = &server_url();
+        let _m = mock_send_message_success(
+            TOKEN,
+            &Message {
+                chat_id: USER_ID,
+                text: "You can write your feedback. If you want the author to get back to you, leave your email.",
+                ..Default::default()
+            },
+        );
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        let mut dialog = Dialog::<Feedback>::new(USER_ID.to_string());
+        dialog.current_step = Feedback::Category;
+        dialog
+            .handle_current_step(&telegram_client, &db_client, AUTHOR_ID, "bug")
+            .await
+            .unwrap();
+        _m.assert();
+        assert_eq!(dialog.current_step, Feedback::Input);
+        assert_eq!(db_client.get_users_dialog(USER_ID).unwrap().step, "Input");
+
+        let _m = mock_send_message_success(
+            TOKEN,
+            &Message {
+                chat_id: AUTHOR_ID,
+                text: "Received input from user(123) [Bug]:\nit crashes",
+                ..Default::default()
+            },
+        );
+        let _m2 = mock_send_message_success(
+            TOKEN,
+            &Message {
+                chat_id: USER_ID,
+                text: "Passed your feedback to my creator. Thanks for the input!",
+                ..Default::default()
+            },
+        );
+        dialog
+            .handle_current_step(&telegram_client, &db_client, AUTHOR_ID, "it crashes")
+            .await
+            .unwrap();
+        _m.assert();
+        _m2.assert();
+        assert!(db_client.get_users_dialog(USER_ID).is_err());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn category_unknown() {
+        let url = &server_url();
+        let _m = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::Regex("What is your feedback about\\?".to_string()))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .expect(1)
+            .create();
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        let mut dialog = Dialog::<Feedback>::new(USER_ID.to_string());
+        dialog.current_step = Feedback::Category;
+        dialog
+            .handle_current_step(&telegram_client, &db_client, AUTHOR_ID, "complaint")
+            .await
+            .unwrap();
+        _m.assert();
+        assert_eq!(dialog.current_step, Feedback::Category);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn input_delivered() {
+        let url = &server_url();
+        let _m = mock_send_message_success(
+            TOKEN,
+            &Message {
+                chat_id: AUTHOR_ID,
+                text: "Received input from user(123) [Other]:\ngreat bot",
+                ..Default::default()
+            },
+        );
+        let _m2 = mock_send_message_success(
+            TOKEN,
+            &Message {
+                chat_id: USER_ID,
+                text: "Passed your feedback to my creator. Thanks for the input!",
+                ..Default::default()
+            },
+        );
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        input_dialog()
+            .handle_current_step(&telegram_client, &db_client, AUTHOR_ID, "great bot")
+            .await
+            .unwrap();
+        _m.assert();
+        _m2.assert();
+        assert!(db_client.get_undelivered_feedback().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn input_too_long() {
+        let url = &server_url();
+        let _m = mock_send_message_success(
+            TOKEN,
+            &Message {
+                chat_id: USER_ID,
+                text: "Your message is too long, please keep it to at most 4096 characters.",
+                ..Default::default()
+            },
+        );
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        let mut dialog = input_dialog();
+        dialog
+            .handle_current_step(
+                &telegram_client,
+                &db_client,
+                AUTHOR_ID,
+                &"a".repeat(MAX_PAYLOAD_LENGTH + 1),
+            )
+            .await
+            .unwrap();
+        _m.assert();
+        assert_eq!(dialog.current_step, Feedback::Input);
+        assert!(dialog.data.is_empty());
+        assert!(db_client.get_undelivered_feedback().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn input_saved_when_author_unreachable() {
+        let url = &server_url();
+        let _m = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::Json(json!(Message {
+                chat_id: AUTHOR_ID,
+                text: "Received input from user(123) [Other]:\ngreat bot",
+                ..Default::default()
+            })))
+            .with_status(403)
+            .with_body(r#"{"ok":false,"error_code":403,"description":"Forbidden: bot was blocked by the user"}"#)
+            .with_header("content-type", "application/json")
+            .expect(1)
+            .create();
+        let _m2 = mock_send_message_success(
+            TOKEN,
+            &Message {
+                chat_id: USER_ID,
+                text: "Saved your feedback; the author will see it soon.",
+                ..Default::default()
+            },
+        );
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        input_dialog()
+            .handle_current_step(&telegram_client, &db_client, AUTHOR_ID, "great bot")
+            .await
+            .unwrap();
+        _m.assert();
+        _m2.assert();
+        let result = db_client.get_undelivered_feedback().unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].text, "great bot");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn deliver_pending_feedback_success() {
+        let url = &server_url();
+        let _m = mock_send_message_success(
+            TOKEN,
+            &Message {
+                chat_id: AUTHOR_ID,
+                text: "Received input from user(123) [Other]:\ngreat bot",
+                ..Default::default()
+            },
+        );
+        let db_client = setup_test_db();
+        db_client
+            .save_feedback(USER_ID, "Other", "great bot")
+            .unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        deliver_pending_feedback(&telegram_client, &db_client, AUTHOR_ID)
+            .await
+            .unwrap();
+        _m.assert();
+        assert!(db_client.get_undelivered_feedback().unwrap().is_empty());
+    }
 }
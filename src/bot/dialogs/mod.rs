@@ -1,54 +1,173 @@
 use std::collections::HashMap;
+use std::convert::TryFrom;
 
+use log::warn;
 use serde::de::DeserializeOwned;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-use crate::db::models::DialogEntity;
+use crate::bot::error::{BotError, DialogError};
+use crate::db::models::{DialogEntity, Subscription};
+use crate::telegram::messenger::Messenger;
+use crate::telegram::types::{InlineKeyboardButton, Message};
 
-pub use self::feedback::Feedback;
-pub use self::subscribe::Subscribe;
+pub use self::feedback::{deliver_pending_feedback, Feedback};
+pub use self::reorder::Reorder;
+pub use self::stop::Stop;
+pub use self::subscribe::{
+    parse_quick_subscribe, parse_subreddit, Subscribe, QUICK_SUBSCRIBE_USAGE,
+};
 pub use self::unsubscribe::Unsubscribe;
 
 mod feedback;
+mod reorder;
+mod stop;
 mod subscribe;
 mod unsubscribe;
 
+/// Separates the dialog command, step and payload in callback data.
+const CALLBACK_SEPARATOR: char = ':';
+
+/// Payloads are stored with the dialog until it finishes, longer ones are rejected.
+pub const MAX_PAYLOAD_LENGTH: usize = 4096;
+
+/// Steps of a dialog, `Options` is what the dialog keeps besides the answers to its steps.
+pub trait DialogStep {
+    type Options: std::fmt::Debug + Clone + PartialEq + Serialize + DeserializeOwned;
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Dialog<T>
 where
-    T: std::hash::Hash + std::cmp::Eq,
+    T: std::hash::Hash + std::cmp::Eq + DialogStep,
 {
     pub command: String,
     pub user_id: String,
     pub current_step: T,
     pub data: HashMap<T, String>,
+    pub options: T::Options,
+}
+
+/// How a dialog is stored, the options are kept next to the answers in the same object.
+#[derive(Serialize, Deserialize)]
+struct DialogData<T: std::hash::Hash + std::cmp::Eq, O> {
+    #[serde(flatten)]
+    options: O,
+    #[serde(flatten)]
+    steps: HashMap<T, String>,
+}
+
+impl<T> Dialog<T>
+where
+    T: std::hash::Hash + std::cmp::Eq + DialogStep + std::string::ToString,
+{
+    /// Inline button tagged with the current step, so presses of buttons from earlier steps or
+    /// finished dialogs can be told apart. Build it after moving to the step expecting the answer.
+    pub fn button(&self, text: &str, payload: &str) -> InlineKeyboardButton {
+        InlineKeyboardButton {
+            text: text.to_string(),
+            callback_data: format!(
+                "{}{}{}{}{}",
+                self.command,
+                CALLBACK_SEPARATOR,
+                self.current_step.to_string(),
+                CALLBACK_SEPARATOR,
+                payload
+            ),
+        }
+    }
+}
+
+impl<T> Dialog<T>
+where
+    T: std::hash::Hash + std::cmp::Eq + DialogStep + Copy,
+{
+    /// Stores the payload of the current step. Payloads over `MAX_PAYLOAD_LENGTH` characters are
+    /// not stored and the user is asked for a shorter one, the dialog stays at the current step.
+    pub async fn store_payload(
+        &mut self,
+        telegram_client: &impl Messenger,
+        payload: &str,
+    ) -> Result<bool, BotError> {
+        if payload.chars().count() > MAX_PAYLOAD_LENGTH {
+            warn!(
+                "rejecting oversized dialog payload from user({}): {} characters",
+                self.user_id,
+                payload.chars().count()
+            );
+            telegram_client
+                .send_message(&Message {
+                    chat_id: &self.user_id,
+                    text: &format!(
+                        "Your message is too long, please keep it to at most {} characters.",
+                        MAX_PAYLOAD_LENGTH
+                    ),
+                    ..Default::default()
+                })
+                .await?;
+            return Ok(false);
+        }
+        self.data.insert(self.current_step, payload.to_string());
+        Ok(true)
+    }
+}
+
+/// Subscription picked from a keyboard. Buttons carry the subscription id since a multireddit name
+/// can push the callback data over Telegram's 64 bytes, a typed subreddit name is matched as well.
+pub fn find_subscription<'a>(
+    subscriptions: &'a [Subscription],
+    payload: &str,
+) -> Option<&'a Subscription> {
+    subscriptions
+        .iter()
+        .find(|subscription| subscription.id.to_string() == payload)
+        .or_else(|| {
+            subscriptions
+                .iter()
+                .find(|subscription| subscription.subreddit == payload)
+        })
 }
 
-impl<T> From<DialogEntity> for Dialog<T>
+/// Splits callback data built by `Dialog::button` into command, step and payload.
+pub fn parse_callback_data(data: &str) -> Option<(&str, &str, &str)> {
+    let mut parts = data.splitn(3, CALLBACK_SEPARATOR);
+    Some((parts.next()?, parts.next()?, parts.next()?))
+}
+
+impl<T> TryFrom<DialogEntity> for Dialog<T>
 where
-    T: std::hash::Hash + std::cmp::Eq + DeserializeOwned + std::str::FromStr,
-    <T as std::str::FromStr>::Err: std::fmt::Debug,
+    T: std::hash::Hash + std::cmp::Eq + DialogStep + DeserializeOwned + std::str::FromStr,
 {
-    fn from(dialog: DialogEntity) -> Self {
-        Dialog {
+    type Error = DialogError;
+
+    fn try_from(dialog: DialogEntity) -> Result<Self, Self::Error> {
+        let current_step =
+            T::from_str(&dialog.step).map_err(|_| DialogError::InvalidStep(dialog.step.clone()))?;
+        let data: DialogData<T, T::Options> =
+            serde_json::from_str(&dialog.data).map_err(DialogError::InvalidData)?;
+        Ok(Dialog {
             user_id: dialog.user_id,
             command: dialog.command,
-            current_step: T::from_str(&dialog.step).unwrap(),
-            data: serde_json::from_str(&dialog.data).unwrap(),
-        }
+            current_step,
+            data: data.steps,
+            options: data.options,
+        })
     }
 }
 
-impl<T> Into<DialogEntity> for Dialog<T>
+impl<T> From<Dialog<T>> for DialogEntity
 where
-    T: std::hash::Hash + std::cmp::Eq + Serialize + std::string::ToString,
+    T: std::hash::Hash + std::cmp::Eq + DialogStep + Serialize + std::string::ToString,
 {
-    fn into(self) -> DialogEntity {
+    fn from(dialog: Dialog<T>) -> Self {
+        let data = DialogData {
+            options: dialog.options,
+            steps: dialog.data,
+        };
         DialogEntity {
-            user_id: self.user_id.clone(),
-            command: self.command.clone(),
-            step: self.current_step.to_string(),
-            data: serde_json::to_string(&self.data).unwrap(),
+            user_id: dialog.user_id,
+            command: dialog.command,
+            step: dialog.current_step.to_string(),
+            data: serde_json::to_string(&data).unwrap(),
         }
     }
 }
@@ -57,6 +176,29 @@ where
 mod tests {
     use super::*;
 
+    #[test]
+    fn button_callback_data() {
+        let mut dialog = Dialog::<Subscribe>::new("123".to_string());
+        dialog.current_step = Subscribe::Weekday;
+        let button = dialog.button("Monday", "0");
+        assert_eq!(button.text, "Monday");
+        assert_eq!(button.callback_data, "/subscribe:Weekday:0");
+        assert_eq!(
+            parse_callback_data(&button.callback_data),
+            Some(("/subscribe", "Weekday", "0"))
+        );
+    }
+
+    #[test]
+    fn parse_callback_data_untagged() {
+        assert_eq!(parse_callback_data("rust"), None);
+        assert_eq!(parse_callback_data("/subscribe:Weekday"), None);
+        assert_eq!(
+            parse_callback_data("/unsubscribe:Subreddit:a:b"),
+            Some(("/unsubscribe", "Subreddit", "a:b"))
+        );
+    }
+
     #[test]
     fn conversion_works() {
         let dialog = Dialog::<Subscribe>::new("123".to_string());
@@ -72,7 +214,7 @@ mod tests {
                 data: "{}".to_string(),
             }
         );
-        let mut dialog_converted: Dialog<Subscribe> = command.into();
+        let mut dialog_converted = Dialog::<Subscribe>::try_from(command).unwrap();
         assert_eq!(dialog_converted, dialog);
 
         dialog_converted
@@ -91,7 +233,43 @@ mod tests {
             }
         );
 
-        let dialog_converted_again: Dialog<Subscribe> = command_converted.into();
+        let dialog_converted_again = Dialog::<Subscribe>::try_from(command_converted).unwrap();
         assert_eq!(dialog_converted_again, dialog_converted);
     }
+
+    #[test]
+    fn conversion_keeps_options() {
+        let mut dialog = Dialog::<Subscribe>::new("123".to_string());
+        dialog.data.insert(Subscribe::Subreddit, "rust".to_string());
+        dialog.options.post_limit = Some(5);
+        let entity: DialogEntity = dialog.clone().into();
+        assert_eq!(entity.data, r#"{"post_limit":5,"Subreddit":"rust"}"#);
+
+        let converted = Dialog::<Subscribe>::try_from(entity).unwrap();
+        assert_eq!(converted, dialog);
+    }
+
+    fn entity(step: &str, data: &str) -> DialogEntity {
+        DialogEntity {
+            user_id: "123".to_string(),
+            command: "/subscribe".to_string(),
+            step: step.to_string(),
+            data: data.to_string(),
+        }
+    }
+
+    #[test]
+    fn conversion_invalid_step() {
+        let result = Dialog::<Subscribe>::try_from(entity("Renamed", "{}"));
+        assert!(matches!(result, Err(DialogError::InvalidStep(step)) if step == "Renamed"));
+    }
+
+    #[test]
+    fn conversion_invalid_data() {
+        let result = Dialog::<Subscribe>::try_from(entity("Subreddit", "{not json"));
+        assert!(matches!(result, Err(DialogError::InvalidData(_))));
+
+        let result = Dialog::<Subscribe>::try_from(entity("Subreddit", r#"{"Renamed":"rust"}"#));
+        assert!(matches!(result, Err(DialogError::InvalidData(_))));
+    }
 }
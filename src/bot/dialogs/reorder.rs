@@ -0,0 +1,327 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumString};
+
+use crate::bot::dialogs::{find_subscription, Dialog, DialogStep};
+use crate::bot::error::BotError;
+use crate::db::client::DbClient;
+use crate::db::models::Subscription;
+use crate::telegram::helpers::build_inline_keyboard_markup;
+use crate::telegram::messenger::Messenger;
+use crate::telegram::types::{InlineKeyboardButton, Message, ReplyMarkup};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Display, EnumString)]
+pub enum Reorder {
+    Start,
+    Subreddit,
+    Move,
+}
+
+impl DialogStep for Reorder {
+    type Options = ();
+}
+
+impl Dialog<Reorder> {
+    pub fn new(user_id: String) -> Self {
+        Dialog {
+            command: "/reorder".to_string(),
+            user_id,
+            current_step: Reorder::Start,
+            data: HashMap::new(),
+            options: (),
+        }
+    }
+
+    pub async fn handle_current_step(
+        &mut self,
+        telegram_client: &impl Messenger,
+        db: &DbClient,
+        payload: &str,
+    ) -> Result<(), BotError> {
+        if !self.store_payload(telegram_client, payload).await? {
+            return Ok(());
+        }
+
+        match self.current_step {
+            Reorder::Start => {
+                let subscriptions = db.get_user_subscriptions(&self.user_id)?;
+                if subscriptions.len() < 2 {
+                    telegram_client
+                        .send_message(&Message {
+                            chat_id: &self.user_id,
+                            text: "You need at least two subscriptions to reorder them",
+                            ..Default::default()
+                        })
+                        .await?;
+                    return Ok(());
+                }
+
+                self.current_step = Reorder::Subreddit;
+                db.insert_or_update_dialog(&self.clone().into())?;
+
+                let buttons = subscriptions
+                    .iter()
+                    .map(|subscription| {
+                        self.button(&subscription.subreddit, &subscription.id.to_string())
+                    })
+                    .collect::<Vec<InlineKeyboardButton>>();
+
+                telegram_client
+                    .send_keyboard(&self.user_id, "Select subreddit to move", buttons, 2)
+                    .await?;
+            }
+            Reorder::Subreddit => {
+                let subscriptions = db.get_user_subscriptions(&self.user_id)?;
+                let id = match find_subscription(&subscriptions, payload) {
+                    Some(subscription) => subscription.id,
+                    None => {
+                        telegram_client
+                            .send_message(&Message {
+                                chat_id: &self.user_id,
+                                text: "Select one of the subreddits above",
+                                ..Default::default()
+                            })
+                            .await?;
+                        return Ok(());
+                    }
+                };
+
+                self.data.insert(Reorder::Subreddit, id.to_string());
+                self.current_step = Reorder::Move;
+                db.insert_or_update_dialog(&self.clone().into())?;
+                self.send_order(telegram_client, &subscriptions).await?;
+            }
+            Reorder::Move => {
+                if payload == "done" {
+                    db.delete_dialog(&self.user_id)?;
+                    telegram_client
+                        .send_message(&Message {
+                            chat_id: &self.user_id,
+                            text: "Saved the order of your subscriptions",
+                            ..Default::default()
+                        })
+                        .await?;
+                    return Ok(());
+                }
+
+                let mut subscriptions = db.get_user_subscriptions(&self.user_id)?;
+                let id = self.selected_id();
+                let index = subscriptions
+                    .iter()
+                    .position(|subscription| Some(subscription.id) == id);
+                let index = match index {
+                    Some(index) => index,
+                    None => {
+                        // Unsubscribed in the meantime.
+                        db.delete_dialog(&self.user_id)?;
+                        return Ok(());
+                    }
+                };
+
+                match payload {
+                    "up" if index > 0 => subscriptions.swap(index, index - 1),
+                    "down" if index + 1 < subscriptions.len() => {
+                        subscriptions.swap(index, index + 1)
+                    }
+                    "up" | "down" => {}
+                    _ => {
+                        telegram_client
+                            .send_message(&Message {
+                                chat_id: &self.user_id,
+                                text: "Use the buttons to move the subreddit up or down",
+                                ..Default::default()
+                            })
+                            .await?;
+                        return Ok(());
+                    }
+                }
+
+                let ids = subscriptions
+                    .iter()
+                    .map(|subscription| subscription.id)
+                    .collect::<Vec<i32>>();
+                db.update_sort_order(&self.user_id, &ids)?;
+                self.send_order(telegram_client, &subscriptions).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Id of the subscription being moved, None for dialogs saved while buttons carried names.
+    fn selected_id(&self) -> Option<i32> {
+        self.data
+            .get(&Reorder::Subreddit)
+            .and_then(|selected| selected.parse().ok())
+    }
+
+    async fn send_order(
+        &self,
+        telegram_client: &impl Messenger,
+        subscriptions: &[Subscription],
+    ) -> Result<(), BotError> {
+        let id = self.selected_id();
+        let subreddit = subscriptions
+            .iter()
+            .find(|subscription| Some(subscription.id) == id)
+            .map_or("", |subscription| subscription.subreddit.as_str());
+        let order = subscriptions
+            .iter()
+            .enumerate()
+            .map(|(index, subscription)| {
+                let marker = if Some(subscription.id) == id {
+                    " <"
+                } else {
+                    ""
+                };
+                format!("{}. {}{}\n", index + 1, subscription.subreddit, marker)
+            })
+            .collect::<String>();
+
+        let buttons = [("Up", "up"), ("Down", "down"), ("Done", "done")]
+            .iter()
+            .map(|(text, payload)| self.button(text, payload))
+            .collect::<Vec<InlineKeyboardButton>>();
+        let markup = build_inline_keyboard_markup(buttons, 3);
+
+        telegram_client
+            .send_message(&Message {
+                chat_id: &self.user_id,
+                text: &format!("Moving {}\n\n{}", subreddit, order),
+                reply_markup: Some(&ReplyMarkup::InlineKeyboardMarkup(markup)),
+                ..Default::default()
+            })
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::{mock, server_url, Matcher};
+    use serial_test::serial;
+
+    use crate::bot::dialogs::{Dialog, Reorder};
+    use crate::db::test_helpers::setup_test_db;
+    use crate::telegram::client::TelegramClient;
+    use crate::telegram::test_helpers::mock_send_message_success;
+    use crate::telegram::types::Message;
+
+    const TOKEN: &str = "token";
+    const USER_ID: &str = "123";
+
+    fn move_dialog(id: i32) -> Dialog<Reorder> {
+        let mut dialog = Dialog::<Reorder>::new(USER_ID.to_string());
+        dialog.current_step = Reorder::Move;
+        dialog.data.insert(Reorder::Subreddit, id.to_string());
+        dialog
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn move_up_persists_order() {
+        let url = &server_url();
+        let _m = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::Regex(
+                r"Moving zig\\n\\n1. golang\\n2. zig <\\n3. rust\\n".to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .expect(1)
+            .create();
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        db_client.subscribe(USER_ID, "golang", 0, 12).unwrap();
+        let zig = db_client.subscribe(USER_ID, "zig", 0, 12).unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        move_dialog(zig.id)
+            .handle_current_step(&telegram_client, &db_client, "up")
+            .await
+            .unwrap();
+        _m.assert();
+
+        let subreddits = db_client
+            .get_user_subscriptions(USER_ID)
+            .unwrap()
+            .into_iter()
+            .map(|subscription| subscription.subreddit)
+            .collect::<Vec<String>>();
+        assert_eq!(subreddits, ["golang", "zig", "rust"]);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn done_clears_dialog() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Saved the order of your subscriptions",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let rust = db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        let dialog = move_dialog(rust.id);
+        db_client
+            .insert_or_update_dialog(&dialog.clone().into())
+            .unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        dialog
+            .clone()
+            .handle_current_step(&telegram_client, &db_client, "done")
+            .await
+            .unwrap();
+        _m.assert();
+        assert!(db_client.get_users_dialog(USER_ID).is_err());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn long_multireddit_selected_by_id() {
+        let url = &server_url();
+        let multireddit = "user/a_rather_long_username/m/an_even_longer_multireddit_name";
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        let subscription = db_client.subscribe(USER_ID, multireddit, 0, 12).unwrap();
+        let callback_data = format!("/reorder:Subreddit:{}", subscription.id);
+        let _m = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::Regex(format!(
+                r#""text":"{}","callback_data":"{}""#,
+                multireddit, callback_data
+            )))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .expect(1)
+            .create();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        let mut dialog = Dialog::<Reorder>::new(USER_ID.to_string());
+        dialog
+            .handle_current_step(&telegram_client, &db_client, "/reorder")
+            .await
+            .unwrap();
+        _m.assert();
+        assert!(callback_data.len() <= 64);
+
+        let _m = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::Regex(format!(
+                r"Moving {}\\n\\n1. rust\\n2. {} <\\n",
+                multireddit, multireddit
+            )))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":692}}"#)
+            .expect(1)
+            .create();
+        dialog
+            .handle_current_step(&telegram_client, &db_client, &subscription.id.to_string())
+            .await
+            .unwrap();
+        _m.assert();
+        assert_eq!(dialog.current_step, Reorder::Move);
+    }
+}
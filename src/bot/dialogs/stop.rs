@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumString};
+
+use crate::bot::dialogs::Dialog;
+use crate::bot::error::BotError;
+use crate::db::client::DbClient;
+use crate::telegram::helpers::build_inline_keyboard_markup;
+use crate::telegram::messenger::Messenger;
+use crate::telegram::types::{Message, ReplyMarkup};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Display, EnumString)]
+pub enum Stop {
+    Start,
+    Confirm,
+}
+
+impl DialogStep for Stop {
+    type Options = ();
+}
+
+impl Dialog<Stop> {
+    pub fn new(user_id: String) -> Self {
+        Dialog {
+            command: "/stop".to_string(),
+            user_id,
+            current_step: Stop::Start,
+            data: HashMap::new(),
+            options: (),
+        }
+    }
+
+    pub async fn handle_current_step(
+        &mut self,
+        telegram_client: &impl Messenger,
+        db: &DbClient,
+        payload: &str,
+    ) -> Result<(), BotError> {
+        if !self.store_payload(telegram_client, payload).await? {
+            return Ok(());
+        }
+
+        match self.current_step {
+            Stop::Start => {
+                self.current_step = Stop::Confirm;
+                db.insert_or_update_dialog(&self.clone().into())?;
+
+                let buttons = vec![
+                    self.button("Yes, delete everything", "yes"),
+                    self.button("Cancel", "cancel"),
+                ];
+                let markup = build_inline_keyboard_markup(buttons, 1);
+
+                telegram_client
+                    .send_message(&Message {
+                        chat_id: &self.user_id,
+                        text: "This will delete your user and all subscriptions. Are you sure?",
+                        reply_markup: Some(&ReplyMarkup::InlineKeyboardMarkup(markup)),
+                        ..Default::default()
+                    })
+                    .await?;
+            }
+            Stop::Confirm => {
+                let text = match payload {
+                    // Deleting the user also deletes the dialog.
+                    "yes" => {
+                        db.delete_user(&self.user_id)?;
+                        "User and subscriptions deleted"
+                    }
+                    "cancel" => {
+                        db.delete_dialog(&self.user_id)?;
+                        "Nothing was deleted"
+                    }
+                    _ => "Use the buttons above to confirm or cancel",
+                };
+
+                telegram_client
+                    .send_message(&Message {
+                        chat_id: &self.user_id,
+                        text,
+                        ..Default::default()
+                    })
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::server_url;
+    use serial_test::serial;
+
+    use crate::bot::dialogs::{Dialog, DialogStep, Stop};
+    use crate::db::test_helpers::setup_test_db;
+    use crate::telegram::client::TelegramClient;
+    use crate::telegram::test_helpers::mock_send_message_success;
+    use crate::telegram::types::Message;
+
+    const TOKEN: &str = "token";
+    const USER_ID: &str = "123";
+
+    #[tokio::test]
+    #[serial]
+    async fn cancel_keeps_data() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Nothing was deleted",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        let mut dialog = Dialog::<Stop>::new(USER_ID.to_string());
+        dialog.current_step = Stop::Confirm;
+        db_client
+            .insert_or_update_dialog(&dialog.clone().into())
+            .unwrap();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        dialog
+            .handle_current_step(&telegram_client, &db_client, "cancel")
+            .await
+            .unwrap();
+        _m.assert();
+        assert!(db_client.get_users_dialog(USER_ID).is_err());
+        assert!(db_client.user_exists(USER_ID).unwrap());
+        assert_eq!(db_client.get_user_subscriptions(USER_ID).unwrap().len(), 1);
+    }
+}
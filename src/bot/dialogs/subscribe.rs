@@ -1,45 +1,251 @@
 use std::collections::HashMap;
 
 use chrono::Weekday;
-use diesel::result::DatabaseErrorKind;
-use diesel::result::Error::DatabaseError;
+use futures::stream::{self, StreamExt};
 use log::error;
 use num::traits::FromPrimitive;
-use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::iter::FromIterator;
 use strum_macros::{Display, EnumString};
 
-use crate::bot::dialogs::Dialog;
+use crate::bot::commands::BLOCKED_SUBREDDIT_TEXT;
+use crate::bot::dialogs::{Dialog, DialogStep};
 use crate::bot::error::BotError;
-use crate::db::client::DbClient;
-use crate::reddit::client::RedditClient;
-use crate::telegram::client::TelegramClient;
+use crate::db::client::{subscription_limit_text, DbClient, DEFAULT_POST_LIMIT, MAX_SUBSCRIPTIONS};
+use crate::db::models::{send_on_index, NewSubscriptionSpec, SubscribeOptions};
+use crate::reddit::client::{Listing, RedditClient, SORTS, TIMEFRAMES};
 use crate::telegram::helpers::build_inline_keyboard_markup;
+use crate::telegram::messenger::Messenger;
 use crate::telegram::types::{InlineKeyboardButton, Message, ReplyMarkup};
 
+/// Subreddits validated against reddit at the same time.
+const VALIDATION_CONCURRENCY: usize = 5;
+
+/// Closest matches offered for a subreddit that doesn't exist.
+const SUGGESTION_LIMIT: u32 = 5;
+/// Telegram rejects buttons with longer callback data.
+const MAX_CALLBACK_DATA_LENGTH: usize = 64;
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize, Display, EnumString)]
 pub enum Subscribe {
     Start,
     Subreddit,
     Weekday,
+    TimePreset,
     Time,
+    /// Summary of the subscription, nothing is saved until it's confirmed.
+    Confirm,
+}
+
+impl DialogStep for Subscribe {
+    type Options = QuickOptions;
+}
+
+/// Post limit and listing given with the quick syntax, they are not asked for in the dialog.
+/// Existing subscriptions keep their listing and new ones get the instance defaults otherwise.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct QuickOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub post_limit: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeframe: Option<String>,
+}
+
+const WEEKDAYS: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+/// `send_on` of a pressed weekday button, a typed day name is accepted as well.
+fn parse_weekday(payload: &str) -> Option<i32> {
+    let payload = payload.trim();
+    match payload.parse::<i32>() {
+        Ok(send_on) if (0..7).contains(&send_on) => Some(send_on),
+        Ok(_) => None,
+        Err(_) => payload.parse::<Weekday>().ok().map(send_on_index),
+    }
+}
+
+/// `send_at` of a pressed hour button or a typed hour.
+fn parse_hour(payload: &str) -> Option<i32> {
+    match payload.trim().parse::<i32>() {
+        Ok(send_at) if (0..24).contains(&send_at) => Some(send_at),
+        _ => None,
+    }
+}
+
+/// Callback data, button label and `send_at` hour of the time presets.
+const TIME_PRESETS: [(&str, &str, i32); 3] = [
+    ("morning", "Morning (8:00)", 8),
+    ("afternoon", "Afternoon (13:00)", 13),
+    ("evening", "Evening (19:00)", 19),
+];
+
+/// Full English name of the weekday, chrono only displays the abbreviation.
+fn weekday_name(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Mon => "Monday",
+        Weekday::Tue => "Tuesday",
+        Weekday::Wed => "Wednesday",
+        Weekday::Thu => "Thursday",
+        Weekday::Fri => "Friday",
+        Weekday::Sat => "Saturday",
+        Weekday::Sun => "Sunday",
+    }
+}
+
+/// Hour of the chosen time preset, None for "custom" or anything else unknown.
+fn preset_hour(payload: &str) -> Option<i32> {
+    let payload = payload.trim().to_lowercase();
+    TIME_PRESETS
+        .iter()
+        .find(|(preset, _, _)| *preset == payload)
+        .map(|(_, _, hour)| *hour)
+}
+
+/// Normalized "user/<name>/m/<multi>" token of a multireddit path or url, e.g.
+/// "https://reddit.com/user/foo/m/news".
+fn parse_multireddit(token: &str) -> Option<String> {
+    let path = token
+        .strip_prefix("https://")
+        .or_else(|| token.strip_prefix("http://"))
+        .unwrap_or(token);
+    let path = ["www.", "old.", "new."]
+        .iter()
+        .find_map(|host| path.strip_prefix(host))
+        .unwrap_or(path);
+    let path = path.strip_prefix("reddit.com").unwrap_or(path);
+    let path = path.strip_prefix('/').unwrap_or(path);
+    let path = path.strip_suffix('/').unwrap_or(path);
+
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    match path.split('/').collect::<Vec<&str>>().as_slice() {
+        [prefix, user, "m", multi]
+            if (*prefix == "u" || *prefix == "user")
+                && !user.is_empty()
+                && user.chars().all(|c| is_word(c) || c == '-')
+                && !multi.is_empty()
+                && multi.chars().all(is_word) =>
+        {
+            Some(format!("user/{}/m/{}", user, multi))
+        }
+        _ => None,
+    }
+}
+
+/// Normalized subreddit of a token, without the "r/" prefix and lowercased, or a multireddit path.
+pub fn parse_subreddit(token: &str) -> Option<String> {
+    let subreddit =
+        parse_multireddit(token).unwrap_or_else(|| token.replace("r/", "").to_lowercase());
+    if subreddit.is_empty() {
+        None
+    } else {
+        Some(subreddit)
+    }
 }
 
 fn parse_subreddits(subreddits: &str) -> Vec<String> {
-    let result = subreddits
-        .replace("r/", "")
-        .replace("\n", " ")
-        .trim()
-        .to_string();
-    let re = Regex::new(r"\s\s+").unwrap();
-    let result = re.replace_all(&result, " ").to_string();
-    let mut result = Vec::from_iter(result.split(' ').map(String::from));
+    let mut result = subreddits
+        .split_whitespace()
+        .filter_map(parse_subreddit)
+        .collect::<Vec<String>>();
     result.sort();
     result.dedup();
     result
 }
 
+/// Arguments of the one-shot `/subscribe <subreddits> [top] [week] [limit] [day] [hour]` syntax.
+/// Steps that are not covered by the arguments are asked for interactively.
+#[derive(Debug, Default, PartialEq)]
+pub struct QuickSubscribe {
+    pub subreddits: Vec<String>,
+    pub post_limit: Option<i32>,
+    pub weekday: Option<i32>,
+    pub hour: Option<i32>,
+    pub sort: Option<String>,
+    pub timeframe: Option<String>,
+}
+
+pub const QUICK_SUBSCRIBE_USAGE: &str =
+    "Usage: /subscribe <subreddits> [sort] [timeframe] [1-100 posts] [day] [0-23 hour], e.g. /subscribe rust top week 10 sun 12";
+
+/// Parses the quick syntax. Subreddits come first, followed by the listing sort (`t` for top) and
+/// timeframe (`w` for week), a number before the day is the post limit and a number after it is the hour.
+pub fn parse_quick_subscribe(args: &str) -> Result<QuickSubscribe, String> {
+    let mut result = QuickSubscribe::default();
+    let mut subreddits = vec![];
+    let mut options_started = false;
+
+    for token in args.split_whitespace() {
+        // The first argument is always a subreddit, even if it looks like an option.
+        if subreddits.is_empty() {
+            subreddits.push(token);
+            continue;
+        }
+
+        let lowercase = token.to_lowercase();
+        let option = match lowercase.as_str() {
+            "t" => "top",
+            "w" => "week",
+            option => option,
+        };
+        if SORTS.contains(&option) {
+            if result.sort.is_some() {
+                return Err(format!("Sort is given more than once - {}", token));
+            }
+            result.sort = Some(option.to_string());
+            options_started = true;
+            continue;
+        }
+        if TIMEFRAMES.contains(&option) {
+            if result.timeframe.is_some() {
+                return Err(format!("Timeframe is given more than once - {}", token));
+            }
+            result.timeframe = Some(option.to_string());
+            options_started = true;
+            continue;
+        }
+
+        if let Ok(weekday) = lowercase.parse::<Weekday>() {
+            if result.weekday.is_some() {
+                return Err(format!("Day is given more than once - {}", token));
+            }
+            result.weekday = Some(send_on_index(weekday));
+            options_started = true;
+        } else if let Ok(number) = token.parse::<i32>() {
+            options_started = true;
+            if result.weekday.is_none() {
+                if result.post_limit.is_some() || !(1..=100).contains(&number) {
+                    return Err("Number of posts must be between 1 and 100.".to_string());
+                }
+                result.post_limit = Some(number);
+            } else {
+                if result.hour.is_some() || !(0..24).contains(&number) {
+                    return Err("Hour must be between 0 and 23.".to_string());
+                }
+                result.hour = Some(number);
+            }
+        } else if options_started {
+            return Err(format!("Unexpected argument - {}", token));
+        } else {
+            subreddits.push(token);
+        }
+    }
+
+    if subreddits.is_empty() {
+        return Err("Subreddit is missing".to_string());
+    }
+    result.subreddits = parse_subreddits(&subreddits.join(" "));
+
+    Ok(result)
+}
+
 impl Dialog<Subscribe> {
     pub fn new(user_id: String) -> Self {
         Dialog {
@@ -47,17 +253,33 @@ impl Dialog<Subscribe> {
             user_id: user_id.clone(),
             current_step: Subscribe::Start,
             data: HashMap::new(),
+            options: QuickOptions::default(),
         }
     }
 
+    /// Same dialog started with /once, the subscription is sent once and then removed.
+    pub fn new_once(user_id: String) -> Self {
+        Dialog {
+            command: "/once".to_string(),
+            ..Dialog::<Subscribe>::new(user_id)
+        }
+    }
+
+    fn is_once(&self) -> bool {
+        self.command == "/once"
+    }
+
     pub async fn handle_current_step(
         &mut self,
-        telegram_client: &TelegramClient,
+        telegram_client: &impl Messenger,
         db: &DbClient,
         reddit_client: &RedditClient,
         payload: &str,
+        listing: &Listing,
     ) -> Result<(), BotError> {
-        self.data.insert(self.current_step, payload.to_string());
+        if !self.store_payload(telegram_client, payload).await? {
+            return Ok(());
+        }
 
         match self.current_step {
             Subscribe::Start => {
@@ -72,137 +294,1221 @@ impl Dialog<Subscribe> {
                     .await?;
             }
             Subscribe::Subreddit => {
-                let subreddits = self.data.get(&Subscribe::Subreddit).unwrap();
-                let subreddits = parse_subreddits(subreddits);
-
-                for subreddit in subreddits {
-                    if !reddit_client.validate_subreddit(&subreddit).await {
-                        telegram_client
-                            .send_message(&Message {
-                                chat_id: &self.user_id,
-                                text: &format!("Invalid subreddit - {}, try again", subreddit),
-                                ..Default::default()
-                            })
-                            .await?;
-                        return Ok(());
-                    }
+                if self
+                    .validate_subreddits(telegram_client, db, reddit_client)
+                    .await?
+                {
+                    self.ask_weekday(telegram_client, db).await?;
+                }
+            }
+            Subscribe::Weekday => match parse_weekday(payload) {
+                Some(send_on) => {
+                    self.data.insert(Subscribe::Weekday, send_on.to_string());
+                    self.ask_time_preset(telegram_client, db).await?;
+                }
+                None => {
+                    telegram_client
+                        .send_message(&Message {
+                            chat_id: &self.user_id,
+                            text: "Select one of the days above",
+                            ..Default::default()
+                        })
+                        .await?;
+                }
+            },
+            Subscribe::TimePreset => match preset_hour(payload) {
+                Some(hour) => {
+                    self.data.insert(Subscribe::Time, hour.to_string());
+                    self.ask_confirm(telegram_client, db, listing).await?;
+                }
+                None => {
+                    self.ask_time(telegram_client, db).await?;
+                }
+            },
+            Subscribe::Time => match parse_hour(payload) {
+                Some(send_at) => {
+                    self.data.insert(Subscribe::Time, send_at.to_string());
+                    self.ask_confirm(telegram_client, db, listing).await?;
+                }
+                None => {
+                    telegram_client
+                        .send_message(&Message {
+                            chat_id: &self.user_id,
+                            text: "Select one of the hours above",
+                            ..Default::default()
+                        })
+                        .await?;
+                }
+            },
+            Subscribe::Confirm => match payload.trim().to_lowercase().as_str() {
+                "confirm" => {
+                    self.finish(telegram_client, db, listing).await?;
+                }
+                "cancel" => {
+                    db.delete_dialog(&self.user_id)?;
+                    telegram_client
+                        .send_message(&Message {
+                            chat_id: &self.user_id,
+                            text: "Cancelled, nothing was saved.",
+                            ..Default::default()
+                        })
+                        .await?;
                 }
+                _ => {
+                    telegram_client
+                        .send_message(&Message {
+                            chat_id: &self.user_id,
+                            text: "Select Confirm or Cancel above",
+                            ..Default::default()
+                        })
+                        .await?;
+                }
+            },
+        }
+        Ok(())
+    }
 
-                let buttons = (0..7)
-                    .map(|weekday| InlineKeyboardButton {
-                        text: format!("{}", Weekday::from_u8(weekday).unwrap()),
-                        callback_data: format!("{}", weekday).clone(),
-                    })
-                    .collect::<Vec<InlineKeyboardButton>>();
+    /// Starts the dialog from the quick syntax, skipping the steps covered by the arguments.
+    pub async fn handle_quick(
+        &mut self,
+        telegram_client: &impl Messenger,
+        db: &DbClient,
+        reddit_client: &RedditClient,
+        args: QuickSubscribe,
+        listing: &Listing,
+    ) -> Result<(), BotError> {
+        self.data.insert(Subscribe::Start, String::new());
+        self.data
+            .insert(Subscribe::Subreddit, args.subreddits.join(" "));
+        self.options = QuickOptions {
+            post_limit: args.post_limit,
+            sort: args.sort,
+            timeframe: args.timeframe,
+        };
+        self.current_step = Subscribe::Subreddit;
+        db.insert_or_update_dialog(&self.clone().into())?;
 
-                let markup = build_inline_keyboard_markup(buttons, 2);
+        if !self
+            .validate_subreddits(telegram_client, db, reddit_client)
+            .await?
+        {
+            return Ok(());
+        }
 
-                self.current_step = Subscribe::Weekday;
-                db.insert_or_update_dialog(&self.clone().into())?;
+        match (args.weekday, args.hour) {
+            (Some(weekday), Some(hour)) => {
+                self.data.insert(Subscribe::Weekday, weekday.to_string());
+                self.data.insert(Subscribe::Time, hour.to_string());
+                self.ask_confirm(telegram_client, db, listing).await
+            }
+            (Some(weekday), None) => {
+                self.data.insert(Subscribe::Weekday, weekday.to_string());
+                self.ask_time_preset(telegram_client, db).await
+            }
+            (None, _) => self.ask_weekday(telegram_client, db).await,
+        }
+    }
 
+    async fn validate_subreddits(
+        &self,
+        telegram_client: &impl Messenger,
+        db: &DbClient,
+        reddit_client: &RedditClient,
+    ) -> Result<bool, BotError> {
+        let subreddits = self.data.get(&Subscribe::Subreddit).unwrap();
+        let subreddits = parse_subreddits(subreddits);
+
+        if subreddits.is_empty() {
+            telegram_client
+                .send_message(&Message {
+                    chat_id: &self.user_id,
+                    text: "Please type at least one subreddit name.",
+                    ..Default::default()
+                })
+                .await?;
+            return Ok(false);
+        }
+
+        for subreddit in &subreddits {
+            if db.is_subreddit_blocked(subreddit)? {
                 telegram_client
                     .send_message(&Message {
                         chat_id: &self.user_id,
-                        text: "On which day do you want to receive the posts?",
-                        reply_markup: Some(&ReplyMarkup::InlineKeyboardMarkup(markup)),
+                        text: BLOCKED_SUBREDDIT_TEXT,
                         ..Default::default()
                     })
                     .await?;
+                return Ok(false);
             }
-            Subscribe::Weekday => {
-                let buttons = (0..24)
-                    .map(|hour| InlineKeyboardButton {
-                        text: format!("{}:00", hour),
-                        callback_data: format!("{}", hour),
-                    })
-                    .collect::<Vec<InlineKeyboardButton>>();
+        }
 
-                let markup = build_inline_keyboard_markup(buttons, 4);
+        let invalid = stream::iter(&subreddits)
+            .map(|subreddit| async move {
+                (subreddit, reddit_client.validate_subreddit(subreddit).await)
+            })
+            .buffered(VALIDATION_CONCURRENCY)
+            .filter_map(|(subreddit, is_valid)| async move {
+                if is_valid {
+                    None
+                } else {
+                    Some(subreddit.as_str())
+                }
+            })
+            .collect::<Vec<&str>>()
+            .await;
 
-                self.current_step = Subscribe::Time;
-                db.insert_or_update_dialog(&self.clone().into())?;
+        if invalid.is_empty() {
+            return Ok(true);
+        }
 
+        if let [invalid] = invalid.as_slice() {
+            let buttons = self
+                .suggestion_buttons(db, reddit_client, &subreddits, invalid)
+                .await?;
+            if !buttons.is_empty() {
+                let markup = build_inline_keyboard_markup(buttons, 1);
                 telegram_client
                     .send_message(&Message {
                         chat_id: &self.user_id,
-                        text: "At what time? (UTC)",
+                        text: &format!(
+                            "Invalid subreddit - {}, did you mean one of these?",
+                            invalid
+                        ),
                         reply_markup: Some(&ReplyMarkup::InlineKeyboardMarkup(markup)),
                         ..Default::default()
                     })
                     .await?;
+                return Ok(false);
+            }
+        }
+
+        let text = if invalid.len() == 1 {
+            format!("Invalid subreddit - {}, try again", invalid[0])
+        } else {
+            format!("Invalid subreddits - {}, try again", invalid.join(", "))
+        };
+        telegram_client
+            .send_message(&Message {
+                chat_id: &self.user_id,
+                text: &text,
+                ..Default::default()
+            })
+            .await?;
+
+        Ok(false)
+    }
+
+    /// Buttons answering the step again with `invalid` replaced by one of reddit's closest matches.
+    async fn suggestion_buttons(
+        &self,
+        db: &DbClient,
+        reddit_client: &RedditClient,
+        subreddits: &[String],
+        invalid: &str,
+    ) -> Result<Vec<InlineKeyboardButton>, BotError> {
+        let suggestions = match reddit_client
+            .search_subreddits(invalid, SUGGESTION_LIMIT)
+            .await
+        {
+            Ok(suggestions) => suggestions,
+            Err(err) => {
+                error!(
+                    "failed to search subreddits similar to {}: {}",
+                    invalid, err
+                );
+                return Ok(vec![]);
+            }
+        };
+
+        let mut buttons = vec![];
+        for suggestion in suggestions {
+            if suggestion.name.eq_ignore_ascii_case(invalid)
+                || db.is_subreddit_blocked(&suggestion.name)?
+            {
+                continue;
             }
-            Subscribe::Time => {
-                let subreddits = self.data.get(&Subscribe::Subreddit).unwrap();
-                let subreddits = parse_subreddits(subreddits);
-
-                let day = self
-                    .data
-                    .get(&Subscribe::Weekday)
-                    .unwrap()
-                    .parse::<i32>()
-                    .unwrap_or(0);
-                let time = self
-                    .data
-                    .get(&Subscribe::Time)
-                    .unwrap()
-                    .parse::<i32>()
-                    .unwrap_or(12);
-
-                for subreddit in &subreddits {
-                    match db.subscribe(&self.user_id, &subreddit, day, time) {
-                        Ok(_) => {
-                            telegram_client
-                                .send_message(&Message {
-                                    chat_id: &self.user_id,
-                                    text: &format!(
-                                        "Subscribed to: {}. Posts will be sent periodically on {} at around {}:00 UTC time.",
-                                        &subreddit, Weekday::from_i32(day).unwrap(), time
-                                    ),
-                                    ..Default::default()
-                                })
-                                .await?;
-                        }
-                        Err(err) => {
-                            error!("err: {}", err);
-                            if let DatabaseError(DatabaseErrorKind::UniqueViolation, _) = err {
-                                telegram_client
-                                    .send_message(&Message {
-                                        chat_id: &self.user_id,
-                                        text: &format!("Already subscribed to {}", &subreddit),
-                                        ..Default::default()
-                                    })
-                                    .await?;
-                            } else {
-                                telegram_client
-                                    .send_message(&Message {
-                                        chat_id: &self.user_id,
-                                        text: "Something went wrong",
-                                        ..Default::default()
-                                    })
-                                    .await?;
-                            }
-                        }
+            let payload = subreddits
+                .iter()
+                .map(|subreddit| {
+                    if subreddit == invalid {
+                        suggestion.name.as_str()
+                    } else {
+                        subreddit.as_str()
                     }
-                }
+                })
+                .collect::<Vec<&str>>()
+                .join(" ");
+            let button = self.button(&format!("r/{}", suggestion.name), &payload);
+            if button.callback_data.len() <= MAX_CALLBACK_DATA_LENGTH {
+                buttons.push(button);
+            }
+        }
+        Ok(buttons)
+    }
+
+    async fn ask_weekday(
+        &mut self,
+        telegram_client: &impl Messenger,
+        db: &DbClient,
+    ) -> Result<(), BotError> {
+        self.current_step = Subscribe::Weekday;
+        db.insert_or_update_dialog(&self.clone().into())?;
+
+        let buttons = WEEKDAYS
+            .iter()
+            .map(|weekday| self.button(&weekday.to_string(), &send_on_index(*weekday).to_string()))
+            .collect::<Vec<InlineKeyboardButton>>();
+
+        let markup = build_inline_keyboard_markup(buttons, 2);
+
+        telegram_client
+            .send_message(&Message {
+                chat_id: &self.user_id,
+                text: "On which day do you want to receive the posts?",
+                reply_markup: Some(&ReplyMarkup::InlineKeyboardMarkup(markup)),
+                ..Default::default()
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    async fn ask_time_preset(
+        &mut self,
+        telegram_client: &impl Messenger,
+        db: &DbClient,
+    ) -> Result<(), BotError> {
+        self.current_step = Subscribe::TimePreset;
+        db.insert_or_update_dialog(&self.clone().into())?;
+
+        let buttons = TIME_PRESETS
+            .iter()
+            .map(|(preset, label, _)| self.button(label, preset))
+            .chain(std::iter::once(self.button("Custom", "custom")))
+            .collect::<Vec<InlineKeyboardButton>>();
+
+        let markup = build_inline_keyboard_markup(buttons, 2);
+
+        telegram_client
+            .send_message(&Message {
+                chat_id: &self.user_id,
+                text: &format!(
+                    "At what time of the day? ({})",
+                    db.get_timezone(&self.user_id)?
+                ),
+                reply_markup: Some(&ReplyMarkup::InlineKeyboardMarkup(markup)),
+                ..Default::default()
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    async fn ask_time(
+        &mut self,
+        telegram_client: &impl Messenger,
+        db: &DbClient,
+    ) -> Result<(), BotError> {
+        self.current_step = Subscribe::Time;
+        db.insert_or_update_dialog(&self.clone().into())?;
+
+        let buttons = (0..24)
+            .map(|hour| self.button(&format!("{}:00", hour), &format!("{}", hour)))
+            .collect::<Vec<InlineKeyboardButton>>();
+
+        telegram_client
+            .send_keyboard(
+                &self.user_id,
+                &format!("At what time? ({})", db.get_timezone(&self.user_id)?),
+                buttons,
+                4,
+            )
+            .await?;
 
+        Ok(())
+    }
+
+    async fn ask_confirm(
+        &mut self,
+        telegram_client: &impl Messenger,
+        db: &DbClient,
+        listing: &Listing,
+    ) -> Result<(), BotError> {
+        self.current_step = Subscribe::Confirm;
+        db.insert_or_update_dialog(&self.clone().into())?;
+
+        let buttons = vec![
+            self.button("Confirm", "confirm"),
+            self.button("Cancel", "cancel"),
+        ];
+        let markup = build_inline_keyboard_markup(buttons, 2);
+
+        telegram_client
+            .send_message(&Message {
+                chat_id: &self.user_id,
+                text: &self.summary(db, listing)?,
+                reply_markup: Some(&ReplyMarkup::InlineKeyboardMarkup(markup)),
+                ..Default::default()
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// What is about to be saved, e.g. "r/rust, top/week, 10 posts, Sundays 12:00 UTC - Confirm?".
+    fn summary(&self, db: &DbClient, listing: &Listing) -> Result<String, BotError> {
+        let subreddits = parse_subreddits(self.data.get(&Subscribe::Subreddit).unwrap())
+            .iter()
+            .map(|subreddit| format!("r/{}", subreddit))
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        let (sort, timeframe) = self.listing(listing);
+
+        let post_limit = match self.options.post_limit {
+            Some(post_limit) => post_limit,
+            None => db
+                .get_default_post_limit(&self.user_id)?
+                .unwrap_or(DEFAULT_POST_LIMIT),
+        };
+
+        let (day, time) = self.schedule();
+        let weekday = weekday_name(Weekday::from_i32(day).unwrap());
+        let schedule = if self.is_once() {
+            format!("once on {}", weekday)
+        } else {
+            format!("{}s", weekday)
+        };
+
+        Ok(format!(
+            "{}, {}/{}, {} posts, {} {}:00 {} - Confirm?",
+            subreddits,
+            sort,
+            timeframe,
+            post_limit,
+            schedule,
+            time,
+            db.get_timezone(&self.user_id)?
+        ))
+    }
+
+    /// Sort and timeframe given with the quick syntax, the instance `listing` fills in the rest.
+    fn listing<'a>(&'a self, listing: &'a Listing) -> (&'a str, &'a str) {
+        let sort = self.options.sort.as_ref().unwrap_or(&listing.sort);
+        let timeframe = self
+            .options
+            .timeframe
+            .as_ref()
+            .unwrap_or(&listing.timeframe);
+        (sort, timeframe)
+    }
+
+    /// The `send_on` and `send_at` picked in the dialog.
+    fn schedule(&self) -> (i32, i32) {
+        let day = self
+            .data
+            .get(&Subscribe::Weekday)
+            .unwrap()
+            .parse::<i32>()
+            .unwrap_or(0);
+        let time = self
+            .data
+            .get(&Subscribe::Time)
+            .unwrap()
+            .parse::<i32>()
+            .unwrap_or(12);
+        (day, time)
+    }
+
+    async fn finish(
+        &self,
+        telegram_client: &impl Messenger,
+        db: &DbClient,
+        listing: &Listing,
+    ) -> Result<(), BotError> {
+        let subreddits = self.data.get(&Subscribe::Subreddit).unwrap();
+        let subreddits = parse_subreddits(subreddits);
+
+        let (day, time) = self.schedule();
+        let timezone = db.get_timezone(&self.user_id)?;
+
+        let specs = subreddits
+            .iter()
+            .map(|subreddit| NewSubscriptionSpec {
+                subreddit,
+                send_on: day,
+                send_at: time,
+            })
+            .collect::<Vec<NewSubscriptionSpec>>();
+
+        let once = self.is_once();
+        let options = SubscribeOptions {
+            post_limit: self.options.post_limit,
+            once,
+            listing: Some(self.listing(listing)),
+            listing_given: self.options.sort.is_some() || self.options.timeframe.is_some(),
+        };
+
+        // Subreddits that are already subscribed to only get their schedule updated and don't count
+        // towards the limit.
+        let existing = db.get_user_subscriptions(&self.user_id)?;
+        let added = subreddits
+            .iter()
+            .filter(|subreddit| !existing.iter().any(|s| &&s.subreddit == subreddit))
+            .count() as i64;
+        if existing.len() as i64 + added > MAX_SUBSCRIPTIONS {
+            telegram_client
+                .send_message(&Message {
+                    chat_id: &self.user_id,
+                    text: &subscription_limit_text(),
+                    ..Default::default()
+                })
+                .await?;
+            db.delete_dialog(&self.user_id)?;
+            return Ok(());
+        }
+
+        let subscriptions = match db.upsert_subscriptions(&self.user_id, &specs, &options) {
+            Ok(subscriptions) => subscriptions,
+            Err(err) => {
+                error!("err: {}", err);
                 telegram_client
                     .send_message(&Message {
                         chat_id: &self.user_id,
-                        text:
-                            "You can use /sendnow to get posts now from all of your subscriptions.",
+                        text: "Something went wrong, nothing was saved.",
                         ..Default::default()
                     })
                     .await?;
                 db.delete_dialog(&self.user_id)?;
+                return Ok(());
             }
+        };
+
+        for (subscription, created) in &subscriptions {
+            let weekday = Weekday::from_i32(day).unwrap();
+            let text = if !created && once && subscription.recurring {
+                format!(
+                    "Updated your schedule for {}. It's already a recurring subscription, posts will be sent on {} at around {}:00 {} time.",
+                    subscription.subreddit, weekday, time, timezone
+                )
+            } else if !created && once {
+                format!(
+                    "Updated your schedule for {}. Posts will be sent once on {} at around {}:00 {} time, then the subscription is removed.",
+                    subscription.subreddit, weekday, time, timezone
+                )
+            } else if !created {
+                format!(
+                    "Updated your schedule for {}. Posts will be sent on {} at around {}:00 {} time.",
+                    subscription.subreddit, weekday, time, timezone
+                )
+            } else if once {
+                format!(
+                    "Subscribed to: {}. Posts will be sent once on {} at around {}:00 {} time, then the subscription is removed.",
+                    subscription.subreddit, weekday, time, timezone
+                )
+            } else {
+                format!(
+                    "Subscribed to: {}. Posts will be sent periodically on {} at around {}:00 {} time.",
+                    subscription.subreddit, weekday, time, timezone
+                )
+            };
+            telegram_client
+                .send_message(&Message {
+                    chat_id: &self.user_id,
+                    text: &text,
+                    ..Default::default()
+                })
+                .await?;
         }
+
+        telegram_client
+            .send_message(&Message {
+                chat_id: &self.user_id,
+                text: "You can use /sendnow to get posts now from all of your subscriptions.",
+                ..Default::default()
+            })
+            .await?;
+        db.delete_dialog(&self.user_id)?;
+
         Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::bot::dialogs::subscribe::parse_subreddits;
+    use std::convert::TryFrom;
+
+    use chrono::{TimeZone, Utc, Weekday};
+    use mockito::{mock, server_url, Matcher};
+    use num::traits::FromPrimitive;
+    use serial_test::serial;
+
+    use crate::bot::commands::BLOCKED_SUBREDDIT_TEXT;
+    use crate::bot::dialogs::subscribe::{
+        parse_hour, parse_multireddit, parse_quick_subscribe, parse_subreddits, parse_weekday,
+        preset_hour, QuickSubscribe, WEEKDAYS,
+    };
+    use crate::bot::dialogs::{Dialog, Subscribe};
+    use crate::db::client::{subscription_limit_text, MAX_SUBSCRIPTIONS};
+    use crate::db::models::send_on_index;
+    use crate::db::test_helpers::setup_test_db;
+    use crate::reddit::client::{Listing, RedditClient};
+    use crate::reddit::test_helpers::mock_subreddit_search_success;
+    use crate::task::task::is_due;
+    use crate::telegram::client::TelegramClient;
+    use crate::telegram::test_helpers::{mock_send_message_success, RecordingMessenger};
+    use crate::telegram::types::Message;
+
+    const TOKEN: &str = "token";
+    const USER_ID: &str = "123";
+
+    fn time_preset_dialog() -> Dialog<Subscribe> {
+        let mut dialog = Dialog::<Subscribe>::new(USER_ID.to_string());
+        dialog.current_step = Subscribe::TimePreset;
+        dialog.data.insert(Subscribe::Subreddit, "rust".to_string());
+        dialog.data.insert(Subscribe::Weekday, "6".to_string());
+        dialog
+    }
+
+    #[test]
+    fn test_preset_hour() {
+        assert_eq!(preset_hour("morning"), Some(8));
+        assert_eq!(preset_hour("afternoon"), Some(13));
+        assert_eq!(preset_hour("Evening "), Some(19));
+        assert_eq!(preset_hour("custom"), None);
+        assert_eq!(preset_hour("noon"), None);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn time_preset() {
+        let url = &server_url();
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let reddit_client = RedditClient::new_with(url);
+        let messenger = RecordingMessenger::new();
+
+        let mut dialog = time_preset_dialog();
+        dialog
+            .handle_current_step(
+                &messenger,
+                &db_client,
+                &reddit_client,
+                "evening",
+                &Listing::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(dialog.current_step, Subscribe::Confirm);
+        assert_eq!(
+            messenger.texts(),
+            ["r/rust, top/week, 10 posts, Sundays 19:00 UTC - Confirm?"]
+        );
+        assert!(db_client
+            .get_user_subscriptions(USER_ID)
+            .unwrap()
+            .is_empty());
+
+        dialog
+            .handle_current_step(
+                &messenger,
+                &db_client,
+                &reddit_client,
+                "confirm",
+                &Listing::default(),
+            )
+            .await
+            .unwrap();
+        let texts = messenger.texts();
+        assert!(texts[1].contains("at around 19:00 UTC"));
+        assert!(texts[2].contains("/sendnow"));
+
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(subscriptions[0].send_on, 6);
+        assert_eq!(subscriptions[0].send_at, 19);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn time_preset_updates_existing() {
+        let url = &server_url();
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let existing = db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        db_client.set_listing(existing.id, "hot", "day").unwrap();
+        let reddit_client = RedditClient::new_with(url);
+        let messenger = RecordingMessenger::new();
+
+        let mut dialog = time_preset_dialog();
+        dialog
+            .handle_current_step(
+                &messenger,
+                &db_client,
+                &reddit_client,
+                "morning",
+                &Listing::default(),
+            )
+            .await
+            .unwrap();
+        dialog
+            .handle_current_step(
+                &messenger,
+                &db_client,
+                &reddit_client,
+                "confirm",
+                &Listing::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            messenger.texts()[1],
+            "Updated your schedule for rust. Posts will be sent on Sun at around 8:00 UTC time."
+        );
+
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(subscriptions.len(), 1);
+        assert_eq!(subscriptions[0].send_on, 6);
+        assert_eq!(subscriptions[0].send_at, 8);
+        assert_eq!(subscriptions[0].sort, "hot");
+        assert_eq!(subscriptions[0].timeframe, "day");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn time_preset_updates_existing_listing() {
+        let url = &server_url();
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let existing = db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        db_client.set_listing(existing.id, "hot", "day").unwrap();
+        let reddit_client = RedditClient::new_with(url);
+        let messenger = RecordingMessenger::new();
+
+        let mut dialog = time_preset_dialog();
+        dialog.options.sort = Some("top".to_string());
+        dialog.options.timeframe = Some("month".to_string());
+        dialog
+            .handle_current_step(
+                &messenger,
+                &db_client,
+                &reddit_client,
+                "morning",
+                &Listing::default(),
+            )
+            .await
+            .unwrap();
+        dialog
+            .handle_current_step(
+                &messenger,
+                &db_client,
+                &reddit_client,
+                "confirm",
+                &Listing::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(messenger.texts().len(), 3);
+
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(subscriptions[0].sort, "top");
+        assert_eq!(subscriptions[0].timeframe, "month");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn once_keeps_existing_recurring() {
+        let url = &server_url();
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        let reddit_client = RedditClient::new_with(url);
+        let messenger = RecordingMessenger::new();
+
+        let mut dialog = time_preset_dialog();
+        dialog.command = "/once".to_string();
+        dialog
+            .handle_current_step(
+                &messenger,
+                &db_client,
+                &reddit_client,
+                "morning",
+                &Listing::default(),
+            )
+            .await
+            .unwrap();
+        dialog
+            .handle_current_step(
+                &messenger,
+                &db_client,
+                &reddit_client,
+                "confirm",
+                &Listing::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            messenger.texts()[1],
+            "Updated your schedule for rust. It's already a recurring subscription, posts will be sent on Sun at around 8:00 UTC time."
+        );
+
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert!(subscriptions[0].recurring);
+        assert_eq!(subscriptions[0].send_at, 8);
+    }
+
+    #[test]
+    fn test_parse_weekday() {
+        assert_eq!(parse_weekday("0"), Some(0));
+        assert_eq!(parse_weekday("6"), Some(6));
+        assert_eq!(parse_weekday("Sunday"), Some(6));
+        assert_eq!(parse_weekday("mon"), Some(0));
+        assert_eq!(parse_weekday("7"), None);
+        assert_eq!(parse_weekday("-1"), None);
+        assert_eq!(parse_weekday("someday"), None);
+    }
+
+    #[test]
+    fn test_parse_hour() {
+        assert_eq!(parse_hour("0"), Some(0));
+        assert_eq!(parse_hour(" 23 "), Some(23));
+        assert_eq!(parse_hour("24"), None);
+        assert_eq!(parse_hour("99"), None);
+        assert_eq!(parse_hour("-1"), None);
+        assert_eq!(parse_hour("noon"), None);
+    }
+
+    #[test]
+    fn weekday_buttons_round_trip() {
+        for (i, weekday) in WEEKDAYS.iter().enumerate() {
+            assert_eq!(Weekday::from_u8(i as u8), Some(*weekday));
+            assert_eq!(Weekday::from_i32(i as i32), Some(*weekday));
+
+            // Button payload as stored by the weekday step and read back for the confirmation.
+            let send_on = parse_weekday(&send_on_index(*weekday).to_string()).unwrap();
+            assert_eq!(send_on, i as i32);
+            assert_eq!(
+                Weekday::from_i32(send_on).unwrap().to_string(),
+                weekday.to_string()
+            );
+        }
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn sunday_is_sent_on_sunday() {
+        let url = &server_url();
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let reddit_client = RedditClient::new_with(url);
+        let messenger = RecordingMessenger::new();
+        let mut dialog = Dialog::<Subscribe>::new(USER_ID.to_string());
+        dialog.current_step = Subscribe::Weekday;
+        dialog.data.insert(Subscribe::Subreddit, "rust".to_string());
+
+        for payload in &["Sunday", "evening", "confirm"] {
+            dialog
+                .handle_current_step(
+                    &messenger,
+                    &db_client,
+                    &reddit_client,
+                    payload,
+                    &Listing::default(),
+                )
+                .await
+                .unwrap();
+        }
+        assert!(messenger.texts()[2].contains("on Sun at around 19:00 UTC"));
+
+        let subscription = &db_client.get_user_subscriptions(USER_ID).unwrap()[0];
+        assert_eq!(subscription.weekday(), Some(Weekday::Sun));
+        // 2020-03-22 is a Sunday
+        assert!(is_due(
+            subscription,
+            Utc.with_ymd_and_hms(2020, 3, 22, 19, 0, 0).unwrap()
+        ));
+        assert!(!is_due(
+            subscription,
+            Utc.with_ymd_and_hms(2020, 3, 21, 19, 0, 0).unwrap()
+        ));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn subreddit_blank() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Please type at least one subreddit name.",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let _m2 = mock("GET", Matcher::Any).expect(0).create();
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let reddit_client = RedditClient::new_with(url);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let mut dialog = Dialog::<Subscribe>::new(USER_ID.to_string());
+        dialog.current_step = Subscribe::Subreddit;
+
+        dialog
+            .handle_current_step(
+                &telegram_client,
+                &db_client,
+                &reddit_client,
+                "  \n ",
+                &Listing::default(),
+            )
+            .await
+            .unwrap();
+        _m.assert();
+        _m2.assert();
+        assert!(db_client
+            .get_user_subscriptions(USER_ID)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn subreddit_suggestions() {
+        let url = &server_url();
+        let _m = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::Regex(
+                r#"Invalid subreddit - rsut, did you mean one of these\?.*"text":"r/rust","callback_data":"/subscribe:Subreddit:golang rust".*"text":"r/rustjerk""#.to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .expect(1)
+            .create();
+        let _m2 = mock("GET", "/r/golang").with_status(200).create();
+        let _m3 = mock("GET", "/r/rsut").with_status(404).create();
+        let _m4 = mock_subreddit_search_success("rsut", &["rsut", "rust", "rustjerk"]);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let reddit_client = RedditClient::new_with(url);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let mut dialog = Dialog::<Subscribe>::new(USER_ID.to_string());
+        dialog.current_step = Subscribe::Subreddit;
+
+        dialog
+            .handle_current_step(
+                &telegram_client,
+                &db_client,
+                &reddit_client,
+                "golang rsut",
+                &Listing::default(),
+            )
+            .await
+            .unwrap();
+        _m.assert();
+        _m4.assert();
+        assert_eq!(dialog.current_step, Subscribe::Subreddit);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn subreddit_blocked() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: BLOCKED_SUBREDDIT_TEXT,
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let _m2 = mock("GET", "/r/spam").expect(0).create();
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.block_subreddit("spam").unwrap();
+        let reddit_client = RedditClient::new_with(url);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let mut dialog = Dialog::<Subscribe>::new(USER_ID.to_string());
+        dialog.current_step = Subscribe::Subreddit;
+
+        dialog
+            .handle_current_step(
+                &telegram_client,
+                &db_client,
+                &reddit_client,
+                "rust Spam",
+                &Listing::default(),
+            )
+            .await
+            .unwrap();
+        _m.assert();
+        _m2.assert();
+        assert!(db_client.get_users_dialog(USER_ID).is_err());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn subreddit_not_blocked() {
+        let url = &server_url();
+        let _m = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::Regex(
+                "On which day do you want to receive the posts".to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .expect(1)
+            .create();
+        let _m2 = mock("GET", "/r/rust").with_status(200).create();
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.block_subreddit("spam").unwrap();
+        let reddit_client = RedditClient::new_with(url);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let mut dialog = Dialog::<Subscribe>::new(USER_ID.to_string());
+        dialog.current_step = Subscribe::Subreddit;
+
+        dialog
+            .handle_current_step(
+                &telegram_client,
+                &db_client,
+                &reddit_client,
+                "rust",
+                &Listing::default(),
+            )
+            .await
+            .unwrap();
+        _m.assert();
+        _m2.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn time_preset_limit_reached() {
+        let url = &server_url();
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let subreddits = (0..MAX_SUBSCRIPTIONS)
+            .map(|i| format!("seeded{}", i))
+            .collect::<Vec<String>>();
+        for subreddit in &subreddits {
+            db_client.subscribe(USER_ID, subreddit, 0, 12).unwrap();
+        }
+        let reddit_client = RedditClient::new_with(url);
+        let messenger = RecordingMessenger::new();
+
+        let mut dialog = time_preset_dialog();
+        dialog
+            .handle_current_step(
+                &messenger,
+                &db_client,
+                &reddit_client,
+                "morning",
+                &Listing::default(),
+            )
+            .await
+            .unwrap();
+        dialog
+            .handle_current_step(
+                &messenger,
+                &db_client,
+                &reddit_client,
+                "confirm",
+                &Listing::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(messenger.texts()[1], subscription_limit_text());
+
+        let count = db_client.get_subscription_count(USER_ID).unwrap();
+        assert_eq!(count, MAX_SUBSCRIPTIONS);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn time_preset_custom() {
+        let url = &server_url();
+        let _m = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::Regex(r"At what time\? \(UTC\)".to_string()))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .expect(1)
+            .create();
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let reddit_client = RedditClient::new_with(url);
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        let mut dialog = time_preset_dialog();
+        dialog
+            .handle_current_step(
+                &telegram_client,
+                &db_client,
+                &reddit_client,
+                "custom",
+                &Listing::default(),
+            )
+            .await
+            .unwrap();
+        _m.assert();
+        assert_eq!(dialog.current_step, Subscribe::Time);
+
+        // Hours outside of the day are asked for again.
+        let message = Message {
+            chat_id: USER_ID,
+            text: "Select one of the hours above",
+            ..Default::default()
+        };
+        let _m2 = mock_send_message_success(TOKEN, &message);
+        dialog
+            .handle_current_step(
+                &telegram_client,
+                &db_client,
+                &reddit_client,
+                "99",
+                &Listing::default(),
+            )
+            .await
+            .unwrap();
+        _m2.assert();
+        assert_eq!(dialog.current_step, Subscribe::Time);
+
+        let _m2 = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":692}}"#)
+            .expect(3)
+            .create();
+        dialog
+            .handle_current_step(
+                &telegram_client,
+                &db_client,
+                &reddit_client,
+                "7",
+                &Listing::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(dialog.current_step, Subscribe::Confirm);
+        dialog
+            .handle_current_step(
+                &telegram_client,
+                &db_client,
+                &reddit_client,
+                "confirm",
+                &Listing::default(),
+            )
+            .await
+            .unwrap();
+        _m2.assert();
+
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(subscriptions[0].send_at, 7);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn confirm_creates_subscription() {
+        let url = &server_url();
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let reddit_client = RedditClient::new_with(url);
+        let messenger = RecordingMessenger::new();
+        let mut dialog = time_preset_dialog();
+        dialog.command = "/once".to_string();
+        dialog
+            .data
+            .insert(Subscribe::Subreddit, "rust golang".to_string());
+        dialog.options = QuickOptions {
+            post_limit: Some(5),
+            sort: Some("hot".to_string()),
+            timeframe: Some("day".to_string()),
+        };
+
+        dialog
+            .handle_current_step(
+                &messenger,
+                &db_client,
+                &reddit_client,
+                "afternoon",
+                &Listing::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            messenger.texts(),
+            ["r/golang, r/rust, hot/day, 5 posts, once on Sunday 13:00 UTC - Confirm?"]
+        );
+        let stored =
+            Dialog::<Subscribe>::try_from(db_client.get_users_dialog(USER_ID).unwrap()).unwrap();
+        assert_eq!(stored.current_step, Subscribe::Confirm);
+        assert_eq!(stored.options, dialog.options);
+
+        // Anything but the buttons keeps the dialog waiting.
+        dialog
+            .handle_current_step(
+                &messenger,
+                &db_client,
+                &reddit_client,
+                "yes",
+                &Listing::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(messenger.texts()[1], "Select Confirm or Cancel above");
+        assert!(db_client
+            .get_user_subscriptions(USER_ID)
+            .unwrap()
+            .is_empty());
+
+        dialog
+            .handle_current_step(
+                &messenger,
+                &db_client,
+                &reddit_client,
+                "confirm",
+                &Listing::default(),
+            )
+            .await
+            .unwrap();
+        let subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(subscriptions.len(), 2);
+        assert_eq!(subscriptions[0].send_on, 6);
+        assert_eq!(subscriptions[0].send_at, 13);
+        assert_eq!(subscriptions[0].post_limit, 5);
+        assert!(db_client.get_users_dialog(USER_ID).is_err());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn cancel_removes_dialog() {
+        let url = &server_url();
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let reddit_client = RedditClient::new_with(url);
+        let messenger = RecordingMessenger::new();
+
+        let mut dialog = time_preset_dialog();
+        dialog
+            .handle_current_step(
+                &messenger,
+                &db_client,
+                &reddit_client,
+                "morning",
+                &Listing::default(),
+            )
+            .await
+            .unwrap();
+        assert!(db_client.get_users_dialog(USER_ID).is_ok());
+
+        dialog
+            .handle_current_step(
+                &messenger,
+                &db_client,
+                &reddit_client,
+                "cancel",
+                &Listing::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(messenger.texts()[1], "Cancelled, nothing was saved.");
+        assert!(db_client
+            .get_user_subscriptions(USER_ID)
+            .unwrap()
+            .is_empty());
+        assert!(db_client.get_users_dialog(USER_ID).is_err());
+    }
 
     #[test]
     fn test_parse_subreddits() {
@@ -222,6 +1528,9 @@ mod tests {
         let result = parse_subreddits(input);
         assert_eq!(result, ["aaa", "bbb", "ccc"]);
 
+        let result = parse_subreddits(" \n r/ ");
+        assert!(result.is_empty());
+
         let input = "aaa\nbbb\nccc\n";
         let result = parse_subreddits(input);
         assert_eq!(result, ["aaa", "bbb", "ccc"]);
@@ -233,5 +1542,138 @@ mod tests {
         let input = "\n\n  \n r/aaa\n\n r/bbb\n  bbb\n\n \n  r/ccc bbb\n \n";
         let result = parse_subreddits(input);
         assert_eq!(result, ["aaa", "bbb", "ccc"]);
+
+        let input = "r/Rust RUST golang";
+        let result = parse_subreddits(input);
+        assert_eq!(result, ["golang", "rust"]);
+    }
+
+    #[test]
+    fn test_parse_multireddit() {
+        let expected = Some("user/foo/m/news".to_string());
+        assert_eq!(
+            parse_multireddit("https://reddit.com/user/foo/m/news"),
+            expected
+        );
+        assert_eq!(
+            parse_multireddit("https://www.reddit.com/user/foo/m/news/"),
+            expected
+        );
+        assert_eq!(parse_multireddit("old.reddit.com/u/foo/m/news"), expected);
+        assert_eq!(parse_multireddit("reddit.com/user/foo/m/news"), expected);
+        assert_eq!(parse_multireddit("/user/foo/m/news"), expected);
+        assert_eq!(parse_multireddit("user/foo/m/news"), expected);
+        assert_eq!(parse_multireddit("rust"), None);
+        assert_eq!(parse_multireddit("https://reddit.com/r/rust"), None);
+        assert_eq!(
+            parse_multireddit("https://example.com/user/foo/m/news"),
+            None
+        );
+
+        let result = parse_subreddits("rust https://reddit.com/user/foo/m/news r/golang");
+        assert_eq!(result, ["golang", "rust", "user/foo/m/news"]);
+    }
+
+    #[test]
+    fn test_parse_quick_subscribe() {
+        let result = parse_quick_subscribe("rust top week 10 sun 12").unwrap();
+        assert_eq!(
+            result,
+            QuickSubscribe {
+                subreddits: vec!["rust".to_string()],
+                post_limit: Some(10),
+                weekday: Some(6),
+                hour: Some(12),
+                sort: Some("top".to_string()),
+                timeframe: Some("week".to_string()),
+            }
+        );
+
+        let result = parse_quick_subscribe("r/rust golang t w Monday 0").unwrap();
+        assert_eq!(
+            result,
+            QuickSubscribe {
+                subreddits: vec!["golang".to_string(), "rust".to_string()],
+                post_limit: None,
+                weekday: Some(0),
+                hour: Some(0),
+                sort: Some("top".to_string()),
+                timeframe: Some("week".to_string()),
+            }
+        );
+
+        let result = parse_quick_subscribe("rust new day").unwrap();
+        assert_eq!(result.sort.as_deref(), Some("new"));
+        assert_eq!(result.timeframe.as_deref(), Some("day"));
+
+        let result = parse_quick_subscribe("sun 5").unwrap();
+        assert_eq!(
+            result,
+            QuickSubscribe {
+                subreddits: vec!["sun".to_string()],
+                post_limit: Some(5),
+                weekday: None,
+                hour: None,
+                sort: None,
+                timeframe: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_quick_subscribe_partial() {
+        let result = parse_quick_subscribe("rust").unwrap();
+        assert_eq!(
+            result,
+            QuickSubscribe {
+                subreddits: vec!["rust".to_string()],
+                ..Default::default()
+            }
+        );
+
+        let result = parse_quick_subscribe("rust 25").unwrap();
+        assert_eq!(result.post_limit, Some(25));
+        assert_eq!(result.weekday, None);
+
+        let result = parse_quick_subscribe("rust wed").unwrap();
+        assert_eq!(result.post_limit, None);
+        assert_eq!(result.weekday, Some(2));
+        assert_eq!(result.hour, None);
+    }
+
+    #[test]
+    fn test_parse_quick_subscribe_invalid() {
+        assert_eq!(
+            parse_quick_subscribe(""),
+            Err("Subreddit is missing".to_string())
+        );
+        assert_eq!(
+            parse_quick_subscribe("rust top hot"),
+            Err("Sort is given more than once - hot".to_string())
+        );
+        assert_eq!(
+            parse_quick_subscribe("rust month all"),
+            Err("Timeframe is given more than once - all".to_string())
+        );
+        assert_eq!(
+            parse_quick_subscribe("rust 0"),
+            Err("Number of posts must be between 1 and 100.".to_string())
+        );
+        assert_eq!(
+            parse_quick_subscribe("rust 10 20"),
+            Err("Number of posts must be between 1 and 100.".to_string())
+        );
+        assert_eq!(
+            parse_quick_subscribe("rust sun 24"),
+            Err("Hour must be between 0 and 23.".to_string())
+        );
+        assert_eq!(
+            parse_quick_subscribe("rust sun mon"),
+            Err("Day is given more than once - mon".to_string())
+        );
+        assert_eq!(
+            parse_quick_subscribe("rust 10 golang"),
+            Err("Unexpected argument - golang".to_string())
+        );
     }
 }
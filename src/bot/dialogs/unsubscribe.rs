@@ -3,12 +3,11 @@ use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 use strum_macros::{Display, EnumString};
 
-use crate::bot::dialogs::Dialog;
+use crate::bot::dialogs::{find_subscription, Dialog, DialogStep};
 use crate::bot::error::BotError;
 use crate::db::client::DbClient;
-use crate::telegram::client::TelegramClient;
-use crate::telegram::helpers::build_inline_keyboard_markup;
-use crate::telegram::types::{InlineKeyboardButton, Message, ReplyMarkup};
+use crate::telegram::messenger::Messenger;
+use crate::telegram::types::{InlineKeyboardButton, Message};
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Display, EnumString)]
 pub enum Unsubscribe {
@@ -16,6 +15,10 @@ pub enum Unsubscribe {
     Subreddit,
 }
 
+impl DialogStep for Unsubscribe {
+    type Options = ();
+}
+
 impl Dialog<Unsubscribe> {
     pub fn new(user_id: String) -> Self {
         Dialog {
@@ -23,16 +26,19 @@ impl Dialog<Unsubscribe> {
             user_id,
             current_step: Unsubscribe::Start,
             data: HashMap::new(),
+            options: (),
         }
     }
 
     pub async fn handle_current_step(
         &mut self,
-        telegram_client: &TelegramClient,
+        telegram_client: &impl Messenger,
         db: &DbClient,
         payload: &str,
     ) -> Result<(), BotError> {
-        self.data.insert(self.current_step, payload.to_string());
+        if !self.store_payload(telegram_client, payload).await? {
+            return Ok(());
+        }
 
         match self.current_step {
             Unsubscribe::Start => {
@@ -48,36 +54,41 @@ impl Dialog<Unsubscribe> {
                         return Ok(());
                     }
 
+                    self.current_step = Unsubscribe::Subreddit;
+                    db.insert_or_update_dialog(&self.clone().into())?;
+
                     let buttons = res
                         .iter()
-                        .map(|subscription| InlineKeyboardButton {
-                            text: subscription.subreddit.clone(),
-                            callback_data: subscription.subreddit.clone(),
+                        .map(|subscription| {
+                            self.button(&subscription.subreddit, &subscription.id.to_string())
                         })
                         .collect::<Vec<InlineKeyboardButton>>();
 
-                    let markup = build_inline_keyboard_markup(buttons, 2);
-
-                    self.current_step = Unsubscribe::Subreddit;
-                    db.insert_or_update_dialog(&self.clone().into())?;
-
                     telegram_client
-                        .send_message(&Message {
-                            chat_id: &self.user_id,
-                            text: "Select subreddit",
-                            reply_markup: Some(&ReplyMarkup::InlineKeyboardMarkup(markup)),
-                            ..Default::default()
-                        })
+                        .send_keyboard(&self.user_id, "Select subreddit", buttons, 2)
                         .await?;
                 }
             }
             Unsubscribe::Subreddit => {
-                let subreddit = self.data.get(&Unsubscribe::Subreddit).unwrap();
-                if let Ok(_) = db.unsubscribe(&self.user_id, &subreddit) {
+                let subscriptions = db.get_user_subscriptions(&self.user_id)?;
+                let subreddit = match find_subscription(&subscriptions, payload) {
+                    Some(subscription) => &subscription.subreddit,
+                    None => {
+                        telegram_client
+                            .send_message(&Message {
+                                chat_id: &self.user_id,
+                                text: "Select one of the subreddits above",
+                                ..Default::default()
+                            })
+                            .await?;
+                        return Ok(());
+                    }
+                };
+                if db.unsubscribe(&self.user_id, subreddit).is_ok() {
                     telegram_client
                         .send_message(&Message {
                             chat_id: &self.user_id,
-                            text: &format!("Unsubscribed from: {}", &payload),
+                            text: &format!("Unsubscribed from: {}", subreddit),
                             ..Default::default()
                         })
                         .await?;
@@ -88,3 +99,72 @@ impl Dialog<Unsubscribe> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use mockito::{mock, server_url, Matcher};
+    use serial_test::serial;
+
+    use crate::bot::dialogs::{Dialog, Unsubscribe};
+    use crate::db::test_helpers::setup_test_db;
+    use crate::telegram::client::TelegramClient;
+    use crate::telegram::test_helpers::mock_send_message_success;
+    use crate::telegram::types::Message;
+
+    const TOKEN: &str = "token";
+    const USER_ID: &str = "123";
+
+    #[tokio::test]
+    #[serial]
+    async fn long_multireddit_selected_by_id() {
+        let url = &server_url();
+        let multireddit = "user/a_rather_long_username/m/an_even_longer_multireddit_name";
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        let subscription = db_client.subscribe(USER_ID, multireddit, 0, 12).unwrap();
+        let callback_data = format!("/unsubscribe:Subreddit:{}", subscription.id);
+        let _m = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::Regex(format!(
+                r#""text":"{}","callback_data":"{}""#,
+                multireddit, callback_data
+            )))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .expect(1)
+            .create();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+
+        let mut dialog = Dialog::<Unsubscribe>::new(USER_ID.to_string());
+        dialog
+            .handle_current_step(&telegram_client, &db_client, "/unsubscribe")
+            .await
+            .unwrap();
+        _m.assert();
+        assert!(callback_data.len() <= 64);
+
+        let text = format!("Unsubscribed from: {}", multireddit);
+        let _m = mock_send_message_success(
+            TOKEN,
+            &Message {
+                chat_id: USER_ID,
+                text: &text,
+                ..Default::default()
+            },
+        );
+        dialog
+            .handle_current_step(&telegram_client, &db_client, &subscription.id.to_string())
+            .await
+            .unwrap();
+        _m.assert();
+
+        let subreddits = db_client
+            .get_user_subscriptions(USER_ID)
+            .unwrap()
+            .into_iter()
+            .map(|subscription| subscription.subreddit)
+            .collect::<Vec<String>>();
+        assert_eq!(subreddits, ["rust"]);
+        assert!(db_client.get_users_dialog(USER_ID).is_err());
+    }
+}
@@ -1,8 +1,10 @@
 use std::error::Error;
 use std::fmt;
 use std::fmt::Formatter;
+use std::io::Error as IoError;
 
 use diesel::result::Error as DatabaseError;
+use diesel_migrations::RunMigrationsError;
 
 use crate::reddit::error::RedditError;
 use crate::telegram::error::TelegramError;
@@ -11,7 +13,29 @@ use crate::telegram::error::TelegramError;
 pub enum BotError {
     TelegramError(TelegramError),
     DatabaseError(DatabaseError),
+    MigrationError(RunMigrationsError),
     RedditError(RedditError),
+    IoError(IoError),
+    /// The bot can't run with the given settings, e.g. Telegram rejects the token.
+    Config(String),
+    /// Processing a subscription failed, with enough context to tell which one and how hard it was tried.
+    Subscription {
+        subreddit: String,
+        user_id: String,
+        attempts: u32,
+        source: Box<BotError>,
+    },
+}
+
+impl BotError {
+    pub fn subscription(subreddit: &str, user_id: &str, attempts: u32, source: BotError) -> Self {
+        BotError::Subscription {
+            subreddit: subreddit.to_string(),
+            user_id: user_id.to_string(),
+            attempts,
+            source: Box::new(source),
+        }
+    }
 }
 
 impl From<TelegramError> for BotError {
@@ -26,12 +50,24 @@ impl From<DatabaseError> for BotError {
     }
 }
 
+impl From<RunMigrationsError> for BotError {
+    fn from(error: RunMigrationsError) -> Self {
+        BotError::MigrationError(error)
+    }
+}
+
 impl From<RedditError> for BotError {
     fn from(error: RedditError) -> Self {
         BotError::RedditError(error)
     }
 }
 
+impl From<IoError> for BotError {
+    fn from(error: IoError) -> Self {
+        BotError::IoError(error)
+    }
+}
+
 impl Error for BotError {}
 
 impl fmt::Display for BotError {
@@ -39,7 +75,52 @@ impl fmt::Display for BotError {
         match self {
             BotError::TelegramError(err) => err.fmt(f),
             BotError::DatabaseError(err) => err.fmt(f),
+            BotError::MigrationError(err) => err.fmt(f),
             BotError::RedditError(err) => err.fmt(f),
+            BotError::IoError(err) => err.fmt(f),
+            BotError::Config(err) => write!(f, "invalid configuration: {}", err),
+            BotError::Subscription {
+                subreddit,
+                user_id,
+                attempts,
+                source,
+            } => write!(
+                f,
+                "{} for user {} failed after {} attempts: {}",
+                subreddit, user_id, attempts, source
+            ),
+        }
+    }
+}
+
+/// Stored dialog that no longer matches its type, e.g. after renaming a step.
+#[derive(Debug)]
+pub enum DialogError {
+    InvalidStep(String),
+    InvalidData(serde_json::Error),
+}
+
+impl Error for DialogError {}
+
+impl fmt::Display for DialogError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DialogError::InvalidStep(step) => write!(f, "invalid dialog step: {}", step),
+            DialogError::InvalidData(err) => write!(f, "invalid dialog data: {}", err),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subscription_error_display() {
+        let err = BotError::subscription("rust", "123", 3, DatabaseError::NotFound.into());
+        assert_eq!(
+            err.to_string(),
+            "rust for user 123 failed after 3 attempts: NotFound"
+        );
+    }
+}
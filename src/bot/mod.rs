@@ -1,4 +1,6 @@
+#[allow(clippy::module_inception)]
 pub mod bot;
 pub mod commands;
 pub mod dialogs;
 pub mod error;
+pub mod throttle;
@@ -0,0 +1,126 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// What to do with a command after checking it against the user's limit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verdict {
+    Allowed,
+    /// The first command over the limit, the user is told to slow down.
+    Throttled,
+    /// Further commands over the limit are dropped silently, so spam isn't answered with spam.
+    Ignored,
+}
+
+#[derive(Default)]
+struct UserCommands {
+    handled_at: VecDeque<Instant>,
+    warned: bool,
+}
+
+/// Per user limit of commands handled within a sliding window, kept in memory only.
+pub struct CommandThrottle {
+    max_commands: usize,
+    window: Duration,
+    users: Mutex<HashMap<String, UserCommands>>,
+}
+
+impl CommandThrottle {
+    /// A `max_commands` of 0 disables the limit.
+    pub fn new(max_commands: usize, window: Duration) -> Self {
+        CommandThrottle {
+            max_commands,
+            window,
+            users: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records a command of the user received at `now`, unless the user is over the limit.
+    pub fn check(&self, user_id: &str, now: Instant) -> Verdict {
+        if self.max_commands == 0 {
+            return Verdict::Allowed;
+        }
+
+        let mut users = self.users.lock().unwrap();
+        // Users that have gone quiet are forgotten, so the map doesn't grow with every user seen.
+        users.retain(|_, user| {
+            user.handled_at
+                .back()
+                .is_some_and(|last| now.duration_since(*last) < self.window)
+        });
+
+        let user = users.entry(user_id.to_string()).or_default();
+        while let Some(first) = user.handled_at.front() {
+            if now.duration_since(*first) < self.window {
+                break;
+            }
+            user.handled_at.pop_front();
+        }
+
+        if user.handled_at.len() < self.max_commands {
+            user.handled_at.push_back(now);
+            user.warned = false;
+            Verdict::Allowed
+        } else if !user.warned {
+            user.warned = true;
+            Verdict::Throttled
+        } else {
+            Verdict::Ignored
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn check_throttles_over_limit() {
+        let throttle = CommandThrottle::new(3, WINDOW);
+        let now = Instant::now();
+
+        assert_eq!(throttle.check("123", now), Verdict::Allowed);
+        assert_eq!(throttle.check("123", now), Verdict::Allowed);
+        assert_eq!(throttle.check("123", now), Verdict::Allowed);
+        assert_eq!(throttle.check("123", now), Verdict::Throttled);
+        assert_eq!(throttle.check("123", now), Verdict::Ignored);
+        // Other users have their own limit.
+        assert_eq!(throttle.check("456", now), Verdict::Allowed);
+    }
+
+    #[test]
+    fn check_allows_after_window() {
+        let throttle = CommandThrottle::new(2, WINDOW);
+        let now = Instant::now();
+
+        assert_eq!(throttle.check("123", now), Verdict::Allowed);
+        assert_eq!(
+            throttle.check("123", now + Duration::from_secs(30)),
+            Verdict::Allowed
+        );
+        assert_eq!(
+            throttle.check("123", now + Duration::from_secs(40)),
+            Verdict::Throttled
+        );
+        // The first command has left the window.
+        assert_eq!(
+            throttle.check("123", now + Duration::from_secs(60)),
+            Verdict::Allowed
+        );
+        assert_eq!(
+            throttle.check("123", now + Duration::from_secs(61)),
+            Verdict::Throttled
+        );
+    }
+
+    #[test]
+    fn check_disabled() {
+        let throttle = CommandThrottle::new(0, WINDOW);
+        let now = Instant::now();
+        for _ in 0..100 {
+            assert_eq!(throttle.check("123", now), Verdict::Allowed);
+        }
+    }
+}
@@ -1,24 +1,75 @@
-use chrono::Utc;
+use chrono::{DateTime, Duration, Utc};
 use diesel::prelude::*;
-use diesel::result::Error;
+use diesel::result::{ConnectionError, Error};
+use diesel_migrations::{setup_database, MigrationConnection, RunMigrationsError};
 use log::{error, info};
 
-use crate::db::models::DialogEntity;
+use crate::db::models::{
+    parse_timezone, BlockedSubreddit, Delivery, DialogEntity, FeedbackEntity, MigrationStatus,
+    NewDelivery, NewFeedback, NewPostSubscription, NewRemovedSubscription, NewSubscriptionSpec,
+    PostSubscription, RemovedSubscription, SentPost, SubscribeOptions, SubscriptionChanges,
+    SubscriptionSettings,
+};
 
 use super::models::{NewSubscription, Subscription, User};
 use super::schema;
 
+const REMOVED_SUBSCRIPTION_TTL_HOURS: i64 = 24;
+/// Watched threads are dropped after this many days, comments rarely come in after that.
+pub const POST_SUBSCRIPTION_TTL_DAYS: i64 = 7;
+pub const DEFAULT_POST_LIMIT: i32 = 10;
+pub const MAX_SUBSCRIPTIONS: i64 = 50;
+pub const DEFAULT_TIMEZONE: &str = "UTC";
+
+pub fn subscription_limit_text() -> String {
+    format!(
+        "You have reached the limit of {} subscriptions. Remove some with /unsubscribe first.",
+        MAX_SUBSCRIPTIONS
+    )
+}
+
+embed_migrations!();
+
 pub struct DbClient {
     pub conn: SqliteConnection,
 }
 
 impl DbClient {
+    /// Only connects, migrations are applied once at startup with `run_migrations`.
     pub fn new(url: &str) -> DbClient {
-        let conn = SqliteConnection::establish(url).expect(&format!("Error connecting to {}", url));
-        conn.execute("PRAGMA foreign_keys = ON")
-            .expect("Failed to enable foreign key support");
+        DbClient::connect(url).unwrap_or_else(|_| panic!("Error connecting to {}", url))
+    }
+
+    pub fn connect(url: &str) -> Result<DbClient, ConnectionError> {
+        let conn = SqliteConnection::establish(url)?;
+        conn.execute("PRAGMA foreign_keys = ON").map_err(|err| {
+            ConnectionError::BadConnection(format!("Failed to enable foreign key support: {}", err))
+        })?;
+
+        Ok(DbClient { conn })
+    }
 
-        DbClient { conn }
+    /// Applies the migrations embedded at build time that haven't run against this database yet.
+    pub fn run_migrations(&self) -> Result<MigrationStatus, RunMigrationsError> {
+        info!("running pending migrations");
+
+        let run = || -> Result<MigrationStatus, RunMigrationsError> {
+            setup_database(&self.conn)?;
+            let before = self.conn.previously_run_migration_versions()?;
+            embedded_migrations::run(&self.conn)?;
+            let after = self.conn.previously_run_migration_versions()?;
+            Ok(MigrationStatus {
+                applied: after.len() - before.len(),
+                version: self.conn.latest_run_migration_version()?,
+            })
+        };
+        match run() {
+            Ok(status) => Ok(status),
+            Err(err) => {
+                error!("failed to run migrations: {}", err);
+                Err(err)
+            }
+        }
     }
 
     pub fn create_user(&self, id: &str) -> Result<User, Error> {
@@ -28,6 +79,12 @@ impl DbClient {
         let new_user = User {
             id: id.to_string(),
             created_at: curr.to_rfc3339(),
+            default_post_limit: None,
+            timezone: DEFAULT_TIMEZONE.to_string(),
+            combined_digest: false,
+            disable_notification: false,
+            link_preview: false,
+            locale: None,
         };
 
         info!("creating new user: {:?}", new_user);
@@ -55,6 +112,17 @@ impl DbClient {
         }
     }
 
+    pub fn user_exists(&self, id: &str) -> Result<bool, Error> {
+        use schema::users::dsl;
+        match diesel::select(diesel::dsl::exists(dsl::users.find(id))).get_result(&self.conn) {
+            Ok(result) => Ok(result),
+            Err(err) => {
+                error!("failed to check if user exists: {}", err);
+                Err(err)
+            }
+        }
+    }
+
     #[allow(dead_code)]
     pub fn get_users(&self) -> Result<Vec<User>, Error> {
         use schema::users::dsl;
@@ -74,29 +142,18 @@ impl DbClient {
         send_on: i32,
         send_at: i32,
     ) -> Result<Subscription, Error> {
-        use schema::users_subscriptions::dsl;
-
         info!(
             "subscribing user_id: {}, subreddit: {}, send_on: {}, send_at: {} ",
             user_id, subreddit, send_on, send_at
         );
 
-        let new_subscription = NewSubscription {
-            user_id,
+        let spec = NewSubscriptionSpec {
             subreddit,
             send_on,
             send_at,
-            last_sent_at: Some(Utc::now().to_rfc3339()),
         };
-
         match self.conn.transaction::<_, Error, _>(|| {
-            diesel::insert_into(dsl::users_subscriptions)
-                .values(&new_subscription)
-                .execute(&self.conn)?;
-
-            dsl::users_subscriptions
-                .order(dsl::id.desc())
-                .first::<Subscription>(&self.conn)
+            self.insert_subscription(user_id, &spec, &SubscribeOptions::default())
         }) {
             Ok(subscription) => Ok(subscription),
             Err(err) => {
@@ -106,229 +163,2792 @@ impl DbClient {
         }
     }
 
-    pub fn update_last_sent(&self, id: i32) -> Result<(), Error> {
+    /// Creates the subscription, or moves the existing subscription to the same subreddit to the
+    /// new schedule. `options` apply to both, except that /once doesn't make an existing recurring
+    /// subscription a one-off and an existing subscription keeps its listing unless one was given.
+    /// Returns the subscription and whether it was created.
+    pub fn upsert_subscription(
+        &self,
+        user_id: &str,
+        spec: &NewSubscriptionSpec,
+        options: &SubscribeOptions,
+    ) -> Result<(Subscription, bool), Error> {
         use schema::users_subscriptions::dsl;
 
-        info!("updating last sent at id: {}", id);
+        info!(
+            "upserting subscription user_id: {}, spec: {:?}, options: {:?}",
+            user_id, spec, options
+        );
 
-        match diesel::update(dsl::users_subscriptions.find(id))
-            .set(dsl::last_sent_at.eq(Utc::now().to_rfc3339()))
-            .execute(&self.conn)
-        {
-            Ok(_) => Ok(()),
+        match self.conn.transaction::<_, Error, _>(|| {
+            let existing = dsl::users_subscriptions
+                .filter(dsl::user_id.eq(user_id))
+                .filter(dsl::subreddit.eq(spec.subreddit))
+                .first::<Subscription>(&self.conn)
+                .optional()?;
+            let existing = match existing {
+                Some(existing) => existing,
+                None => return Ok((self.insert_subscription(user_id, spec, options)?, true)),
+            };
+
+            let (sort, timeframe) = match options.listing {
+                Some(listing) if options.listing_given => listing,
+                _ => (existing.sort.as_str(), existing.timeframe.as_str()),
+            };
+            diesel::update(dsl::users_subscriptions.find(existing.id))
+                .set((
+                    dsl::send_on.eq(spec.send_on),
+                    dsl::send_at.eq(spec.send_at),
+                    dsl::post_limit.eq(options.post_limit.unwrap_or(existing.post_limit)),
+                    dsl::recurring.eq(existing.recurring || !options.once),
+                    dsl::sort.eq(sort),
+                    dsl::timeframe.eq(timeframe),
+                ))
+                .execute(&self.conn)?;
+            let subscription = dsl::users_subscriptions
+                .find(existing.id)
+                .first::<Subscription>(&self.conn)?;
+            Ok((subscription, false))
+        }) {
+            Ok(result) => Ok(result),
             Err(err) => {
-                error!("failed to update last sent date: {}", err);
+                error!("failed to upsert subscription: {}", err);
                 Err(err)
             }
         }
     }
 
-    pub fn unsubscribe(&self, user_id: &str, subreddit: &str) -> Result<(), Error> {
+    /// Upserts the subscriptions in a single transaction, a failure leaves all of them as they
+    /// were. Returns the subscriptions and whether they were created.
+    pub fn upsert_subscriptions(
+        &self,
+        user_id: &str,
+        specs: &[NewSubscriptionSpec],
+        options: &SubscribeOptions,
+    ) -> Result<Vec<(Subscription, bool)>, Error> {
         info!(
-            "unsubscribing user_id: {}, subreddit: {}",
-            user_id, subreddit
+            "upserting subscriptions user_id: {}, specs: {:?}, options: {:?}",
+            user_id, specs, options
         );
+
+        match self.conn.transaction::<_, Error, _>(|| {
+            specs
+                .iter()
+                .map(|spec| self.upsert_subscription(user_id, spec, options))
+                .collect::<Result<Vec<(Subscription, bool)>, Error>>()
+        }) {
+            Ok(subscriptions) => Ok(subscriptions),
+            Err(err) => {
+                error!("failed to upsert subscriptions: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Users that never reordered keep the default order, otherwise new subscriptions go last.
+    fn next_sort_order(&self, user_id: &str) -> Result<i32, Error> {
         use schema::users_subscriptions::dsl;
 
-        match diesel::delete(
-            dsl::users_subscriptions
-                .filter(dsl::user_id.eq(user_id).and(dsl::subreddit.eq(subreddit))),
-        )
-        .execute(&self.conn)
+        let max_sort_order = dsl::users_subscriptions
+            .filter(dsl::user_id.eq(user_id))
+            .select(diesel::dsl::max(dsl::sort_order))
+            .first::<Option<i32>>(&self.conn)?
+            .unwrap_or(0);
+        Ok(if max_sort_order > 0 {
+            max_sort_order + 1
+        } else {
+            0
+        })
+    }
+
+    /// Every subscription is created here. The user's default post limit applies unless `options`
+    /// give one, without a listing the column defaults do.
+    fn insert_subscription(
+        &self,
+        user_id: &str,
+        spec: &NewSubscriptionSpec,
+        options: &SubscribeOptions,
+    ) -> Result<Subscription, Error> {
+        use schema::users_subscriptions::dsl;
+
+        let post_limit = match options.post_limit {
+            Some(post_limit) => post_limit,
+            None => self
+                .get_default_post_limit(user_id)?
+                .unwrap_or(DEFAULT_POST_LIMIT),
+        };
+        let sort_order = self.next_sort_order(user_id)?;
+        let (disable_notification, link_preview) = self.get_user_prefs(user_id)?;
+
+        let new_subscription = NewSubscription {
+            user_id,
+            subreddit: spec.subreddit,
+            send_on: spec.send_on,
+            send_at: spec.send_at,
+            last_sent_at: Some(Utc::now().to_rfc3339()),
+            post_limit,
+            target_chat_id: user_id,
+            sort_order,
+            disable_notification,
+            link_preview,
+            recurring: !options.once,
+            sort: options.listing.map(|(sort, _)| sort),
+            timeframe: options.listing.map(|(_, timeframe)| timeframe),
+        };
+
+        diesel::insert_into(dsl::users_subscriptions)
+            .values(&new_subscription)
+            .execute(&self.conn)?;
+
+        dsl::users_subscriptions
+            .order(dsl::id.desc())
+            .first::<Subscription>(&self.conn)
+    }
+
+    pub fn get_default_post_limit(&self, user_id: &str) -> Result<Option<i32>, Error> {
+        use schema::users::dsl;
+        match dsl::users
+            .find(user_id)
+            .select(dsl::default_post_limit)
+            .first::<Option<i32>>(&self.conn)
+            .optional()
         {
-            Ok(_) => Ok(()),
+            Ok(result) => Ok(result.flatten()),
             Err(err) => {
-                error!("failed to unsubscribe: {}", err);
+                error!("failed to get default post limit: {}", err);
                 Err(err)
             }
         }
     }
 
-    pub fn get_subscriptions(&self) -> Result<Vec<Subscription>, Error> {
-        use schema::users_subscriptions::dsl;
-        match dsl::users_subscriptions.load::<Subscription>(&self.conn) {
-            Ok(result) => Ok(result),
+    /// IANA name of the user's timezone, `DEFAULT_TIMEZONE` for unknown users.
+    pub fn get_timezone(&self, user_id: &str) -> Result<String, Error> {
+        use schema::users::dsl;
+        match dsl::users
+            .find(user_id)
+            .select(dsl::timezone)
+            .first::<String>(&self.conn)
+            .optional()
+        {
+            Ok(result) => Ok(result.unwrap_or_else(|| DEFAULT_TIMEZONE.to_string())),
             Err(err) => {
-                error!("failed to get subscriptions: {}", err);
+                error!("failed to get timezone: {}", err);
                 Err(err)
             }
         }
     }
 
-    pub fn get_user_subscriptions(&self, user_id: &str) -> Result<Vec<Subscription>, Error> {
-        use schema::users_subscriptions::dsl;
-        match dsl::users_subscriptions
-            .filter(dsl::user_id.eq(user_id))
-            .load::<Subscription>(&self.conn)
+    pub fn set_timezone(&self, user_id: &str, timezone: &str) -> Result<(), Error> {
+        use schema::users::dsl;
+
+        info!(
+            "setting timezone user_id: {}, timezone: {}",
+            user_id, timezone
+        );
+
+        match diesel::update(dsl::users.find(user_id))
+            .set(dsl::timezone.eq(timezone))
+            .execute(&self.conn)
         {
-            Ok(result) => Ok(result),
+            Ok(_) => Ok(()),
             Err(err) => {
-                error!("failed to get subscriptions: {}", err);
+                error!("failed to set timezone: {}", err);
                 Err(err)
             }
         }
     }
 
-    pub fn get_users_dialog(&self, user_id: &str) -> Result<DialogEntity, Error> {
-        use schema::dialogs::dsl;
-        match dsl::dialogs
-            .filter(dsl::user_id.eq(user_id))
-            .first::<DialogEntity>(&self.conn)
+    pub fn get_locale(&self, user_id: &str) -> Result<Option<String>, Error> {
+        use schema::users::dsl;
+        match dsl::users
+            .find(user_id)
+            .select(dsl::locale)
+            .first::<Option<String>>(&self.conn)
+            .optional()
         {
-            Ok(result) => Ok(result),
+            Ok(result) => Ok(result.flatten()),
             Err(err) => {
-                error!("failed to get users dialog: {}", err);
+                error!("failed to get locale: {}", err);
                 Err(err)
             }
         }
     }
 
-    pub fn insert_or_update_dialog(&self, dialog: &DialogEntity) -> Result<(), Error> {
-        use schema::dialogs::dsl;
-        info!("inserting or updating dialog: {:?}", dialog);
+    pub fn set_locale(&self, user_id: &str, locale: Option<&str>) -> Result<(), Error> {
+        use schema::users::dsl;
 
-        match diesel::replace_into(dsl::dialogs)
-            .values(vec![dialog])
+        info!("setting locale user_id: {}, locale: {:?}", user_id, locale);
+
+        match diesel::update(dsl::users.find(user_id))
+            .set(dsl::locale.eq(locale))
             .execute(&self.conn)
         {
             Ok(_) => Ok(()),
             Err(err) => {
-                error!("failed to insert or update dialog: {}", err);
+                error!("failed to set locale: {}", err);
                 Err(err)
             }
         }
     }
 
-    pub fn delete_dialog(&self, user_id: &str) -> Result<(), Error> {
-        use schema::dialogs::dsl;
-        info!("deleting dialog for user: {}", user_id);
+    pub fn set_default_post_limit(&self, user_id: &str, limit: Option<i32>) -> Result<(), Error> {
+        use schema::users::dsl;
 
-        match diesel::delete(dsl::dialogs)
-            .filter(dsl::user_id.eq(user_id))
+        info!(
+            "setting default post limit user_id: {}, limit: {:?}",
+            user_id, limit
+        );
+
+        match diesel::update(dsl::users.find(user_id))
+            .set(dsl::default_post_limit.eq(limit))
             .execute(&self.conn)
         {
             Ok(_) => Ok(()),
             Err(err) => {
-                error!("failed to delete dialog: {}", err);
+                error!("failed to set default post limit: {}", err);
                 Err(err)
             }
         }
     }
-}
 
-#[cfg(test)]
-mod test {
-    use serial_test::serial;
+    /// Whether new subscriptions of the user are sent silently and with a link preview,
+    /// both off for unknown users.
+    pub fn get_user_prefs(&self, user_id: &str) -> Result<(bool, bool), Error> {
+        use schema::users::dsl;
+        match dsl::users
+            .find(user_id)
+            .select((dsl::disable_notification, dsl::link_preview))
+            .first::<(bool, bool)>(&self.conn)
+            .optional()
+        {
+            Ok(result) => Ok(result.unwrap_or((false, false))),
+            Err(err) => {
+                error!("failed to get user prefs: {}", err);
+                Err(err)
+            }
+        }
+    }
 
-    use super::*;
-    use crate::db::test_helpers::setup_test_db;
+    /// Changes the defaults new subscriptions start with, existing subscriptions keep theirs.
+    pub fn update_user_prefs(
+        &self,
+        user_id: &str,
+        disable_notification: bool,
+        link_preview: bool,
+    ) -> Result<(), Error> {
+        use schema::users::dsl;
 
-    const USER_ID: &str = "1";
+        info!(
+            "updating user prefs user_id: {}, disable_notification: {}, link_preview: {}",
+            user_id, disable_notification, link_preview
+        );
 
-    #[test]
-    #[serial]
-    fn users() {
-        let client = setup_test_db();
-        let result = client.get_users().unwrap();
-        assert_eq!(result.len(), 0);
+        match diesel::update(dsl::users.find(user_id))
+            .set((
+                dsl::disable_notification.eq(disable_notification),
+                dsl::link_preview.eq(link_preview),
+            ))
+            .execute(&self.conn)
+        {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                error!("failed to update user prefs: {}", err);
+                Err(err)
+            }
+        }
+    }
 
-        client.create_user(USER_ID).unwrap();
-        let result = client.get_users().unwrap();
-        assert_eq!(result.len(), 1);
+    pub fn set_combined_digest(&self, user_id: &str, combined_digest: bool) -> Result<(), Error> {
+        use schema::users::dsl;
 
-        let result = client.create_user(USER_ID).unwrap_err();
-        let result = format!("{}", result);
-        assert!(result.contains("UNIQUE constraint failed: users.id"));
+        info!(
+            "setting combined digest user_id: {}, combined_digest: {}",
+            user_id, combined_digest
+        );
 
-        client.delete_user(USER_ID).unwrap();
-        let result = client.get_users().unwrap();
-        assert_eq!(result.len(), 0);
+        match diesel::update(dsl::users.find(user_id))
+            .set(dsl::combined_digest.eq(combined_digest))
+            .execute(&self.conn)
+        {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                error!("failed to set combined digest: {}", err);
+                Err(err)
+            }
+        }
     }
 
-    #[test]
-    #[serial]
-    fn user_subscriptions() {
-        let client = setup_test_db();
-        client.create_user(USER_ID).unwrap();
-
-        let result = client.get_user_subscriptions(USER_ID).unwrap();
-        assert_eq!(result.len(), 0);
-
-        client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+    #[allow(dead_code)]
+    pub fn set_post_limit(&self, id: i32, post_limit: i32) -> Result<(), Error> {
+        use schema::users_subscriptions::dsl;
 
-        let result = client.get_user_subscriptions(USER_ID).unwrap();
-        assert_eq!(result.len(), 1);
-        assert_eq!(result[0].subreddit, "rust");
+        info!("setting post limit id: {}, post_limit: {}", id, post_limit);
 
-        let result = client.subscribe(USER_ID, "rust", 0, 12).unwrap_err();
-        let result = format!("{}", result);
-        assert!(result.contains(
-            "UNIQUE constraint failed: users_subscriptions.user_id, users_subscriptions.subreddit"
-        ));
+        match diesel::update(dsl::users_subscriptions.find(id))
+            .set(dsl::post_limit.eq(post_limit))
+            .execute(&self.conn)
+        {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                error!("failed to set post limit: {}", err);
+                Err(err)
+            }
+        }
+    }
 
-        client
-            .subscribe(USER_ID, "Whatcouldgowrong", 0, 12)
-            .unwrap();
-        let result = client.get_user_subscriptions(USER_ID).unwrap();
-        assert_eq!(result.len(), 2);
-        assert_eq!(result[0].subreddit, "Whatcouldgowrong");
-        assert_eq!(result[1].subreddit, "rust");
+    #[allow(dead_code)]
+    pub fn set_recurring(&self, id: i32, recurring: bool) -> Result<(), Error> {
+        use schema::users_subscriptions::dsl;
 
-        client.unsubscribe(USER_ID, "rust").unwrap();
-        let result = client.get_user_subscriptions(USER_ID).unwrap();
-        assert_eq!(result.len(), 1);
-        assert_eq!(result[0].subreddit, "Whatcouldgowrong");
+        info!("setting recurring id: {}, recurring: {}", id, recurring);
 
-        client.delete_user(USER_ID).unwrap();
-        let result = client.get_user_subscriptions(USER_ID).unwrap();
-        assert_eq!(result.len(), 0);
+        match diesel::update(dsl::users_subscriptions.find(id))
+            .set(dsl::recurring.eq(recurring))
+            .execute(&self.conn)
+        {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                error!("failed to set recurring: {}", err);
+                Err(err)
+            }
+        }
     }
 
-    #[test]
-    #[serial]
-    fn subscriptions() {
-        const SECOND_USER_ID: &str = "2";
-
-        let client = setup_test_db();
-        client.create_user(USER_ID).unwrap();
-        client.create_user(SECOND_USER_ID).unwrap();
+    #[allow(dead_code)]
+    pub fn set_listing(&self, id: i32, sort: &str, timeframe: &str) -> Result<(), Error> {
+        use schema::users_subscriptions::dsl;
 
-        let result = client.get_user_subscriptions(USER_ID).unwrap();
-        assert_eq!(result.len(), 0);
+        info!(
+            "setting listing id: {}, sort: {}, timeframe: {}",
+            id, sort, timeframe
+        );
 
-        let result = client.get_user_subscriptions(SECOND_USER_ID).unwrap();
+        match diesel::update(dsl::users_subscriptions.find(id))
+            .set((dsl::sort.eq(sort), dsl::timeframe.eq(timeframe)))
+            .execute(&self.conn)
+        {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                error!("failed to set listing: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Removes the subscription without keeping it around for /resubscribe.
+    pub fn delete_subscription(&self, id: i32) -> Result<(), Error> {
+        use schema::users_subscriptions::dsl;
+
+        info!("deleting subscription id: {}", id);
+
+        match diesel::delete(dsl::users_subscriptions.find(id)).execute(&self.conn) {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                error!("failed to delete subscription: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Persists the order of a user's subscriptions, `ids` listed from first to last.
+    pub fn update_sort_order(&self, user_id: &str, ids: &[i32]) -> Result<(), Error> {
+        use schema::users_subscriptions::dsl;
+
+        info!("updating sort order user_id: {}, ids: {:?}", user_id, ids);
+
+        match self.conn.transaction::<_, Error, _>(|| {
+            for (index, id) in ids.iter().enumerate() {
+                diesel::update(
+                    dsl::users_subscriptions.filter(dsl::id.eq(*id).and(dsl::user_id.eq(user_id))),
+                )
+                .set(dsl::sort_order.eq(index as i32 + 1))
+                .execute(&self.conn)?;
+            }
+            Ok(())
+        }) {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                error!("failed to update sort order: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    pub fn update_last_sent(&self, id: i32) -> Result<(), Error> {
+        use schema::users_subscriptions::dsl;
+
+        info!("updating last sent at id: {}", id);
+
+        match diesel::update(dsl::users_subscriptions.find(id))
+            .set(dsl::last_sent_at.eq(Utc::now().to_rfc3339()))
+            .execute(&self.conn)
+        {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                error!("failed to update last sent date: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Records a sent digest and returns the number of consecutive empty digests so far.
+    pub fn touch_last_sent(&self, id: i32, post_ids: &[&str]) -> Result<i32, Error> {
+        use schema::sent_posts::dsl;
+        use schema::users_subscriptions::dsl as subscriptions_dsl;
+
+        info!("touching last sent at id: {}, post_ids: {:?}", id, post_ids);
+
+        let sent_at = Utc::now().to_rfc3339();
+        let sent_posts = post_ids
+            .iter()
+            .map(|post_id| SentPost {
+                subscription_id: id,
+                post_id: post_id.to_string(),
+                sent_at: sent_at.clone(),
+            })
+            .collect::<Vec<SentPost>>();
+
+        match self.conn.transaction::<_, Error, _>(|| {
+            self.update_last_sent(id)?;
+            diesel::replace_into(dsl::sent_posts)
+                .values(&sent_posts)
+                .execute(&self.conn)?;
+            diesel::insert_into(schema::deliveries::table)
+                .values(&NewDelivery {
+                    subscription_id: id,
+                    sent_at: &sent_at,
+                    post_count: post_ids.len() as i32,
+                })
+                .execute(&self.conn)?;
+
+            let subscription = subscriptions_dsl::users_subscriptions.find(id);
+            if post_ids.is_empty() {
+                diesel::update(subscription)
+                    .set(subscriptions_dsl::empty_streak.eq(subscriptions_dsl::empty_streak + 1))
+                    .execute(&self.conn)?;
+            } else {
+                diesel::update(subscription)
+                    .set(subscriptions_dsl::empty_streak.eq(0))
+                    .execute(&self.conn)?;
+            }
+            subscription
+                .select(subscriptions_dsl::empty_streak)
+                .first::<i32>(&self.conn)
+        }) {
+            Ok(empty_streak) => Ok(empty_streak),
+            Err(err) => {
+                error!("failed to touch last sent: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Number of digests sent for the user's subscription to `subreddit` and the latest `limit` of
+    /// them, newest first. None when the user isn't subscribed to it.
+    pub fn delivery_history(
+        &self,
+        user_id: &str,
+        subreddit: &str,
+        limit: i64,
+    ) -> Result<Option<(i64, Vec<Delivery>)>, Error> {
+        use schema::deliveries::dsl;
+        use schema::users_subscriptions::dsl as subscriptions_dsl;
+
+        info!(
+            "getting delivery history user_id: {}, subreddit: {}",
+            user_id, subreddit
+        );
+
+        match self.conn.transaction::<_, Error, _>(|| {
+            let id = subscriptions_dsl::users_subscriptions
+                .filter(subscriptions_dsl::user_id.eq(user_id))
+                .filter(subscriptions_dsl::subreddit.eq(subreddit))
+                .select(subscriptions_dsl::id)
+                .first::<i32>(&self.conn)
+                .optional()?;
+            let id = match id {
+                Some(id) => id,
+                None => return Ok(None),
+            };
+
+            let count = dsl::deliveries
+                .filter(dsl::subscription_id.eq(id))
+                .count()
+                .get_result::<i64>(&self.conn)?;
+            let recent = dsl::deliveries
+                .filter(dsl::subscription_id.eq(id))
+                .order((dsl::sent_at.desc(), dsl::id.desc()))
+                .limit(limit)
+                .load::<Delivery>(&self.conn)?;
+            Ok(Some((count, recent)))
+        }) {
+            Ok(result) => Ok(result),
+            Err(err) => {
+                error!("failed to get delivery history: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    pub fn get_sent_post_ids(&self, id: i32) -> Result<Vec<String>, Error> {
+        use schema::sent_posts::dsl;
+        match dsl::sent_posts
+            .filter(dsl::subscription_id.eq(id))
+            .select(dsl::post_id)
+            .order(dsl::post_id)
+            .load::<String>(&self.conn)
+        {
+            Ok(result) => Ok(result),
+            Err(err) => {
+                error!("failed to get sent post ids: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Applies `changes` to the user's subscription to `subreddit`, returning how many were updated
+    /// so callers can tell the user isn't subscribed.
+    pub fn update_subscription(
+        &self,
+        user_id: &str,
+        subreddit: &str,
+        changes: SubscriptionChanges,
+    ) -> Result<usize, Error> {
+        use schema::users_subscriptions::dsl;
+
+        info!(
+            "updating subscription user_id: {}, subreddit: {}, changes: {:?}",
+            user_id, subreddit, changes
+        );
+
+        match diesel::update(
+            dsl::users_subscriptions
+                .filter(dsl::user_id.eq(user_id).and(dsl::subreddit.eq(subreddit))),
+        )
+        .set(changes)
+        .execute(&self.conn)
+        {
+            Ok(count) => Ok(count),
+            Err(err) => {
+                error!("failed to update subscription: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Remembers the message of the digest that was just sent, for editing it with the next one.
+    pub fn set_last_message_id(&self, id: i32, message_id: Option<&str>) -> Result<(), Error> {
+        use schema::users_subscriptions::dsl;
+
+        info!(
+            "setting last message id id: {}, message_id: {:?}",
+            id, message_id
+        );
+
+        match diesel::update(dsl::users_subscriptions.find(id))
+            .set(dsl::last_message_id.eq(message_id))
+            .execute(&self.conn)
+        {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                error!("failed to set last message id: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Points a subscription at a renamed subreddit, keeping its schedule and options.
+    pub fn rename_subscription(
+        &self,
+        user_id: &str,
+        subreddit: &str,
+        new_subreddit: &str,
+    ) -> Result<usize, Error> {
+        use schema::users_subscriptions::dsl;
+
+        info!(
+            "renaming subscription user_id: {}, subreddit: {}, new_subreddit: {}",
+            user_id, subreddit, new_subreddit
+        );
+
+        match diesel::update(
+            dsl::users_subscriptions
+                .filter(dsl::user_id.eq(user_id).and(dsl::subreddit.eq(subreddit))),
+        )
+        .set(dsl::subreddit.eq(new_subreddit))
+        .execute(&self.conn)
+        {
+            Ok(count) => Ok(count),
+            Err(err) => {
+                error!("failed to rename subscription: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    pub fn unsubscribe(&self, user_id: &str, subreddit: &str) -> Result<(), Error> {
+        info!(
+            "unsubscribing user_id: {}, subreddit: {}",
+            user_id, subreddit
+        );
+        use schema::removed_subscriptions::dsl as removed_dsl;
+        use schema::users_subscriptions::dsl;
+
+        match self.conn.transaction::<_, Error, _>(|| {
+            let subscriptions = dsl::users_subscriptions
+                .filter(dsl::user_id.eq(user_id).and(dsl::subreddit.eq(subreddit)))
+                .load::<Subscription>(&self.conn)?;
+
+            for subscription in &subscriptions {
+                let settings = serde_json::to_string(&SubscriptionSettings::from(subscription))
+                    .map_err(|err| Error::SerializationError(Box::new(err)))?;
+                diesel::insert_into(removed_dsl::removed_subscriptions)
+                    .values(&NewRemovedSubscription {
+                        user_id: &subscription.user_id,
+                        subreddit: &subscription.subreddit,
+                        send_on: subscription.send_on,
+                        send_at: subscription.send_at,
+                        settings,
+                        removed_at: Utc::now().to_rfc3339(),
+                    })
+                    .execute(&self.conn)?;
+            }
+
+            diesel::delete(
+                dsl::users_subscriptions
+                    .filter(dsl::user_id.eq(user_id).and(dsl::subreddit.eq(subreddit))),
+            )
+            .execute(&self.conn)
+        }) {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                error!("failed to unsubscribe: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// The subscription /resubscribe would restore, None when nothing was removed in the last
+    /// `REMOVED_SUBSCRIPTION_TTL_HOURS`.
+    pub fn get_removed_subscription(
+        &self,
+        user_id: &str,
+    ) -> Result<Option<RemovedSubscription>, Error> {
+        use schema::removed_subscriptions::dsl;
+
+        let cutoff = (Utc::now() - Duration::hours(REMOVED_SUBSCRIPTION_TTL_HOURS)).to_rfc3339();
+        match dsl::removed_subscriptions
+            .filter(dsl::user_id.eq(user_id))
+            .filter(dsl::removed_at.ge(&cutoff))
+            .order(dsl::id.desc())
+            .first::<RemovedSubscription>(&self.conn)
+            .optional()
+        {
+            Ok(removed) => Ok(removed),
+            Err(err) => {
+                error!("failed to get removed subscription: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    pub fn restore_subscription(&self, user_id: &str) -> Result<Option<Subscription>, Error> {
+        use schema::removed_subscriptions::dsl;
+        use schema::users_subscriptions::dsl as subscriptions_dsl;
+
+        info!("restoring last removed subscription for user: {}", user_id);
+
+        let cutoff = (Utc::now() - Duration::hours(REMOVED_SUBSCRIPTION_TTL_HOURS)).to_rfc3339();
+
+        match self.conn.transaction::<_, Error, _>(|| {
+            diesel::delete(dsl::removed_subscriptions.filter(dsl::removed_at.lt(&cutoff)))
+                .execute(&self.conn)?;
+
+            let removed = dsl::removed_subscriptions
+                .filter(dsl::user_id.eq(user_id))
+                .order(dsl::id.desc())
+                .first::<RemovedSubscription>(&self.conn)
+                .optional()?;
+
+            let removed = match removed {
+                Some(removed) => removed,
+                None => return Ok(None),
+            };
+
+            let subscription = self.subscribe(
+                &removed.user_id,
+                &removed.subreddit,
+                removed.send_on,
+                removed.send_at,
+            )?;
+            let settings = serde_json::from_str::<SubscriptionSettings>(&removed.settings)
+                .map_err(|err| Error::DeserializationError(Box::new(err)))?;
+            diesel::update(subscriptions_dsl::users_subscriptions.find(subscription.id))
+                .set(&settings)
+                .execute(&self.conn)?;
+            diesel::delete(dsl::removed_subscriptions.find(removed.id)).execute(&self.conn)?;
+
+            subscriptions_dsl::users_subscriptions
+                .find(subscription.id)
+                .first::<Subscription>(&self.conn)
+                .map(Some)
+        }) {
+            Ok(subscription) => Ok(subscription),
+            Err(err) => {
+                error!("failed to restore subscription: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    pub fn get_subscriptions(&self) -> Result<Vec<Subscription>, Error> {
+        use schema::users_subscriptions::dsl;
+        match dsl::users_subscriptions
+            .order((dsl::user_id, dsl::sort_order, dsl::subreddit))
+            .load::<Subscription>(&self.conn)
+        {
+            Ok(result) => Ok(result),
+            Err(err) => {
+                error!("failed to get subscriptions: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// All subscriptions paired with their user's timezone and combined digest setting,
+    /// subscriptions without a user are left out.
+    pub fn get_subscriptions_with_user_settings(
+        &self,
+    ) -> Result<Vec<(Subscription, String, bool)>, Error> {
+        use schema::users::dsl as users_dsl;
+        use schema::users_subscriptions::dsl;
+        match dsl::users_subscriptions
+            .inner_join(users_dsl::users)
+            .select((
+                schema::users_subscriptions::all_columns,
+                users_dsl::timezone,
+                users_dsl::combined_digest,
+            ))
+            .order((dsl::user_id, dsl::sort_order, dsl::subreddit))
+            .load::<(Subscription, String, bool)>(&self.conn)
+        {
+            Ok(result) => Ok(result),
+            Err(err) => {
+                error!("failed to get subscriptions with user settings: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Deletes subscriptions whose user no longer exists and returns how many were removed.
+    pub fn prune_orphan_subscriptions(&self) -> Result<usize, Error> {
+        use schema::users::dsl as users_dsl;
+        use schema::users_subscriptions::dsl;
+
+        info!("pruning orphan subscriptions");
+
+        match diesel::delete(
+            dsl::users_subscriptions
+                .filter(dsl::user_id.ne_all(users_dsl::users.select(users_dsl::id))),
+        )
+        .execute(&self.conn)
+        {
+            Ok(result) => Ok(result),
+            Err(err) => {
+                error!("failed to prune orphan subscriptions: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Starts watching the thread at `permalink`, only comments posted from now on are sent.
+    pub fn watch_post(&self, user_id: &str, permalink: &str) -> Result<PostSubscription, Error> {
+        use schema::post_subscriptions::dsl;
+
+        info!(
+            "watching post user_id: {}, permalink: {}",
+            user_id, permalink
+        );
+
+        let now = Utc::now().to_rfc3339();
+        match self.conn.transaction::<_, Error, _>(|| {
+            diesel::insert_into(dsl::post_subscriptions)
+                .values(&NewPostSubscription {
+                    user_id,
+                    permalink,
+                    created_at: &now,
+                    last_checked_at: &now,
+                })
+                .execute(&self.conn)?;
+
+            dsl::post_subscriptions
+                .order(dsl::id.desc())
+                .first::<PostSubscription>(&self.conn)
+        }) {
+            Ok(post_subscription) => Ok(post_subscription),
+            Err(err) => {
+                error!("failed to watch post: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    pub fn get_post_subscriptions(&self) -> Result<Vec<PostSubscription>, Error> {
+        use schema::post_subscriptions::dsl;
+        match dsl::post_subscriptions
+            .order(dsl::id.asc())
+            .load::<PostSubscription>(&self.conn)
+        {
+            Ok(post_subscriptions) => Ok(post_subscriptions),
+            Err(err) => {
+                error!("failed to get post subscriptions: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Stops watching the thread at `permalink`, false when the user wasn't watching it.
+    pub fn delete_post_subscription(&self, user_id: &str, permalink: &str) -> Result<bool, Error> {
+        use schema::post_subscriptions::dsl;
+
+        info!(
+            "deleting post subscription user_id: {}, permalink: {}",
+            user_id, permalink
+        );
+
+        match diesel::delete(
+            dsl::post_subscriptions
+                .filter(dsl::user_id.eq(user_id))
+                .filter(dsl::permalink.eq(permalink)),
+        )
+        .execute(&self.conn)
+        {
+            Ok(deleted) => Ok(deleted > 0),
+            Err(err) => {
+                error!("failed to delete post subscription: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Deletes the watched threads older than `POST_SUBSCRIPTION_TTL_DAYS` and returns how many
+    /// were removed.
+    pub fn delete_expired_post_subscriptions(&self) -> Result<usize, Error> {
+        use schema::post_subscriptions::dsl;
+
+        let cutoff = (Utc::now() - Duration::days(POST_SUBSCRIPTION_TTL_DAYS)).to_rfc3339();
+        match diesel::delete(dsl::post_subscriptions.filter(dsl::created_at.lt(&cutoff)))
+            .execute(&self.conn)
+        {
+            Ok(deleted) => Ok(deleted),
+            Err(err) => {
+                error!("failed to delete expired post subscriptions: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    pub fn touch_post_checked(&self, id: i32, checked_at: &str) -> Result<(), Error> {
+        use schema::post_subscriptions::dsl;
+
+        info!(
+            "touching post checked at id: {}, checked_at: {}",
+            id, checked_at
+        );
+
+        match diesel::update(dsl::post_subscriptions.find(id))
+            .set(dsl::last_checked_at.eq(checked_at))
+            .execute(&self.conn)
+        {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                error!("failed to update post checked date: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    pub fn save_feedback(
+        &self,
+        user_id: &str,
+        category: &str,
+        text: &str,
+    ) -> Result<FeedbackEntity, Error> {
+        use schema::feedback::dsl;
+
+        info!(
+            "saving feedback user_id: {}, category: {}",
+            user_id, category
+        );
+
+        let now = Utc::now().to_rfc3339();
+        match self.conn.transaction::<_, Error, _>(|| {
+            diesel::insert_into(dsl::feedback)
+                .values(&NewFeedback {
+                    user_id,
+                    text,
+                    created_at: &now,
+                    category,
+                })
+                .execute(&self.conn)?;
+
+            dsl::feedback
+                .order(dsl::id.desc())
+                .first::<FeedbackEntity>(&self.conn)
+        }) {
+            Ok(feedback) => Ok(feedback),
+            Err(err) => {
+                error!("failed to save feedback: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    pub fn get_undelivered_feedback(&self) -> Result<Vec<FeedbackEntity>, Error> {
+        use schema::feedback::dsl;
+        match dsl::feedback
+            .filter(dsl::delivered.eq(false))
+            .order(dsl::id.asc())
+            .load::<FeedbackEntity>(&self.conn)
+        {
+            Ok(feedback) => Ok(feedback),
+            Err(err) => {
+                error!("failed to get undelivered feedback: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    pub fn set_feedback_delivered(&self, id: i32) -> Result<(), Error> {
+        use schema::feedback::dsl;
+
+        info!("setting feedback delivered id: {}", id);
+
+        match diesel::update(dsl::feedback.find(id))
+            .set(dsl::delivered.eq(true))
+            .execute(&self.conn)
+        {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                error!("failed to set feedback delivered: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Blocks the subreddit, returns 0 when it was already blocked.
+    pub fn block_subreddit(&self, subreddit: &str) -> Result<usize, Error> {
+        use schema::blocked_subreddits::dsl;
+
+        info!("blocking subreddit: {}", subreddit);
+
+        let blocked = BlockedSubreddit {
+            subreddit: subreddit.to_lowercase(),
+            blocked_at: Utc::now().to_rfc3339(),
+        };
+        match diesel::insert_or_ignore_into(dsl::blocked_subreddits)
+            .values(&blocked)
+            .execute(&self.conn)
+        {
+            Ok(count) => Ok(count),
+            Err(err) => {
+                error!("failed to block subreddit: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Unblocks the subreddit, returns 0 when it wasn't blocked.
+    pub fn unblock_subreddit(&self, subreddit: &str) -> Result<usize, Error> {
+        use schema::blocked_subreddits::dsl;
+
+        info!("unblocking subreddit: {}", subreddit);
+
+        match diesel::delete(dsl::blocked_subreddits.find(subreddit.to_lowercase()))
+            .execute(&self.conn)
+        {
+            Ok(count) => Ok(count),
+            Err(err) => {
+                error!("failed to unblock subreddit: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Whether the subreddit is blocked, a combined "a+b" path is blocked when any of its
+    /// subreddits is.
+    pub fn is_subreddit_blocked(&self, subreddit: &str) -> Result<bool, Error> {
+        use schema::blocked_subreddits::dsl;
+
+        let subreddits = subreddit
+            .split('+')
+            .map(str::to_lowercase)
+            .collect::<Vec<String>>();
+        match diesel::select(diesel::dsl::exists(
+            dsl::blocked_subreddits.filter(dsl::subreddit.eq_any(subreddits)),
+        ))
+        .get_result(&self.conn)
+        {
+            Ok(result) => Ok(result),
+            Err(err) => {
+                error!("failed to check if subreddit is blocked: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    pub fn get_blocked_subreddits(&self) -> Result<Vec<String>, Error> {
+        use schema::blocked_subreddits::dsl;
+        match dsl::blocked_subreddits
+            .select(dsl::subreddit)
+            .order(dsl::subreddit)
+            .load::<String>(&self.conn)
+        {
+            Ok(result) => Ok(result),
+            Err(err) => {
+                error!("failed to get blocked subreddits: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Subscriptions not sent since `older_than`, including ones never sent at all, least recent first.
+    /// New subscriptions start out with `last_sent_at` set to their creation time.
+    pub fn get_stale_subscriptions(
+        &self,
+        older_than: DateTime<Utc>,
+    ) -> Result<Vec<Subscription>, Error> {
+        use schema::users_subscriptions::dsl;
+        match dsl::users_subscriptions
+            .filter(
+                dsl::last_sent_at
+                    .is_null()
+                    .or(dsl::last_sent_at.lt(older_than.to_rfc3339())),
+            )
+            .order((dsl::last_sent_at, dsl::id))
+            .load::<Subscription>(&self.conn)
+        {
+            Ok(result) => Ok(result),
+            Err(err) => {
+                error!("failed to get stale subscriptions: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Users created before `created_before` that neither subscribed to a subreddit nor watch a post.
+    pub fn get_inactive_users(&self, created_before: DateTime<Utc>) -> Result<Vec<User>, Error> {
+        use schema::post_subscriptions::dsl as post_subscriptions_dsl;
+        use schema::users::dsl;
+        use schema::users_subscriptions::dsl as subscriptions_dsl;
+        match dsl::users
+            .filter(dsl::created_at.lt(created_before.to_rfc3339()))
+            .filter(diesel::dsl::not(dsl::id.eq_any(
+                subscriptions_dsl::users_subscriptions.select(subscriptions_dsl::user_id),
+            )))
+            .filter(diesel::dsl::not(dsl::id.eq_any(
+                post_subscriptions_dsl::post_subscriptions.select(post_subscriptions_dsl::user_id),
+            )))
+            .order((dsl::created_at, dsl::id))
+            .load::<User>(&self.conn)
+        {
+            Ok(result) => Ok(result),
+            Err(err) => {
+                error!("failed to get inactive users: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Deletes the users `get_inactive_users` reports and returns how many were removed.
+    pub fn delete_inactive_users(&self, created_before: DateTime<Utc>) -> Result<usize, Error> {
+        use schema::users::dsl;
+
+        info!("deleting users inactive since: {}", created_before);
+
+        match self.conn.transaction::<_, Error, _>(|| {
+            let ids = self
+                .get_inactive_users(created_before)?
+                .into_iter()
+                .map(|user| user.id)
+                .collect::<Vec<String>>();
+            diesel::delete(dsl::users.filter(dsl::id.eq_any(ids))).execute(&self.conn)
+        }) {
+            Ok(count) => Ok(count),
+            Err(err) => {
+                error!("failed to delete inactive users: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Distinct chats that digests are delivered to.
+    pub fn get_target_chat_ids(&self) -> Result<Vec<String>, Error> {
+        use schema::users_subscriptions::dsl;
+        match dsl::users_subscriptions
+            .select(dsl::target_chat_id)
+            .distinct()
+            .order(dsl::target_chat_id)
+            .load::<String>(&self.conn)
+        {
+            Ok(result) => Ok(result),
+            Err(err) => {
+                error!("failed to get target chat ids: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Subreddits with the most subscriptions across all users, most subscribed first.
+    pub fn top_subreddits(&self, limit: i64) -> Result<Vec<(String, i64)>, Error> {
+        use diesel::dsl::sql;
+        use diesel::sql_types::BigInt;
+        use schema::users_subscriptions::dsl;
+        // Diesel 1.4 can't mix aggregates with grouped columns, so the count is plain SQL.
+        match dsl::users_subscriptions
+            .group_by(dsl::subreddit)
+            .select((dsl::subreddit, sql::<BigInt>("COUNT(*)")))
+            .order(sql::<BigInt>("COUNT(*) DESC, subreddit"))
+            .limit(limit)
+            .load::<(String, i64)>(&self.conn)
+        {
+            Ok(result) => Ok(result),
+            Err(err) => {
+                error!("failed to get top subreddits: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Number of different subreddits subscribed to across all users.
+    pub fn tracked_subreddit_count(&self) -> Result<i64, Error> {
+        use diesel::dsl::sql;
+        use diesel::sql_types::BigInt;
+        use schema::users_subscriptions::dsl;
+        match dsl::users_subscriptions
+            .select(sql::<BigInt>("COUNT(DISTINCT subreddit)"))
+            .get_result::<i64>(&self.conn)
+        {
+            Ok(result) => Ok(result),
+            Err(err) => {
+                error!("failed to get tracked subreddit count: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Number of registered users.
+    pub fn user_count(&self) -> Result<i64, Error> {
+        use schema::users::dsl;
+        match dsl::users.count().get_result::<i64>(&self.conn) {
+            Ok(result) => Ok(result),
+            Err(err) => {
+                error!("failed to get user count: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Number of subscriptions across all users.
+    pub fn subscription_count(&self) -> Result<i64, Error> {
+        use schema::users_subscriptions::dsl;
+        match dsl::users_subscriptions
+            .count()
+            .get_result::<i64>(&self.conn)
+        {
+            Ok(result) => Ok(result),
+            Err(err) => {
+                error!("failed to get total subscription count: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Writes a consistent copy of the database to `path` with `VACUUM INTO`, unlike copying the
+    /// file it doesn't pick up half written transactions. Fails if the file already exists.
+    pub fn snapshot(&self, path: &str) -> Result<(), Error> {
+        use diesel::sql_types::Text;
+
+        info!("writing database snapshot to: {}", path);
+
+        match diesel::sql_query("VACUUM INTO ?")
+            .bind::<Text, _>(path)
+            .execute(&self.conn)
+        {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                error!("failed to write database snapshot: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    pub fn get_subscription_count(&self, user_id: &str) -> Result<i64, Error> {
+        use schema::users_subscriptions::dsl;
+        match dsl::users_subscriptions
+            .filter(dsl::user_id.eq(user_id))
+            .count()
+            .get_result::<i64>(&self.conn)
+        {
+            Ok(result) => Ok(result),
+            Err(err) => {
+                error!("failed to get subscription count: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    pub fn get_user_subscriptions(&self, user_id: &str) -> Result<Vec<Subscription>, Error> {
+        use schema::users_subscriptions::dsl;
+        match dsl::users_subscriptions
+            .filter(dsl::user_id.eq(user_id))
+            .order((dsl::sort_order, dsl::subreddit))
+            .load::<Subscription>(&self.conn)
+        {
+            Ok(result) => Ok(result),
+            Err(err) => {
+                error!("failed to get subscriptions: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Subscriptions of the user with the next time they're due in the user's timezone, soonest first.
+    pub fn get_user_subscriptions_with_next(
+        &self,
+        user_id: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<(Subscription, DateTime<Utc>)>, Error> {
+        let timezone = parse_timezone(&self.get_timezone(user_id)?);
+        let mut result = self
+            .get_user_subscriptions(user_id)?
+            .into_iter()
+            .map(|subscription| {
+                let next = subscription.next_send_at(now, timezone);
+                (subscription, next)
+            })
+            .collect::<Vec<(Subscription, DateTime<Utc>)>>();
+        result.sort_by_key(|(_, next)| *next);
+        Ok(result)
+    }
+
+    pub fn get_users_dialog(&self, user_id: &str) -> Result<DialogEntity, Error> {
+        use schema::dialogs::dsl;
+        match dsl::dialogs
+            .filter(dsl::user_id.eq(user_id))
+            .first::<DialogEntity>(&self.conn)
+        {
+            Ok(result) => Ok(result),
+            Err(err) => {
+                error!("failed to get users dialog: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Like `get_users_dialog`, but a missing dialog is `Ok(None)` so only real failures are errors.
+    pub fn get_users_dialog_opt(&self, user_id: &str) -> Result<Option<DialogEntity>, Error> {
+        use schema::dialogs::dsl;
+        match dsl::dialogs
+            .filter(dsl::user_id.eq(user_id))
+            .first::<DialogEntity>(&self.conn)
+            .optional()
+        {
+            Ok(result) => Ok(result),
+            Err(err) => {
+                error!("failed to get users dialog: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// All dialogs that are currently in progress, ordered by user.
+    pub fn get_all_dialogs(&self) -> Result<Vec<DialogEntity>, Error> {
+        use schema::dialogs::dsl;
+        match dsl::dialogs
+            .order(dsl::user_id)
+            .load::<DialogEntity>(&self.conn)
+        {
+            Ok(result) => Ok(result),
+            Err(err) => {
+                error!("failed to get all dialogs: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    pub fn insert_or_update_dialog(&self, dialog: &DialogEntity) -> Result<(), Error> {
+        use schema::dialogs::dsl;
+        info!("inserting or updating dialog: {:?}", dialog);
+
+        match diesel::replace_into(dsl::dialogs)
+            .values(vec![dialog])
+            .execute(&self.conn)
+        {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                error!("failed to insert or update dialog: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Deletes the dialogs of all users, returns the number of deleted dialogs.
+    pub fn delete_all_dialogs(&self) -> Result<usize, Error> {
+        use schema::dialogs::dsl;
+        info!("deleting all dialogs");
+
+        match diesel::delete(dsl::dialogs).execute(&self.conn) {
+            Ok(count) => Ok(count),
+            Err(err) => {
+                error!("failed to delete all dialogs: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    pub fn delete_dialog(&self, user_id: &str) -> Result<(), Error> {
+        use schema::dialogs::dsl;
+        info!("deleting dialog for user: {}", user_id);
+
+        match diesel::delete(dsl::dialogs)
+            .filter(dsl::user_id.eq(user_id))
+            .execute(&self.conn)
+        {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                error!("failed to delete dialog: {}", err);
+                Err(err)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use diesel::result::DatabaseErrorKind;
+    use serial_test::serial;
+
+    use super::*;
+    use crate::db::test_helpers::{setup_test_db, setup_test_db_with};
+
+    const USER_ID: &str = "1";
+
+    #[test]
+    #[serial]
+    fn users() {
+        let client = setup_test_db();
+        let result = client.get_users().unwrap();
+        assert_eq!(result.len(), 0);
+
+        client.create_user(USER_ID).unwrap();
+        let result = client.get_users().unwrap();
+        assert_eq!(result.len(), 1);
+
+        let result = client.create_user(USER_ID).unwrap_err();
+        let result = format!("{}", result);
+        assert!(result.contains("UNIQUE constraint failed: users.id"));
+
+        client.delete_user(USER_ID).unwrap();
+        let result = client.get_users().unwrap();
+        assert_eq!(result.len(), 0);
+    }
+
+    #[test]
+    #[serial]
+    fn user_subscriptions() {
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+
+        let result = client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(result.len(), 0);
+
+        client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+
+        let result = client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].subreddit, "rust");
+
+        let result = client.subscribe(USER_ID, "rust", 0, 12).unwrap_err();
+        let result = format!("{}", result);
+        assert!(result.contains(
+            "UNIQUE constraint failed: users_subscriptions.user_id, users_subscriptions.subreddit"
+        ));
+
+        client
+            .subscribe(USER_ID, "Whatcouldgowrong", 0, 12)
+            .unwrap();
+        let result = client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].subreddit, "Whatcouldgowrong");
+        assert_eq!(result[1].subreddit, "rust");
+
+        client.unsubscribe(USER_ID, "rust").unwrap();
+        let result = client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].subreddit, "Whatcouldgowrong");
+
+        client.delete_user(USER_ID).unwrap();
+        let result = client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(result.len(), 0);
+    }
+
+    #[test]
+    #[serial]
+    fn subscriptions() {
+        const SECOND_USER_ID: &str = "2";
+
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+        client.create_user(SECOND_USER_ID).unwrap();
+
+        let result = client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(result.len(), 0);
+
+        let result = client.get_user_subscriptions(SECOND_USER_ID).unwrap();
+        assert_eq!(result.len(), 0);
+
+        client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+
+        let result = client.get_user_subscriptions(SECOND_USER_ID).unwrap();
+        assert_eq!(result.len(), 0);
+
+        let result = client.get_subscriptions().unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].subreddit, "rust");
+    }
+
+    #[test]
+    #[serial]
+    fn update_sort_order() {
+        const SECOND_USER_ID: &str = "2";
+
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+        client.create_user(SECOND_USER_ID).unwrap();
+        let rust = client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        let golang = client.subscribe(USER_ID, "golang", 0, 12).unwrap();
+        let zig = client.subscribe(USER_ID, "zig", 0, 12).unwrap();
+        client.subscribe(SECOND_USER_ID, "python", 0, 12).unwrap();
+
+        // Alphabetical until reordered.
+        let result = client.get_user_subscriptions(USER_ID).unwrap();
+        let subreddits = result
+            .iter()
+            .map(|subscription| subscription.subreddit.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(subreddits, ["golang", "rust", "zig"]);
+
+        client
+            .update_sort_order(USER_ID, &[zig.id, rust.id, golang.id])
+            .unwrap();
+        let result = client.get_user_subscriptions(USER_ID).unwrap();
+        let subreddits = result
+            .iter()
+            .map(|subscription| subscription.subreddit.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(subreddits, ["zig", "rust", "golang"]);
+
+        // New subscriptions are appended once the user has a custom order.
+        client.subscribe(USER_ID, "c", 0, 12).unwrap();
+        let result = client.get_subscriptions().unwrap();
+        let subreddits = result
+            .iter()
+            .map(|subscription| subscription.subreddit.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(subreddits, ["zig", "rust", "golang", "c", "python"]);
+
+        // Ids of other users are left alone.
+        let python = client.get_user_subscriptions(SECOND_USER_ID).unwrap();
+        client.update_sort_order(USER_ID, &[python[0].id]).unwrap();
+        let result = client.get_user_subscriptions(SECOND_USER_ID).unwrap();
+        assert_eq!(result[0].sort_order, 0);
+    }
+
+    #[test]
+    #[serial]
+    fn update_last_sent() {
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+
+        let result = client.get_user_subscriptions(USER_ID).unwrap();
         assert_eq!(result.len(), 0);
 
         client.subscribe(USER_ID, "rust", 0, 12).unwrap();
 
-        let result = client.get_user_subscriptions(SECOND_USER_ID).unwrap();
-        assert_eq!(result.len(), 0);
+        let result = client.get_subscriptions().unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].subreddit, "rust");
+        assert_eq!(result[0].send_on, 0);
+        assert_eq!(result[0].send_at, 12);
+
+        client.update_last_sent(result[0].id).unwrap();
+        let result = client.get_subscriptions().unwrap();
+        assert!(result[0].last_sent_at.is_some());
+    }
+
+    #[test]
+    #[serial]
+    fn default_post_limit() {
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+
+        let result = client.get_default_post_limit(USER_ID).unwrap();
+        assert_eq!(result, None);
+        let result = client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        assert_eq!(result.post_limit, DEFAULT_POST_LIMIT);
+
+        client.set_default_post_limit(USER_ID, Some(5)).unwrap();
+        let result = client.get_default_post_limit(USER_ID).unwrap();
+        assert_eq!(result, Some(5));
+        let result = client.subscribe(USER_ID, "golang", 0, 12).unwrap();
+        assert_eq!(result.post_limit, 5);
+
+        client.set_default_post_limit(USER_ID, None).unwrap();
+        let result = client.subscribe(USER_ID, "python", 0, 12).unwrap();
+        assert_eq!(result.post_limit, DEFAULT_POST_LIMIT);
+    }
+
+    #[test]
+    #[serial]
+    fn user_prefs() {
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+
+        let result = client.get_user_prefs(USER_ID).unwrap();
+        assert_eq!(result, (false, false));
+        let rust = client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        assert!(!rust.disable_notification);
+        assert!(!rust.link_preview);
+
+        client.update_user_prefs(USER_ID, true, true).unwrap();
+        let result = client.get_user_prefs(USER_ID).unwrap();
+        assert_eq!(result, (true, true));
+        let result = client.subscribe(USER_ID, "golang", 0, 12).unwrap();
+        assert!(result.disable_notification);
+        assert!(result.link_preview);
+        let (result, created) = client
+            .upsert_subscription(
+                USER_ID,
+                &NewSubscriptionSpec {
+                    subreddit: "python",
+                    send_on: 0,
+                    send_at: 12,
+                },
+                &SubscribeOptions::default(),
+            )
+            .unwrap();
+        assert!(created);
+        assert!(result.disable_notification);
+        assert!(result.link_preview);
+
+        let result = client.get_user_subscriptions(USER_ID).unwrap();
+        let rust = result.iter().find(|s| s.subreddit == "rust").unwrap();
+        assert!(!rust.disable_notification);
+        assert!(!rust.link_preview);
+    }
+
+    #[test]
+    #[serial]
+    fn user_subscriptions_with_next() {
+        use chrono::TimeZone;
+
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+        client.subscribe(USER_ID, "rust", 6, 12).unwrap();
+        client.subscribe(USER_ID, "golang", 0, 8).unwrap();
+        client.subscribe(USER_ID, "python", 6, 18).unwrap();
+
+        // Sunday
+        let now = Utc.with_ymd_and_hms(2020, 3, 22, 13, 0, 0).unwrap();
+        let result = client
+            .get_user_subscriptions_with_next(USER_ID, now)
+            .unwrap();
+        let result = result
+            .iter()
+            .map(|(subscription, next)| (subscription.subreddit.as_str(), *next))
+            .collect::<Vec<(&str, DateTime<Utc>)>>();
+        assert_eq!(
+            result,
+            [
+                (
+                    "python",
+                    Utc.with_ymd_and_hms(2020, 3, 22, 18, 0, 0).unwrap()
+                ),
+                (
+                    "golang",
+                    Utc.with_ymd_and_hms(2020, 3, 23, 8, 0, 0).unwrap()
+                ),
+                ("rust", Utc.with_ymd_and_hms(2020, 3, 29, 12, 0, 0).unwrap()),
+            ]
+        );
+
+        // Riga is 2 hours ahead of UTC in March.
+        client.set_timezone(USER_ID, "Europe/Riga").unwrap();
+        let result = client
+            .get_user_subscriptions_with_next(USER_ID, now)
+            .unwrap();
+        assert_eq!(result[0].0.subreddit, "python");
+        assert_eq!(
+            result[0].1,
+            Utc.with_ymd_and_hms(2020, 3, 22, 16, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn timezone() {
+        let client = setup_test_db();
+        assert_eq!(client.get_timezone(USER_ID).unwrap(), DEFAULT_TIMEZONE);
+        client.create_user(USER_ID).unwrap();
+        assert_eq!(client.get_timezone(USER_ID).unwrap(), DEFAULT_TIMEZONE);
+
+        client.set_timezone(USER_ID, "Europe/Riga").unwrap();
+        assert_eq!(client.get_timezone(USER_ID).unwrap(), "Europe/Riga");
+    }
+
+    #[test]
+    #[serial]
+    fn locale() {
+        let client = setup_test_db();
+        assert_eq!(client.get_locale(USER_ID).unwrap(), None);
+        client.create_user(USER_ID).unwrap();
+        assert_eq!(client.get_locale(USER_ID).unwrap(), None);
+
+        client.set_locale(USER_ID, Some("de")).unwrap();
+        assert_eq!(client.get_locale(USER_ID).unwrap().as_deref(), Some("de"));
+        client.set_locale(USER_ID, None).unwrap();
+        assert_eq!(client.get_locale(USER_ID).unwrap(), None);
+    }
+
+    #[test]
+    #[serial]
+    fn show_scores() {
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+        let result = client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        assert!(!result.show_scores);
+
+        let result = client
+            .update_subscription(
+                USER_ID,
+                "rust",
+                SubscriptionChanges {
+                    show_scores: Some(true),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(result, 1);
+        let result = client
+            .update_subscription(
+                USER_ID,
+                "golang",
+                SubscriptionChanges {
+                    show_scores: Some(true),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(result, 0);
+        let result = client.get_user_subscriptions(USER_ID).unwrap();
+        assert!(result[0].show_scores);
+    }
+
+    #[test]
+    #[serial]
+    fn include_excerpt() {
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+        let result = client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        assert!(!result.include_excerpt);
+
+        let result = client
+            .update_subscription(
+                USER_ID,
+                "rust",
+                SubscriptionChanges {
+                    include_excerpt: Some(true),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(result, 1);
+        let result = client.get_user_subscriptions(USER_ID).unwrap();
+        assert!(result[0].include_excerpt);
+
+        let result = client
+            .update_subscription(
+                USER_ID,
+                "golang",
+                SubscriptionChanges {
+                    include_excerpt: Some(true),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(result, 0);
+    }
+
+    #[test]
+    #[serial]
+    fn subscription_count() {
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+        client.create_user("2").unwrap();
+        assert_eq!(client.get_subscription_count(USER_ID).unwrap(), 0);
+
+        client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        client.subscribe(USER_ID, "golang", 0, 12).unwrap();
+        client.subscribe("2", "rust", 0, 12).unwrap();
+        assert_eq!(client.get_subscription_count(USER_ID).unwrap(), 2);
+    }
+
+    #[test]
+    #[serial]
+    fn snapshot() {
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+        client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+
+        let path = ".tmp/snapshot.db";
+        std::fs::remove_file(path).ok();
+        client.snapshot(path).unwrap();
+        // Subscribed after the snapshot, not part of it.
+        client.subscribe(USER_ID, "golang", 0, 12).unwrap();
+
+        let snapshot = DbClient::new(&format!("file:{}", path));
+        let result = snapshot.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].subreddit, "rust");
+
+        assert!(client.snapshot(path).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn run_migrations() {
+        let mut migrations = std::fs::read_dir("migrations")
+            .unwrap()
+            .map(|entry| entry.unwrap())
+            .filter(|entry| entry.path().is_dir())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        migrations.sort();
+        let latest = migrations.last().unwrap();
+        let latest_version = latest.split('_').next().unwrap().replace('-', "");
+
+        let client = setup_test_db_with(false);
+        let result = client.run_migrations().unwrap();
+        assert_eq!(
+            result,
+            MigrationStatus {
+                applied: migrations.len(),
+                version: Some(latest_version.clone()),
+            }
+        );
+        assert_eq!(client.run_migrations().unwrap().applied, 0);
+
+        // Roll back the latest migration, it's pending again.
+        let down = std::fs::read_to_string(format!("migrations/{}/down.sql", latest)).unwrap();
+        client.conn.execute(&down).unwrap();
+        client
+            .conn
+            .execute(&format!(
+                "DELETE FROM __diesel_schema_migrations WHERE version = '{}'",
+                latest_version
+            ))
+            .unwrap();
+        let result = client.run_migrations().unwrap();
+        assert_eq!(
+            result,
+            MigrationStatus {
+                applied: 1,
+                version: Some(latest_version),
+            }
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn new_client_does_not_run_migrations() {
+        let client = setup_test_db_with(false);
+        let other = DbClient::new("file:.tmp/test.db");
+        assert!(other.get_users().is_err());
+
+        let applied = client.run_migrations().unwrap().applied;
+        assert!(applied > 0);
+        let other = DbClient::new("file:.tmp/test.db");
+        assert_eq!(other.run_migrations().unwrap().applied, 0);
+        assert_eq!(
+            other
+                .conn
+                .previously_run_migration_versions()
+                .unwrap()
+                .len(),
+            applied
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn totals() {
+        let client = setup_test_db();
+        assert_eq!(client.user_count().unwrap(), 0);
+        assert_eq!(client.subscription_count().unwrap(), 0);
+
+        client.create_user(USER_ID).unwrap();
+        client.create_user("2").unwrap();
+        client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        client.subscribe(USER_ID, "golang", 0, 12).unwrap();
+        client.subscribe("2", "rust", 0, 12).unwrap();
+        assert_eq!(client.user_count().unwrap(), 2);
+        assert_eq!(client.subscription_count().unwrap(), 3);
+
+        assert_eq!(client.tracked_subreddit_count().unwrap(), 2);
+
+        client.unsubscribe(USER_ID, "golang").unwrap();
+        assert_eq!(client.subscription_count().unwrap(), 2);
+        assert_eq!(client.tracked_subreddit_count().unwrap(), 1);
+        client.delete_user("2").unwrap();
+        assert_eq!(client.user_count().unwrap(), 1);
+        assert_eq!(client.subscription_count().unwrap(), 1);
+    }
+
+    #[test]
+    #[serial]
+    fn set_post_limit() {
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+        let subscription = client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+
+        client.set_post_limit(subscription.id, 25).unwrap();
+        let result = client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(result[0].post_limit, 25);
+    }
+
+    #[test]
+    #[serial]
+    fn set_recurring() {
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+        let subscription = client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        assert!(subscription.recurring);
+
+        client.set_recurring(subscription.id, false).unwrap();
+        let result = client.get_user_subscriptions(USER_ID).unwrap();
+        assert!(!result[0].recurring);
+
+        client.delete_subscription(subscription.id).unwrap();
+        assert!(client.get_user_subscriptions(USER_ID).unwrap().is_empty());
+        assert!(client.restore_subscription(USER_ID).unwrap().is_none());
+    }
+
+    #[test]
+    #[serial]
+    fn set_listing() {
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+        let subscription = client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        assert_eq!(subscription.sort, "top");
+        assert_eq!(subscription.timeframe, "week");
+
+        client.set_listing(subscription.id, "new", "day").unwrap();
+        let result = client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(result[0].sort, "new");
+        assert_eq!(result[0].timeframe, "day");
+    }
+
+    #[test]
+    #[serial]
+    fn include_top_comment() {
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+        let result = client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        assert!(!result.include_top_comment);
+
+        let result = client
+            .update_subscription(
+                USER_ID,
+                "rust",
+                SubscriptionChanges {
+                    include_top_comment: Some(true),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(result, 1);
+        let result = client.get_user_subscriptions(USER_ID).unwrap();
+        assert!(result[0].include_top_comment);
+    }
+
+    #[test]
+    #[serial]
+    fn prefer_external_url() {
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+        let result = client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        assert!(!result.prefer_external_url);
+
+        let result = client
+            .update_subscription(
+                USER_ID,
+                "rust",
+                SubscriptionChanges {
+                    prefer_external_url: Some(true),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(result, 1);
+        let result = client.get_user_subscriptions(USER_ID).unwrap();
+        assert!(result[0].prefer_external_url);
+    }
+
+    #[test]
+    #[serial]
+    fn link_style() {
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+        let result = client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        assert_eq!(result.link_style, "permalink");
+
+        let result = client
+            .update_subscription(
+                USER_ID,
+                "rust",
+                SubscriptionChanges {
+                    link_style: Some("short"),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(result, 1);
+        let result = client
+            .update_subscription(
+                USER_ID,
+                "golang",
+                SubscriptionChanges {
+                    link_style: Some("short"),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(result, 0);
+        let result = client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(result[0].link_style, "short");
+    }
+
+    #[test]
+    #[serial]
+    fn upsert_subscription() {
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+        client.set_default_post_limit(USER_ID, Some(5)).unwrap();
+        let spec = NewSubscriptionSpec {
+            subreddit: "rust",
+            send_on: 0,
+            send_at: 12,
+        };
+
+        let (created, is_new) = client
+            .upsert_subscription(USER_ID, &spec, &SubscribeOptions::default())
+            .unwrap();
+        assert!(is_new);
+        assert_eq!(created.subreddit, "rust");
+        assert_eq!(created.send_on, 0);
+        assert_eq!(created.send_at, 12);
+        assert_eq!(created.post_limit, 5);
+        assert_eq!(created.target_chat_id, USER_ID);
+        assert!(created.last_sent_at.is_some());
+
+        client.set_post_limit(created.id, 25).unwrap();
+        let spec = NewSubscriptionSpec {
+            send_on: 6,
+            send_at: 8,
+            ..spec
+        };
+        let (updated, is_new) = client
+            .upsert_subscription(USER_ID, &spec, &SubscribeOptions::default())
+            .unwrap();
+        assert!(!is_new);
+        assert_eq!(updated.id, created.id);
+        assert_eq!(updated.send_on, 6);
+        assert_eq!(updated.send_at, 8);
+        assert_eq!(updated.post_limit, 25);
+        assert_eq!(updated.last_sent_at, created.last_sent_at);
+        assert_eq!(client.get_subscription_count(USER_ID).unwrap(), 1);
+
+        let options = SubscribeOptions {
+            post_limit: Some(7),
+            listing: Some(("new", "day")),
+            listing_given: true,
+            ..Default::default()
+        };
+        let (updated, is_new) = client
+            .upsert_subscription(USER_ID, &spec, &options)
+            .unwrap();
+        assert!(!is_new);
+        assert_eq!(updated.post_limit, 7);
+        assert_eq!(
+            (updated.sort.as_str(), updated.timeframe.as_str()),
+            ("new", "day")
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn upsert_subscriptions() {
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+        let existing = client.subscribe(USER_ID, "golang", 0, 12).unwrap();
+        client.set_listing(existing.id, "hot", "day").unwrap();
+
+        let specs = [
+            NewSubscriptionSpec {
+                subreddit: "rust",
+                send_on: 3,
+                send_at: 8,
+            },
+            NewSubscriptionSpec {
+                subreddit: "golang",
+                send_on: 3,
+                send_at: 8,
+            },
+        ];
+        let options = SubscribeOptions {
+            post_limit: Some(5),
+            once: true,
+            listing: Some(("top", "month")),
+            listing_given: false,
+        };
+        let result = client
+            .upsert_subscriptions(USER_ID, &specs, &options)
+            .unwrap();
+        assert_eq!(result.len(), 2);
+        let (rust, created) = &result[0];
+        assert!(created);
+        assert_eq!(rust.subreddit, "rust");
+        assert_eq!(rust.send_on, 3);
+        assert_eq!(rust.post_limit, 5);
+        assert!(!rust.recurring);
+        assert_eq!(
+            (rust.sort.as_str(), rust.timeframe.as_str()),
+            ("top", "month")
+        );
+        let (golang, created) = &result[1];
+        assert!(!created);
+        assert_eq!(golang.id, existing.id);
+        assert_eq!(golang.send_on, 3);
+        assert_eq!(golang.post_limit, 5);
+        assert!(golang.recurring);
+        // The listing only given by the instance defaults doesn't replace the user's own.
+        assert_eq!(
+            (golang.sort.as_str(), golang.timeframe.as_str()),
+            ("hot", "day")
+        );
+
+        let options = SubscribeOptions {
+            listing_given: true,
+            ..options
+        };
+        let result = client
+            .upsert_subscriptions(USER_ID, &specs[1..], &options)
+            .unwrap();
+        let (golang, _) = &result[0];
+        assert_eq!(
+            (golang.sort.as_str(), golang.timeframe.as_str()),
+            ("top", "month")
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn upsert_subscriptions_once_then_recurring() {
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+        let specs = [NewSubscriptionSpec {
+            subreddit: "rust",
+            send_on: 0,
+            send_at: 12,
+        }];
+        let once = SubscribeOptions {
+            once: true,
+            ..Default::default()
+        };
+        let result = client.upsert_subscriptions(USER_ID, &specs, &once).unwrap();
+        assert!(!result[0].0.recurring);
+
+        let result = client
+            .upsert_subscriptions(USER_ID, &specs, &SubscribeOptions::default())
+            .unwrap();
+        let (rust, created) = &result[0];
+        assert!(!created);
+        assert!(rust.recurring);
+
+        let result = client.upsert_subscriptions(USER_ID, &specs, &once).unwrap();
+        assert!(result[0].0.recurring);
+    }
+
+    #[test]
+    #[serial]
+    fn upsert_subscriptions_all_or_nothing() {
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+        client
+            .conn
+            .execute(
+                "CREATE TRIGGER reject_golang BEFORE INSERT ON users_subscriptions \
+                 WHEN NEW.subreddit = 'golang' BEGIN SELECT RAISE(ABORT, 'rejected'); END",
+            )
+            .unwrap();
+
+        let specs = [
+            NewSubscriptionSpec {
+                subreddit: "rust",
+                send_on: 0,
+                send_at: 12,
+            },
+            NewSubscriptionSpec {
+                subreddit: "golang",
+                send_on: 0,
+                send_at: 12,
+            },
+        ];
+        let options = SubscribeOptions {
+            post_limit: Some(5),
+            ..Default::default()
+        };
+        let result = client.upsert_subscriptions(USER_ID, &specs, &options);
+        assert!(result.is_err());
+        assert!(client.get_user_subscriptions(USER_ID).unwrap().is_empty());
+    }
+
+    #[test]
+    #[serial]
+    fn target_chat_ids() {
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+        client.create_user("2").unwrap();
+        client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        client.subscribe(USER_ID, "golang", 0, 12).unwrap();
+        client.subscribe("2", "rust", 0, 12).unwrap();
+        client
+            .update_subscription(
+                "2",
+                "rust",
+                SubscriptionChanges {
+                    target_chat_id: Some("-100123"),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+        let result = client.get_target_chat_ids().unwrap();
+        assert_eq!(result, ["-100123", USER_ID]);
+    }
+
+    #[test]
+    #[serial]
+    fn target_chat_id() {
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+        let result = client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        assert_eq!(result.target_chat_id, USER_ID);
+
+        let result = client
+            .update_subscription(
+                USER_ID,
+                "rust",
+                SubscriptionChanges {
+                    target_chat_id: Some("-100123"),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(result, 1);
+        let result = client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(result[0].target_chat_id, "-100123");
+
+        let result = client
+            .update_subscription(
+                USER_ID,
+                "golang",
+                SubscriptionChanges {
+                    target_chat_id: Some("-100123"),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(result, 0);
+    }
+
+    #[test]
+    #[serial]
+    fn update_subscription_clears_nullable() {
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+        client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        client
+            .update_subscription(
+                USER_ID,
+                "rust",
+                SubscriptionChanges {
+                    tag: Some(Some("#rust")),
+                    max_title_len: Some(Some(40)),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+        let result = client
+            .update_subscription(
+                USER_ID,
+                "rust",
+                SubscriptionChanges {
+                    tag: Some(None),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(result, 1);
+        let result = client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(result[0].tag, None);
+        assert_eq!(result[0].max_title_len, Some(40));
+    }
+
+    #[test]
+    #[serial]
+    fn touch_last_sent() {
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+        let subscription = client.subscribe(USER_ID, "rust", 0, 12).unwrap();
 
+        client
+            .touch_last_sent(subscription.id, &["aaa", "bbb"])
+            .unwrap();
+        let result = client.get_sent_post_ids(subscription.id).unwrap();
+        assert_eq!(result, ["aaa", "bbb"]);
         let result = client.get_subscriptions().unwrap();
-        assert_eq!(result.len(), 1);
-        assert_eq!(result[0].subreddit, "rust");
+        assert_ne!(result[0].last_sent_at, subscription.last_sent_at);
+
+        client.touch_last_sent(subscription.id, &["bbb"]).unwrap();
+        let result = client.get_sent_post_ids(subscription.id).unwrap();
+        assert_eq!(result, ["aaa", "bbb"]);
     }
 
     #[test]
     #[serial]
-    fn update_last_sent() {
+    fn touch_last_sent_empty_streak() {
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+        let subscription = client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        assert_eq!(subscription.empty_streak, 0);
+
+        let result = client.touch_last_sent(subscription.id, &[]).unwrap();
+        assert_eq!(result, 1);
+        let result = client.touch_last_sent(subscription.id, &[]).unwrap();
+        assert_eq!(result, 2);
+        let result = client.get_subscriptions().unwrap();
+        assert_eq!(result[0].empty_streak, 2);
+
+        let result = client.touch_last_sent(subscription.id, &["aaa"]).unwrap();
+        assert_eq!(result, 0);
+        let result = client.touch_last_sent(subscription.id, &[]).unwrap();
+        assert_eq!(result, 1);
+    }
+
+    #[test]
+    #[serial]
+    fn rename_subscription() {
         let client = setup_test_db();
         client.create_user(USER_ID).unwrap();
+        let subscription = client.subscribe(USER_ID, "rust", 3, 18).unwrap();
+        client.subscribe(USER_ID, "golang", 0, 12).unwrap();
+        client.set_post_limit(subscription.id, 5).unwrap();
 
+        let result = client
+            .rename_subscription(USER_ID, "rust", "rustlang")
+            .unwrap();
+        assert_eq!(result, 1);
         let result = client.get_user_subscriptions(USER_ID).unwrap();
-        assert_eq!(result.len(), 0);
+        let renamed = result.iter().find(|s| s.id == subscription.id).unwrap();
+        assert_eq!(renamed.subreddit, "rustlang");
+        assert_eq!(renamed.send_on, 3);
+        assert_eq!(renamed.send_at, 18);
+        assert_eq!(renamed.post_limit, 5);
+
+        let result = client.rename_subscription(USER_ID, "rust", "zig").unwrap();
+        assert_eq!(result, 0);
+
+        let result = client
+            .rename_subscription(USER_ID, "rustlang", "golang")
+            .unwrap_err();
+        let result = format!("{}", result);
+        assert!(result.contains("UNIQUE constraint failed"));
+    }
+
+    #[test]
+    #[serial]
+    fn get_subscriptions_with_user_settings() {
+        const SECOND_USER_ID: &str = "2";
+
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+        client.create_user(SECOND_USER_ID).unwrap();
+        client
+            .conn
+            .execute("UPDATE users SET timezone = 'Europe/Riga' WHERE id = '2'")
+            .unwrap();
+        client.set_combined_digest(SECOND_USER_ID, true).unwrap();
+        client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        client.subscribe(SECOND_USER_ID, "golang", 0, 12).unwrap();
+        client.subscribe(SECOND_USER_ID, "rust", 0, 12).unwrap();
+
+        let result = client
+            .get_subscriptions_with_user_settings()
+            .unwrap()
+            .into_iter()
+            .map(|(subscription, timezone, combined_digest)| {
+                (
+                    subscription.user_id,
+                    subscription.subreddit,
+                    timezone,
+                    combined_digest,
+                )
+            })
+            .collect::<Vec<(String, String, String, bool)>>();
+        assert_eq!(
+            result,
+            [
+                (
+                    USER_ID.to_string(),
+                    "rust".to_string(),
+                    "UTC".to_string(),
+                    false
+                ),
+                (
+                    SECOND_USER_ID.to_string(),
+                    "golang".to_string(),
+                    "Europe/Riga".to_string(),
+                    true
+                ),
+                (
+                    SECOND_USER_ID.to_string(),
+                    "rust".to_string(),
+                    "Europe/Riga".to_string(),
+                    true
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn delivery_history() {
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+        let subscription = client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        client.subscribe(USER_ID, "golang", 0, 12).unwrap();
+
+        let result = client.delivery_history(USER_ID, "rust", 2).unwrap();
+        assert_eq!(result, Some((0, vec![])));
+        let result = client.delivery_history(USER_ID, "zig", 2).unwrap();
+        assert_eq!(result, None);
+
+        client
+            .touch_last_sent(subscription.id, &["a", "b"])
+            .unwrap();
+        client.touch_last_sent(subscription.id, &[]).unwrap();
+        client.touch_last_sent(subscription.id, &["c"]).unwrap();
+
+        let (count, recent) = client
+            .delivery_history(USER_ID, "rust", 2)
+            .unwrap()
+            .unwrap();
+        assert_eq!(count, 3);
+        let post_counts = recent
+            .iter()
+            .map(|delivery| delivery.post_count)
+            .collect::<Vec<i32>>();
+        assert_eq!(post_counts, [1, 0]);
+
+        let result = client.delivery_history(USER_ID, "golang", 2).unwrap();
+        assert_eq!(result, Some((0, vec![])));
+    }
+
+    #[test]
+    #[serial]
+    fn top_subreddits() {
+        let client = setup_test_db();
+        for user_id in &["1", "2", "3"] {
+            client.create_user(user_id).unwrap();
+            client.subscribe(user_id, "rust", 0, 12).unwrap();
+        }
+        client.subscribe("1", "golang", 0, 12).unwrap();
+        client.subscribe("2", "golang", 0, 12).unwrap();
+        client.subscribe("3", "zig", 0, 12).unwrap();
+        client.subscribe("1", "elixir", 0, 12).unwrap();
+
+        let result = client.top_subreddits(3).unwrap();
+        assert_eq!(
+            result,
+            [
+                ("rust".to_string(), 3),
+                ("golang".to_string(), 2),
+                ("elixir".to_string(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn post_subscriptions() {
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+        let permalink = "/r/rust/comments/fbenua/a_halfhour_to_learn_rust/";
+
+        let post_subscription = client.watch_post(USER_ID, permalink).unwrap();
+        assert_eq!(post_subscription.user_id, USER_ID);
+        assert_eq!(post_subscription.permalink, permalink);
+        assert_eq!(
+            post_subscription.last_checked_at,
+            post_subscription.created_at
+        );
+        assert!(matches!(
+            client.watch_post(USER_ID, permalink),
+            Err(Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _))
+        ));
+
+        client
+            .touch_post_checked(post_subscription.id, "2020-03-22T12:00:05+00:00")
+            .unwrap();
+        let result = client.get_post_subscriptions().unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].last_checked_at, "2020-03-22T12:00:05+00:00");
+
+        client.delete_user(USER_ID).unwrap();
+        assert!(client.get_post_subscriptions().unwrap().is_empty());
+    }
+
+    #[test]
+    #[serial]
+    fn delete_post_subscription() {
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+        let permalink = "/r/rust/comments/fbenua/a_halfhour_to_learn_rust/";
+        client.watch_post(USER_ID, permalink).unwrap();
+
+        assert!(!client
+            .delete_post_subscription(USER_ID, "/r/rust/comments/other/")
+            .unwrap());
+        assert!(client.delete_post_subscription(USER_ID, permalink).unwrap());
+        assert!(client.get_post_subscriptions().unwrap().is_empty());
+        assert!(!client.delete_post_subscription(USER_ID, permalink).unwrap());
+    }
+
+    #[test]
+    #[serial]
+    fn delete_expired_post_subscriptions() {
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+        client.watch_post(USER_ID, "/r/rust/comments/old/").unwrap();
+        client
+            .watch_post(USER_ID, "/r/rust/comments/recent/")
+            .unwrap();
+        let created_at = (Utc::now() - Duration::days(POST_SUBSCRIPTION_TTL_DAYS + 1)).to_rfc3339();
+        client
+            .conn
+            .execute(&format!(
+                "UPDATE post_subscriptions SET created_at = '{}' WHERE permalink = '/r/rust/comments/old/'",
+                created_at
+            ))
+            .unwrap();
+
+        assert_eq!(client.delete_expired_post_subscriptions().unwrap(), 1);
+        let result = client.get_post_subscriptions().unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].permalink, "/r/rust/comments/recent/");
+    }
+
+    #[test]
+    #[serial]
+    fn feedback() {
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+
+        let first = client.save_feedback(USER_ID, "Other", "great bot").unwrap();
+        assert_eq!(first.user_id, USER_ID);
+        assert_eq!(first.text, "great bot");
+        assert_eq!(first.category, "Other");
+        assert!(!first.delivered);
+        let second = client
+            .save_feedback(USER_ID, "Feature request", "add more feeds")
+            .unwrap();
+        assert_eq!(
+            client.get_undelivered_feedback().unwrap(),
+            [first.clone(), second.clone()]
+        );
+
+        client.set_feedback_delivered(first.id).unwrap();
+        assert_eq!(client.get_undelivered_feedback().unwrap(), [second]);
+
+        // Feedback outlives the user, it still has to reach the author.
+        client.delete_user(USER_ID).unwrap();
+        assert_eq!(client.get_undelivered_feedback().unwrap().len(), 1);
+    }
+
+    #[test]
+    #[serial]
+    fn inactive_users() {
+        let client = setup_test_db();
+        client.create_user("inactive").unwrap();
+        client.create_user("subscribed").unwrap();
+        client.subscribe("subscribed", "rust", 0, 12).unwrap();
+        client.create_user("watching").unwrap();
+        client
+            .watch_post(
+                "watching",
+                "/r/rust/comments/fbenua/a_halfhour_to_learn_rust/",
+            )
+            .unwrap();
+        client
+            .conn
+            .execute("UPDATE users SET created_at = '2020-03-22T12:00:05+00:00'")
+            .unwrap();
+        client.create_user("new").unwrap();
+
+        let since = Utc::now() - Duration::days(30);
+        let result = client
+            .get_inactive_users(since)
+            .unwrap()
+            .into_iter()
+            .map(|user| user.id)
+            .collect::<Vec<String>>();
+        assert_eq!(result, ["inactive"]);
+
+        assert_eq!(client.delete_inactive_users(since).unwrap(), 1);
+        assert!(!client.user_exists("inactive").unwrap());
+        assert!(client.user_exists("subscribed").unwrap());
+        assert!(client.user_exists("watching").unwrap());
+        assert!(client.user_exists("new").unwrap());
+        assert!(client.get_inactive_users(since).unwrap().is_empty());
+    }
+
+    #[test]
+    #[serial]
+    fn stale_subscriptions() {
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+        let stale = client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        let never_sent = client.subscribe(USER_ID, "golang", 0, 12).unwrap();
+        client.subscribe(USER_ID, "zig", 0, 12).unwrap();
+        client
+            .conn
+            .execute(&format!(
+                "UPDATE users_subscriptions SET last_sent_at = '2020-03-22T12:00:05+00:00' WHERE id = {}",
+                stale.id
+            ))
+            .unwrap();
+        client
+            .conn
+            .execute(&format!(
+                "UPDATE users_subscriptions SET last_sent_at = NULL WHERE id = {}",
+                never_sent.id
+            ))
+            .unwrap();
+
+        let result = client
+            .get_stale_subscriptions(Utc::now() - Duration::days(14))
+            .unwrap()
+            .into_iter()
+            .map(|subscription| subscription.subreddit)
+            .collect::<Vec<String>>();
+        assert_eq!(result, ["golang", "rust"]);
+    }
+
+    #[test]
+    #[serial]
+    fn blocked_subreddits() {
+        let client = setup_test_db();
+        assert!(!client.is_subreddit_blocked("spam").unwrap());
 
+        assert_eq!(client.block_subreddit("Spam").unwrap(), 1);
+        assert_eq!(client.block_subreddit("spam").unwrap(), 0);
+        client.block_subreddit("abuse").unwrap();
+        assert!(client.is_subreddit_blocked("spam").unwrap());
+        assert!(client.is_subreddit_blocked("SPAM").unwrap());
+        assert!(!client.is_subreddit_blocked("rust").unwrap());
+        assert!(client.is_subreddit_blocked("rust+Spam").unwrap());
+        assert!(!client.is_subreddit_blocked("rust+golang").unwrap());
+        assert_eq!(client.get_blocked_subreddits().unwrap(), ["abuse", "spam"]);
+
+        assert_eq!(client.unblock_subreddit("SPAM").unwrap(), 1);
+        assert_eq!(client.unblock_subreddit("spam").unwrap(), 0);
+        assert!(!client.is_subreddit_blocked("spam").unwrap());
+    }
+
+    #[test]
+    #[serial]
+    fn prune_orphan_subscriptions() {
+        const SECOND_USER_ID: &str = "2";
+
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+        client.create_user(SECOND_USER_ID).unwrap();
         client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        client.subscribe(USER_ID, "golang", 0, 12).unwrap();
+        client.subscribe(SECOND_USER_ID, "rust", 0, 12).unwrap();
+
+        let result = client.prune_orphan_subscriptions().unwrap();
+        assert_eq!(result, 0);
+
+        // Orphans can only appear with foreign keys disabled.
+        client.conn.execute("PRAGMA foreign_keys = OFF").unwrap();
+        client.delete_user(USER_ID).unwrap();
+        client.conn.execute("PRAGMA foreign_keys = ON").unwrap();
+        assert!(!client.user_exists(USER_ID).unwrap());
+        assert!(client.user_exists(SECOND_USER_ID).unwrap());
 
+        let result = client.prune_orphan_subscriptions().unwrap();
+        assert_eq!(result, 2);
         let result = client.get_subscriptions().unwrap();
         assert_eq!(result.len(), 1);
-        assert_eq!(result[0].subreddit, "rust");
-        assert_eq!(result[0].send_on, 0);
-        assert_eq!(result[0].send_at, 12);
+        assert_eq!(result[0].user_id, SECOND_USER_ID);
+    }
 
-        client.update_last_sent(result[0].id).unwrap();
+    #[test]
+    #[serial]
+    fn touch_last_sent_rollback() {
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+        let subscription = client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        client.conn.execute("DROP TABLE sent_posts").unwrap();
+
+        let result = client.touch_last_sent(subscription.id, &["aaa"]);
+        assert!(result.is_err());
         let result = client.get_subscriptions().unwrap();
-        assert!(result[0].last_sent_at.is_some());
+        assert_eq!(result[0].last_sent_at, subscription.last_sent_at);
+    }
+
+    #[test]
+    #[serial]
+    fn restore_subscription() {
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+
+        let result = client.restore_subscription(USER_ID).unwrap();
+        assert!(result.is_none());
+
+        client.subscribe(USER_ID, "rust", 3, 18).unwrap();
+        client.subscribe(USER_ID, "golang", 1, 8).unwrap();
+        client.unsubscribe(USER_ID, "rust").unwrap();
+        client.unsubscribe(USER_ID, "golang").unwrap();
+        let result = client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(result.len(), 0);
+
+        let result = client.restore_subscription(USER_ID).unwrap().unwrap();
+        assert_eq!(result.subreddit, "golang");
+        assert_eq!(result.send_on, 1);
+        assert_eq!(result.send_at, 8);
+
+        let result = client.restore_subscription(USER_ID).unwrap().unwrap();
+        assert_eq!(result.subreddit, "rust");
+        assert_eq!(result.send_on, 3);
+        assert_eq!(result.send_at, 18);
+
+        let result = client.restore_subscription(USER_ID).unwrap();
+        assert!(result.is_none());
+        let result = client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(result.len(), 2);
+    }
+
+    #[test]
+    #[serial]
+    fn get_removed_subscription() {
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+        assert!(client.get_removed_subscription(USER_ID).unwrap().is_none());
+
+        client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        client.subscribe(USER_ID, "golang", 0, 12).unwrap();
+        client.unsubscribe(USER_ID, "rust").unwrap();
+        client.unsubscribe(USER_ID, "golang").unwrap();
+        let removed = client.get_removed_subscription(USER_ID).unwrap().unwrap();
+        assert_eq!(removed.subreddit, "golang");
+
+        client
+            .conn
+            .execute("UPDATE removed_subscriptions SET removed_at = '2020-03-22T12:00:00+00:00'")
+            .unwrap();
+        assert!(client.get_removed_subscription(USER_ID).unwrap().is_none());
+    }
+
+    #[test]
+    #[serial]
+    fn restore_subscription_settings() {
+        use schema::users_subscriptions::dsl;
+
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+        let subscription = client.subscribe(USER_ID, "rust", 3, 18).unwrap();
+        let settings = SubscriptionSettings {
+            max_age_hours: Some(48),
+            post_limit: 5,
+            include_excerpt: true,
+            target_chat_id: String::from("-100123"),
+            include_top_comment: true,
+            prefer_external_url: true,
+            empty_text: Some(String::from("Nothing new.")),
+            error_text: Some(String::from("Down again.")),
+            as_poll: true,
+            max_title_len: Some(40),
+            tag: Some(String::from("#rust")),
+            posts_per_message: Some(5),
+            as_photos: true,
+            recurring: false,
+            edit_in_place: true,
+            sort: String::from("hot"),
+            timeframe: String::from("day"),
+            disable_notification: true,
+            link_preview: true,
+            link_style: String::from("short"),
+            protect_content: true,
+            since_last_sent: true,
+            show_scores: true,
+        };
+        diesel::update(dsl::users_subscriptions.find(subscription.id))
+            .set(&settings)
+            .execute(&client.conn)
+            .unwrap();
+
+        client.unsubscribe(USER_ID, "rust").unwrap();
+        let result = client.restore_subscription(USER_ID).unwrap().unwrap();
+        assert_eq!(result.subreddit, "rust");
+        assert_eq!(SubscriptionSettings::from(&result), settings);
+    }
+
+    #[test]
+    #[serial]
+    fn restore_subscription_expired() {
+        use schema::removed_subscriptions::dsl;
+
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+        client.subscribe(USER_ID, "rust", 3, 18).unwrap();
+        client.unsubscribe(USER_ID, "rust").unwrap();
+
+        let removed_at = (Utc::now() - Duration::hours(25)).to_rfc3339();
+        diesel::update(dsl::removed_subscriptions)
+            .set(dsl::removed_at.eq(removed_at))
+            .execute(&client.conn)
+            .unwrap();
+
+        let result = client.restore_subscription(USER_ID).unwrap();
+        assert!(result.is_none());
+        let result = client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(result.len(), 0);
     }
 
     #[test]
@@ -363,4 +2983,62 @@ mod test {
         let result = client.get_users_dialog(USER_ID);
         assert!(result.is_err());
     }
+
+    #[test]
+    #[serial]
+    fn users_dialog_opt() {
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+
+        let result = client.get_users_dialog_opt(USER_ID).unwrap();
+        assert_eq!(result, None);
+
+        let dialog = DialogEntity {
+            user_id: USER_ID.to_string(),
+            command: "/subscribe".to_string(),
+            step: "One".to_string(),
+            data: "".to_string(),
+        };
+        client.insert_or_update_dialog(&dialog).unwrap();
+        let result = client.get_users_dialog_opt(USER_ID).unwrap();
+        assert_eq!(result, Some(dialog));
+    }
+
+    #[test]
+    #[serial]
+    fn users_dialog_opt_error() {
+        let client = setup_test_db_with(false);
+
+        let result = client.get_users_dialog_opt(USER_ID);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn all_dialogs() {
+        let client = setup_test_db();
+        client.create_user(USER_ID).unwrap();
+        client.create_user("2").unwrap();
+
+        let result = client.get_all_dialogs().unwrap();
+        assert!(result.is_empty());
+
+        let subscribe = DialogEntity {
+            user_id: "2".to_string(),
+            command: "/subscribe".to_string(),
+            step: "Weekday".to_string(),
+            data: r#"{"Subreddit":"rust"}"#.to_string(),
+        };
+        let feedback = DialogEntity {
+            user_id: USER_ID.to_string(),
+            command: "/feedback".to_string(),
+            step: "Start".to_string(),
+            data: "{}".to_string(),
+        };
+        client.insert_or_update_dialog(&subscribe).unwrap();
+        client.insert_or_update_dialog(&feedback).unwrap();
+
+        let result = client.get_all_dialogs().unwrap();
+        assert_eq!(result, [feedback, subscribe]);
+    }
 }
@@ -1,4 +1,15 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use num::traits::FromPrimitive;
+use serde::{Deserialize, Serialize};
+
+use super::schema::blocked_subreddits;
+use super::schema::deliveries;
 use super::schema::dialogs;
+use super::schema::feedback;
+use super::schema::post_subscriptions;
+use super::schema::removed_subscriptions;
+use super::schema::sent_posts;
 use super::schema::users;
 use super::schema::users_subscriptions;
 
@@ -7,6 +18,14 @@ use super::schema::users_subscriptions;
 pub struct User {
     pub id: String,
     pub created_at: String,
+    pub default_post_limit: Option<i32>,
+    pub timezone: String,
+    pub combined_digest: bool,
+    /// Defaults new subscriptions start with, set via /prefs.
+    pub disable_notification: bool,
+    pub link_preview: bool,
+    /// Language tag picking the thousands separator of scores, e.g. "de", set via /locale.
+    pub locale: Option<String>,
 }
 
 #[derive(Debug, Queryable, Default)]
@@ -17,6 +36,124 @@ pub struct Subscription {
     pub last_sent_at: Option<String>,
     pub send_on: i32,
     pub send_at: i32,
+    pub max_age_hours: Option<i64>,
+    pub post_limit: i32,
+    pub include_excerpt: bool,
+    pub empty_streak: i32,
+    pub target_chat_id: String,
+    pub include_top_comment: bool,
+    pub prefer_external_url: bool,
+    pub sort_order: i32,
+    pub empty_text: Option<String>,
+    pub error_text: Option<String>,
+    pub as_poll: bool,
+    pub max_title_len: Option<i32>,
+    pub tag: Option<String>,
+    /// Posts per digest message, None to only split messages that get too long.
+    pub posts_per_message: Option<i32>,
+    /// Image posts are sent as photos, NSFW ones blurred until tapped.
+    pub as_photos: bool,
+    /// One-off subscriptions are removed after their digest has been sent.
+    pub recurring: bool,
+    /// The previous digest is edited instead of sending a new one, see `last_message_id`.
+    pub edit_in_place: bool,
+    /// Message of the previous digest, None when it took more than one message.
+    pub last_message_id: Option<String>,
+    /// Listing the posts come from, e.g. "top" posts of the "week".
+    pub sort: String,
+    pub timeframe: String,
+    /// Digests are sent without a notification sound.
+    pub disable_notification: bool,
+    /// Telegram shows a preview of the first link in the digest.
+    pub link_preview: bool,
+    /// Extra link shown under each post, see `LinkStyle`.
+    pub link_style: String,
+    /// Digests can't be forwarded or saved, e.g. for channels sharing them.
+    pub protect_content: bool,
+    /// Digests contain the newest posts since the last one was sent instead of the listing's top
+    /// posts, up to `post_limit`.
+    pub since_last_sent: bool,
+    /// The score of each post is shown next to its title.
+    pub show_scores: bool,
+}
+
+/// The user's IANA timezone, e.g. "Europe/Riga". Unknown names fall back to UTC.
+pub fn parse_timezone(name: &str) -> Tz {
+    name.parse().unwrap_or(Tz::UTC)
+}
+
+/// `send_on` of the weekday. Days are numbered from Monday as 0 to Sunday as 6, the same
+/// numbering `Weekday::from_i32` reads them back with.
+pub fn send_on_index(weekday: Weekday) -> i32 {
+    weekday.num_days_from_monday() as i32
+}
+
+impl Subscription {
+    /// Day the subscription is sent on, None for a `send_on` outside of 0..=6.
+    pub fn weekday(&self) -> Option<Weekday> {
+        Weekday::from_i32(self.send_on)
+    }
+
+    /// Next time the subscription is due in the user's `timezone`, a slot that has already passed
+    /// rolls over to next week. An hour outside of 0..24 is clamped into the day.
+    pub fn next_send_at(&self, now: DateTime<Utc>, timezone: Tz) -> DateTime<Utc> {
+        let local_now = now.with_timezone(&timezone);
+        let weekday = local_now.weekday().num_days_from_monday() as i64;
+        let days_ahead = (self.send_on as i64 - weekday).rem_euclid(7);
+        let hour = self.send_at.clamp(0, 23) as u32;
+        let slot = |date: NaiveDate| {
+            let local = date.and_hms_opt(hour, 0, 0).unwrap();
+            // An hour skipped by a daylight saving change is read as UTC.
+            timezone
+                .from_local_datetime(&local)
+                .earliest()
+                .unwrap_or_else(|| timezone.from_utc_datetime(&local))
+                .with_timezone(&Utc)
+        };
+        let date = local_now.date_naive() + Duration::days(days_ahead);
+        let next = slot(date);
+        if next < now {
+            slot(date + Duration::weeks(1))
+        } else {
+            next
+        }
+    }
+
+    /// Listing the posts come from, e.g. "top of the week" or "hot". Only top and controversial
+    /// listings have a timeframe.
+    fn listing(&self) -> String {
+        match (self.sort.as_str(), self.timeframe.as_str()) {
+            ("top", "all") | ("controversial", "all") => format!("{} of all time", self.sort),
+            ("top", _) | ("controversial", _) => format!("{} of the {}", self.sort, self.timeframe),
+            _ => self.sort.clone(),
+        }
+    }
+
+    /// Human readable one-liner, e.g. "rust - Sun at 12:00 UTC, top of the week, never sent". The
+    /// hour is in the user's `timezone`, the last sent time in UTC.
+    pub fn summary(&self, timezone: &str) -> String {
+        let day = match Weekday::from_i32(self.send_on) {
+            Some(day) => day.to_string(),
+            None => format!("day {}", self.send_on),
+        };
+        let last_sent = match self
+            .last_sent_at
+            .as_ref()
+            .and_then(|date| date.parse::<DateTime<Utc>>().ok())
+        {
+            Some(date) => format!("last sent {}", date.format("%a, %d %b at %H:%M UTC")),
+            None => "never sent".to_string(),
+        };
+        format!(
+            "{} - {} at {}:00 {}, {}, {}",
+            self.subreddit,
+            day,
+            self.send_at,
+            timezone,
+            self.listing(),
+            last_sent
+        )
+    }
 }
 
 #[derive(Insertable)]
@@ -27,6 +164,46 @@ pub struct NewSubscription<'a> {
     pub send_on: i32,
     pub send_at: i32,
     pub last_sent_at: Option<String>,
+    pub post_limit: i32,
+    pub target_chat_id: &'a str,
+    pub sort_order: i32,
+    pub disable_notification: bool,
+    pub link_preview: bool,
+    pub recurring: bool,
+    /// Left out of the insert when `None`, so the column default applies.
+    pub sort: Option<&'a str>,
+    pub timeframe: Option<&'a str>,
+}
+
+/// Outcome of `DbClient::run_migrations`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MigrationStatus {
+    /// Migrations applied by this run.
+    pub applied: usize,
+    /// Latest applied migration, e.g. "20200315155708". None for a database without any.
+    pub version: Option<String>,
+}
+
+/// A subscription to be created by `DbClient::upsert_subscription`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NewSubscriptionSpec<'a> {
+    pub subreddit: &'a str,
+    pub send_on: i32,
+    pub send_at: i32,
+}
+
+/// Settings `DbClient::upsert_subscriptions` applies to every subscription it creates or updates.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SubscribeOptions<'a> {
+    /// Overrides the user's default post limit.
+    pub post_limit: Option<i32>,
+    /// The subscriptions are removed after their next digest, otherwise they are made recurring.
+    /// An existing recurring subscription isn't turned into a one-off.
+    pub once: bool,
+    /// Sort and timeframe of new subscriptions.
+    pub listing: Option<(&'a str, &'a str)>,
+    /// Existing subscriptions keep their listing unless the user asked for one.
+    pub listing_given: bool,
 }
 
 #[derive(Debug, Queryable, Insertable, Clone, PartialEq)]
@@ -37,3 +214,338 @@ pub struct DialogEntity {
     pub step: String,
     pub data: String,
 }
+
+#[derive(Debug, Queryable, Insertable, Clone, PartialEq)]
+#[table_name = "sent_posts"]
+pub struct SentPost {
+    pub subscription_id: i32,
+    pub post_id: String,
+    pub sent_at: String,
+}
+
+/// A digest sent for a subscription.
+#[derive(Debug, Queryable, Clone, PartialEq)]
+pub struct Delivery {
+    pub id: i32,
+    pub subscription_id: i32,
+    pub sent_at: String,
+    pub post_count: i32,
+}
+
+#[derive(Insertable)]
+#[table_name = "deliveries"]
+pub struct NewDelivery<'a> {
+    pub subscription_id: i32,
+    pub sent_at: &'a str,
+    pub post_count: i32,
+}
+
+/// Feedback left via /feedback, kept until it has been forwarded to the author.
+#[derive(Debug, Queryable, Clone, PartialEq)]
+pub struct FeedbackEntity {
+    pub id: i32,
+    pub user_id: String,
+    pub text: String,
+    pub created_at: String,
+    pub delivered: bool,
+    /// Label of the category picked in the dialog, e.g. "Bug".
+    pub category: String,
+}
+
+#[derive(Insertable)]
+#[table_name = "feedback"]
+pub struct NewFeedback<'a> {
+    pub user_id: &'a str,
+    pub text: &'a str,
+    pub created_at: &'a str,
+    pub category: &'a str,
+}
+
+/// A reddit thread whose new top comments are sent to the user.
+#[derive(Debug, Queryable, Clone, PartialEq)]
+pub struct PostSubscription {
+    pub id: i32,
+    pub user_id: String,
+    pub permalink: String,
+    pub created_at: String,
+    pub last_checked_at: String,
+}
+
+#[derive(Insertable)]
+#[table_name = "post_subscriptions"]
+pub struct NewPostSubscription<'a> {
+    pub user_id: &'a str,
+    pub permalink: &'a str,
+    pub created_at: &'a str,
+    pub last_checked_at: &'a str,
+}
+
+/// Subreddit that can't be subscribed to on this instance, stored lowercase.
+#[derive(Debug, Queryable, Insertable, Clone, PartialEq)]
+#[table_name = "blocked_subreddits"]
+pub struct BlockedSubreddit {
+    pub subreddit: String,
+    pub blocked_at: String,
+}
+
+#[derive(Debug, Queryable)]
+pub struct RemovedSubscription {
+    pub id: i32,
+    pub user_id: String,
+    pub subreddit: String,
+    pub send_on: i32,
+    pub send_at: i32,
+    /// `SubscriptionSettings` as JSON.
+    pub settings: String,
+    #[allow(dead_code)]
+    pub removed_at: String,
+}
+
+#[derive(Insertable)]
+#[table_name = "removed_subscriptions"]
+pub struct NewRemovedSubscription<'a> {
+    pub user_id: &'a str,
+    pub subreddit: &'a str,
+    pub send_on: i32,
+    pub send_at: i32,
+    pub settings: String,
+    pub removed_at: String,
+}
+
+/// Everything the user chose for a subscription besides its schedule, kept when it's removed so
+/// /resubscribe can restore it as it was.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, AsChangeset)]
+#[table_name = "users_subscriptions"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct SubscriptionSettings {
+    pub max_age_hours: Option<i64>,
+    pub post_limit: i32,
+    pub include_excerpt: bool,
+    pub target_chat_id: String,
+    pub include_top_comment: bool,
+    pub prefer_external_url: bool,
+    pub empty_text: Option<String>,
+    pub error_text: Option<String>,
+    pub as_poll: bool,
+    pub max_title_len: Option<i32>,
+    pub tag: Option<String>,
+    pub posts_per_message: Option<i32>,
+    pub as_photos: bool,
+    pub recurring: bool,
+    pub edit_in_place: bool,
+    pub sort: String,
+    pub timeframe: String,
+    pub disable_notification: bool,
+    pub link_preview: bool,
+    pub link_style: String,
+    pub protect_content: bool,
+    pub since_last_sent: bool,
+    /// Settings removed before scores could be shown don't have it.
+    #[serde(default)]
+    pub show_scores: bool,
+}
+
+impl From<&Subscription> for SubscriptionSettings {
+    fn from(subscription: &Subscription) -> Self {
+        SubscriptionSettings {
+            max_age_hours: subscription.max_age_hours,
+            post_limit: subscription.post_limit,
+            include_excerpt: subscription.include_excerpt,
+            target_chat_id: subscription.target_chat_id.clone(),
+            include_top_comment: subscription.include_top_comment,
+            prefer_external_url: subscription.prefer_external_url,
+            empty_text: subscription.empty_text.clone(),
+            error_text: subscription.error_text.clone(),
+            as_poll: subscription.as_poll,
+            max_title_len: subscription.max_title_len,
+            tag: subscription.tag.clone(),
+            posts_per_message: subscription.posts_per_message,
+            as_photos: subscription.as_photos,
+            recurring: subscription.recurring,
+            edit_in_place: subscription.edit_in_place,
+            sort: subscription.sort.clone(),
+            timeframe: subscription.timeframe.clone(),
+            disable_notification: subscription.disable_notification,
+            link_preview: subscription.link_preview,
+            link_style: subscription.link_style.clone(),
+            protect_content: subscription.protect_content,
+            since_last_sent: subscription.since_last_sent,
+            show_scores: subscription.show_scores,
+        }
+    }
+}
+
+/// Changes to a single subscription's options, the fields left as `None` are kept. The nullable
+/// columns take `Some(None)` to go back to the default.
+#[derive(Debug, Default, AsChangeset)]
+#[table_name = "users_subscriptions"]
+pub struct SubscriptionChanges<'a> {
+    pub include_excerpt: Option<bool>,
+    pub include_top_comment: Option<bool>,
+    pub prefer_external_url: Option<bool>,
+    pub link_style: Option<&'a str>,
+    pub protect_content: Option<bool>,
+    pub since_last_sent: Option<bool>,
+    pub show_scores: Option<bool>,
+    pub as_poll: Option<bool>,
+    pub as_photos: Option<bool>,
+    pub edit_in_place: Option<bool>,
+    pub max_title_len: Option<Option<i32>>,
+    pub posts_per_message: Option<Option<i32>>,
+    pub tag: Option<Option<&'a str>>,
+    pub empty_text: Option<Option<&'a str>>,
+    pub error_text: Option<Option<&'a str>>,
+    pub target_chat_id: Option<&'a str>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subscription(send_on: i32, send_at: i32) -> Subscription {
+        Subscription {
+            send_on,
+            send_at,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn summary() {
+        let subscription = Subscription {
+            subreddit: "rust".to_string(),
+            send_on: 6,
+            send_at: 12,
+            last_sent_at: Some("2020-03-22T12:00:05+00:00".to_string()),
+            sort: "top".to_string(),
+            timeframe: "week".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            subscription.summary("UTC"),
+            "rust - Sun at 12:00 UTC, top of the week, last sent Sun, 22 Mar at 12:00 UTC"
+        );
+
+        let subscription = Subscription {
+            last_sent_at: None,
+            send_on: 0,
+            send_at: 8,
+            ..subscription
+        };
+        assert_eq!(
+            subscription.summary("UTC"),
+            "rust - Mon at 8:00 UTC, top of the week, never sent"
+        );
+
+        let subscription = Subscription {
+            sort: "hot".to_string(),
+            timeframe: "day".to_string(),
+            ..subscription
+        };
+        assert_eq!(
+            subscription.summary("UTC"),
+            "rust - Mon at 8:00 UTC, hot, never sent"
+        );
+
+        let subscription = Subscription {
+            sort: "controversial".to_string(),
+            timeframe: "month".to_string(),
+            ..subscription
+        };
+        assert_eq!(
+            subscription.summary("UTC"),
+            "rust - Mon at 8:00 UTC, controversial of the month, never sent"
+        );
+
+        let subscription = Subscription {
+            sort: "top".to_string(),
+            timeframe: "all".to_string(),
+            ..subscription
+        };
+        assert_eq!(
+            subscription.summary("UTC"),
+            "rust - Mon at 8:00 UTC, top of all time, never sent"
+        );
+    }
+
+    #[test]
+    fn weekday_round_trips() {
+        for weekday in &[Weekday::Mon, Weekday::Wed, Weekday::Sun] {
+            let result = subscription(send_on_index(*weekday), 12).weekday();
+            assert_eq!(result, Some(*weekday));
+        }
+        assert_eq!(send_on_index(Weekday::Sun), 6);
+        assert_eq!(subscription(7, 12).weekday(), None);
+    }
+
+    #[test]
+    fn next_send_at_later_this_week() {
+        // Wednesday
+        let now = Utc.with_ymd_and_hms(2020, 3, 18, 10, 30, 0).unwrap();
+        let result = subscription(4, 12).next_send_at(now, Tz::UTC);
+        assert_eq!(result, Utc.with_ymd_and_hms(2020, 3, 20, 12, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn next_send_at_later_today() {
+        // Sunday
+        let now = Utc.with_ymd_and_hms(2020, 3, 22, 11, 0, 0).unwrap();
+        let result = subscription(6, 12).next_send_at(now, Tz::UTC);
+        assert_eq!(result, Utc.with_ymd_and_hms(2020, 3, 22, 12, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn next_send_at_wraps_around() {
+        // Sunday 13:00, subscription is Sunday 12:00
+        let now = Utc.with_ymd_and_hms(2020, 3, 22, 13, 0, 0).unwrap();
+        let result = subscription(6, 12).next_send_at(now, Tz::UTC);
+        assert_eq!(result, Utc.with_ymd_and_hms(2020, 3, 29, 12, 0, 0).unwrap());
+
+        // Sunday, subscription is Monday
+        let result = subscription(0, 8).next_send_at(now, Tz::UTC);
+        assert_eq!(result, Utc.with_ymd_and_hms(2020, 3, 23, 8, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn next_send_at_boundary() {
+        // Sunday 12:00 exactly, the slot is due right now
+        let now = Utc.with_ymd_and_hms(2020, 3, 22, 12, 0, 0).unwrap();
+        let result = subscription(6, 12).next_send_at(now, Tz::UTC);
+        assert_eq!(result, now);
+
+        // A second later it has passed
+        let now = Utc.with_ymd_and_hms(2020, 3, 22, 12, 0, 1).unwrap();
+        let result = subscription(6, 12).next_send_at(now, Tz::UTC);
+        assert_eq!(result, Utc.with_ymd_and_hms(2020, 3, 29, 12, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn next_send_at_invalid_hour() {
+        // Wednesday
+        let now = Utc.with_ymd_and_hms(2020, 3, 18, 10, 30, 0).unwrap();
+        let result = subscription(4, 99).next_send_at(now, Tz::UTC);
+        assert_eq!(result, Utc.with_ymd_and_hms(2020, 3, 20, 23, 0, 0).unwrap());
+        let result = subscription(4, -1).next_send_at(now, Tz::UTC);
+        assert_eq!(result, Utc.with_ymd_and_hms(2020, 3, 20, 0, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn next_send_at_timezone() {
+        // Sunday 23:30 UTC is already Monday 01:30 in Riga.
+        let now = Utc.with_ymd_and_hms(2020, 3, 22, 23, 30, 0).unwrap();
+        let result = subscription(0, 8).next_send_at(now, Tz::Europe__Riga);
+        assert_eq!(result, Utc.with_ymd_and_hms(2020, 3, 23, 6, 0, 0).unwrap());
+
+        // Monday 08:00 in Riga has passed, wraps around to next week.
+        let now = Utc.with_ymd_and_hms(2020, 3, 23, 7, 0, 0).unwrap();
+        let result = subscription(0, 8).next_send_at(now, Tz::Europe__Riga);
+        assert_eq!(result, Utc.with_ymd_and_hms(2020, 3, 30, 5, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn parse_timezone_works() {
+        assert_eq!(parse_timezone("Europe/Riga"), Tz::Europe__Riga);
+        assert_eq!(parse_timezone("UTC"), Tz::UTC);
+        assert_eq!(parse_timezone("Mars/Olympus"), Tz::UTC);
+    }
+}
@@ -1,3 +1,19 @@
+table! {
+    blocked_subreddits (subreddit) {
+        subreddit -> Text,
+        blocked_at -> Text,
+    }
+}
+
+table! {
+    deliveries (id) {
+        id -> Integer,
+        subscription_id -> Integer,
+        sent_at -> Text,
+        post_count -> Integer,
+    }
+}
+
 table! {
     dialogs (user_id) {
         user_id -> Text,
@@ -7,10 +23,57 @@ table! {
     }
 }
 
+table! {
+    feedback (id) {
+        id -> Integer,
+        user_id -> Text,
+        text -> Text,
+        created_at -> Text,
+        delivered -> Bool,
+        category -> Text,
+    }
+}
+
+table! {
+    post_subscriptions (id) {
+        id -> Integer,
+        user_id -> Text,
+        permalink -> Text,
+        created_at -> Text,
+        last_checked_at -> Text,
+    }
+}
+
+table! {
+    removed_subscriptions (id) {
+        id -> Integer,
+        user_id -> Text,
+        subreddit -> Text,
+        send_on -> Integer,
+        send_at -> Integer,
+        settings -> Text,
+        removed_at -> Text,
+    }
+}
+
+table! {
+    sent_posts (subscription_id, post_id) {
+        subscription_id -> Integer,
+        post_id -> Text,
+        sent_at -> Text,
+    }
+}
+
 table! {
     users (id) {
         id -> Text,
         created_at -> Text,
+        default_post_limit -> Nullable<Integer>,
+        timezone -> Text,
+        combined_digest -> Bool,
+        disable_notification -> Bool,
+        link_preview -> Bool,
+        locale -> Nullable<Text>,
     }
 }
 
@@ -22,10 +85,50 @@ table! {
         last_sent_at -> Nullable<Text>,
         send_on -> Integer,
         send_at -> Integer,
+        max_age_hours -> Nullable<BigInt>,
+        post_limit -> Integer,
+        include_excerpt -> Bool,
+        empty_streak -> Integer,
+        target_chat_id -> Text,
+        include_top_comment -> Bool,
+        prefer_external_url -> Bool,
+        sort_order -> Integer,
+        empty_text -> Nullable<Text>,
+        error_text -> Nullable<Text>,
+        as_poll -> Bool,
+        max_title_len -> Nullable<Integer>,
+        tag -> Nullable<Text>,
+        posts_per_message -> Nullable<Integer>,
+        as_photos -> Bool,
+        recurring -> Bool,
+        edit_in_place -> Bool,
+        last_message_id -> Nullable<Text>,
+        sort -> Text,
+        timeframe -> Text,
+        disable_notification -> Bool,
+        link_preview -> Bool,
+        link_style -> Text,
+        protect_content -> Bool,
+        since_last_sent -> Bool,
+        show_scores -> Bool,
     }
 }
 
+joinable!(deliveries -> users_subscriptions (subscription_id));
 joinable!(dialogs -> users (user_id));
+joinable!(post_subscriptions -> users (user_id));
+joinable!(removed_subscriptions -> users (user_id));
+joinable!(sent_posts -> users_subscriptions (subscription_id));
 joinable!(users_subscriptions -> users (user_id));
 
-allow_tables_to_appear_in_same_query!(dialogs, users, users_subscriptions,);
+allow_tables_to_appear_in_same_query!(
+    blocked_subreddits,
+    deliveries,
+    dialogs,
+    feedback,
+    post_subscriptions,
+    removed_subscriptions,
+    sent_posts,
+    users,
+    users_subscriptions,
+);
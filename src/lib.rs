@@ -3,33 +3,315 @@ extern crate diesel;
 #[macro_use]
 extern crate diesel_migrations;
 
-use crate::bot::bot::init_bot;
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::time::Instant;
+
+use log::{info, warn};
+
+use crate::bot::bot::{init_bot, self_check, BotSettings};
 pub use crate::bot::error::BotError;
 use crate::db::client::DbClient;
+use crate::reddit::client::{Listing, RedditClient};
+use crate::task::metrics::{serve_metrics, SchedulerMetrics};
 use crate::task::task::init_task;
+use crate::telegram::client::TelegramClient;
 
 mod bot;
+// diesel 1.4's table!, derives and embed_migrations! put impl blocks inside a function or const
+// item, which newer compilers warn about.
+#[allow(unknown_lints, non_local_definitions)]
 mod db;
 mod reddit;
 mod task;
 mod telegram;
 
-embed_migrations!();
+/// Optional settings of the bot.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Address the scheduler metrics are served on, not served when unset.
+    pub metrics_addr: Option<String>,
+    /// With the scheduler off digests are never sent automatically, all commands including
+    /// /sendnow keep working.
+    pub enable_scheduler: bool,
+    /// Appended to the end of every digest.
+    pub footer: Option<String>,
+    /// Listing of new subscriptions, top of the week when not set. Users can still override it
+    /// when subscribing.
+    pub default_sort: Option<String>,
+    pub default_timeframe: Option<String>,
+    /// The author is sent a message once the bot is up.
+    pub notify_startup: bool,
+    /// Commands a single user may send per minute, 0 lifts the limit. Other users are only counted
+    /// when they use one of the public commands, /about and /popular.
+    pub commands_per_minute: u32,
+    /// Attempts at fetching a subreddit's posts when reddit times out or can't be reached, at
+    /// least one is made.
+    pub fetch_attempts: u32,
+}
 
+/// Runs the bot until it fails. The token, the default sort and timeframe and `author_id`, which
+/// has to be a numeric Telegram user id, are verified before anything starts.
 pub async fn start(
     tg_token: String,
-    bot_name: String,
     database_url: String,
     author_id: String,
+    config: Config,
 ) -> Result<(), BotError> {
-    run_migrations(&database_url);
-    init_task(tg_token.clone(), database_url.clone());
-    init_bot(&tg_token, &bot_name, &database_url, &author_id).await;
+    let listing = default_listing(
+        config.default_sort.as_deref(),
+        config.default_timeframe.as_deref(),
+    )
+    .map_err(BotError::Config)?;
+    validate_author_id(&author_id)?;
+    let metrics_listener = bind_metrics(config.metrics_addr.as_deref(), config.enable_scheduler)?;
+    let bot_name = self_check(
+        &TelegramClient::new(tg_token.clone()),
+        &author_id,
+        config.notify_startup,
+    )
+    .await?;
+    let settings = BotSettings {
+        footer: config.footer.clone(),
+        started_at: Instant::now(),
+        listing,
+        commands_per_minute: config.commands_per_minute,
+    };
+    // One client for the bot and the scheduler, so /ratelimit sees the requests of both.
+    let reddit_client = Arc::new(RedditClient::new().with_fetch_attempts(config.fetch_attempts));
+    let metrics = init(
+        &tg_token,
+        &database_url,
+        config.enable_scheduler,
+        config.footer.as_deref(),
+        &reddit_client,
+    );
+    if let (Some(metrics), Some(listener)) = (metrics, metrics_listener) {
+        serve_metrics(listener, metrics);
+    }
+    init_bot(
+        &tg_token,
+        &database_url,
+        &author_id,
+        &bot_name,
+        &settings,
+        &reddit_client,
+    )
+    .await
+}
+
+fn default_listing(sort: Option<&str>, timeframe: Option<&str>) -> Result<Listing, String> {
+    let listing = Listing::default();
+    Listing::new(
+        sort.unwrap_or(&listing.sort),
+        timeframe.unwrap_or(&listing.timeframe),
+    )
+}
 
-    Ok(())
+/// Listener for the scheduler metrics, None when they aren't served. Binding happens up front so an
+/// address that is taken or invalid is reported like any other bad setting.
+fn bind_metrics(
+    metrics_addr: Option<&str>,
+    enable_scheduler: bool,
+) -> Result<Option<TcpListener>, BotError> {
+    match metrics_addr {
+        Some(_) if !enable_scheduler => {
+            warn!("scheduler is disabled, not serving metrics");
+            Ok(None)
+        }
+        Some(metrics_addr) => TcpListener::bind(metrics_addr).map(Some).map_err(|err| {
+            BotError::Config(format!(
+                "failed to bind metrics address {}: {}",
+                metrics_addr, err
+            ))
+        }),
+        None => Ok(None),
+    }
+}
+
+/// Telegram user ids are numeric, a username would silently lock everyone out of the bot.
+fn validate_author_id(author_id: &str) -> Result<(), BotError> {
+    match author_id.parse::<i64>() {
+        Ok(_) => Ok(()),
+        Err(_) => Err(BotError::Config(format!(
+            "author id must be a numeric Telegram user id, got: {:?}",
+            author_id
+        ))),
+    }
+}
+
+/// Prepares the database and starts the scheduler in the background, returns without blocking.
+fn init(
+    tg_token: &str,
+    database_url: &str,
+    enable_scheduler: bool,
+    footer: Option<&str>,
+    reddit_client: &Arc<RedditClient>,
+) -> Option<Arc<SchedulerMetrics>> {
+    run_migrations(database_url);
+    if !enable_scheduler {
+        info!("scheduler is disabled, digests are only sent on demand");
+        return None;
+    }
+    Some(init_task(
+        tg_token.to_string(),
+        database_url.to_string(),
+        footer.map(str::to_string),
+        reddit_client.clone(),
+    ))
 }
 
 fn run_migrations(database_url: &str) {
-    let db_client = DbClient::new(database_url);
-    embedded_migrations::run(&db_client.conn).expect("Failed to run migrations");
+    let status = DbClient::new(database_url)
+        .run_migrations()
+        .expect("Failed to run migrations");
+    info!(
+        "applied {} migrations, schema version: {}",
+        status.applied,
+        status.version.unwrap_or_default()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::thread;
+    use std::time::Duration;
+
+    use chrono::{Datelike, Utc};
+    use diesel::Connection;
+    use mockito::{mock, server_url, Matcher};
+    use serial_test::serial;
+
+    use super::*;
+    use crate::bot::commands::send_now;
+    use crate::db::test_helpers::setup_test_db;
+    use crate::reddit::test_helpers::mock_reddit_success;
+    use crate::telegram::test_helpers::mock_send_message_success;
+    use crate::telegram::types::Message;
+
+    fn assert_start<F, Fut>(_: F)
+    where
+        F: Fn(String, String, String, Config) -> Fut,
+        Fut: Future<Output = Result<(), BotError>>,
+    {
+    }
+
+    #[test]
+    fn start_signature() {
+        // main.rs calls start(token, database_url, author_id, config)
+        assert_start(start);
+    }
+
+    #[test]
+    fn validate_author_id_works() {
+        assert!(validate_author_id("123").is_ok());
+        assert!(validate_author_id("-100123").is_ok());
+        assert_eq!(
+            validate_author_id("@author").unwrap_err().to_string(),
+            "invalid configuration: author id must be a numeric Telegram user id, got: \"@author\""
+        );
+        assert!(validate_author_id("").is_err());
+        assert!(validate_author_id(" 123").is_err());
+    }
+
+    #[test]
+    fn bind_metrics_works() {
+        assert!(bind_metrics(None, true).unwrap().is_none());
+        assert!(bind_metrics(Some("127.0.0.1:0"), false).unwrap().is_none());
+        let listener = bind_metrics(Some("127.0.0.1:0"), true).unwrap().unwrap();
+
+        let taken = listener.local_addr().unwrap().to_string();
+        let err = bind_metrics(Some(&taken), true).unwrap_err();
+        assert!(matches!(err, BotError::Config(_)));
+        assert!(err.to_string().starts_with(&format!(
+            "invalid configuration: failed to bind metrics address {}",
+            taken
+        )));
+    }
+
+    #[tokio::test]
+    async fn start_rejects_username_author() {
+        let result = start(
+            "token".to_string(),
+            "file:.tmp/test.db".to_string(),
+            "@author".to_string(),
+            Config {
+                metrics_addr: None,
+                enable_scheduler: false,
+                footer: None,
+                default_sort: None,
+                default_timeframe: None,
+                notify_startup: false,
+                commands_per_minute: 0,
+                fetch_attempts: 1,
+            },
+        )
+        .await;
+        assert!(matches!(result, Err(BotError::Config(_))));
+    }
+
+    #[tokio::test]
+    async fn start_rejects_invalid_listing() {
+        let config = Config {
+            metrics_addr: None,
+            enable_scheduler: false,
+            footer: None,
+            default_sort: Some("best".to_string()),
+            default_timeframe: None,
+            notify_startup: false,
+            commands_per_minute: 0,
+            fetch_attempts: 1,
+        };
+        let result = start(
+            "token".to_string(),
+            "file:.tmp/test.db".to_string(),
+            "123".to_string(),
+            config,
+        )
+        .await;
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "invalid configuration: Unsupported sort - best"
+        );
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn init_without_scheduler() {
+        let url = &server_url();
+        let db = setup_test_db();
+        db.create_user("123").unwrap();
+        let send_on = Utc::now().weekday().num_days_from_monday() as i32;
+        db.subscribe("123", "rust", send_on, 0).unwrap();
+        db.conn
+            .execute("UPDATE users_subscriptions SET last_sent_at = NULL")
+            .unwrap();
+
+        // A scheduler would fetch the due subscription on its first cycle, right after starting.
+        let not_fetched = mock("GET", Matcher::Any).expect(0).create();
+        let reddit_client = Arc::new(RedditClient::new_with(url));
+        let metrics = init("token", "file:.tmp/test.db", false, None, &reddit_client);
+        assert!(metrics.is_none());
+        thread::sleep(Duration::from_millis(500));
+        not_fetched.assert();
+        drop(not_fetched);
+        let subscriptions = db.get_subscriptions().unwrap();
+        assert_eq!(subscriptions[0].last_sent_at, None);
+
+        let message = Message {
+            chat_id: "123",
+            text: &format!("Weekly popular posts from: \"rust\"\n\nA half-hour to learn Rust\n{}/r/rust/comments/fbenua/a_halfhour_to_learn_rust/\n\n", url),
+            disable_web_page_preview: true,
+            ..Default::default()
+        };
+        let _m1 = mock_send_message_success("token", &message);
+        let _m2 = mock_reddit_success("rust");
+        let telegram_client = TelegramClient::new_with("token".to_string(), url.to_string());
+        send_now(&telegram_client, &db, &reddit_client, "123", None)
+            .await
+            .unwrap();
+        _m1.assert();
+        _m2.assert();
+    }
 }
@@ -1,7 +1,10 @@
 use std::env;
 
 use dotenv::dotenv;
-use reddit_bot::{start, BotError};
+use reddit_bot::{start, BotError, Config};
+
+const DEFAULT_COMMANDS_PER_MINUTE: u32 = 20;
+const DEFAULT_FETCH_ATTEMPTS: u32 = 3;
 
 #[tokio::main]
 async fn main() -> Result<(), BotError> {
@@ -11,9 +14,48 @@ async fn main() -> Result<(), BotError> {
     let token = env::var("TG_TOKEN").expect("missing TG_TOKEN env var");
     let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
     let author_id = env::var("TG_AUTHOR").expect("missing TG_AUTHOR env var");
-    let bot_name = env::var("BOT_NAME").expect("missing BOT_NAME env var");
+    let metrics_addr = env::var("METRICS_ADDR").ok();
+    // Set to "false" to only send digests on demand via /sendnow.
+    let enable_scheduler = env::var("ENABLE_SCHEDULER").map_or(true, |value| value != "false");
+    // Appended to every digest, e.g. "Sourced from Reddit. Reply /stop to unsubscribe."
+    let footer = env::var("DIGEST_FOOTER").ok();
+    // Listing of new subscriptions, e.g. "hot" or "top" and "day" or "month". Top of the week when unset.
+    let default_sort = env::var("DEFAULT_SORT").ok();
+    let default_timeframe = env::var("DEFAULT_TIMEFRAME").ok();
+    // Set to "true" to get a message from the bot whenever it starts.
+    let notify_startup = env::var("NOTIFY_STARTUP").as_deref() == Ok("true");
+    // Commands a single user may send per minute, 0 to disable the limit.
+    let commands_per_minute = match env::var("COMMANDS_PER_MINUTE") {
+        Ok(value) => value.parse().map_err(|_| {
+            BotError::Config(format!(
+                "COMMANDS_PER_MINUTE must be a whole number, got: {:?}",
+                value
+            ))
+        })?,
+        Err(_) => DEFAULT_COMMANDS_PER_MINUTE,
+    };
+    // Attempts at fetching a subreddit when reddit times out or can't be reached.
+    let fetch_attempts = match env::var("FETCH_ATTEMPTS") {
+        Ok(value) => value.parse().map_err(|_| {
+            BotError::Config(format!(
+                "FETCH_ATTEMPTS must be a whole number, got: {:?}",
+                value
+            ))
+        })?,
+        Err(_) => DEFAULT_FETCH_ATTEMPTS,
+    };
 
-    start(token, bot_name, database_url, author_id).await?;
+    let config = Config {
+        metrics_addr,
+        enable_scheduler,
+        footer,
+        default_sort,
+        default_timeframe,
+        notify_startup,
+        commands_per_minute,
+        fetch_attempts,
+    };
+    start(token, database_url, author_id, config).await?;
 
     Ok(())
 }
@@ -1,14 +1,96 @@
-use log::{error, warn};
-use reqwest::Client;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use chrono::{DateTime, TimeZone, Utc};
+use log::{error, info, warn};
+use reqwest::header::{HeaderMap, COOKIE};
+use reqwest::{Client, Response, Url};
 use serde_json::Value;
 use tokio::time::{sleep, Duration};
 use ua_generator::ua::spoof_ua;
 
 use super::error::RedditError;
-use super::post::Post;
+use super::post::{plain_text, Comment, Post};
+
+const TOP_COMMENT_LENGTH: usize = 200;
+const TOP_COMMENT_CACHE_SIZE: usize = 1000;
+/// Comments requested per thread by `fetch_comments`, newer ones are rarely among the top ones anyway.
+const COMMENTS_LIMIT: u32 = 50;
+const OVER18_COOKIE: &str = "over18=1";
+/// Posts requested per page when following a listing across pages, the most reddit returns at once.
+const PAGE_LIMIT: u32 = 100;
+/// Requests that take longer fail as timeouts, which are retried when fetching posts.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Attempts at fetching the posts of a subscription when reddit times out or can't be reached.
+const DEFAULT_FETCH_ATTEMPTS: u32 = 3;
+
+/// Feeds that always exist and are subscribed to by name like a subreddit.
+/// The front page is the site wide top listing, the others live under /r/ like regular subreddits.
+const FRONT_PAGE: &str = "frontpage";
+const RESERVED_FEEDS: [&str; 3] = [FRONT_PAGE, "popular", "all"];
+
+/// Listing sorts and `t` values reddit accepts, the timeframe only matters for top and controversial.
+pub const SORTS: [&str; 5] = ["top", "hot", "new", "rising", "controversial"];
+pub const TIMEFRAMES: [&str; 6] = ["hour", "day", "week", "month", "year", "all"];
+
+/// Whether reddit applies the timeframe to listings of the sort.
+pub fn sort_has_timeframe(sort: &str) -> bool {
+    sort == "top" || sort == "controversial"
+}
+
+/// Which posts of a subreddit are fetched, e.g. top posts of the week.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Listing {
+    pub sort: String,
+    pub timeframe: String,
+}
+
+impl Listing {
+    /// Fails with a message naming the unsupported value.
+    pub fn new(sort: &str, timeframe: &str) -> Result<Self, String> {
+        let sort = sort.to_lowercase();
+        let timeframe = timeframe.to_lowercase();
+        if !SORTS.contains(&sort.as_str()) {
+            return Err(format!("Unsupported sort - {}", sort));
+        }
+        if !TIMEFRAMES.contains(&timeframe.as_str()) {
+            return Err(format!("Unsupported timeframe - {}", timeframe));
+        }
+        Ok(Listing { sort, timeframe })
+    }
+}
+
+impl Default for Listing {
+    fn default() -> Self {
+        Listing {
+            sort: "top".to_string(),
+            timeframe: "week".to_string(),
+        }
+    }
+}
 
 pub struct RedditClient {
     base_url: String,
+    /// Top comments by permalink, so the same thread isn't fetched again for every subscriber.
+    top_comments: Mutex<HashMap<String, Option<String>>>,
+    rate_limit: Mutex<Option<RateLimit>>,
+    fetch_attempts: u32,
+}
+
+/// Rate limit reported by reddit with the last listing response.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub remaining: u64,
+    pub reset_secs: u64,
+    pub seen_at: DateTime<Utc>,
+}
+
+/// Subreddit found by `RedditClient::search_subreddits`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubredditInfo {
+    pub name: String,
+    pub title: String,
+    pub subscribers: u64,
 }
 
 impl RedditClient {
@@ -19,60 +101,62 @@ impl RedditClient {
     pub fn new_with(base_url: &str) -> Self {
         RedditClient {
             base_url: base_url.to_string(),
+            top_comments: Mutex::new(HashMap::new()),
+            rate_limit: Mutex::new(None),
+            fetch_attempts: DEFAULT_FETCH_ATTEMPTS,
         }
     }
 
-    pub async fn fetch_posts(&self, subreddit: &str) -> Result<Vec<Post>, RedditError> {
-        let url = format!("{}/r/{}/top.json?limit=10&t=week", self.base_url, subreddit);
-        let client = self.get_client();
-        let res = client.get(&url).send().await?;
-
-        if let Some(remaining) = res.headers().get("x-ratelimit-remaining") {
-            let remaining_request_count: u64 =
-                remaining.to_str().unwrap().to_string().parse().unwrap();
-            if remaining_request_count < 20 {
-                if let Some(reset) = res.headers().get("x-ratelimit-reset") {
-                    let reset: u64 = reset.to_str().unwrap().to_string().parse().unwrap();
-                    warn!(
-                        "running out of remaining reddit requests, sleeping for: {} seconds",
-                        reset
-                    );
-                    sleep(Duration::from_secs(reset)).await;
-                }
-            }
+    /// Makes up to `fetch_attempts` attempts at the posts of a subscription, at least one.
+    pub fn with_fetch_attempts(self, fetch_attempts: u32) -> Self {
+        RedditClient {
+            fetch_attempts: fetch_attempts.max(1),
+            ..self
         }
+    }
 
-        let body = res.text().await?;
-        let body: Value = serde_json::from_str(&body)?;
+    /// Attempts at fetching the posts of a subscription when reddit times out or can't be reached.
+    pub fn fetch_attempts(&self) -> u32 {
+        self.fetch_attempts
+    }
+
+    pub async fn fetch_posts(
+        &self,
+        subreddit: &str,
+        listing: &Listing,
+        limit: i32,
+        max_age_hours: Option<i64>,
+    ) -> Result<Vec<Post>, RedditError> {
+        let body = self.fetch_raw(subreddit, listing, limit).await?;
+        if let Some(err) = listing_error(&body) {
+            error!("reddit error for subreddit: {}: {}", subreddit, err);
+            return Err(err);
+        }
 
         let data = body.get("data");
-        if None == data {
+        if data.is_none() {
             error!("Missing data in response for subreddit: {}", subreddit);
             return Err(RedditError::Error);
         }
 
         let children = data.unwrap().get("children");
 
-        if None == children {
+        if children.is_none() {
             error!("Missing children in response for subreddit: {}", subreddit);
             return Err(RedditError::Error);
         }
 
         let children = children.unwrap();
+        let cutoff = max_age_hours.map(|hours| Utc::now() - chrono::Duration::hours(hours));
 
         let posts = if let Value::Array(children) = children {
             children
                 .iter()
-                .map(|child| {
-                    let title = child.get("data").unwrap().get("title").unwrap();
-                    let link = child.get("data").unwrap().get("permalink").unwrap();
-                    let title = if let Value::String(v) = title { v } else { "" }.to_string();
-                    let link = if let Value::String(v) = link { v } else { "" }.to_string();
-                    Post {
-                        title,
-                        link: format!("{}{}", self.base_url, link),
-                    }
+                .filter(|child| match (cutoff, parse_created_utc(child)) {
+                    (Some(cutoff), Some(created_at)) => created_at >= cutoff,
+                    _ => true,
                 })
+                .map(|child| self.parse_post(child))
                 .collect()
         } else {
             warn!("response did not contain an array");
@@ -82,8 +166,267 @@ impl RedditClient {
         Ok(posts)
     }
 
+    /// Newest posts created at or after `since`, following the listing's `after` cursor across pages.
+    /// Stops at the first older post, at the end of the listing or once `max_posts` were collected.
+    pub async fn fetch_posts_since(
+        &self,
+        subreddit: &str,
+        since: DateTime<Utc>,
+        max_posts: usize,
+    ) -> Result<Vec<Post>, RedditError> {
+        let mut posts = vec![];
+        let mut after: Option<String> = None;
+        loop {
+            let mut url = format!(
+                "{}{}/new.json?limit={}",
+                self.base_url,
+                listing_path(subreddit),
+                PAGE_LIMIT
+            );
+            if let Some(after) = &after {
+                url.push_str(&format!("&after={}", after));
+            }
+            let body = self.fetch_listing(&url).await?;
+            if let Some(err) = listing_error(&body) {
+                error!("reddit error for subreddit: {}: {}", subreddit, err);
+                return Err(err);
+            }
+
+            let children = match body["data"]["children"].as_array() {
+                Some(children) => children,
+                None => {
+                    error!("Missing children in response for subreddit: {}", subreddit);
+                    return Err(RedditError::Error);
+                }
+            };
+
+            for child in children {
+                if let Some(created_at) = parse_created_utc(child) {
+                    if created_at < since {
+                        return Ok(posts);
+                    }
+                }
+                posts.push(self.parse_post(child));
+                if posts.len() >= max_posts {
+                    return Ok(posts);
+                }
+            }
+
+            after = match body["data"]["after"].as_str() {
+                Some(next) if !children.is_empty() => Some(next.to_string()),
+                _ => return Ok(posts),
+            };
+        }
+    }
+
+    fn parse_post(&self, child: &Value) -> Post {
+        let data = child.get("data").unwrap();
+        let id = data.get("id").and_then(Value::as_str).unwrap_or("");
+        // Crossposts carry the original title and link in crosspost_parent_list.
+        let original = data
+            .get("crosspost_parent_list")
+            .and_then(Value::as_array)
+            .and_then(|parents| parents.first());
+        let source = original.unwrap_or(data);
+        let title = source.get("title").unwrap();
+        let link = source.get("permalink").unwrap();
+        let selftext = source.get("selftext").and_then(Value::as_str);
+        let title = if let Value::String(v) = title { v } else { "" }.to_string();
+        let link = if let Value::String(v) = link { v } else { "" }.to_string();
+        Post {
+            id: id.to_string(),
+            title,
+            link: format!("{}{}", self.base_url, link),
+            is_crosspost: original.is_some(),
+            selftext: selftext.unwrap_or("").to_string(),
+            top_comment: None,
+            external_url: parse_external_url(source),
+            over_18: data.get("over_18").and_then(Value::as_bool) == Some(true),
+            image_url: parse_image_url(source),
+            score: data
+                .get("score")
+                .and_then(Value::as_i64)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Listing exactly as returned by reddit, without any processing. Used for debugging odd renders.
+    pub async fn fetch_raw(
+        &self,
+        subreddit: &str,
+        listing: &Listing,
+        limit: i32,
+    ) -> Result<Value, RedditError> {
+        let url = format!(
+            "{}{}/{}.json?limit={}&t={}",
+            self.base_url,
+            listing_path(subreddit),
+            listing.sort,
+            limit,
+            listing.timeframe
+        );
+        self.fetch_listing(&url).await
+    }
+
+    /// Listing at `url` as json, backing off when reddit's rate limit is about to run out.
+    async fn fetch_listing(&self, url: &str) -> Result<Value, RedditError> {
+        let res = self.get_listing(url).await?;
+
+        if let Some(rate_limit) = parse_rate_limit(res.headers()) {
+            *self.rate_limit.lock().unwrap() = Some(rate_limit);
+            if rate_limit.remaining < 20 {
+                warn!(
+                    "running out of remaining reddit requests, sleeping for: {} seconds",
+                    rate_limit.reset_secs
+                );
+                sleep(Duration::from_secs(rate_limit.reset_secs)).await;
+            }
+        }
+
+        let body = res.text().await?;
+        let body: Value = serde_json::from_str(&body)?;
+        Ok(body)
+    }
+
+    /// Highest scored comment of the thread at `permalink`, stripped of markdown and truncated.
+    pub async fn fetch_top_comment(&self, permalink: &str) -> Result<Option<String>, RedditError> {
+        if let Some(comment) = self.cached_top_comment(permalink) {
+            return Ok(comment);
+        }
+
+        let url = format!(
+            "{}{}.json?limit=1&sort=top",
+            self.base_url,
+            permalink.trim_end_matches('/')
+        );
+        let body = self.get_thread(&url).await?;
+        let comment = comment_children(&body, permalink)?
+            .iter()
+            .find(|child| child.get("kind").and_then(Value::as_str) == Some("t1"))
+            .and_then(|child| child.get("data"))
+            .and_then(|data| data.get("body"))
+            .and_then(Value::as_str)
+            .and_then(|body| plain_text(body, TOP_COMMENT_LENGTH));
+
+        let mut cache = self.top_comments.lock().unwrap();
+        if cache.len() >= TOP_COMMENT_CACHE_SIZE {
+            cache.clear();
+        }
+        cache.insert(permalink.to_string(), comment.clone());
+
+        Ok(comment)
+    }
+
+    /// Top level comments of the thread at `permalink` posted after `since`, highest scored first.
+    pub async fn fetch_comments(
+        &self,
+        permalink: &str,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<Comment>, RedditError> {
+        let permalink = permalink.trim_end_matches('/');
+        let url = format!(
+            "{}{}.json?limit={}&sort=top&depth=1",
+            self.base_url, permalink, COMMENTS_LIMIT
+        );
+        let body = self.get_thread(&url).await?;
+
+        let comments = comment_children(&body, permalink)?
+            .iter()
+            .filter(|child| child.get("kind").and_then(Value::as_str) == Some("t1"))
+            .filter(|child| match parse_created_utc(child) {
+                Some(created_at) => created_at > since,
+                None => false,
+            })
+            .filter_map(|child| {
+                let data = child.get("data")?;
+                let id = data.get("id").and_then(Value::as_str)?;
+                let body = data.get("body").and_then(Value::as_str)?;
+                Some(Comment {
+                    id: id.to_string(),
+                    author: data["author"].as_str().unwrap_or_default().to_string(),
+                    body: plain_text(body, TOP_COMMENT_LENGTH)?,
+                    score: data["score"].as_i64().unwrap_or_default(),
+                    link: format!("{}{}/{}/", self.base_url, permalink, id),
+                })
+            })
+            .collect();
+        Ok(comments)
+    }
+
+    async fn get_thread(&self, url: &str) -> Result<Value, RedditError> {
+        let client = self.get_client();
+        let body = client.get(url).send().await?.text().await?;
+        let body: Value = serde_json::from_str(&body)?;
+        Ok(body)
+    }
+
+    /// Gated subreddits redirect to an /over18 interstitial until the consent cookie is sent.
+    async fn get_listing(&self, url: &str) -> Result<Response, RedditError> {
+        let client = self.get_client();
+        let res = client.get(url).send().await?;
+        if !res.url().path().starts_with("/over18") {
+            return Ok(res);
+        }
+
+        info!("retrying with over18 cookie: {}", url);
+        let res = client.get(url).header(COOKIE, OVER18_COOKIE).send().await?;
+        Ok(res)
+    }
+
+    /// Last rate limit seen by this client, None before the first listing was fetched.
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        *self.rate_limit.lock().unwrap()
+    }
+
+    fn cached_top_comment(&self, permalink: &str) -> Option<Option<String>> {
+        self.top_comments.lock().unwrap().get(permalink).cloned()
+    }
+
+    /// Subreddits matching the query, as ordered by reddit's search.
+    pub async fn search_subreddits(
+        &self,
+        query: &str,
+        limit: u32,
+    ) -> Result<Vec<SubredditInfo>, RedditError> {
+        let url = format!("{}/subreddits/search.json", self.base_url);
+        let client = self.get_client();
+        let body = client
+            .get(&url)
+            .query(&[("q", query), ("limit", &limit.to_string())])
+            .send()
+            .await?
+            .text()
+            .await?;
+        let body: Value = serde_json::from_str(&body)?;
+
+        let children = match body["data"]["children"].as_array() {
+            Some(children) => children,
+            None => {
+                error!("Missing children in subreddit search for: {}", query);
+                return Err(RedditError::Error);
+            }
+        };
+
+        let subreddits = children
+            .iter()
+            .filter_map(|child| {
+                let data = &child["data"];
+                Some(SubredditInfo {
+                    name: data["display_name"].as_str()?.to_string(),
+                    title: data["title"].as_str().unwrap_or_default().to_string(),
+                    subscribers: data["subscribers"].as_u64().unwrap_or_default(),
+                })
+            })
+            .collect();
+        Ok(subreddits)
+    }
+
     pub async fn validate_subreddit(&self, subreddit: &str) -> bool {
-        let url = format!("{}/r/{}", self.base_url, subreddit);
+        if is_reserved_feed(subreddit) {
+            return true;
+        }
+
+        let url = format!("{}{}", self.base_url, listing_path(subreddit));
         let client = self.get_client();
 
         if let Ok(resp) = client.get(&url).send().await {
@@ -94,16 +437,108 @@ impl RedditClient {
     }
 
     fn get_client(&self) -> Client {
-        Client::builder().user_agent(spoof_ua()).build().unwrap()
+        Client::builder()
+            .user_agent(spoof_ua())
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .unwrap()
     }
 }
 
+fn is_reserved_feed(subreddit: &str) -> bool {
+    RESERVED_FEEDS
+        .iter()
+        .any(|feed| feed.eq_ignore_ascii_case(subreddit))
+}
+
+/// Path of a subscription's listing, multireddits are stored as "user/<name>/m/<multi>".
+fn listing_path(subreddit: &str) -> String {
+    if subreddit.eq_ignore_ascii_case(FRONT_PAGE) {
+        String::new()
+    } else if subreddit.starts_with("user/") {
+        format!("/{}", subreddit)
+    } else {
+        format!("/r/{}", subreddit)
+    }
+}
+
+/// Url the post links to, None for self posts and links pointing back to reddit.
+fn parse_external_url(data: &Value) -> Option<String> {
+    if data.get("is_self").and_then(Value::as_bool) == Some(true) {
+        return None;
+    }
+    let url = data.get("url").and_then(Value::as_str)?;
+    let host = Url::parse(url).ok()?.host_str()?.to_string();
+    if host == "reddit.com"
+        || host.ends_with(".reddit.com")
+        || host == "redd.it"
+        || host.ends_with(".redd.it")
+    {
+        return None;
+    }
+    Some(url.to_string())
+}
+
+/// Url of the image of an image post, reddit marks them with `post_hint`.
+fn parse_image_url(data: &Value) -> Option<String> {
+    if data.get("post_hint").and_then(Value::as_str) != Some("image") {
+        return None;
+    }
+    data.get("url").and_then(Value::as_str).map(str::to_string)
+}
+
+/// Reddit sends the remaining count as a float, e.g. "598.0".
+fn parse_rate_limit(headers: &HeaderMap) -> Option<RateLimit> {
+    let header = |name: &str| -> Option<u64> {
+        let value = headers.get(name)?.to_str().ok()?.parse::<f64>().ok()?;
+        Some(value as u64)
+    };
+    Some(RateLimit {
+        remaining: header("x-ratelimit-remaining")?,
+        reset_secs: header("x-ratelimit-reset")?,
+        seen_at: Utc::now(),
+    })
+}
+
+/// Some failures come back with a 200 status and a body like `{"error": 404, "message": "Not Found"}`.
+fn listing_error(body: &Value) -> Option<RedditError> {
+    let code = body.get("error")?.as_u64()?;
+    let message = body
+        .get("message")
+        .and_then(Value::as_str)
+        .unwrap_or("")
+        .to_string();
+    Some(RedditError::ApiError(code, message))
+}
+
+/// The thread response is a pair of listings, the post itself followed by its comments.
+fn comment_children<'a>(body: &'a Value, permalink: &str) -> Result<&'a Vec<Value>, RedditError> {
+    let comments = body
+        .get(1)
+        .and_then(|listing| listing.get("data"))
+        .and_then(|data| data.get("children"))
+        .and_then(Value::as_array);
+    match comments {
+        Some(comments) => Ok(comments),
+        None => {
+            error!("Missing comments in response for: {}", permalink);
+            Err(RedditError::Error)
+        }
+    }
+}
+
+fn parse_created_utc(child: &Value) -> Option<DateTime<Utc>> {
+    let created_utc = child.get("data")?.get("created_utc")?.as_f64()?;
+    Utc.timestamp_opt(created_utc as i64, 0).single()
+}
+
 #[cfg(test)]
 mod tests {
-    use mockito::{mock, server_url};
+    use mockito::{mock, server_url, Matcher};
+    use serde_json::json;
 
     use super::*;
-    use crate::reddit::test_helpers::mock_reddit_success;
+    use crate::reddit::test_helpers::{mock_listing_success, mock_reddit_success};
 
     #[test]
     fn correct_domain() {
@@ -117,18 +552,256 @@ mod tests {
         let subreddit = "rust";
         let _m = mock_reddit_success(subreddit);
         let reddit_client = RedditClient::new_with(url);
-        let result = reddit_client.fetch_posts(subreddit).await.unwrap();
+        let result = reddit_client
+            .fetch_posts(subreddit, &Listing::default(), 10, None)
+            .await
+            .unwrap();
         assert_eq!(result.len(), 1);
         assert_eq!(
             result[0],
             Post {
+                id: "fbenua".to_string(),
                 title: "A half-hour to learn Rust".to_string(),
                 link: format!("{}/r/rust/comments/fbenua/a_halfhour_to_learn_rust/", url),
+                is_crosspost: false,
+                selftext: String::new(),
+                top_comment: None,
+                external_url: Some(
+                    "https://fasterthanli.me/blog/2020/a-half-hour-to-learn-rust/".to_string()
+                ),
+                over_18: false,
+                image_url: None,
+                score: 567,
             }
         );
         _m.assert();
     }
 
+    #[tokio::test]
+    async fn fetch_posts_listing() {
+        let url = &server_url();
+        let _m = mock("GET", "/r/rust/new.json?limit=10&t=day")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"kind":"Listing","data":{"children":[]}}"#)
+            .create();
+        let reddit_client = RedditClient::new_with(url);
+        let listing = Listing::new("new", "day").unwrap();
+
+        let result = reddit_client
+            .fetch_posts("rust", &listing, 10, None)
+            .await
+            .unwrap();
+        assert!(result.is_empty());
+        _m.assert();
+    }
+
+    fn new_listing_page(posts: &[(&str, i64)], after: Option<&str>) -> String {
+        let children = posts
+            .iter()
+            .map(|(id, created_utc)| {
+                json!({"kind": "t3", "data": {
+                    "id": id,
+                    "title": format!("Post {}", id),
+                    "permalink": format!("/r/rust/comments/{}/", id),
+                    "selftext": "",
+                    "is_self": true,
+                    "created_utc": *created_utc as f64,
+                }})
+            })
+            .collect::<Vec<_>>();
+        json!({"kind": "Listing", "data": {"children": children, "after": after}}).to_string()
+    }
+
+    #[tokio::test]
+    async fn fetch_posts_since_follows_pages() {
+        let url = &server_url();
+        let since = Utc.timestamp_opt(1_600_000_000, 0).unwrap();
+        let _first = mock("GET", "/r/rust/new.json?limit=100")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(new_listing_page(
+                &[("a", 1_600_000_300), ("b", 1_600_000_200)],
+                Some("t3_b"),
+            ))
+            .create();
+        let _second = mock("GET", "/r/rust/new.json?limit=100&after=t3_b")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(new_listing_page(
+                &[
+                    ("c", 1_600_000_000),
+                    ("d", 1_599_999_999),
+                    ("e", 1_599_999_000),
+                ],
+                Some("t3_e"),
+            ))
+            .create();
+        let _third = mock("GET", "/r/rust/new.json?limit=100&after=t3_e")
+            .expect(0)
+            .create();
+        let reddit_client = RedditClient::new_with(url);
+
+        let result = reddit_client
+            .fetch_posts_since("rust", since, 100)
+            .await
+            .unwrap();
+        let ids = result
+            .iter()
+            .map(|post| post.id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["a", "b", "c"]);
+        assert_eq!(result[0].title, "Post a");
+        _first.assert();
+        _second.assert();
+        _third.assert();
+    }
+
+    #[tokio::test]
+    async fn fetch_posts_since_max_posts() {
+        let url = &server_url();
+        let since = Utc.timestamp_opt(1_600_000_000, 0).unwrap();
+        let _first = mock("GET", "/r/rust/new.json?limit=100")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(new_listing_page(
+                &[("a", 1_600_000_300), ("b", 1_600_000_200)],
+                Some("t3_b"),
+            ))
+            .create();
+        let _second = mock("GET", "/r/rust/new.json?limit=100&after=t3_b")
+            .expect(0)
+            .create();
+        let reddit_client = RedditClient::new_with(url);
+
+        let result = reddit_client
+            .fetch_posts_since("rust", since, 2)
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 2);
+        _first.assert();
+        _second.assert();
+    }
+
+    #[tokio::test]
+    async fn fetch_posts_since_end_of_listing() {
+        let url = &server_url();
+        let since = Utc.timestamp_opt(1_600_000_000, 0).unwrap();
+        let _m = mock("GET", "/r/rust/new.json?limit=100")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(new_listing_page(&[("a", 1_600_000_300)], None))
+            .create();
+        let reddit_client = RedditClient::new_with(url);
+
+        let result = reddit_client
+            .fetch_posts_since("rust", since, 100)
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 1);
+        _m.assert();
+    }
+
+    #[test]
+    fn listing_new() {
+        assert_eq!(Listing::new("TOP", "Week").unwrap(), Listing::default());
+        assert_eq!(
+            Listing::new("best", "week").unwrap_err(),
+            "Unsupported sort - best"
+        );
+        assert_eq!(
+            Listing::new("top", "decade").unwrap_err(),
+            "Unsupported timeframe - decade"
+        );
+    }
+
+    #[tokio::test]
+    async fn fetch_posts_rate_limit() {
+        let url = &server_url();
+        let _m = mock("GET", "/r/rust/top.json?limit=10&t=week")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_header("x-ratelimit-remaining", "598.0")
+            .with_header("x-ratelimit-reset", "120")
+            .with_body(r#"{"kind":"Listing","data":{"children":[]}}"#)
+            .create();
+        let reddit_client = RedditClient::new_with(url);
+        assert_eq!(reddit_client.rate_limit(), None);
+
+        reddit_client
+            .fetch_posts("rust", &Listing::default(), 10, None)
+            .await
+            .unwrap();
+        let result = reddit_client.rate_limit().unwrap();
+        assert_eq!(result.remaining, 598);
+        assert_eq!(result.reset_secs, 120);
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn fetch_raw_success() {
+        let url = &server_url();
+        let _m = mock_reddit_success("rust");
+        let reddit_client = RedditClient::new_with(url);
+        let result = reddit_client
+            .fetch_raw("rust", &Listing::default(), 10)
+            .await
+            .unwrap();
+        assert_eq!(result["kind"], "Listing");
+        assert_eq!(result["data"]["children"][0]["data"]["id"], "fbenua");
+        assert_eq!(
+            result["data"]["children"][0]["data"]["title"],
+            "A half-hour to learn Rust"
+        );
+        _m.assert();
+    }
+
+    #[test]
+    fn listing_path_multireddit() {
+        assert_eq!(listing_path("rust"), "/r/rust");
+        assert_eq!(listing_path("user/foo/m/news"), "/user/foo/m/news");
+    }
+
+    #[test]
+    fn listing_path_reserved_feeds() {
+        assert_eq!(listing_path("frontpage"), "");
+        assert_eq!(listing_path("FrontPage"), "");
+        assert_eq!(listing_path("popular"), "/r/popular");
+        assert_eq!(listing_path("all"), "/r/all");
+    }
+
+    #[tokio::test]
+    async fn fetch_posts_reserved_feeds() {
+        let url = &server_url();
+        let _m = mock_listing_success("");
+        let _m2 = mock_listing_success("/r/popular");
+        let _m3 = mock_listing_success("/r/all");
+        let reddit_client = RedditClient::new_with(url);
+        for feed in &["frontpage", "popular", "all"] {
+            let result = reddit_client
+                .fetch_posts(feed, &Listing::default(), 10, None)
+                .await
+                .unwrap();
+            assert_eq!(result.len(), 1);
+        }
+        _m.assert();
+        _m2.assert();
+        _m3.assert();
+    }
+
+    #[tokio::test]
+    async fn fetch_posts_multireddit() {
+        let url = &server_url();
+        let _m = mock_listing_success("/user/foo/m/news");
+        let reddit_client = RedditClient::new_with(url);
+        let result = reddit_client
+            .fetch_posts("user/foo/m/news", &Listing::default(), 10, None)
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 1);
+        _m.assert();
+    }
+
     #[tokio::test]
     async fn fetch_posts_invalid_children() {
         let url = &server_url();
@@ -155,7 +828,10 @@ mod tests {
         .create();
 
         let reddit_client = RedditClient::new_with(url);
-        let result = reddit_client.fetch_posts(subreddit).await.unwrap();
+        let result = reddit_client
+            .fetch_posts(subreddit, &Listing::default(), 10, None)
+            .await
+            .unwrap();
         assert_eq!(result.len(), 0);
         _m.assert();
     }
@@ -179,8 +855,467 @@ mod tests {
         .create();
 
         let reddit_client = RedditClient::new_with(url);
-        let result = reddit_client.fetch_posts(subreddit).await;
-        assert_eq!(result.is_err(), true);
+        let result = reddit_client
+            .fetch_posts(subreddit, &Listing::default(), 10, None)
+            .await;
+        assert!(result.is_err());
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn fetch_posts_error_body() {
+        let url = &server_url();
+        let subreddit = "rust";
+        let _m = mock(
+            "GET",
+            format!("/r/{}/top.json?limit=10&t=week", subreddit).as_str(),
+        )
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(r#"{"error": 404, "message": "Not Found"}"#)
+        .create();
+
+        let reddit_client = RedditClient::new_with(url);
+        let result = reddit_client
+            .fetch_posts(subreddit, &Listing::default(), 10, None)
+            .await;
+        match result {
+            Err(RedditError::ApiError(code, message)) => {
+                assert_eq!(code, 404);
+                assert_eq!(message, "Not Found");
+            }
+            other => panic!("expected an api error, got: {:?}", other),
+        }
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn fetch_posts_since_error_body() {
+        let url = &server_url();
+        let _m = mock("GET", "/r/rust/new.json?limit=100")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"error": 403, "message": "Forbidden"}"#)
+            .create();
+
+        let reddit_client = RedditClient::new_with(url);
+        let result = reddit_client
+            .fetch_posts_since("rust", Utc::now(), 10)
+            .await;
+        assert!(matches!(result, Err(RedditError::ApiError(403, _))));
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn fetch_posts_max_age() {
+        let url = &server_url();
+
+        let body = format!(
+            r#"{{
+            "kind": "Listing",
+              "data": {{
+                "children": [
+                  {{"kind": "t3", "data": {{"title": "Old", "permalink": "/r/rust/comments/old/", "created_utc": 1582992651.0}}}},
+                  {{"kind": "t3", "data": {{"title": "Recent", "permalink": "/r/rust/comments/recent/", "created_utc": {}.0}}}},
+                  {{"kind": "t3", "data": {{"title": "Unknown", "permalink": "/r/rust/comments/unknown/"}}}}
+                ]
+              }}
+            }}
+         "#,
+            Utc::now().timestamp() - 3600
+        );
+        let subreddit = "rust";
+        let _m = mock(
+            "GET",
+            format!("/r/{}/top.json?limit=10&t=week", subreddit).as_str(),
+        )
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(body)
+        .expect(2)
+        .create();
+
+        let reddit_client = RedditClient::new_with(url);
+        let result = reddit_client
+            .fetch_posts(subreddit, &Listing::default(), 10, None)
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 3);
+
+        let result = reddit_client
+            .fetch_posts(subreddit, &Listing::default(), 10, Some(24))
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].title, "Recent");
+        assert_eq!(result[1].title, "Unknown");
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn fetch_posts_crosspost() {
+        let url = &server_url();
+
+        let body = r#"{
+            "kind": "Listing",
+              "data": {
+                "children": [
+                  {"kind": "t3", "data": {
+                    "id": "xpost",
+                    "title": "Crossposted title",
+                    "permalink": "/r/programming/comments/xpost/crossposted_title/",
+                    "crosspost_parent_list": [
+                      {"id": "orig", "title": "Original title", "permalink": "/r/rust/comments/orig/original_title/"}
+                    ]
+                  }}
+                ]
+              }
+            }
+         "#;
+        let subreddit = "programming";
+        let _m = mock(
+            "GET",
+            format!("/r/{}/top.json?limit=10&t=week", subreddit).as_str(),
+        )
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(body)
+        .create();
+
+        let reddit_client = RedditClient::new_with(url);
+        let result = reddit_client
+            .fetch_posts(subreddit, &Listing::default(), 10, None)
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(
+            result[0],
+            Post {
+                id: "xpost".to_string(),
+                title: "Original title".to_string(),
+                link: format!("{}/r/rust/comments/orig/original_title/", url),
+                is_crosspost: true,
+                selftext: String::new(),
+                top_comment: None,
+                external_url: None,
+                over_18: false,
+                image_url: None,
+                score: 0,
+            }
+        );
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn fetch_posts_external_url() {
+        let url = &server_url();
+
+        let body = r#"{
+            "kind": "Listing",
+              "data": {
+                "children": [
+                  {"kind": "t3", "data": {"id": "news", "title": "Rust 1.50 released", "permalink": "/r/rust/comments/news/", "is_self": false, "url": "https://blog.rust-lang.org/2021/02/11/Rust-1.50.0.html"}},
+                  {"kind": "t3", "data": {"id": "text", "title": "Question", "permalink": "/r/rust/comments/text/", "is_self": true, "url": "https://www.reddit.com/r/rust/comments/text/"}},
+                  {"kind": "t3", "data": {"id": "image", "title": "Ferris", "permalink": "/r/rust/comments/image/", "is_self": false, "url": "https://i.redd.it/ferris.png"}},
+                  {"kind": "t3", "data": {"id": "gallery", "title": "Gallery", "permalink": "/r/rust/comments/gallery/", "is_self": false, "url": "https://www.reddit.com/gallery/gallery"}},
+                  {"kind": "t3", "data": {"id": "short", "title": "Shortener", "permalink": "/r/rust/comments/short/", "is_self": false, "url": "https://notredd.it/rust"}}
+                ]
+              }
+            }"#;
+        let _m = mock("GET", "/r/rust/top.json?limit=10&t=week")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(body)
+            .create();
+
+        let reddit_client = RedditClient::new_with(url);
+        let result = reddit_client
+            .fetch_posts("rust", &Listing::default(), 10, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            result
+                .iter()
+                .map(|post| post.external_url.as_deref())
+                .collect::<Vec<Option<&str>>>(),
+            [
+                Some("https://blog.rust-lang.org/2021/02/11/Rust-1.50.0.html"),
+                None,
+                None,
+                None,
+                Some("https://notredd.it/rust")
+            ]
+        );
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn fetch_posts_images() {
+        let url = &server_url();
+        let body = r#"{
+            "kind": "Listing",
+              "data": {
+                "children": [
+                  {"kind": "t3", "data": {"id": "sfw", "title": "Ferris", "permalink": "/r/rust/comments/sfw/", "post_hint": "image", "over_18": false, "url": "https://i.redd.it/ferris.png"}},
+                  {"kind": "t3", "data": {"id": "nsfw", "title": "Unsafe", "permalink": "/r/rust/comments/nsfw/", "post_hint": "image", "over_18": true, "url": "https://i.redd.it/unsafe.png"}},
+                  {"kind": "t3", "data": {"id": "link", "title": "News", "permalink": "/r/rust/comments/link/", "post_hint": "link", "over_18": true, "url": "https://blog.rust-lang.org/"}}
+                ]
+              }
+            }"#;
+        let _m = mock("GET", "/r/rust/top.json?limit=10&t=week")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(body)
+            .create();
+
+        let reddit_client = RedditClient::new_with(url);
+        let result = reddit_client
+            .fetch_posts("rust", &Listing::default(), 10, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            result
+                .iter()
+                .map(|post| (post.image_url.as_deref(), post.over_18))
+                .collect::<Vec<(Option<&str>, bool)>>(),
+            [
+                (Some("https://i.redd.it/ferris.png"), false),
+                (Some("https://i.redd.it/unsafe.png"), true),
+                (None, true)
+            ]
+        );
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn fetch_posts_selftext() {
+        let url = &server_url();
+
+        let body = format!(
+            r#"{{
+            "kind": "Listing",
+              "data": {{
+                "children": [
+                  {{"kind": "t3", "data": {{"id": "text", "title": "What is your favorite crate?", "permalink": "/r/rust/comments/text/", "selftext": "{}"}}}},
+                  {{"kind": "t3", "data": {{"id": "link", "title": "Link post", "permalink": "/r/rust/comments/link/", "selftext": ""}}}}
+                ]
+              }}
+            }}
+         "#,
+            "**Asking** for a [friend](https://example.com). ".repeat(20)
+        );
+        let subreddit = "rust";
+        let _m = mock(
+            "GET",
+            format!("/r/{}/top.json?limit=10&t=week", subreddit).as_str(),
+        )
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(body)
+        .create();
+
+        let reddit_client = RedditClient::new_with(url);
+        let result = reddit_client
+            .fetch_posts(subreddit, &Listing::default(), 10, None)
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 2);
+
+        let excerpt = result[0].excerpt(200).unwrap();
+        assert!(excerpt.starts_with("Asking for a friend. Asking for a friend."));
+        assert!(excerpt.ends_with('…'));
+        assert!(excerpt.chars().count() <= 201);
+        assert_eq!(result[1].excerpt(200), None);
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn fetch_posts_over18() {
+        let url = &server_url();
+        let path = "/r/nsfw/top.json?limit=10&t=week";
+
+        let _interstitial = mock("GET", path)
+            .match_header("cookie", Matcher::Missing)
+            .with_status(302)
+            .with_header(
+                "location",
+                &format!(
+                    "{}/over18?dest=https%3A%2F%2Fwww.reddit.com%2Fr%2Fnsfw",
+                    url
+                ),
+            )
+            .create();
+        let _over18 = mock("GET", Matcher::Regex(r"^/over18".to_string()))
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body("<html>You must be 18+ to view this community</html>")
+            .create();
+        let _posts = mock("GET", path)
+            .match_header("cookie", "over18=1")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"kind": "Listing", "data": {"children": [
+                    {"kind": "t3", "data": {"id": "gated", "title": "Gated post", "permalink": "/r/nsfw/comments/gated/"}}
+                ]}}"#,
+            )
+            .create();
+
+        let reddit_client = RedditClient::new_with(url);
+        let result = reddit_client
+            .fetch_posts("nsfw", &Listing::default(), 10, None)
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].title, "Gated post");
+        _interstitial.assert();
+        _over18.assert();
+        _posts.assert();
+    }
+
+    #[tokio::test]
+    async fn fetch_top_comment_success() {
+        let url = &server_url();
+        let permalink = "/r/rust/comments/fbenua/a_halfhour_to_learn_rust/";
+        let body = r#"[
+            {"kind": "Listing", "data": {"children": [{"kind": "t3", "data": {"id": "fbenua"}}]}},
+            {"kind": "Listing", "data": {"children": [
+                {"kind": "t1", "data": {"id": "c1", "score": 420, "body": "This is **great**, thanks &amp; well done"}},
+                {"kind": "more", "data": {"count": 12, "children": ["c2", "c3"]}}
+            ]}}
+        ]"#;
+        let _m = mock(
+            "GET",
+            "/r/rust/comments/fbenua/a_halfhour_to_learn_rust.json?limit=1&sort=top",
+        )
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(body)
+        .expect(1)
+        .create();
+
+        let reddit_client = RedditClient::new_with(url);
+        let result = reddit_client.fetch_top_comment(permalink).await.unwrap();
+        assert_eq!(
+            result,
+            Some("This is great, thanks & well done".to_string())
+        );
+
+        // Served from the cache the second time around.
+        let result = reddit_client.fetch_top_comment(permalink).await.unwrap();
+        assert_eq!(
+            result,
+            Some("This is great, thanks & well done".to_string())
+        );
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn fetch_top_comment_no_comments() {
+        let url = &server_url();
+        let body = r#"[
+            {"kind": "Listing", "data": {"children": [{"kind": "t3", "data": {"id": "quiet"}}]}},
+            {"kind": "Listing", "data": {"children": []}}
+        ]"#;
+        let _m = mock(
+            "GET",
+            "/r/rust/comments/quiet/nothing_here.json?limit=1&sort=top",
+        )
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(body)
+        .create();
+
+        let reddit_client = RedditClient::new_with(url);
+        let result = reddit_client
+            .fetch_top_comment("/r/rust/comments/quiet/nothing_here/")
+            .await
+            .unwrap();
+        assert_eq!(result, None);
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn fetch_top_comment_malformed() {
+        let url = &server_url();
+        let _m = mock(
+            "GET",
+            "/r/rust/comments/broken/thread.json?limit=1&sort=top",
+        )
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(r#"{"data": {}}"#)
+        .create();
+
+        let reddit_client = RedditClient::new_with(url);
+        let result = reddit_client
+            .fetch_top_comment("/r/rust/comments/broken/thread/")
+            .await;
+        assert!(result.is_err());
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn fetch_comments_success() {
+        let url = &server_url();
+        let body = r#"[
+            {"kind": "Listing", "data": {"children": [{"kind": "t3", "data": {"id": "fbenua"}}]}},
+            {"kind": "Listing", "data": {"children": [
+                {"kind": "t1", "data": {"id": "c1", "author": "ferris", "score": 420, "created_utc": 1584878400.0, "body": "This is **great**"}},
+                {"kind": "t1", "data": {"id": "c2", "author": "koavf", "score": 12, "created_utc": 1584871200.0, "body": "Too old"}},
+                {"kind": "t1", "data": {"id": "c3", "author": "[deleted]", "score": 3, "created_utc": 1584882000.0, "body": "   "}},
+                {"kind": "more", "data": {"count": 12, "children": ["c4", "c5"]}}
+            ]}}
+        ]"#;
+        let _m = mock(
+            "GET",
+            "/r/rust/comments/fbenua/a_halfhour_to_learn_rust.json?limit=50&sort=top&depth=1",
+        )
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(body)
+        .expect(1)
+        .create();
+
+        let reddit_client = RedditClient::new_with(url);
+        let since = Utc.with_ymd_and_hms(2020, 3, 22, 11, 0, 0).unwrap();
+        let result = reddit_client
+            .fetch_comments("/r/rust/comments/fbenua/a_halfhour_to_learn_rust/", since)
+            .await
+            .unwrap();
+        assert_eq!(
+            result,
+            vec![Comment {
+                id: "c1".to_string(),
+                author: "ferris".to_string(),
+                body: "This is great".to_string(),
+                score: 420,
+                link: format!(
+                    "{}/r/rust/comments/fbenua/a_halfhour_to_learn_rust/c1/",
+                    url
+                ),
+            }]
+        );
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn fetch_comments_malformed() {
+        let url = &server_url();
+        let _m = mock(
+            "GET",
+            "/r/rust/comments/broken/thread.json?limit=50&sort=top&depth=1",
+        )
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(r#"{"data": {}}"#)
+        .create();
+
+        let reddit_client = RedditClient::new_with(url);
+        let result = reddit_client
+            .fetch_comments("/r/rust/comments/broken/thread/", Utc::now())
+            .await;
+        assert!(result.is_err());
         _m.assert();
     }
 
@@ -196,7 +1331,33 @@ mod tests {
 
         let reddit_client = RedditClient::new_with(url);
         let result = reddit_client.validate_subreddit(subreddit).await;
-        assert_eq!(result, true);
+        assert!(result);
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn validate_subreddit_multireddit() {
+        let url = &server_url();
+        let _m = mock("GET", "/user/foo/m/news")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .create();
+
+        let reddit_client = RedditClient::new_with(url);
+        let result = reddit_client.validate_subreddit("user/foo/m/news").await;
+        assert!(result);
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn validate_subreddit_reserved_feeds() {
+        let url = &server_url();
+        let _m = mock("GET", Matcher::Any).expect(0).create();
+
+        let reddit_client = RedditClient::new_with(url);
+        for feed in &["frontpage", "popular", "All"] {
+            assert!(reddit_client.validate_subreddit(feed).await);
+        }
         _m.assert();
     }
 
@@ -212,7 +1373,62 @@ mod tests {
 
         let reddit_client = RedditClient::new_with(url);
         let result = reddit_client.validate_subreddit(subreddit).await;
-        assert_eq!(result, false);
+        assert!(!result);
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn search_subreddits_success() {
+        let url = &server_url();
+        let _m = mock("GET", "/subreddits/search.json")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("q".to_string(), "rust lang".to_string()),
+                Matcher::UrlEncoded("limit".to_string(), "5".to_string()),
+            ]))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"kind": "Listing", "data": {"children": [
+                {"kind": "t5", "data": {"display_name": "rust", "title": "The Rust Programming Language", "subscribers": 300000}},
+                {"kind": "t5", "data": {"title": "Missing name"}},
+                {"kind": "t5", "data": {"display_name": "learnrust", "title": "Learn Rust", "subscribers": null}}
+            ]}}"#)
+            .create();
+
+        let reddit_client = RedditClient::new_with(url);
+        let result = reddit_client
+            .search_subreddits("rust lang", 5)
+            .await
+            .unwrap();
+        assert_eq!(
+            result,
+            [
+                SubredditInfo {
+                    name: "rust".to_string(),
+                    title: "The Rust Programming Language".to_string(),
+                    subscribers: 300000,
+                },
+                SubredditInfo {
+                    name: "learnrust".to_string(),
+                    title: "Learn Rust".to_string(),
+                    subscribers: 0,
+                },
+            ]
+        );
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn search_subreddits_error() {
+        let url = &server_url();
+        let _m = mock("GET", "/subreddits/search.json")
+            .match_query(Matcher::Any)
+            .with_status(200)
+            .with_body(r#"{"kind": "Listing"}"#)
+            .create();
+
+        let reddit_client = RedditClient::new_with(url);
+        let result = reddit_client.search_subreddits("rust", 5).await;
+        assert!(result.is_err());
         _m.assert();
     }
 }
@@ -6,9 +6,23 @@ use std::fmt::Formatter;
 pub enum RedditError {
     NetworkError(reqwest::Error),
     MalformedResponse(serde_json::error::Error),
+    /// Reddit answered with an `error` body instead of a listing, e.g. 404 "Not Found".
+    ApiError(u64, String),
     Error,
 }
 
+impl RedditError {
+    /// The request didn't complete in time, worth retrying.
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, RedditError::NetworkError(err) if err.is_timeout())
+    }
+
+    /// Reddit couldn't be reached at all, worth retrying.
+    pub fn is_connect(&self) -> bool {
+        matches!(self, RedditError::NetworkError(err) if err.is_connect())
+    }
+}
+
 impl From<reqwest::Error> for RedditError {
     fn from(error: reqwest::Error) -> Self {
         RedditError::NetworkError(error)
@@ -28,7 +42,58 @@ impl fmt::Display for RedditError {
         match self {
             RedditError::NetworkError(err) => err.fmt(f),
             RedditError::MalformedResponse(err) => err.fmt(f),
+            RedditError::ApiError(code, message) => write!(f, "reddit error {}: {}", code, message),
             _ => Ok(()),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn connect_error() {
+        // Nothing listens on the port once the listener is dropped.
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let error: RedditError = reqwest::get(format!("http://{}", addr))
+            .await
+            .unwrap_err()
+            .into();
+        assert!(error.is_connect());
+        assert!(!error.is_timeout());
+    }
+
+    #[tokio::test]
+    async fn timeout_error() {
+        // Accepts the connection but never responds.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let error: RedditError = reqwest::Client::builder()
+            .timeout(Duration::from_millis(50))
+            .build()
+            .unwrap()
+            .get(format!("http://{}", addr))
+            .send()
+            .await
+            .unwrap_err()
+            .into();
+        assert!(error.is_timeout());
+        assert!(!error.is_connect());
+    }
+
+    #[test]
+    fn decode_error() {
+        let error: RedditError = serde_json::from_str::<serde_json::Value>("<html>")
+            .unwrap_err()
+            .into();
+        assert!(!error.is_timeout());
+        assert!(!error.is_connect());
+    }
+}
@@ -1,13 +1,141 @@
 use std::fmt;
 
-#[derive(Debug, PartialEq)]
+use lazy_static::lazy_static;
+use regex::Regex;
+
+#[derive(Debug, PartialEq, Default)]
 pub struct Post {
+    pub id: String,
     pub title: String,
     pub link: String,
+    pub is_crosspost: bool,
+    pub selftext: String,
+    pub top_comment: Option<String>,
+    /// Url of the linked article, None for self posts and reddit hosted media.
+    pub external_url: Option<String>,
+    /// Marked NSFW on reddit.
+    pub over_18: bool,
+    /// Url of the image for image posts, None for everything else.
+    pub image_url: Option<String>,
+    pub score: i64,
+}
+
+/// Top level comment of a thread, see `RedditClient::fetch_comments`.
+#[derive(Debug, PartialEq, Default)]
+pub struct Comment {
+    pub id: String,
+    pub author: String,
+    /// Body stripped of markdown and truncated.
+    pub body: String,
+    pub score: i64,
+    pub link: String,
+}
+
+impl Post {
+    /// Selftext stripped of markdown and truncated to `max_len` characters, None for link posts.
+    pub fn excerpt(&self, max_len: usize) -> Option<String> {
+        plain_text(&self.selftext, max_len)
+    }
+}
+
+lazy_static! {
+    static ref LINKS: Regex = Regex::new(r"\[([^\]]*)\]\([^)]*\)").unwrap();
+    /// Emphasis, strikethrough and inline code are only stripped in pairs, so underscores in
+    /// snake_case names and urls are kept.
+    static ref PAIRED_MARKUP: [Regex; 4] = [
+        Regex::new(r"\*\*?([^*\n]+?)\*\*?").unwrap(),
+        Regex::new(r"\b__?([^_\n]+?)__?\b").unwrap(),
+        Regex::new(r"~~([^~\n]+?)~~").unwrap(),
+        Regex::new(r"`+([^`\n]+?)`+").unwrap(),
+    ];
+    /// Headings and quotes, only at the start of a line.
+    static ref LINE_MARKUP: Regex = Regex::new(r"(?m)^[ \t]*(#{1,6}|>)[ \t]?").unwrap();
+    static ref WHITESPACE: Regex = Regex::new(r"\s+").unwrap();
+}
+
+/// Text stripped of markdown and truncated to `max_len` characters, None when nothing is left.
+pub fn plain_text(text: &str, max_len: usize) -> Option<String> {
+    let text = text
+        .replace("&amp;", "&")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">");
+    let mut text = LINKS.replace_all(&text, "$1").into_owned();
+    for markup in PAIRED_MARKUP.iter() {
+        text = markup.replace_all(&text, "$1").into_owned();
+    }
+    let text = LINE_MARKUP.replace_all(&text, "");
+    let text = WHITESPACE.replace_all(&text, " ");
+    let text = text.trim();
+
+    if text.is_empty() {
+        return None;
+    }
+
+    Some(truncate(text, max_len))
+}
+
+/// Text cut to `max_len` characters with an ellipsis appended, safe for multibyte text.
+pub fn truncate(text: &str, max_len: usize) -> String {
+    if text.chars().count() > max_len {
+        let truncated = text.chars().take(max_len).collect::<String>();
+        format!("{}…", truncated.trim_end())
+    } else {
+        text.to_string()
+    }
 }
 
 impl fmt::Display for Post {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}\n{}\n", self.title, self.link)
+        if self.is_crosspost {
+            write!(f, "{} (crosspost)\n{}\n", self.title, self.link)
+        } else {
+            write!(f, "{}\n{}\n", self.title, self.link)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post(selftext: &str) -> Post {
+        Post {
+            selftext: selftext.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn excerpt_strips_markdown() {
+        let result = post("# Title\n\n**bold** _and_ ~~gone~~ [link](https://example.com) &amp; `code`\n\n> quote").excerpt(200);
+        assert_eq!(
+            result,
+            Some("Title bold and gone link & code quote".to_string())
+        );
+    }
+
+    #[test]
+    fn excerpt_keeps_unpaired_markup() {
+        let text = "Use snake_case names, see https://example.com/some_page#top or 2 * 3 > 5";
+        assert_eq!(post(text).excerpt(200), Some(text.to_string()));
+    }
+
+    #[test]
+    fn excerpt_truncates() {
+        let result = post(&"word ".repeat(100)).excerpt(12);
+        assert_eq!(result, Some("word word wo…".to_string()));
+    }
+
+    #[test]
+    fn truncate_multibyte() {
+        assert_eq!(truncate("Ржавчина — язык", 8), "Ржавчина…");
+        assert_eq!(truncate("🦀🦀🦀🦀", 2), "🦀🦀…");
+        assert_eq!(truncate("日本語のタイトル", 8), "日本語のタイトル");
+    }
+
+    #[test]
+    fn excerpt_empty() {
+        assert_eq!(post("").excerpt(200), None);
+        assert_eq!(post(" \n ").excerpt(200), None);
     }
 }
@@ -0,0 +1,1187 @@
+use reqwest::Url;
+use strum_macros::{Display, EnumString};
+
+use crate::db::models::Subscription;
+use crate::reddit::client::sort_has_timeframe;
+use crate::reddit::post::{truncate, Comment, Post};
+use crate::telegram::helpers::{escape_html, escape_markdown};
+use crate::telegram::types::ParseMode;
+
+pub const MAX_MESSAGE_LENGTH: usize = 4096;
+const EXCERPT_LENGTH: usize = 200;
+/// Telegram polls allow at most 10 options of up to 100 characters each.
+const MAX_POLL_OPTIONS: usize = 10;
+const MAX_POLL_OPTION_LENGTH: usize = 100;
+/// Longest entity `escape_html` produces, "&quot;".
+const MAX_ENTITY_LENGTH: usize = 6;
+/// Languages grouping thousands with "." or a space, all others use ",".
+const DOT_SEPARATED_LANGUAGES: [&str; 8] = ["da", "de", "es", "id", "it", "nl", "pt", "tr"];
+const SPACE_SEPARATED_LANGUAGES: [&str; 10] =
+    ["cs", "fi", "fr", "lt", "lv", "nb", "pl", "ru", "sv", "uk"];
+
+/// Link shown under each post next to its permalink, which already points to the comments.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Display, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum LinkStyle {
+    /// Only the permalink.
+    #[default]
+    Permalink,
+    /// Also the short `https://redd.it/<id>` link.
+    Short,
+    /// Also the no-participation `np.reddit.com` link.
+    Np,
+}
+
+impl LinkStyle {
+    /// Link added under the permalink of the post, None when there's nothing to add.
+    fn extra_link(self, post: &Post) -> Option<String> {
+        match self {
+            LinkStyle::Permalink => None,
+            LinkStyle::Short if !post.id.is_empty() => Some(format!("https://redd.it/{}", post.id)),
+            LinkStyle::Short => None,
+            LinkStyle::Np => Url::parse(&post.link)
+                .ok()
+                .map(|link| format!("https://np.reddit.com{}", link.path())),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    /// Header of the first message, `{subreddit}` is replaced with the subreddit name and
+    /// `{timeframe}` with the label of the timeframe, e.g. "Weekly". Sorts without a timeframe,
+    /// e.g. "new", leave the label out.
+    pub header_template: String,
+    /// Reddit's `t` parameter of the top listing the posts come from.
+    pub timeframe: String,
+    /// Shown in place of the posts when there are none, None to name the timeframe, e.g. "No
+    /// popular posts this month."
+    pub empty_text: Option<String>,
+    pub numbered: bool,
+    pub parse_mode: Option<ParseMode>,
+    pub max_message_length: usize,
+    /// Starts a new message after this many posts, on top of splitting messages that get too long.
+    pub posts_per_message: Option<usize>,
+    pub include_excerpt: bool,
+    pub prefer_external_url: bool,
+    pub link_style: LinkStyle,
+    /// Titles longer than this many characters are cut off with an ellipsis.
+    pub max_title_length: Option<usize>,
+    /// Prepended to the header, e.g. an emoji telling themed subscriptions apart.
+    pub tag: Option<String>,
+    /// Appended once to the last message, e.g. a disclaimer configured by the operator.
+    pub footer: Option<String>,
+    /// Shows the score of each post next to its title.
+    pub show_scores: bool,
+    /// Language tag of the reader, e.g. "de", picking the thousands separator of scores. It's a
+    /// setting of the user, `for_subscription` leaves it unset.
+    pub locale: Option<String>,
+}
+
+impl RenderOptions {
+    pub fn for_subscription(subscription: &Subscription) -> Self {
+        let mut opts = RenderOptions {
+            include_excerpt: subscription.include_excerpt,
+            prefer_external_url: subscription.prefer_external_url,
+            link_style: subscription.link_style.parse().unwrap_or_default(),
+            max_title_length: subscription.max_title_len.map(|len| len as usize),
+            posts_per_message: subscription.posts_per_message.map(|count| count as usize),
+            tag: subscription.tag.clone(),
+            show_scores: subscription.show_scores,
+            ..Default::default()
+        };
+        if subscription.since_last_sent {
+            opts.header_template =
+                "New posts from: \"{subreddit}\" since the last digest".to_string();
+            opts.empty_text = Some("No new posts since the last digest.".to_string());
+        }
+        if let Some(empty_text) = &subscription.empty_text {
+            opts.empty_text = Some(empty_text.clone());
+        }
+        if !subscription.timeframe.is_empty() {
+            opts.timeframe = subscription.timeframe.clone();
+        }
+        opts
+    }
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            header_template: "{timeframe} popular posts from: \"{subreddit}\"".to_string(),
+            timeframe: "week".to_string(),
+            empty_text: None,
+            numbered: false,
+            parse_mode: None,
+            max_message_length: MAX_MESSAGE_LENGTH,
+            posts_per_message: None,
+            include_excerpt: false,
+            prefer_external_url: false,
+            link_style: LinkStyle::Permalink,
+            max_title_length: None,
+            tag: None,
+            footer: None,
+            show_scores: false,
+            locale: None,
+        }
+    }
+}
+
+/// Header label of a top listing timeframe, unknown ones are shown as "Top".
+pub fn timeframe_label(timeframe: &str) -> &'static str {
+    match timeframe {
+        "hour" => "Hourly",
+        "day" => "Daily",
+        "week" => "Weekly",
+        "month" => "Monthly",
+        "year" => "Yearly",
+        "all" => "All-time",
+        _ => "Top",
+    }
+}
+
+/// Text of a digest without posts, only top listings are limited to a `timeframe`.
+fn default_empty_text(timeframe: Option<&str>) -> &'static str {
+    match timeframe {
+        Some("hour") => "No popular posts this hour.",
+        Some("day") => "No popular posts today.",
+        Some("week") => "No popular posts this week.",
+        Some("month") => "No popular posts this month.",
+        Some("year") => "No popular posts this year.",
+        _ => "No popular posts.",
+    }
+}
+
+/// Text with its first letter in upper case, e.g. a header whose timeframe label was left out.
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn escape(text: &str, parse_mode: Option<ParseMode>) -> String {
+    match parse_mode {
+        Some(ParseMode::Html) => escape_html(text),
+        Some(ParseMode::MarkdownV2) => escape_markdown(text),
+        None => text.to_string(),
+    }
+}
+
+/// Timeframe the posts are limited to. Reddit only applies it to top and controversial listings,
+/// an unset sort is "top".
+fn listing_timeframe<'a>(subscription: &Subscription, opts: &'a RenderOptions) -> Option<&'a str> {
+    if subscription.sort.is_empty() || sort_has_timeframe(&subscription.sort) {
+        Some(opts.timeframe.as_str())
+    } else {
+        None
+    }
+}
+
+pub fn build_digest(
+    subscription: &Subscription,
+    posts: &[Post],
+    opts: &RenderOptions,
+) -> Vec<String> {
+    let timeframe = listing_timeframe(subscription, opts);
+    let header = opts
+        .header_template
+        .replace("{subreddit}", &subscription.subreddit);
+    let header = match timeframe {
+        Some(timeframe) => header.replace("{timeframe}", timeframe_label(timeframe)),
+        None => capitalize(header.replace("{timeframe}", "").trim_start()),
+    };
+    let header = match &opts.tag {
+        Some(tag) => format!("{} {}", tag, header),
+        None => header,
+    };
+    let mut messages = vec![];
+    let mut message = format!("{}\n\n", escape(&header, opts.parse_mode));
+
+    if posts.is_empty() {
+        let empty_text = match &opts.empty_text {
+            Some(empty_text) => empty_text.as_str(),
+            None => default_empty_text(timeframe),
+        };
+        message.push_str(&format!("{}\n", escape(empty_text, opts.parse_mode)));
+    }
+
+    for (i, post) in posts.iter().enumerate() {
+        let title = match opts.max_title_length {
+            Some(max_len) => truncate(&post.title, max_len),
+            None => post.title.clone(),
+        };
+        let title = if post.is_crosspost {
+            format!("{} (crosspost)", title)
+        } else {
+            title
+        };
+        let title = if opts.show_scores {
+            format!(
+                "{} ({} points)",
+                title,
+                format_number(post.score, opts.locale.as_deref())
+            )
+        } else {
+            title
+        };
+        let title = if opts.numbered {
+            format!("{}. {}", i + 1, title)
+        } else {
+            title
+        };
+        let excerpt = match post.excerpt(EXCERPT_LENGTH) {
+            Some(excerpt) if opts.include_excerpt => {
+                format!("{}\n", escape(&excerpt, opts.parse_mode))
+            }
+            _ => String::new(),
+        };
+        let top_comment = match &post.top_comment {
+            Some(comment) => format!(
+                "{}\n",
+                escape(&format!("Top comment: {}", comment), opts.parse_mode)
+            ),
+            None => String::new(),
+        };
+        let link = match &post.external_url {
+            Some(external_url) if opts.prefer_external_url => external_url,
+            _ => &post.link,
+        };
+        let extra_link = match opts.link_style.extra_link(post) {
+            Some(extra_link) => format!("{}\n", escape(&extra_link, opts.parse_mode)),
+            None => String::new(),
+        };
+        let entry = format!(
+            "{}\n{}{}\n{}{}\n",
+            escape(&title, opts.parse_mode),
+            excerpt,
+            escape(link, opts.parse_mode),
+            extra_link,
+            top_comment
+        );
+
+        // An entry too long for a message of its own is spread over several.
+        if entry.chars().count() > opts.max_message_length {
+            message.push_str(&entry);
+            let mut pieces = split_message(&message, opts.max_message_length);
+            message = pieces.pop().unwrap_or_default();
+            messages.extend(pieces);
+            continue;
+        }
+
+        let group_full =
+            matches!(opts.posts_per_message, Some(count) if count > 0 && i > 0 && i % count == 0);
+        if group_full
+            || (!message.is_empty()
+                && message.chars().count() + entry.chars().count() > opts.max_message_length)
+        {
+            messages.push(message);
+            message = String::new();
+        }
+        message.push_str(&entry);
+    }
+
+    if let Some(footer) = &opts.footer {
+        let footer = format!("{}\n", escape(footer, opts.parse_mode));
+        if message.chars().count() + footer.chars().count() > opts.max_message_length {
+            messages.push(message);
+            message = String::new();
+        }
+        message.push_str(&footer);
+    }
+
+    messages.push(message);
+    messages
+}
+
+/// Header of the combined digest, e.g. "Your weekly digest". The timeframe is only named when all of
+/// the subscriptions list their posts by it.
+pub fn combined_header(subscriptions: &[Subscription]) -> String {
+    let timeframe = subscriptions.first().map(|s| s.timeframe.as_str());
+    let shared = subscriptions.iter().all(|s| {
+        !s.since_last_sent && sort_has_timeframe(&s.sort) && Some(s.timeframe.as_str()) == timeframe
+    });
+    match timeframe {
+        Some(timeframe) if shared && timeframe_label(timeframe) != "Top" => {
+            format!("Your {} digest", timeframe_label(timeframe).to_lowercase())
+        }
+        _ => "Your digest".to_string(),
+    }
+}
+
+/// Digests of several subscriptions merged into as few messages as possible, one section per subreddit.
+/// A section is only split across messages when it doesn't fit next to the previous ones.
+pub fn build_combined_digest(
+    header: &str,
+    sections: &[Vec<String>],
+    max_message_length: usize,
+) -> Vec<String> {
+    let mut messages = vec![];
+    let mut message = format!("{}\n\n", header);
+    // The header alone is never sent, the first part stays with it even when that's too long.
+    let mut has_parts = false;
+
+    for part in sections.iter().flatten() {
+        if has_parts && message.chars().count() + part.chars().count() > max_message_length {
+            messages.push(message);
+            message = String::new();
+        }
+        message.push_str(part);
+        has_parts = true;
+    }
+
+    messages.push(message);
+    messages
+}
+
+/// Pieces of a message of at most `max_len` characters. Cuts are made after the last line break
+/// that fits, otherwise they are moved before an escape sequence they would break, e.g. "&amp;".
+fn split_message(message: &str, max_len: usize) -> Vec<String> {
+    let mut pieces = vec![];
+    let mut rest = message;
+    while let Some((end, _)) = rest.char_indices().nth(max_len) {
+        let window = &rest[..end];
+        let cut = match window.rfind('\n') {
+            Some(newline) if newline > 0 => newline + 1,
+            _ => escape_safe_cut(window),
+        };
+        pieces.push(rest[..cut].to_string());
+        rest = &rest[cut..];
+    }
+    pieces.push(rest.to_string());
+    pieces
+}
+
+/// Byte length of `window` without an HTML entity or Markdown escape cut off at its end, the
+/// whole window when there would be nothing left.
+fn escape_safe_cut(window: &str) -> usize {
+    let mut cut = window.len();
+    if let Some(ampersand) = window.rfind('&') {
+        if cut - ampersand < MAX_ENTITY_LENGTH && !window[ampersand..].contains(';') {
+            cut = ampersand;
+        }
+    }
+    let backslashes = window[..cut]
+        .chars()
+        .rev()
+        .take_while(|c| *c == '\\')
+        .count();
+    if backslashes % 2 == 1 {
+        cut -= 1;
+    }
+    if cut == 0 {
+        window.len()
+    } else {
+        cut
+    }
+}
+
+/// New comments of a watched thread, bodies are already truncated so it always fits a single message.
+/// `skipped` new comments didn't make the cut and are only counted.
+pub fn build_comments(permalink: &str, comments: &[Comment], skipped: usize) -> String {
+    let mut message = format!("New top comments in {}\n\n", permalink);
+    for comment in comments {
+        message.push_str(&format!(
+            "{} ({} points): {}\n{}\n\n",
+            comment.author,
+            format_number(comment.score, None),
+            comment.body,
+            comment.link
+        ));
+    }
+    match skipped {
+        0 => {}
+        1 => message.push_str("1 more new comment in the thread.\n"),
+        skipped => message.push_str(&format!("{} more new comments in the thread.\n", skipped)),
+    }
+    message
+}
+
+/// Number with its thousands grouped, e.g. "12,345". `locale` is a language tag like "de" or
+/// "pt-BR" picking the separator, "," when not set or unknown.
+pub fn format_number(value: i64, locale: Option<&str>) -> String {
+    let language = locale
+        .and_then(|locale| locale.split(['-', '_']).next())
+        .map(str::to_lowercase)
+        .unwrap_or_default();
+    let separator = if DOT_SEPARATED_LANGUAGES.contains(&language.as_str()) {
+        '.'
+    } else if SPACE_SEPARATED_LANGUAGES.contains(&language.as_str()) {
+        // Non-breaking, so a number is never split over two lines.
+        '\u{a0}'
+    } else {
+        ','
+    };
+
+    let digits = value.unsigned_abs().to_string();
+    let mut formatted = digits
+        .as_bytes()
+        .rchunks(3)
+        .rev()
+        .map(|group| std::str::from_utf8(group).unwrap())
+        .collect::<Vec<&str>>()
+        .join(&separator.to_string());
+    if value < 0 {
+        formatted.insert(0, '-');
+    }
+    formatted
+}
+
+/// Question and options of a "which to read" poll, one option per post title.
+pub fn build_poll(subscription: &Subscription, posts: &[Post]) -> (String, Vec<String>) {
+    let opts = RenderOptions::for_subscription(subscription);
+    // Named like in the digest header, e.g. "weekly popular posts".
+    let kind = match listing_timeframe(subscription, &opts) {
+        _ if subscription.since_last_sent => "new posts".to_string(),
+        Some(timeframe) if timeframe_label(timeframe) != "Top" => format!(
+            "{} popular posts",
+            timeframe_label(timeframe).to_lowercase()
+        ),
+        _ => "popular posts".to_string(),
+    };
+    let question = format!(
+        "Which of these {} from \"{}\" will you read?",
+        kind, subscription.subreddit
+    );
+    let options = posts
+        .iter()
+        .take(MAX_POLL_OPTIONS)
+        .map(|post| truncate(&post.title, MAX_POLL_OPTION_LENGTH - 1))
+        .collect();
+    (question, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subscription() -> Subscription {
+        Subscription {
+            subreddit: "rust".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn posts() -> Vec<Post> {
+        vec![
+            Post {
+                id: "1".to_string(),
+                title: "First".to_string(),
+                link: "https://reddit.com/r/rust/comments/1/".to_string(),
+                is_crosspost: false,
+                selftext: String::new(),
+                top_comment: None,
+                external_url: None,
+                over_18: false,
+                image_url: None,
+                score: 12345,
+            },
+            Post {
+                id: "2".to_string(),
+                title: "Second".to_string(),
+                link: "https://reddit.com/r/rust/comments/2/".to_string(),
+                is_crosspost: true,
+                selftext: String::new(),
+                top_comment: None,
+                external_url: None,
+                over_18: false,
+                image_url: None,
+                score: 42,
+            },
+        ]
+    }
+
+    #[test]
+    fn build_digest_success() {
+        let result = build_digest(&subscription(), &posts(), &RenderOptions::default());
+        assert_eq!(
+            result,
+            ["Weekly popular posts from: \"rust\"\n\nFirst\nhttps://reddit.com/r/rust/comments/1/\n\nSecond (crosspost)\nhttps://reddit.com/r/rust/comments/2/\n\n"]
+        );
+    }
+
+    #[test]
+    fn build_digest_numbered() {
+        let opts = RenderOptions {
+            numbered: true,
+            ..Default::default()
+        };
+        let result = build_digest(&subscription(), &posts(), &opts);
+        assert_eq!(
+            result,
+            ["Weekly popular posts from: \"rust\"\n\n1. First\nhttps://reddit.com/r/rust/comments/1/\n\n2. Second (crosspost)\nhttps://reddit.com/r/rust/comments/2/\n\n"]
+        );
+    }
+
+    #[test]
+    fn build_digest_timeframe_label() {
+        let cases = [
+            ("day", "Daily"),
+            ("week", "Weekly"),
+            ("month", "Monthly"),
+            ("all", "All-time"),
+        ];
+        for (timeframe, label) in &cases {
+            let opts = RenderOptions {
+                timeframe: timeframe.to_string(),
+                ..Default::default()
+            };
+            let result = build_digest(&subscription(), &[], &opts);
+            assert!(result[0].starts_with(&format!("{} popular posts from: \"rust\"\n\n", label)));
+        }
+        assert_eq!(timeframe_label("decade"), "Top");
+    }
+
+    #[test]
+    fn build_digest_sort_without_timeframe() {
+        let subscription = Subscription {
+            sort: "new".to_string(),
+            timeframe: "week".to_string(),
+            ..subscription()
+        };
+        let opts = RenderOptions::for_subscription(&subscription);
+        let result = build_digest(&subscription, &[], &opts);
+        assert_eq!(
+            result,
+            ["Popular posts from: \"rust\"\n\nNo popular posts.\n"]
+        );
+    }
+
+    #[test]
+    fn build_digest_no_posts_this_month() {
+        let subscription = Subscription {
+            sort: "top".to_string(),
+            timeframe: "month".to_string(),
+            ..subscription()
+        };
+        let opts = RenderOptions::for_subscription(&subscription);
+        let result = build_digest(&subscription, &[], &opts);
+        assert_eq!(
+            result,
+            ["Monthly popular posts from: \"rust\"\n\nNo popular posts this month.\n"]
+        );
+    }
+
+    #[test]
+    fn build_digest_header_template() {
+        let opts = RenderOptions {
+            header_template: "Top of r/{subreddit}".to_string(),
+            ..Default::default()
+        };
+        let result = build_digest(&subscription(), &[], &opts);
+        assert_eq!(result, ["Top of r/rust\n\nNo popular posts this week.\n"]);
+    }
+
+    #[test]
+    fn build_digest_no_posts() {
+        let result = build_digest(&subscription(), &[], &RenderOptions::default());
+        assert_eq!(
+            result,
+            ["Weekly popular posts from: \"rust\"\n\nNo popular posts this week.\n"]
+        );
+    }
+
+    #[test]
+    fn build_digest_custom_empty_text() {
+        let subscription = Subscription {
+            empty_text: Some("Quiet week in the crab shack.".to_string()),
+            ..subscription()
+        };
+        let opts = RenderOptions::for_subscription(&subscription);
+        let result = build_digest(&subscription, &[], &opts);
+        assert_eq!(
+            result,
+            ["Weekly popular posts from: \"rust\"\n\nQuiet week in the crab shack.\n"]
+        );
+    }
+
+    #[test]
+    fn build_digest_since_last_sent() {
+        let subscription = Subscription {
+            since_last_sent: true,
+            ..subscription()
+        };
+        let opts = RenderOptions::for_subscription(&subscription);
+        let result = build_digest(&subscription, &[], &opts);
+        assert_eq!(
+            result,
+            ["New posts from: \"rust\" since the last digest\n\nNo new posts since the last digest.\n"]
+        );
+    }
+
+    #[test]
+    fn build_digest_escapes_html() {
+        let opts = RenderOptions {
+            parse_mode: Some(ParseMode::Html),
+            ..Default::default()
+        };
+        let posts = vec![Post {
+            id: "1".to_string(),
+            title: "Vec<T> & you".to_string(),
+            link: "https://reddit.com/r/rust/comments/1/?a=1&b=2".to_string(),
+            is_crosspost: false,
+            selftext: String::new(),
+            top_comment: None,
+            external_url: None,
+            over_18: false,
+            image_url: None,
+            score: 0,
+        }];
+        let result = build_digest(&subscription(), &posts, &opts);
+        assert_eq!(
+            result,
+            ["Weekly popular posts from: &quot;rust&quot;\n\nVec&lt;T&gt; &amp; you\nhttps://reddit.com/r/rust/comments/1/?a=1&amp;b=2\n\n"]
+        );
+    }
+
+    #[test]
+    fn build_digest_escapes_markdown() {
+        let opts = RenderOptions {
+            header_template: "r/{subreddit}".to_string(),
+            parse_mode: Some(ParseMode::MarkdownV2),
+            numbered: true,
+            ..Default::default()
+        };
+        let result = build_digest(&subscription(), &posts()[..1], &opts);
+        assert_eq!(
+            result,
+            ["r/rust\n\n1\\. First\nhttps://reddit\\.com/r/rust/comments/1/\n\n"]
+        );
+    }
+
+    #[test]
+    fn build_digest_excerpt() {
+        let mut posts = posts();
+        posts[0].selftext = "Some **text**".to_string();
+        let result = build_digest(&subscription(), &posts, &RenderOptions::default());
+        assert!(!result[0].contains("Some text"));
+
+        let opts = RenderOptions {
+            include_excerpt: true,
+            ..Default::default()
+        };
+        let result = build_digest(&subscription(), &posts, &opts);
+        assert_eq!(
+            result,
+            ["Weekly popular posts from: \"rust\"\n\nFirst\nSome text\nhttps://reddit.com/r/rust/comments/1/\n\nSecond (crosspost)\nhttps://reddit.com/r/rust/comments/2/\n\n"]
+        );
+    }
+
+    #[test]
+    fn build_poll_caps_options() {
+        let posts = (0..12)
+            .map(|i| Post {
+                id: i.to_string(),
+                title: if i == 0 {
+                    "Long ".repeat(30)
+                } else {
+                    format!("Post {}", i)
+                },
+                link: format!("https://reddit.com/r/rust/comments/{}/", i),
+                is_crosspost: false,
+                selftext: String::new(),
+                top_comment: None,
+                external_url: None,
+                over_18: false,
+                image_url: None,
+                score: 0,
+            })
+            .collect::<Vec<Post>>();
+
+        let (question, options) = build_poll(&subscription(), &posts);
+        assert_eq!(
+            question,
+            "Which of these weekly popular posts from \"rust\" will you read?"
+        );
+        assert_eq!(options.len(), 10);
+        assert_eq!(options[0].chars().count(), 100);
+        assert!(options[0].ends_with('…'));
+        assert_eq!(options[1], "Post 1");
+        assert_eq!(options[9], "Post 9");
+    }
+
+    #[test]
+    fn build_poll_names_listing() {
+        let question = |sort: &str, timeframe: &str, since_last_sent: bool| {
+            let subscription = Subscription {
+                sort: sort.to_string(),
+                timeframe: timeframe.to_string(),
+                since_last_sent,
+                ..subscription()
+            };
+            build_poll(&subscription, &posts()).0
+        };
+        assert_eq!(
+            question("top", "month", false),
+            "Which of these monthly popular posts from \"rust\" will you read?"
+        );
+        assert_eq!(
+            question("hot", "month", false),
+            "Which of these popular posts from \"rust\" will you read?"
+        );
+        assert_eq!(
+            question("top", "week", true),
+            "Which of these new posts from \"rust\" will you read?"
+        );
+    }
+
+    #[test]
+    fn build_digest_max_title_length() {
+        let posts = vec![Post {
+            id: "1".to_string(),
+            title: "Ржавчина — лучший язык".to_string(),
+            link: "https://reddit.com/r/rust/comments/1/".to_string(),
+            is_crosspost: true,
+            ..Default::default()
+        }];
+        let subscription = Subscription {
+            max_title_len: Some(10),
+            ..subscription()
+        };
+        let opts = RenderOptions::for_subscription(&subscription);
+        let result = build_digest(&subscription, &posts, &opts);
+        assert_eq!(
+            result,
+            ["Weekly popular posts from: \"rust\"\n\nРжавчина —… (crosspost)\nhttps://reddit.com/r/rust/comments/1/\n\n"]
+        );
+
+        let result = build_digest(&subscription, &posts, &RenderOptions::default());
+        assert!(result[0].contains("Ржавчина — лучший язык (crosspost)\n"));
+    }
+
+    #[test]
+    fn build_digest_tag() {
+        let subscription = Subscription {
+            tag: Some("🦀".to_string()),
+            ..subscription()
+        };
+        let opts = RenderOptions::for_subscription(&subscription);
+        let result = build_digest(&subscription, &posts()[..1], &opts);
+        assert_eq!(
+            result,
+            ["🦀 Weekly popular posts from: \"rust\"\n\nFirst\nhttps://reddit.com/r/rust/comments/1/\n\n"]
+        );
+
+        let subscription = Subscription {
+            tag: None,
+            ..subscription
+        };
+        let opts = RenderOptions::for_subscription(&subscription);
+        let result = build_digest(&subscription, &posts()[..1], &opts);
+        assert!(result[0].starts_with("Weekly popular posts from: \"rust\"\n\n"));
+    }
+
+    #[test]
+    fn build_digest_external_url() {
+        let mut posts = posts();
+        posts[0].external_url = Some("https://example.com/article".to_string());
+        let result = build_digest(&subscription(), &posts, &RenderOptions::default());
+        assert!(!result[0].contains("https://example.com/article"));
+
+        let opts = RenderOptions {
+            prefer_external_url: true,
+            ..Default::default()
+        };
+        let result = build_digest(&subscription(), &posts, &opts);
+        assert_eq!(
+            result,
+            ["Weekly popular posts from: \"rust\"\n\nFirst\nhttps://example.com/article\n\nSecond (crosspost)\nhttps://reddit.com/r/rust/comments/2/\n\n"]
+        );
+    }
+
+    #[test]
+    fn link_style_parse() {
+        assert_eq!("permalink".parse::<LinkStyle>(), Ok(LinkStyle::Permalink));
+        assert_eq!("short".parse::<LinkStyle>(), Ok(LinkStyle::Short));
+        assert_eq!("np".parse::<LinkStyle>(), Ok(LinkStyle::Np));
+        assert!("both".parse::<LinkStyle>().is_err());
+        assert_eq!(LinkStyle::Np.to_string(), "np");
+    }
+
+    #[test]
+    fn build_digest_link_style_permalink() {
+        let opts = RenderOptions {
+            link_style: LinkStyle::Permalink,
+            ..Default::default()
+        };
+        let result = build_digest(&subscription(), &posts()[..1], &opts);
+        assert_eq!(
+            result,
+            ["Weekly popular posts from: \"rust\"\n\nFirst\nhttps://reddit.com/r/rust/comments/1/\n\n"]
+        );
+    }
+
+    #[test]
+    fn build_digest_link_style_short() {
+        let opts = RenderOptions {
+            link_style: LinkStyle::Short,
+            ..Default::default()
+        };
+        let result = build_digest(&subscription(), &posts()[..1], &opts);
+        assert_eq!(
+            result,
+            ["Weekly popular posts from: \"rust\"\n\nFirst\nhttps://reddit.com/r/rust/comments/1/\nhttps://redd.it/1\n\n"]
+        );
+    }
+
+    #[test]
+    fn build_digest_link_style_np() {
+        let opts = RenderOptions {
+            link_style: LinkStyle::Np,
+            ..Default::default()
+        };
+        let result = build_digest(&subscription(), &posts()[..1], &opts);
+        assert_eq!(
+            result,
+            ["Weekly popular posts from: \"rust\"\n\nFirst\nhttps://reddit.com/r/rust/comments/1/\nhttps://np.reddit.com/r/rust/comments/1/\n\n"]
+        );
+    }
+
+    #[test]
+    fn render_options_link_style() {
+        let subscription = Subscription {
+            link_style: "short".to_string(),
+            ..subscription()
+        };
+        let opts = RenderOptions::for_subscription(&subscription);
+        assert_eq!(opts.link_style, LinkStyle::Short);
+
+        let subscription = Subscription {
+            link_style: String::new(),
+            ..subscription
+        };
+        let opts = RenderOptions::for_subscription(&subscription);
+        assert_eq!(opts.link_style, LinkStyle::Permalink);
+    }
+
+    #[test]
+    fn build_digest_top_comment() {
+        let mut posts = posts();
+        posts[1].top_comment = Some("Nice & tidy".to_string());
+        let opts = RenderOptions {
+            parse_mode: Some(ParseMode::Html),
+            ..Default::default()
+        };
+        let result = build_digest(&subscription(), &posts, &opts);
+        assert_eq!(
+            result,
+            ["Weekly popular posts from: &quot;rust&quot;\n\nFirst\nhttps://reddit.com/r/rust/comments/1/\n\nSecond (crosspost)\nhttps://reddit.com/r/rust/comments/2/\nTop comment: Nice &amp; tidy\n\n"]
+        );
+    }
+
+    #[test]
+    fn build_digest_chunks_long_messages() {
+        let posts = (0..10)
+            .map(|i| Post {
+                id: i.to_string(),
+                title: "x".repeat(1000),
+                link: format!("https://reddit.com/r/rust/comments/{}/", i),
+                is_crosspost: false,
+                selftext: String::new(),
+                top_comment: None,
+                external_url: None,
+                over_18: false,
+                image_url: None,
+                score: 0,
+            })
+            .collect::<Vec<Post>>();
+
+        let result = build_digest(&subscription(), &posts, &RenderOptions::default());
+        assert_eq!(result.len(), 4);
+        assert!(result
+            .iter()
+            .all(|message| message.chars().count() <= MAX_MESSAGE_LENGTH));
+        assert!(result[0].starts_with("Weekly popular posts from: \"rust\""));
+        assert_eq!(result.concat().matches("https://reddit.com").count(), 10);
+    }
+
+    #[test]
+    fn build_digest_custom_message_length() {
+        let opts = RenderOptions {
+            max_message_length: 50,
+            ..Default::default()
+        };
+        let result = build_digest(&subscription(), &posts(), &opts);
+        assert_eq!(
+            result,
+            [
+                "Weekly popular posts from: \"rust\"\n\n",
+                "First\nhttps://reddit.com/r/rust/comments/1/\n\n",
+                "Second (crosspost)\nhttps://reddit.com/r/rust/comments/2/\n\n",
+            ]
+        );
+    }
+
+    #[test]
+    fn build_digest_splits_long_entry() {
+        let mut posts = posts();
+        posts[0].title = "a".repeat(100);
+        let opts = RenderOptions {
+            max_message_length: 60,
+            ..Default::default()
+        };
+        let result = build_digest(&subscription(), &posts, &opts);
+        assert!(result.len() > 2);
+        assert!(result.iter().all(|message| message.chars().count() <= 60));
+        let unsplit = build_digest(&subscription(), &posts, &RenderOptions::default());
+        assert_eq!(result.concat(), unsplit.concat());
+    }
+
+    #[test]
+    fn build_digest_splits_long_entry_between_entities() {
+        let mut posts = posts();
+        posts[0].title = "&".repeat(30);
+        let opts = RenderOptions {
+            parse_mode: Some(ParseMode::Html),
+            max_message_length: 42,
+            ..Default::default()
+        };
+        let result = build_digest(&subscription(), &posts, &opts);
+        assert!(result.iter().all(|message| message.chars().count() <= 42));
+        assert!(result
+            .iter()
+            .all(|message| message.matches('&').count() == message.matches(';').count()));
+    }
+
+    #[test]
+    fn build_combined_digest_groups_by_subreddit() {
+        let golang = Subscription {
+            subreddit: "golang".to_string(),
+            ..Default::default()
+        };
+        let sections = vec![
+            build_digest(&subscription(), &posts(), &RenderOptions::default()),
+            build_digest(&golang, &[], &RenderOptions::default()),
+        ];
+
+        let result = build_combined_digest("Your weekly digest", &sections, MAX_MESSAGE_LENGTH);
+        assert_eq!(
+            result,
+            ["Your weekly digest\n\nWeekly popular posts from: \"rust\"\n\nFirst\nhttps://reddit.com/r/rust/comments/1/\n\nSecond (crosspost)\nhttps://reddit.com/r/rust/comments/2/\n\nWeekly popular posts from: \"golang\"\n\nNo popular posts this week.\n"]
+        );
+    }
+
+    #[test]
+    fn build_combined_digest_chunks_long_messages() {
+        let sections = vec![vec!["a".repeat(30)], vec!["b".repeat(30), "c".repeat(30)]];
+
+        let result = build_combined_digest("Your weekly digest", &sections, 60);
+        assert_eq!(
+            result,
+            [
+                format!("Your weekly digest\n\n{}", "a".repeat(30)),
+                format!("{}{}", "b".repeat(30), "c".repeat(30)),
+            ]
+        );
+    }
+
+    #[test]
+    fn build_combined_digest_long_first_part() {
+        let sections = vec![vec!["a".repeat(60)], vec!["b".repeat(30)]];
+
+        let result = build_combined_digest("Your weekly digest", &sections, 60);
+        assert_eq!(
+            result,
+            [
+                format!("Your weekly digest\n\n{}", "a".repeat(60)),
+                "b".repeat(30),
+            ]
+        );
+    }
+
+    #[test]
+    fn combined_header_names_shared_timeframe() {
+        let listing = |sort: &str, timeframe: &str| Subscription {
+            sort: sort.to_string(),
+            timeframe: timeframe.to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            combined_header(&[listing("top", "week"), listing("controversial", "week")]),
+            "Your weekly digest"
+        );
+        assert_eq!(
+            combined_header(&[listing("top", "day"), listing("top", "week")]),
+            "Your digest"
+        );
+        assert_eq!(
+            combined_header(&[listing("top", "month"), listing("hot", "month")]),
+            "Your digest"
+        );
+        let since_last_sent = Subscription {
+            since_last_sent: true,
+            ..listing("top", "week")
+        };
+        assert_eq!(combined_header(&[since_last_sent]), "Your digest");
+    }
+
+    #[test]
+    fn build_comments_works() {
+        let comments = vec![
+            Comment {
+                id: "c1".to_string(),
+                author: "ferris".to_string(),
+                body: "This is great".to_string(),
+                score: 1420,
+                link: "https://reddit.com/r/rust/comments/1/title/c1/".to_string(),
+            },
+            Comment {
+                id: "c2".to_string(),
+                author: "koavf".to_string(),
+                body: "Agreed".to_string(),
+                score: -2,
+                link: "https://reddit.com/r/rust/comments/1/title/c2/".to_string(),
+            },
+        ];
+
+        let result = build_comments("/r/rust/comments/1/title/", &comments, 0);
+        assert_eq!(
+            result,
+            "New top comments in /r/rust/comments/1/title/\n\nferris (1,420 points): This is great\nhttps://reddit.com/r/rust/comments/1/title/c1/\n\nkoavf (-2 points): Agreed\nhttps://reddit.com/r/rust/comments/1/title/c2/\n\n"
+        );
+
+        let result = build_comments("/r/rust/comments/1/title/", &comments[..1], 1);
+        assert!(result.ends_with("/c1/\n\n1 more new comment in the thread.\n"));
+        let result = build_comments("/r/rust/comments/1/title/", &comments[..1], 4);
+        assert!(result.ends_with("/c1/\n\n4 more new comments in the thread.\n"));
+    }
+
+    #[test]
+    fn format_number_works() {
+        assert_eq!(format_number(0, None), "0");
+        assert_eq!(format_number(999, None), "999");
+        assert_eq!(format_number(1000, None), "1,000");
+        assert_eq!(format_number(12345, None), "12,345");
+        assert_eq!(format_number(1234567, None), "1,234,567");
+        assert_eq!(format_number(-12345, None), "-12,345");
+        assert_eq!(format_number(i64::MIN, None), "-9,223,372,036,854,775,808");
+    }
+
+    #[test]
+    fn format_number_locale() {
+        assert_eq!(format_number(12345, Some("de")), "12.345");
+        assert_eq!(format_number(1234567, Some("pt-BR")), "1.234.567");
+        assert_eq!(format_number(12345, Some("fr_FR")), "12\u{a0}345");
+        assert_eq!(format_number(12345, Some("en")), "12,345");
+        assert_eq!(format_number(12345, Some("xx")), "12,345");
+        assert_eq!(format_number(999, Some("de")), "999");
+    }
+
+    #[test]
+    fn build_digest_scores() {
+        let subscription = Subscription {
+            show_scores: true,
+            ..subscription()
+        };
+        let opts = RenderOptions::for_subscription(&subscription);
+        let result = build_digest(&subscription, &posts(), &opts);
+        assert_eq!(
+            result[0],
+            "Weekly popular posts from: \"rust\"\n\nFirst (12,345 points)\nhttps://reddit.com/r/rust/comments/1/\n\nSecond (crosspost) (42 points)\nhttps://reddit.com/r/rust/comments/2/\n\n"
+        );
+
+        let opts = RenderOptions {
+            locale: Some("de".to_string()),
+            ..opts
+        };
+        let result = build_digest(&subscription, &posts(), &opts);
+        assert!(result[0].contains("First (12.345 points)\n"));
+    }
+
+    #[test]
+    fn build_digest_footer() {
+        let opts = RenderOptions {
+            posts_per_message: Some(1),
+            footer: Some("Sourced from Reddit. Reply /stop to unsubscribe.".to_string()),
+            ..Default::default()
+        };
+        let result = build_digest(&subscription(), &posts(), &opts);
+        assert_eq!(result.len(), 2);
+        assert!(!result[0].contains("Sourced from Reddit"));
+        assert!(result[1].ends_with(
+            "https://reddit.com/r/rust/comments/2/\n\nSourced from Reddit. Reply /stop to unsubscribe.\n"
+        ));
+        assert_eq!(
+            result
+                .iter()
+                .filter(|message| message.contains("Sourced from Reddit"))
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn build_digest_footer_counts_against_length() {
+        let opts = RenderOptions {
+            footer: Some("f".repeat(20)),
+            ..Default::default()
+        };
+        let message_length = build_digest(&subscription(), &posts(), &RenderOptions::default())[0]
+            .chars()
+            .count();
+
+        let result = build_digest(
+            &subscription(),
+            &posts(),
+            &RenderOptions {
+                max_message_length: message_length + 10,
+                ..opts
+            },
+        );
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[1], format!("{}\n", "f".repeat(20)));
+        assert!(result
+            .iter()
+            .all(|message| message.chars().count() <= message_length + 10));
+    }
+
+    #[test]
+    fn build_digest_posts_per_message() {
+        let opts = RenderOptions {
+            posts_per_message: Some(1),
+            ..Default::default()
+        };
+        let result = build_digest(&subscription(), &posts(), &opts);
+        assert_eq!(
+            result,
+            [
+                "Weekly popular posts from: \"rust\"\n\nFirst\nhttps://reddit.com/r/rust/comments/1/\n\n",
+                "Second (crosspost)\nhttps://reddit.com/r/rust/comments/2/\n\n",
+            ]
+        );
+    }
+
+    #[test]
+    fn build_digest_posts_per_message_chunks_long_groups() {
+        let posts = (0..6)
+            .map(|i| Post {
+                id: i.to_string(),
+                title: "x".repeat(1500),
+                link: format!("https://reddit.com/r/rust/comments/{}/", i),
+                is_crosspost: false,
+                selftext: String::new(),
+                top_comment: None,
+                external_url: None,
+                over_18: false,
+                image_url: None,
+                score: 0,
+            })
+            .collect::<Vec<Post>>();
+        let opts = RenderOptions {
+            posts_per_message: Some(3),
+            ..Default::default()
+        };
+
+        let result = build_digest(&subscription(), &posts, &opts);
+        let posts_per_message = result
+            .iter()
+            .map(|message| message.matches("https://reddit.com").count())
+            .collect::<Vec<usize>>();
+        assert_eq!(posts_per_message, [2, 1, 2, 1]);
+        assert!(result
+            .iter()
+            .all(|message| message.chars().count() <= MAX_MESSAGE_LENGTH));
+    }
+}
@@ -0,0 +1,240 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use log::{info, warn};
+
+/// Scheduler counters, cumulative since the process started, along with the size of the
+/// user base as of the last completed cycle.
+#[derive(Debug, Default)]
+pub struct SchedulerMetrics {
+    cycles: AtomicU64,
+    evaluated: AtomicU64,
+    sent: AtomicU64,
+    skipped: AtomicU64,
+    errored: AtomicU64,
+    users: AtomicU64,
+    subscriptions: AtomicU64,
+}
+
+impl SchedulerMetrics {
+    pub fn cycle_completed(&self) {
+        self.cycles.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn evaluated(&self) {
+        self.evaluated.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn sent(&self) {
+        self.sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn skipped(&self) {
+        self.skipped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn errored(&self) {
+        self.errored.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn set_totals(&self, users: i64, subscriptions: i64) {
+        self.users.store(users as u64, Ordering::Relaxed);
+        self.subscriptions
+            .store(subscriptions as u64, Ordering::Relaxed);
+    }
+
+    /// Counters and gauges in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        [
+            (
+                "reddit_bot_scheduler_cycles_total",
+                "Completed scheduler cycles.",
+                "counter",
+                &self.cycles,
+            ),
+            (
+                "reddit_bot_subscriptions_evaluated_total",
+                "Subscriptions looked at by the scheduler.",
+                "counter",
+                &self.evaluated,
+            ),
+            (
+                "reddit_bot_subscriptions_sent_total",
+                "Subscriptions whose digest was sent.",
+                "counter",
+                &self.sent,
+            ),
+            (
+                "reddit_bot_subscriptions_skipped_total",
+                "Subscriptions that were not due or already sent.",
+                "counter",
+                &self.skipped,
+            ),
+            (
+                "reddit_bot_subscriptions_errored_total",
+                "Subscriptions that failed to process.",
+                "counter",
+                &self.errored,
+            ),
+            (
+                "reddit_bot_users",
+                "Registered users.",
+                "gauge",
+                &self.users,
+            ),
+            (
+                "reddit_bot_subscriptions",
+                "Subscriptions across all users.",
+                "gauge",
+                &self.subscriptions,
+            ),
+        ]
+        .iter()
+        .map(|(name, help, kind, value)| {
+            format!(
+                "# HELP {} {}\n# TYPE {} {}\n{} {}\n",
+                name,
+                help,
+                name,
+                kind,
+                name,
+                value.load(Ordering::Relaxed)
+            )
+        })
+        .collect()
+    }
+}
+
+/// A client that stalls is dropped after this long, other clients are answered meanwhile.
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Answers every request on `listener` with the current metrics, each connection on its own thread.
+pub fn serve_metrics(listener: TcpListener, metrics: Arc<SchedulerMetrics>) {
+    if let Ok(addr) = listener.local_addr() {
+        info!("serving metrics on {}", addr);
+    }
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let metrics = Arc::clone(&metrics);
+                    thread::spawn(move || answer(stream, &metrics));
+                }
+                Err(err) => warn!("failed to accept metrics connection: {}", err),
+            }
+        }
+    });
+}
+
+fn answer(mut stream: TcpStream, metrics: &SchedulerMetrics) {
+    let timeouts = stream
+        .set_read_timeout(Some(CONNECTION_TIMEOUT))
+        .and_then(|_| stream.set_write_timeout(Some(CONNECTION_TIMEOUT)));
+    if let Err(err) = timeouts {
+        warn!("failed to set metrics connection timeouts: {}", err);
+        return;
+    }
+
+    if let Err(err) = read_request_head(&stream) {
+        warn!("failed to read metrics request: {}", err);
+        return;
+    }
+
+    let body = metrics.render();
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    );
+    if let Err(err) = stream.write_all(response.as_bytes()) {
+        warn!("failed to write metrics response: {}", err);
+    }
+}
+
+/// Consumes the request line and headers, every path gets the metrics so they're not parsed.
+fn read_request_head(stream: &TcpStream) -> io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    #[test]
+    fn render() {
+        let metrics = SchedulerMetrics::default();
+        metrics.evaluated();
+        metrics.evaluated();
+        metrics.sent();
+        metrics.skipped();
+        metrics.cycle_completed();
+        metrics.set_totals(3, 7);
+
+        let result = metrics.render();
+        assert!(result.starts_with(
+            "# HELP reddit_bot_scheduler_cycles_total Completed scheduler cycles.\n# TYPE reddit_bot_scheduler_cycles_total counter\nreddit_bot_scheduler_cycles_total 1\n"
+        ));
+        assert!(result.contains("\nreddit_bot_subscriptions_evaluated_total 2\n"));
+        assert!(result.contains("\nreddit_bot_subscriptions_sent_total 1\n"));
+        assert!(result.contains("\nreddit_bot_subscriptions_skipped_total 1\n"));
+        assert!(result.contains("\nreddit_bot_subscriptions_errored_total 0\n"));
+        assert!(result.contains("\n# TYPE reddit_bot_users gauge\nreddit_bot_users 3\n"));
+        assert!(result.contains("\nreddit_bot_subscriptions 7\n"));
+    }
+
+    #[test]
+    fn serve() {
+        let metrics = Arc::new(SchedulerMetrics::default());
+        metrics.sent();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        serve_metrics(listener, metrics);
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("\nreddit_bot_subscriptions_sent_total 1\n"));
+    }
+
+    #[test]
+    fn serve_after_silent_client() {
+        let metrics = Arc::new(SchedulerMetrics::default());
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        serve_metrics(listener, metrics);
+
+        // Connects without ever sending a request.
+        let _silent = TcpStream::connect(addr).unwrap();
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        // Answered without waiting for the silent client to time out.
+        stream
+            .set_read_timeout(Some(CONNECTION_TIMEOUT / 5))
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    }
+}
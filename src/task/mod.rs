@@ -1 +1,4 @@
+pub mod digest;
+pub mod metrics;
+#[allow(clippy::module_inception)]
 pub mod task;
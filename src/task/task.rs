@@ -1,142 +1,1091 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::thread;
 use std::time::Duration;
 
 use chrono::prelude::*;
-use chrono::{Datelike, Utc, Weekday};
+use chrono::{Datelike, Utc};
+use diesel::result::ConnectionError;
 use log::{debug, error, info, warn};
-use num::traits::FromPrimitive;
+use reqwest::Url;
 use tokio::runtime::Runtime;
+use tokio::time::sleep;
 
 use crate::db::client::DbClient;
-use crate::db::models::Subscription;
-use crate::reddit::client::RedditClient;
+use crate::db::models::{parse_timezone, PostSubscription, Subscription};
+use crate::reddit::client::{Listing, RedditClient};
+use crate::reddit::error::RedditError;
+use crate::reddit::post::Post;
+use crate::task::digest::{
+    build_combined_digest, build_comments, build_digest, build_poll, combined_header,
+    RenderOptions, MAX_MESSAGE_LENGTH,
+};
+use crate::task::metrics::SchedulerMetrics;
 use crate::telegram::client::TelegramClient;
 use crate::telegram::error::TelegramError;
-use crate::telegram::types::Message;
+use crate::telegram::messenger::Messenger;
+use crate::telegram::types::{EditMessage, Image, Message, ParseMode};
 use crate::BotError;
 
-pub fn init_task(token: String, database_url: String) {
+/// Consecutive empty digests after which the user is nudged to relax the subscription filters.
+const EMPTY_STREAK_NUDGE_THRESHOLD: i32 = 3;
+
+/// Consecutive failed cycles after which the database connection is re-established.
+const DB_ERROR_THRESHOLD: u32 = 3;
+const RECONNECT_BASE_DELAY: Duration = Duration::from_secs(1);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(300);
+/// Reconnect attempts before giving up until the next failed cycle, about half an hour in total.
+const RECONNECT_ATTEMPTS: u32 = 15;
+
+/// Sent to the target chat when a digest can't fetch posts, `{subreddit}` is replaced with its name.
+const DEFAULT_ERROR_TEXT: &str = "Couldn't get popular posts from \"{subreddit}\" this week.";
+
+pub const FETCH_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Posts per digest that get a top comment, each one costs an extra request to reddit.
+const TOP_COMMENT_POST_LIMIT: usize = 5;
+/// How far back a "since last sent" digest reaches before its first send.
+const SINCE_LAST_SENT_DEFAULT_DAYS: i64 = 7;
+
+/// Minimum time between two checks of a watched thread for new comments.
+const POST_CHECK_INTERVAL_MINUTES: i64 = 60;
+/// New comments of a watched thread sent per check, highest scored first. The rest are counted.
+const WATCHED_COMMENTS_LIMIT: usize = 3;
+
+/// Ids of subscriptions whose digest went out, along with the day in the user's timezone it was
+/// sent on. Survives scheduler restarts, so a digest that was sent but couldn't be marked as sent in
+/// the database isn't sent again on the same day. It's only kept in memory: after the process itself
+/// restarts the log starts out empty and `last_sent_at` is all that's left.
+#[derive(Debug, Default)]
+pub struct ProcessingLog {
+    sent: HashMap<i32, NaiveDate>,
+}
+
+impl ProcessingLog {
+    pub fn was_sent(&self, id: i32, date: NaiveDate) -> bool {
+        self.sent.get(&id) == Some(&date)
+    }
+
+    /// Replaces the day the subscription was last sent on, so the log keeps one entry per
+    /// subscription.
+    pub fn mark_sent(&mut self, id: i32, date: NaiveDate) {
+        self.sent.insert(id, date);
+    }
+}
+
+/// `footer` is appended to every digest, e.g. a disclaimer required on a public instance.
+/// `reddit_client` is shared with the bot, so both see the same rate limit.
+pub fn init_task(
+    token: String,
+    database_url: String,
+    footer: Option<String>,
+    reddit_client: Arc<RedditClient>,
+) -> Arc<SchedulerMetrics> {
+    let metrics = Arc::new(SchedulerMetrics::default());
+    spawn_task(
+        token,
+        database_url,
+        footer,
+        reddit_client,
+        Arc::new(Mutex::new(ProcessingLog::default())),
+        metrics.clone(),
+    );
+    metrics
+}
+
+fn spawn_task(
+    token: String,
+    database_url: String,
+    footer: Option<String>,
+    reddit_client: Arc<RedditClient>,
+    processing_log: Arc<Mutex<ProcessingLog>>,
+    metrics: Arc<SchedulerMetrics>,
+) {
     let db = DbClient::new(&database_url);
-    let reddit_client = RedditClient::new();
+    let cycle_reddit_client = reddit_client.clone();
     let telegram_client = TelegramClient::new(token.to_string());
+    let cycle_log = processing_log.clone();
+    let cycle_metrics = metrics.clone();
+    let cycle_database_url = database_url.clone();
+    let cycle_footer = footer.clone();
 
     thread::spawn(move || {
         let result = std::panic::catch_unwind(move || {
             let rt = Runtime::new().unwrap();
 
             rt.block_on(async {
+                let mut db = db;
+                let mut db_errors = 0;
                 loop {
-                    if let Ok(user_subscriptions) = db.get_subscriptions() {
-                        for user_subscription in user_subscriptions {
-                            let now = Utc::now();
-                            let send_on = Weekday::from_i32(user_subscription.send_on).unwrap();
-                            let send_at = user_subscription.send_at as u32;
-                            if now.weekday() != send_on || now.hour() < send_at {
-                                debug!(
-                                    "skipping subscription - now: {}, send_on: {}, send_at: {}",
-                                    now, send_on, send_at
+                    let result = process_cycle(
+                        &db,
+                        &telegram_client,
+                        &cycle_reddit_client,
+                        &cycle_log,
+                        &cycle_metrics,
+                        cycle_footer.as_deref(),
+                        Duration::from_secs(10),
+                    )
+                    .await;
+                    match result {
+                        Ok(_) => db_errors = 0,
+                        Err(err) => {
+                            error!("failed to process cycle: {}", err);
+                            db_errors += 1;
+                            if db_errors >= DB_ERROR_THRESHOLD {
+                                warn!(
+                                    "database failed {} cycles in a row, reconnecting",
+                                    db_errors
                                 );
-                                continue;
-                            }
-
-                            if let Some(date) = &user_subscription.last_sent_at {
-                                if let Ok(parsed) = date.parse::<DateTime<Utc>>() {
-                                    if parsed.date().eq(&now.date()) {
-                                        debug!("already sent today: {:?}", &user_subscription);
-                                        continue;
-                                    }
-                                }
-                            }
-                            match process_subscription(
-                                &db,
-                                &telegram_client,
-                                &reddit_client,
-                                &user_subscription,
-                            )
-                            .await
-                            {
-                                Ok(_) => {
-                                    info!("processed subscription: {:?}", &user_subscription);
-                                }
-                                Err(err) => {
-                                    if let BotError::TelegramError(TelegramError::Unsuccessful(
-                                        err,
-                                    )) = err
-                                    {
-                                        if err.contains("Forbidden: bot was blocked by the user") {
-                                            warn!(
-                                                "bot is blocked by user, removing user: {} from db",
-                                                &user_subscription.user_id
-                                            );
-                                            db.delete_user(&user_subscription.user_id).ok();
-                                        } else {
-                                            error!("failed to process subscription: {}", err);
-                                        }
-                                    } else {
-                                        error!("failed to process subscription: {}", err);
+                                match reconnect(
+                                    &cycle_database_url,
+                                    RECONNECT_BASE_DELAY,
+                                    RECONNECT_ATTEMPTS,
+                                ) {
+                                    Ok(reconnected) => {
+                                        db = reconnected;
+                                        db_errors = 0;
                                     }
+                                    // The next failed cycle tries again.
+                                    Err(err) => error!("failed to reconnect to database: {}", err),
                                 }
                             }
-                            thread::sleep(Duration::from_secs(10));
                         }
                     }
                     thread::sleep(Duration::from_secs(30));
                 }
             });
         });
-        if let Err(_) = result {
+        if result.is_err() {
             error!("thread panicked, recovering");
-            init_task(token, database_url);
+            spawn_task(
+                token,
+                database_url,
+                footer,
+                reddit_client,
+                processing_log,
+                metrics,
+            );
         }
     });
 }
 
-pub async fn process_subscription(
+/// Re-establishes the database connection, doubling the delay between attempts up to a limit.
+/// Fails with the last error once `attempts` have been made.
+fn reconnect(
+    database_url: &str,
+    base_delay: Duration,
+    attempts: u32,
+) -> Result<DbClient, ConnectionError> {
+    let mut delay = base_delay;
+    let mut attempt = 1;
+    loop {
+        let err = match DbClient::connect(database_url) {
+            Ok(db) => match db.get_subscriptions() {
+                Ok(_) => {
+                    info!("database connection re-established");
+                    return Ok(db);
+                }
+                Err(err) => ConnectionError::BadConnection(format!(
+                    "database is reachable but not usable yet: {}",
+                    err
+                )),
+            },
+            Err(err) => err,
+        };
+        warn!(
+            "reconnect attempt {} of {} failed: {}",
+            attempt, attempts, err
+        );
+        if attempt >= attempts {
+            return Err(err);
+        }
+        thread::sleep(delay);
+        delay = std::cmp::min(delay * 2, RECONNECT_MAX_DELAY);
+        attempt += 1;
+    }
+}
+
+async fn process_cycle(
     db: &DbClient,
-    telegram_client: &TelegramClient,
+    telegram_client: &impl Messenger,
     reddit_client: &RedditClient,
-    user_subscription: &Subscription,
+    processing_log: &Mutex<ProcessingLog>,
+    metrics: &SchedulerMetrics,
+    footer: Option<&str>,
+    delay: Duration,
+) -> Result<(), diesel::result::Error> {
+    let user_subscriptions = db.get_subscriptions_with_user_settings()?;
+    // Each batch is sent as one digest, along with the timezone of its user and their current day.
+    let mut batches: Vec<(String, NaiveDate, Vec<Subscription>)> = vec![];
+    let mut combined_batches: HashMap<String, usize> = HashMap::new();
+    for (user_subscription, timezone, combined_digest) in user_subscriptions {
+        metrics.evaluated();
+        // The day and hour of the subscription are in the user's timezone.
+        let now = Utc::now().with_timezone(&parse_timezone(&timezone));
+        if !is_due(&user_subscription, now) {
+            debug!(
+                "skipping subscription - now: {}, send_on: {}, send_at: {}, timezone: {}",
+                now, user_subscription.send_on, user_subscription.send_at, timezone
+            );
+            metrics.skipped();
+            continue;
+        }
+
+        if already_sent_on(&user_subscription.last_sent_at, now) {
+            debug!("already sent today: {:?}", &user_subscription);
+            metrics.skipped();
+            continue;
+        }
+
+        if lock(processing_log).was_sent(user_subscription.id, now.date_naive()) {
+            debug!(
+                "already sent today, not marked in the database: {:?}",
+                &user_subscription
+            );
+            metrics.skipped();
+            continue;
+        }
+
+        // Subreddits blocked after subscribing aren't fetched anymore.
+        if db.is_subreddit_blocked(&user_subscription.subreddit)? {
+            info!(
+                "skipping subscription {} of blocked subreddit {}",
+                user_subscription.id, user_subscription.subreddit
+            );
+            metrics.skipped();
+            continue;
+        }
+
+        // Digests delivered to other chats, e.g. channels, are left out of the combined digest.
+        if combined_digest && user_subscription.target_chat_id == user_subscription.user_id {
+            if let Some(&index) = combined_batches.get(&user_subscription.user_id) {
+                batches[index].2.push(user_subscription);
+                continue;
+            }
+            combined_batches.insert(user_subscription.user_id.clone(), batches.len());
+        }
+        batches.push((timezone, now.date_naive(), vec![user_subscription]));
+    }
+
+    for (timezone, date, batch) in batches {
+        let result = match batch.as_slice() {
+            [user_subscription] => {
+                process_subscription(
+                    db,
+                    telegram_client,
+                    reddit_client,
+                    user_subscription,
+                    footer,
+                    false,
+                )
+                .await
+            }
+            subscriptions => {
+                process_combined_digest(db, telegram_client, reddit_client, subscriptions, footer)
+                    .await
+            }
+        };
+        match result {
+            // Nothing was sent, one-off subscriptions went away along with the user.
+            Ok(Outcome::UnknownUser) => {
+                for _ in &batch {
+                    metrics.skipped();
+                }
+            }
+            Ok(_) => {
+                for user_subscription in &batch {
+                    lock(processing_log).mark_sent(user_subscription.id, date);
+                    metrics.sent();
+                    info!(
+                        "processed subscription {} for user {}: {}",
+                        user_subscription.id,
+                        user_subscription.user_id,
+                        user_subscription.summary(&timezone)
+                    );
+                    if !user_subscription.recurring {
+                        if let Err(err) = db.delete_subscription(user_subscription.id) {
+                            error!(
+                                "failed to delete one-off subscription {}: {}",
+                                user_subscription.id, err
+                            );
+                        }
+                    }
+                }
+            }
+            Err(err) => {
+                for _ in &batch {
+                    metrics.errored();
+                }
+                handle_error(db, telegram_client, &batch[0], err).await;
+            }
+        }
+        thread::sleep(delay);
+    }
+    if let Err(err) =
+        process_post_subscriptions(db, telegram_client, reddit_client, Utc::now()).await
+    {
+        error!("failed to process post subscriptions: {}", err);
+    }
+    metrics.set_totals(db.user_count()?, db.subscription_count()?);
+    metrics.cycle_completed();
+
+    Ok(())
+}
+
+/// Checks the watched threads that are due for new comments, failures are retried on the next check.
+/// Expired threads are dropped first, users that blocked the bot are removed.
+async fn process_post_subscriptions(
+    db: &DbClient,
+    telegram_client: &impl Messenger,
+    reddit_client: &RedditClient,
+    now: DateTime<Utc>,
+) -> Result<(), diesel::result::Error> {
+    let expired = db.delete_expired_post_subscriptions()?;
+    if expired > 0 {
+        info!("removed {} expired post subscriptions", expired);
+    }
+
+    for post_subscription in db.get_post_subscriptions()? {
+        // Unparseable timestamps are checked right away, which also repairs them.
+        let last_checked_at = post_subscription
+            .last_checked_at
+            .parse::<DateTime<Utc>>()
+            .unwrap_or_else(|_| now - chrono::Duration::minutes(POST_CHECK_INTERVAL_MINUTES));
+        if now - last_checked_at < chrono::Duration::minutes(POST_CHECK_INTERVAL_MINUTES) {
+            continue;
+        }
+
+        match process_post_subscription(
+            db,
+            telegram_client,
+            reddit_client,
+            &post_subscription,
+            last_checked_at,
+            now,
+        )
+        .await
+        {
+            Ok(_) => info!(
+                "processed post subscription {} for user {}",
+                post_subscription.id, post_subscription.user_id
+            ),
+            Err(err) if is_blocked(&err) => {
+                warn!(
+                    "bot is blocked by user, removing user: {} from db",
+                    &post_subscription.user_id
+                );
+                db.delete_user(&post_subscription.user_id).ok();
+            }
+            Err(err) => error!(
+                "failed to process post subscription {}: {}",
+                post_subscription.id, err
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Sends the comments posted to the watched thread since the last check.
+pub async fn process_post_subscription(
+    db: &DbClient,
+    telegram_client: &impl Messenger,
+    reddit_client: &RedditClient,
+    post_subscription: &PostSubscription,
+    since: DateTime<Utc>,
+    now: DateTime<Utc>,
 ) -> Result<(), BotError> {
-    let posts = reddit_client
-        .fetch_posts(&user_subscription.subreddit)
+    let comments = reddit_client
+        .fetch_comments(&post_subscription.permalink, since)
         .await?;
+    if !comments.is_empty() {
+        let count = comments.len().min(WATCHED_COMMENTS_LIMIT);
+        telegram_client
+            .send_message(&Message {
+                chat_id: &post_subscription.user_id,
+                text: &build_comments(
+                    &post_subscription.permalink,
+                    &comments[..count],
+                    comments.len() - count,
+                ),
+                disable_web_page_preview: true,
+                ..Default::default()
+            })
+            .await?;
+    }
+    db.touch_post_checked(post_subscription.id, &now.to_rfc3339())?;
+    Ok(())
+}
 
-    let mut message = format!(
-        "Weekly popular posts from: \"{}\"\n\n",
-        &user_subscription.subreddit
-    );
+/// Whether sending failed because the user blocked the bot.
+fn is_blocked(err: &BotError) -> bool {
+    matches!(err, BotError::TelegramError(TelegramError::Unsuccessful(err))
+        if err.contains("Forbidden: bot was blocked by the user"))
+}
+
+/// Removes users that blocked the bot. When the posts couldn't be fetched the target chat is told
+/// with the error text of the subscription.
+async fn handle_error(
+    db: &DbClient,
+    telegram_client: &impl Messenger,
+    user_subscription: &Subscription,
+    err: BotError,
+) {
+    let err = match report_fetch_error(telegram_client, user_subscription, &err).await {
+        // The user has been told, don't retry on every cycle.
+        Ok(true) => {
+            if let Err(err) = db.update_last_sent(user_subscription.id) {
+                error!(
+                    "failed to mark subscription {} as sent: {}",
+                    user_subscription.id, err
+                );
+            }
+            return;
+        }
+        Ok(false) => err,
+        Err(err) => BotError::from(err),
+    };
+
+    if is_blocked(&err) && user_subscription.target_chat_id == user_subscription.user_id {
+        warn!(
+            "bot is blocked by user, removing user: {} from db",
+            &user_subscription.user_id
+        );
+        db.delete_user(&user_subscription.user_id).ok();
+    } else {
+        error!("failed to process subscription: {}", err);
+    }
+}
 
-    for post in posts.iter() {
-        message.push_str(format!("{}\n", post).as_str());
+/// Sends the error text of the subscription to its chat, the way its digests are sent, when `err`
+/// is a failure to fetch its posts. Returns whether the error was reported.
+pub async fn report_fetch_error(
+    telegram_client: &impl Messenger,
+    user_subscription: &Subscription,
+    err: &BotError,
+) -> Result<bool, TelegramError> {
+    if !is_fetch_error(err) {
+        return Ok(false);
     }
 
+    error!("failed to process subscription: {}", err);
     telegram_client
         .send_message(&Message {
-            chat_id: &user_subscription.user_id,
-            text: &message,
-            disable_web_page_preview: true,
+            chat_id: &user_subscription.target_chat_id,
+            text: &build_error_text(user_subscription),
+            disable_notification: user_subscription.disable_notification,
+            protect_content: user_subscription.protect_content,
             ..Default::default()
         })
         .await?;
-    db.update_last_sent(user_subscription.id)?;
+    Ok(true)
+}
+
+/// Whether processing failed because reddit couldn't be reached or answered with an error.
+fn is_fetch_error(err: &BotError) -> bool {
+    matches!(err, BotError::Subscription { source, .. }
+        if matches!(source.as_ref(), BotError::RedditError(_)))
+}
+
+/// Whether the subscription's day has come and its hour has passed at `now`, which is in the user's
+/// timezone, ignoring when it was last sent. Subscriptions with an invalid `send_on` are never due.
+pub fn is_due<T: TimeZone>(subscription: &Subscription, now: DateTime<T>) -> bool {
+    match subscription.weekday() {
+        Some(send_on) => now.weekday() == send_on && now.hour() >= subscription.send_at as u32,
+        None => {
+            warn!(
+                "subscription {} has an invalid send_on: {}",
+                subscription.id, subscription.send_on
+            );
+            false
+        }
+    }
+}
+
+/// Whether `last_sent_at` falls on the same day as `now` in the timezone of `now`, unparseable
+/// timestamps count as never sent.
+pub fn already_sent_on<T: TimeZone>(last_sent_at: &Option<String>, now: DateTime<T>) -> bool {
+    match last_sent_at
+        .as_ref()
+        .and_then(|date| date.parse::<DateTime<Utc>>().ok())
+    {
+        Some(parsed) => parsed.with_timezone(&now.timezone()).date_naive() == now.date_naive(),
+        None => false,
+    }
+}
+
+/// Start of a "since last sent" digest, a week back for subscriptions that were never sent. With
+/// `max_age_hours` older posts are left out, like they are from the other digests.
+fn since_last_sent(
+    last_sent_at: &Option<String>,
+    max_age_hours: Option<i64>,
+    now: DateTime<Utc>,
+) -> DateTime<Utc> {
+    let since = last_sent_at
+        .as_ref()
+        .and_then(|date| date.parse::<DateTime<Utc>>().ok())
+        .unwrap_or_else(|| now - chrono::Duration::days(SINCE_LAST_SENT_DEFAULT_DAYS));
+    match max_age_hours {
+        Some(hours) => since.max(now - chrono::Duration::hours(hours)),
+        None => since,
+    }
+}
+
+fn lock(processing_log: &Mutex<ProcessingLog>) -> MutexGuard<'_, ProcessingLog> {
+    processing_log
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// What became of a subscription handed to `process_subscription`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The digest went out and was recorded as sent.
+    Sent,
+    /// Every post had already been sent, nothing went out and nothing was recorded.
+    NothingNew,
+    /// The user was removed in the meantime, e.g. after blocking the bot, nothing went out.
+    UnknownUser,
+}
+
+/// Sends the digest of a subscription. With `only_new` posts that were already sent for the
+/// subscription are left out, when none are left the digest isn't sent at all.
+pub async fn process_subscription(
+    db: &DbClient,
+    telegram_client: &impl Messenger,
+    reddit_client: &RedditClient,
+    user_subscription: &Subscription,
+    footer: Option<&str>,
+    only_new: bool,
+) -> Result<Outcome, BotError> {
+    if !db.user_exists(&user_subscription.user_id)? {
+        warn!(
+            "skipping subscription {} of unknown user: {}",
+            user_subscription.id, user_subscription.user_id
+        );
+        return Ok(Outcome::UnknownUser);
+    }
+
+    let mut posts = fetch_posts(reddit_client, user_subscription, FETCH_RETRY_DELAY)
+        .await
+        .map_err(|(err, attempts)| {
+            BotError::subscription(
+                &user_subscription.subreddit,
+                &user_subscription.user_id,
+                attempts,
+                err.into(),
+            )
+        })?;
+
+    if only_new {
+        let sent_post_ids = db.get_sent_post_ids(user_subscription.id)?;
+        posts.retain(|post| !sent_post_ids.contains(&post.id));
+        // An empty digest would count towards the empty streak and move `last_sent_at`.
+        if posts.is_empty() {
+            return Ok(Outcome::NothingNew);
+        }
+    }
+
+    // Image posts go out as photos ahead of the digest of the remaining posts.
+    let photos = if user_subscription.as_photos {
+        let (photos, rest): (Vec<Post>, Vec<Post>) =
+            posts.into_iter().partition(|post| post.image_url.is_some());
+        posts = rest;
+        photos
+    } else {
+        vec![]
+    };
+    let post_ids = photos
+        .iter()
+        .chain(&posts)
+        .map(|post| post.id.as_str())
+        .collect::<Vec<&str>>();
+    // The digest counts as sent once its first message is delivered, a later failure must not send
+    // it all over again on the next cycle.
+    let mut recorded = false;
+    for post in &photos {
+        let caption = format!("{}\n{}", post.title, post.link);
+        let result = telegram_client
+            .send_photo(&Image {
+                chat_id: &user_subscription.target_chat_id,
+                photo: post.image_url.as_deref().unwrap_or_default(),
+                caption: Some(&caption),
+                disable_notification: user_subscription.disable_notification,
+                protect_content: user_subscription.protect_content,
+                has_spoiler: post.over_18,
+            })
+            .await;
+        // E.g. Telegram can't fetch the image, the post is sent as text instead.
+        if let Err(err) = result {
+            warn!(
+                "failed to send photo {} of subscription {}, sending it as text: {}",
+                post.id, user_subscription.id, err
+            );
+            telegram_client
+                .send_message(&Message {
+                    chat_id: &user_subscription.target_chat_id,
+                    text: &caption,
+                    disable_notification: user_subscription.disable_notification,
+                    disable_web_page_preview: !user_subscription.link_preview,
+                    protect_content: user_subscription.protect_content,
+                    ..Default::default()
+                })
+                .await?;
+        }
+        record_once(
+            db,
+            telegram_client,
+            user_subscription,
+            &post_ids,
+            &mut recorded,
+        )
+        .await;
+    }
+
+    // Polls need at least two options, smaller digests are sent as a regular message. When every
+    // post went out as a photo there's no digest left to send. Without a digest message the footer
+    // follows on its own.
+    if user_subscription.as_poll && posts.len() >= 2 {
+        let (question, options) = build_poll(user_subscription, &posts);
+        telegram_client
+            .send_poll(&user_subscription.target_chat_id, &question, &options)
+            .await?;
+        record_once(
+            db,
+            telegram_client,
+            user_subscription,
+            &post_ids,
+            &mut recorded,
+        )
+        .await;
+        send_footer(telegram_client, user_subscription, footer).await?;
+    } else if posts.is_empty() && !photos.is_empty() {
+        send_footer(telegram_client, user_subscription, footer).await?;
+    } else {
+        let opts = RenderOptions {
+            footer: footer.map(str::to_string),
+            locale: db.get_locale(&user_subscription.user_id)?,
+            ..RenderOptions::for_subscription(user_subscription)
+        };
+        let messages = build_digest(user_subscription, &posts, &opts);
+        send_digest(
+            db,
+            telegram_client,
+            user_subscription,
+            &messages,
+            opts.parse_mode,
+            &post_ids,
+            &mut recorded,
+        )
+        .await?;
+    }
+    Ok(Outcome::Sent)
+}
+
+async fn send_footer(
+    telegram_client: &impl Messenger,
+    user_subscription: &Subscription,
+    footer: Option<&str>,
+) -> Result<(), BotError> {
+    if let Some(footer) = footer {
+        telegram_client
+            .send_message(&Message {
+                chat_id: &user_subscription.target_chat_id,
+                text: footer,
+                disable_notification: user_subscription.disable_notification,
+                disable_web_page_preview: !user_subscription.link_preview,
+                protect_content: user_subscription.protect_content,
+                ..Default::default()
+            })
+            .await?;
+    }
+    Ok(())
+}
 
+/// Sends the messages of a digest. With edit in place a digest that fits in one message replaces
+/// the previous one instead, an identical digest leaves it as it was.
+async fn send_digest(
+    db: &DbClient,
+    telegram_client: &impl Messenger,
+    user_subscription: &Subscription,
+    messages: &[String],
+    parse_mode: Option<ParseMode>,
+    post_ids: &[&str],
+    recorded: &mut bool,
+) -> Result<(), BotError> {
+    if let (true, [text], Some(message_id)) = (
+        user_subscription.edit_in_place,
+        messages,
+        &user_subscription.last_message_id,
+    ) {
+        let result = telegram_client
+            .edit_message_text(&EditMessage {
+                chat_id: &user_subscription.target_chat_id,
+                message_id,
+                text,
+                disable_web_page_preview: !user_subscription.link_preview,
+                parse_mode,
+                ..Default::default()
+            })
+            .await;
+        match result {
+            Ok(()) => {
+                record_once(db, telegram_client, user_subscription, post_ids, recorded).await;
+                return Ok(());
+            }
+            // E.g. the message was deleted in the meantime, the digest is sent anew.
+            Err(err) => warn!(
+                "failed to edit message {} of subscription {}: {}",
+                message_id, user_subscription.id, err
+            ),
+        }
+    }
+
+    let mut message_ids = Vec::with_capacity(messages.len());
+    for message in messages {
+        let message_id = telegram_client
+            .send_message(&Message {
+                chat_id: &user_subscription.target_chat_id,
+                text: message,
+                disable_notification: user_subscription.disable_notification,
+                disable_web_page_preview: !user_subscription.link_preview,
+                protect_content: user_subscription.protect_content,
+                parse_mode,
+                ..Default::default()
+            })
+            .await?;
+        record_once(db, telegram_client, user_subscription, post_ids, recorded).await;
+        message_ids.push(message_id);
+    }
+    if user_subscription.edit_in_place {
+        let last_message_id = match message_ids.as_slice() {
+            [message_id] => Some(message_id.as_str()),
+            _ => None,
+        };
+        db.set_last_message_id(user_subscription.id, last_message_id)?;
+    }
     Ok(())
 }
 
+/// Sends the digests of several subscriptions of the same user together, grouped by subreddit. They
+/// share a message where they fit, longer digests are split over several.
+/// Subreddits that can't be fetched get their error text in place of the posts.
+pub async fn process_combined_digest(
+    db: &DbClient,
+    telegram_client: &impl Messenger,
+    reddit_client: &RedditClient,
+    user_subscriptions: &[Subscription],
+    footer: Option<&str>,
+) -> Result<Outcome, BotError> {
+    let user_id = match user_subscriptions.first() {
+        Some(user_subscription) => &user_subscription.user_id,
+        None => return Ok(Outcome::NothingNew),
+    };
+    if !db.user_exists(user_id)? {
+        warn!("skipping combined digest of unknown user: {}", user_id);
+        return Ok(Outcome::UnknownUser);
+    }
+
+    let locale = db.get_locale(user_id)?;
+    let mut sections = vec![];
+    let mut fetched = vec![];
+    let mut failed = vec![];
+    for user_subscription in user_subscriptions {
+        match fetch_posts(reddit_client, user_subscription, FETCH_RETRY_DELAY).await {
+            Ok(posts) => {
+                // The combined digest is a single text digest sent anew, posts per message, image
+                // posts sent as photos and edit in place of the subscriptions don't apply to it.
+                let opts = RenderOptions {
+                    posts_per_message: None,
+                    locale: locale.clone(),
+                    ..RenderOptions::for_subscription(user_subscription)
+                };
+                sections.push(build_digest(user_subscription, &posts, &opts));
+                fetched.push((user_subscription, posts));
+            }
+            Err((err, attempts)) => {
+                warn!(
+                    "failed to fetch posts of {} for combined digest after {} attempts: {}",
+                    user_subscription.subreddit, attempts, err
+                );
+                sections.push(vec![format!("{}\n\n", build_error_text(user_subscription))]);
+                failed.push(user_subscription.id);
+            }
+        }
+    }
+
+    if let Some(footer) = footer {
+        sections.push(vec![format!("{}\n", footer)]);
+    }
+
+    // The merged subscriptions may differ in prefs, the digest is silent or previewed only if all of them are.
+    let disable_notification = user_subscriptions
+        .iter()
+        .all(|user_subscription| user_subscription.disable_notification);
+    let link_preview = user_subscriptions
+        .iter()
+        .all(|user_subscription| user_subscription.link_preview);
+    // Protecting part of the merged digest would protect all of it.
+    let protect_content = user_subscriptions
+        .iter()
+        .any(|user_subscription| user_subscription.protect_content);
+    let header = combined_header(user_subscriptions);
+    let messages = build_combined_digest(&header, &sections, MAX_MESSAGE_LENGTH);
+    for (index, message) in messages.iter().enumerate() {
+        telegram_client
+            .send_message(&Message {
+                chat_id: user_id,
+                text: message,
+                disable_notification,
+                disable_web_page_preview: !link_preview,
+                protect_content,
+                ..Default::default()
+            })
+            .await?;
+
+        // The digest counts as sent once its first message is delivered, a later failure must not
+        // send it all over again on the next cycle.
+        if index == 0 {
+            // The user has been told, don't retry on every cycle.
+            for id in &failed {
+                db.update_last_sent(*id)?;
+            }
+            for (user_subscription, posts) in &fetched {
+                let post_ids = posts
+                    .iter()
+                    .map(|post| post.id.as_str())
+                    .collect::<Vec<&str>>();
+                record_sent(db, telegram_client, user_subscription, &post_ids).await;
+            }
+        }
+    }
+
+    Ok(Outcome::Sent)
+}
+
+/// Posts of the subscription, failing with the last error and the number of attempts made.
+pub async fn fetch_posts(
+    reddit_client: &RedditClient,
+    user_subscription: &Subscription,
+    retry_delay: Duration,
+) -> Result<Vec<Post>, (RedditError, u32)> {
+    let listing = Listing {
+        sort: user_subscription.sort.clone(),
+        timeframe: user_subscription.timeframe.clone(),
+    };
+    let mut attempts = 0;
+    let mut posts = loop {
+        attempts += 1;
+        let result = if user_subscription.since_last_sent {
+            reddit_client
+                .fetch_posts_since(
+                    &user_subscription.subreddit,
+                    since_last_sent(
+                        &user_subscription.last_sent_at,
+                        user_subscription.max_age_hours,
+                        Utc::now(),
+                    ),
+                    user_subscription.post_limit.max(0) as usize,
+                )
+                .await
+        } else {
+            reddit_client
+                .fetch_posts(
+                    &user_subscription.subreddit,
+                    &listing,
+                    user_subscription.post_limit,
+                    user_subscription.max_age_hours,
+                )
+                .await
+        };
+        match result {
+            Ok(posts) => break posts,
+            Err(err)
+                if (err.is_timeout() || err.is_connect())
+                    && attempts < reddit_client.fetch_attempts() =>
+            {
+                warn!(
+                    "attempt {} at fetching {} failed, retrying: {}",
+                    attempts, user_subscription.subreddit, err
+                );
+                sleep(retry_delay).await;
+            }
+            Err(err) => return Err((err, attempts)),
+        }
+    };
+
+    if user_subscription.include_top_comment {
+        for post in posts.iter_mut().take(TOP_COMMENT_POST_LIMIT) {
+            let permalink = match Url::parse(&post.link) {
+                Ok(url) => url.path().to_string(),
+                Err(_) => continue,
+            };
+            match reddit_client.fetch_top_comment(&permalink).await {
+                Ok(comment) => post.top_comment = comment,
+                Err(err) => warn!("failed to fetch top comment for {}: {}", permalink, err),
+            }
+        }
+    }
+
+    Ok(posts)
+}
+
+/// Records the digest as sent unless `recorded` says it already was.
+async fn record_once(
+    db: &DbClient,
+    telegram_client: &impl Messenger,
+    user_subscription: &Subscription,
+    post_ids: &[&str],
+    recorded: &mut bool,
+) {
+    if !*recorded {
+        *recorded = true;
+        record_sent(db, telegram_client, user_subscription, post_ids).await;
+    }
+}
+
+/// Remembers the sent posts and nudges the user once the subscription keeps coming up empty.
+async fn record_sent(
+    db: &DbClient,
+    telegram_client: &impl Messenger,
+    user_subscription: &Subscription,
+    post_ids: &[&str],
+) {
+    // The digest is already out, failing to record it doesn't fail the digest.
+    let empty_streak = match db.touch_last_sent(user_subscription.id, post_ids) {
+        Ok(empty_streak) => empty_streak,
+        Err(err) => {
+            error!(
+                "failed to mark subscription {} as sent: {}",
+                user_subscription.id, err
+            );
+            return;
+        }
+    };
+
+    if empty_streak == EMPTY_STREAK_NUDGE_THRESHOLD {
+        let result = telegram_client
+            .send_message(&Message {
+                chat_id: &user_subscription.user_id,
+                text: &build_empty_streak_text(&user_subscription.subreddit, empty_streak),
+                ..Default::default()
+            })
+            .await;
+        if let Err(err) = result {
+            error!(
+                "failed to nudge user {} about empty digests: {}",
+                user_subscription.user_id, err
+            );
+        }
+    }
+}
+
+pub fn build_error_text(subscription: &Subscription) -> String {
+    subscription
+        .error_text
+        .as_deref()
+        .unwrap_or(DEFAULT_ERROR_TEXT)
+        .replace("{subreddit}", &subscription.subreddit)
+}
+
+fn build_empty_streak_text(subreddit: &str, empty_streak: i32) -> String {
+    format!(
+        "Your r/{} filters have produced no posts for {} weeks — consider relaxing them.",
+        subreddit, empty_streak
+    )
+}
+
 #[cfg(test)]
 mod tests {
-    use mockito::server_url;
+    use std::net::TcpListener;
+
+    use chrono_tz::Tz;
+    use diesel::Connection;
+    use mockito::{mock, server_url, Matcher};
+    use serde_json::json;
     use serial_test::serial;
 
+    use crate::db::models::SubscriptionChanges;
     use crate::db::test_helpers::setup_test_db;
     use crate::reddit::test_helpers::mock_reddit_success;
-    use crate::telegram::test_helpers::mock_send_message_success;
+    use crate::telegram::test_helpers::{
+        mock_send_message_not_called, mock_send_message_success, RecordingMessenger,
+    };
 
     use super::*;
 
     const USER_ID: &str = "123";
     const TOKEN: &str = "token";
 
+    #[test]
+    fn is_due_works() {
+        let subscription = Subscription {
+            send_on: 6,
+            send_at: 12,
+            ..Default::default()
+        };
+        // Sunday
+        assert!(is_due(
+            &subscription,
+            Utc.with_ymd_and_hms(2020, 3, 22, 12, 0, 0).unwrap()
+        ));
+        assert!(!is_due(
+            &subscription,
+            Utc.with_ymd_and_hms(2020, 3, 22, 11, 59, 0).unwrap()
+        ));
+        // Saturday and Monday
+        assert!(!is_due(
+            &subscription,
+            Utc.with_ymd_and_hms(2020, 3, 21, 12, 0, 0).unwrap()
+        ));
+        assert!(!is_due(
+            &subscription,
+            Utc.with_ymd_and_hms(2020, 3, 23, 12, 0, 0).unwrap()
+        ));
+
+        let invalid = Subscription {
+            send_on: 7,
+            ..subscription
+        };
+        assert!(!is_due(
+            &invalid,
+            Utc.with_ymd_and_hms(2020, 3, 22, 12, 0, 0).unwrap()
+        ));
+    }
+
+    #[test]
+    fn already_sent_on_works() {
+        let now = Utc.with_ymd_and_hms(2020, 3, 22, 12, 0, 0).unwrap();
+        assert!(!already_sent_on(&None, now));
+        assert!(already_sent_on(
+            &Some("2020-03-22T00:00:05+00:00".to_string()),
+            now
+        ));
+        assert!(!already_sent_on(
+            &Some("2020-03-21T23:59:59+00:00".to_string()),
+            now
+        ));
+        // Converted to the timezone of now before comparing days.
+        assert!(already_sent_on(
+            &Some("2020-03-21T23:30:00-01:00".to_string()),
+            now
+        ));
+        assert!(!already_sent_on(&Some("yesterday".to_string()), now));
+
+        let now = Utc
+            .with_ymd_and_hms(2020, 3, 21, 23, 0, 0)
+            .unwrap()
+            .with_timezone(&Tz::Europe__Riga);
+        assert!(already_sent_on(
+            &Some("2020-03-21T22:30:00+00:00".to_string()),
+            now
+        ));
+        assert!(!already_sent_on(
+            &Some("2020-03-21T21:30:00+00:00".to_string()),
+            now
+        ));
+    }
+
     #[tokio::test]
     #[serial]
     async fn process_subscription_success() {
@@ -154,19 +1103,103 @@ mod tests {
         let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
         let reddit_client = RedditClient::new_with(url);
         let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let user_subscription = db_client.subscribe(USER_ID, subreddit, 0, 12).unwrap();
 
-        let user_subscription = Subscription {
-            id: 123,
-            user_id: USER_ID.to_string(),
-            subreddit: subreddit.to_string(),
+        process_subscription(
+            &db_client,
+            &telegram_client,
+            &reddit_client,
+            &user_subscription,
+            None,
+            false,
+        )
+        .await
+        .unwrap();
+
+        _m.assert();
+        _m2.assert();
+
+        let result = db_client.get_sent_post_ids(user_subscription.id).unwrap();
+        assert_eq!(result, ["fbenua"]);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn process_subscription_user_prefs() {
+        let url = &server_url();
+        let subreddit = "rust";
+        let expected_message = Message {
+            chat_id: USER_ID,
+            text: &format!("Weekly popular posts from: \"rust\"\n\nA half-hour to learn Rust\n{}/r/rust/comments/fbenua/a_halfhour_to_learn_rust/\n\n", url),
+            disable_notification: true,
+            disable_web_page_preview: false,
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &expected_message);
+        let _m2 = mock_reddit_success(subreddit);
+
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.update_user_prefs(USER_ID, true, true).unwrap();
+        let user_subscription = db_client.subscribe(USER_ID, subreddit, 0, 12).unwrap();
+
+        process_subscription(
+            &db_client,
+            &telegram_client,
+            &reddit_client,
+            &user_subscription,
+            None,
+            false,
+        )
+        .await
+        .unwrap();
+
+        _m.assert();
+        _m2.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn process_subscription_protect_content() {
+        let url = &server_url();
+        let subreddit = "rust";
+        let expected_message = Message {
+            chat_id: USER_ID,
+            text: &format!("Weekly popular posts from: \"rust\"\n\nA half-hour to learn Rust\n{}/r/rust/comments/fbenua/a_halfhour_to_learn_rust/\n\n", url),
+            disable_web_page_preview: true,
+            protect_content: true,
             ..Default::default()
         };
+        let _m = mock_send_message_success(TOKEN, &expected_message);
+        let _m2 = mock_reddit_success(subreddit);
+
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, subreddit, 0, 12).unwrap();
+        db_client
+            .update_subscription(
+                USER_ID,
+                subreddit,
+                SubscriptionChanges {
+                    protect_content: Some(true),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let user_subscription = db_client.get_user_subscriptions(USER_ID).unwrap().remove(0);
 
         process_subscription(
             &db_client,
             &telegram_client,
             &reddit_client,
             &user_subscription,
+            None,
+            false,
         )
         .await
         .unwrap();
@@ -174,4 +1207,1802 @@ mod tests {
         _m.assert();
         _m2.assert();
     }
+
+    #[tokio::test]
+    #[serial]
+    async fn process_subscription_show_scores() {
+        let url = &server_url();
+        let subreddit = "rust";
+        let expected_message = Message {
+            chat_id: USER_ID,
+            text: &format!("Weekly popular posts from: \"rust\"\n\nA half-hour to learn Rust (567 points)\n{}/r/rust/comments/fbenua/a_halfhour_to_learn_rust/\n\n", url),
+            disable_web_page_preview: true,
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &expected_message);
+        let _m2 = mock_reddit_success(subreddit);
+
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.set_locale(USER_ID, Some("de")).unwrap();
+        db_client.subscribe(USER_ID, subreddit, 0, 12).unwrap();
+        db_client
+            .update_subscription(
+                USER_ID,
+                subreddit,
+                SubscriptionChanges {
+                    show_scores: Some(true),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let user_subscription = db_client.get_user_subscriptions(USER_ID).unwrap().remove(0);
+
+        process_subscription(
+            &db_client,
+            &telegram_client,
+            &reddit_client,
+            &user_subscription,
+            None,
+            false,
+        )
+        .await
+        .unwrap();
+
+        _m.assert();
+        _m2.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn process_subscription_only_new() {
+        let url = &server_url();
+        let subreddit = "rust";
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let user_subscription = db_client.subscribe(USER_ID, subreddit, 0, 12).unwrap();
+        db_client
+            .touch_last_sent(user_subscription.id, &["fbenua"])
+            .unwrap();
+
+        let sent = db_client.get_user_subscriptions(USER_ID).unwrap().remove(0);
+
+        let _m = mock_send_message_not_called(TOKEN);
+        let _m2 = mock_reddit_success(subreddit);
+        let outcome = process_subscription(
+            &db_client,
+            &telegram_client,
+            &reddit_client,
+            &user_subscription,
+            None,
+            true,
+        )
+        .await
+        .unwrap();
+        assert_eq!(outcome, Outcome::NothingNew);
+        _m.assert();
+
+        // Nothing was sent, so nothing is recorded either.
+        let result = db_client.get_user_subscriptions(USER_ID).unwrap().remove(0);
+        assert_eq!(result.last_sent_at, sent.last_sent_at);
+        assert_eq!(result.empty_streak, 0);
+
+        let _m = mock_send_message_success(
+            TOKEN,
+            &Message {
+                chat_id: USER_ID,
+                text: &format!("Weekly popular posts from: \"rust\"\n\nA half-hour to learn Rust\n{}/r/rust/comments/fbenua/a_halfhour_to_learn_rust/\n\n", url),
+                disable_web_page_preview: true,
+                ..Default::default()
+            },
+        );
+        let _m2 = mock_reddit_success(subreddit);
+        process_subscription(
+            &db_client,
+            &telegram_client,
+            &reddit_client,
+            &user_subscription,
+            None,
+            false,
+        )
+        .await
+        .unwrap();
+        _m.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn process_subscription_top_comment() {
+        let url = &server_url();
+        let subreddit = "rust";
+        let expected_message = Message {
+            chat_id: USER_ID,
+            text: &format!("Weekly popular posts from: \"rust\"\n\nA half-hour to learn Rust\n{}/r/rust/comments/fbenua/a_halfhour_to_learn_rust/\nTop comment: Great read\n\n", url),
+            disable_web_page_preview: true,
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &expected_message);
+        let _m2 = mock_reddit_success(subreddit);
+        let _m3 = mock(
+            "GET",
+            "/r/rust/comments/fbenua/a_halfhour_to_learn_rust.json?limit=1&sort=top",
+        )
+        .with_status(200)
+        .with_body(r#"[{"kind": "Listing", "data": {"children": []}}, {"kind": "Listing", "data": {"children": [{"kind": "t1", "data": {"body": "Great read"}}]}}]"#)
+        .expect(1)
+        .create();
+
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, subreddit, 0, 12).unwrap();
+        db_client
+            .update_subscription(
+                USER_ID,
+                subreddit,
+                SubscriptionChanges {
+                    include_top_comment: Some(true),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let user_subscription = db_client.get_user_subscriptions(USER_ID).unwrap().remove(0);
+
+        process_subscription(
+            &db_client,
+            &telegram_client,
+            &reddit_client,
+            &user_subscription,
+            None,
+            false,
+        )
+        .await
+        .unwrap();
+
+        _m.assert();
+        _m2.assert();
+        _m3.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn process_subscription_target_chat() {
+        let url = &server_url();
+        let subreddit = "rust";
+        let expected_message = Message {
+            chat_id: "-100123",
+            text: &format!("Weekly popular posts from: \"rust\"\n\nA half-hour to learn Rust\n{}/r/rust/comments/fbenua/a_halfhour_to_learn_rust/\n\n", url),
+            disable_web_page_preview: true,
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &expected_message);
+        let _m2 = mock_reddit_success(subreddit);
+
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, subreddit, 0, 12).unwrap();
+        db_client
+            .update_subscription(
+                USER_ID,
+                subreddit,
+                SubscriptionChanges {
+                    target_chat_id: Some("-100123"),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let user_subscription = db_client.get_user_subscriptions(USER_ID).unwrap().remove(0);
+
+        process_subscription(
+            &db_client,
+            &telegram_client,
+            &reddit_client,
+            &user_subscription,
+            None,
+            false,
+        )
+        .await
+        .unwrap();
+
+        _m.assert();
+        _m2.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn process_subscription_empty_streak() {
+        let url = &server_url();
+        let subreddit = "rust";
+        let expected_message = Message {
+            chat_id: USER_ID,
+            text: "Weekly popular posts from: \"rust\"\n\nNo popular posts this week.\n",
+            disable_web_page_preview: true,
+            ..Default::default()
+        };
+        let _m2 = mock("GET", "/r/rust/top.json?limit=10&t=week")
+            .with_status(200)
+            .with_body(r#"{"data":{"children":[]}}"#)
+            .create();
+
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let user_subscription = db_client.subscribe(USER_ID, subreddit, 0, 12).unwrap();
+
+        for _ in 1..EMPTY_STREAK_NUDGE_THRESHOLD {
+            let _m = mock_send_message_success(TOKEN, &expected_message);
+            process_subscription(
+                &db_client,
+                &telegram_client,
+                &reddit_client,
+                &user_subscription,
+                None,
+                false,
+            )
+            .await
+            .unwrap();
+            _m.assert();
+        }
+
+        let _m = mock_send_message_success(TOKEN, &expected_message);
+        let nudge = Message {
+            chat_id: USER_ID,
+            text:
+                "Your r/rust filters have produced no posts for 3 weeks — consider relaxing them.",
+            ..Default::default()
+        };
+        let _m3 = mock_send_message_success(TOKEN, &nudge);
+        process_subscription(
+            &db_client,
+            &telegram_client,
+            &reddit_client,
+            &user_subscription,
+            None,
+            false,
+        )
+        .await
+        .unwrap();
+        _m.assert();
+        _m3.assert();
+
+        let result = db_client.get_subscriptions().unwrap();
+        assert_eq!(result[0].empty_streak, EMPTY_STREAK_NUDGE_THRESHOLD);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn process_subscription_as_poll() {
+        let url = &server_url();
+        let _m = mock("POST", format!("/bot{}/sendPoll", TOKEN).as_str())
+            .match_body(Matcher::Json(json!({
+                "chat_id": USER_ID,
+                "question": "Which of these weekly popular posts from \"rust\" will you read?",
+                "options": ["First", "Second"],
+                "disable_notification": false
+            })))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .expect(1)
+            .create();
+        let _m2 = mock_send_message_not_called(TOKEN);
+        let _m3 = mock("GET", "/r/rust/top.json?limit=10&t=week")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"kind": "Listing", "data": {"children": [
+                    {"kind": "t3", "data": {"id": "1", "title": "First", "permalink": "/r/rust/comments/1/"}},
+                    {"kind": "t3", "data": {"id": "2", "title": "Second", "permalink": "/r/rust/comments/2/"}}
+                ]}}"#,
+            )
+            .create();
+
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        db_client
+            .update_subscription(
+                USER_ID,
+                "rust",
+                SubscriptionChanges {
+                    as_poll: Some(true),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let user_subscription = db_client.get_user_subscriptions(USER_ID).unwrap().remove(0);
+
+        process_subscription(
+            &db_client,
+            &telegram_client,
+            &reddit_client,
+            &user_subscription,
+            None,
+            false,
+        )
+        .await
+        .unwrap();
+        _m.assert();
+        _m2.assert();
+        _m3.assert();
+
+        let result = db_client.get_sent_post_ids(user_subscription.id).unwrap();
+        assert_eq!(result, ["1", "2"]);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn process_subscription_as_poll_footer() {
+        let url = &server_url();
+        let _m = mock("GET", "/r/rust/top.json?limit=10&t=week")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"kind": "Listing", "data": {"children": [
+                    {"kind": "t3", "data": {"id": "1", "title": "First", "permalink": "/r/rust/comments/1/"}},
+                    {"kind": "t3", "data": {"id": "2", "title": "Second", "permalink": "/r/rust/comments/2/"}}
+                ]}}"#,
+            )
+            .create();
+
+        let messenger = RecordingMessenger::new();
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        db_client
+            .update_subscription(
+                USER_ID,
+                "rust",
+                SubscriptionChanges {
+                    as_poll: Some(true),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let user_subscription = db_client.get_user_subscriptions(USER_ID).unwrap().remove(0);
+
+        process_subscription(
+            &db_client,
+            &messenger,
+            &reddit_client,
+            &user_subscription,
+            Some("Sourced from Reddit."),
+            false,
+        )
+        .await
+        .unwrap();
+        _m.assert();
+        assert_eq!(
+            messenger.texts(),
+            [
+                "Which of these weekly popular posts from \"rust\" will you read?",
+                "Sourced from Reddit."
+            ]
+        );
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn process_subscription_nsfw_photo() {
+        let url = &server_url();
+        let _m = mock("POST", format!("/bot{}/sendPhoto", TOKEN).as_str())
+            .match_body(Matcher::Json(json!({
+                "chat_id": USER_ID,
+                "photo": "https://i.redd.it/unsafe.png",
+                "caption": format!("Unsafe\n{}/r/rust/comments/nsfw/", url),
+                "disable_notification": false,
+                "has_spoiler": true
+            })))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .expect(1)
+            .create();
+        let _m2 = mock("POST", format!("/bot{}/sendPhoto", TOKEN).as_str())
+            .match_body(Matcher::Json(json!({
+                "chat_id": USER_ID,
+                "photo": "https://i.redd.it/ferris.png",
+                "caption": format!("Ferris\n{}/r/rust/comments/sfw/", url),
+                "disable_notification": false
+            })))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":692}}"#)
+            .expect(1)
+            .create();
+        let _m3 = mock_send_message_not_called(TOKEN);
+        let _m4 = mock("GET", "/r/rust/top.json?limit=10&t=week")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"kind": "Listing", "data": {"children": [
+                    {"kind": "t3", "data": {"id": "nsfw", "title": "Unsafe", "permalink": "/r/rust/comments/nsfw/", "post_hint": "image", "over_18": true, "url": "https://i.redd.it/unsafe.png"}},
+                    {"kind": "t3", "data": {"id": "sfw", "title": "Ferris", "permalink": "/r/rust/comments/sfw/", "post_hint": "image", "over_18": false, "url": "https://i.redd.it/ferris.png"}}
+                ]}}"#,
+            )
+            .create();
+
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        db_client
+            .update_subscription(
+                USER_ID,
+                "rust",
+                SubscriptionChanges {
+                    as_photos: Some(true),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let user_subscription = db_client.get_user_subscriptions(USER_ID).unwrap().remove(0);
+
+        process_subscription(
+            &db_client,
+            &telegram_client,
+            &reddit_client,
+            &user_subscription,
+            None,
+            false,
+        )
+        .await
+        .unwrap();
+        _m.assert();
+        _m2.assert();
+        _m3.assert();
+        _m4.assert();
+
+        let result = db_client.get_sent_post_ids(user_subscription.id).unwrap();
+        assert_eq!(result, ["nsfw", "sfw"]);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn process_subscription_photos_footer() {
+        let url = &server_url();
+        let _m = mock("GET", "/r/rust/top.json?limit=10&t=week")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"kind": "Listing", "data": {"children": [
+                    {"kind": "t3", "data": {"id": "sfw", "title": "Ferris", "permalink": "/r/rust/comments/sfw/", "post_hint": "image", "over_18": false, "url": "https://i.redd.it/ferris.png"}}
+                ]}}"#,
+            )
+            .create();
+
+        let messenger = RecordingMessenger::new();
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        db_client
+            .update_subscription(
+                USER_ID,
+                "rust",
+                SubscriptionChanges {
+                    as_photos: Some(true),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let user_subscription = db_client.get_user_subscriptions(USER_ID).unwrap().remove(0);
+
+        process_subscription(
+            &db_client,
+            &messenger,
+            &reddit_client,
+            &user_subscription,
+            Some("Sourced from Reddit."),
+            false,
+        )
+        .await
+        .unwrap();
+        _m.assert();
+        assert_eq!(
+            messenger.texts(),
+            ["https://i.redd.it/ferris.png", "Sourced from Reddit."]
+        );
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn process_cycle_photo_fails() {
+        let url = &server_url();
+        let _m = mock("POST", format!("/bot{}/sendPhoto", TOKEN).as_str())
+            .match_body(Matcher::PartialJson(
+                json!({"photo": "https://i.redd.it/ferris.png"}),
+            ))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .expect(1)
+            .create();
+        let _m2 = mock("POST", format!("/bot{}/sendPhoto", TOKEN).as_str())
+            .match_body(Matcher::PartialJson(
+                json!({"photo": "https://i.redd.it/broken.png"}),
+            ))
+            .with_status(400)
+            .with_body(r#"{"ok":false,"error_code":400,"description":"Bad Request: wrong file identifier/HTTP URL specified"}"#)
+            .expect(1)
+            .create();
+        let fallback = Message {
+            chat_id: USER_ID,
+            text: &format!("Broken\n{}/r/rust/comments/broken/", url),
+            disable_web_page_preview: true,
+            ..Default::default()
+        };
+        let _m3 = mock_send_message_success(TOKEN, &fallback);
+        let _m4 = mock("GET", "/r/rust/top.json?limit=10&t=week")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"kind": "Listing", "data": {"children": [
+                    {"kind": "t3", "data": {"id": "sfw", "title": "Ferris", "permalink": "/r/rust/comments/sfw/", "post_hint": "image", "url": "https://i.redd.it/ferris.png"}},
+                    {"kind": "t3", "data": {"id": "broken", "title": "Broken", "permalink": "/r/rust/comments/broken/", "post_hint": "image", "url": "https://i.redd.it/broken.png"}}
+                ]}}"#,
+            )
+            .expect(1)
+            .create();
+
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let send_on = Utc::now().weekday().num_days_from_monday() as i32;
+        let subscription = db_client.subscribe(USER_ID, "rust", send_on, 0).unwrap();
+        db_client
+            .update_subscription(
+                USER_ID,
+                "rust",
+                SubscriptionChanges {
+                    as_photos: Some(true),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        db_client
+            .conn
+            .execute("UPDATE users_subscriptions SET last_sent_at = NULL")
+            .unwrap();
+
+        // The second cycle runs as if the process restarted, only the database remembers the digest.
+        for _ in 0..2 {
+            process_cycle(
+                &db_client,
+                &telegram_client,
+                &reddit_client,
+                &Mutex::new(ProcessingLog::default()),
+                &SchedulerMetrics::default(),
+                None,
+                Duration::from_secs(0),
+            )
+            .await
+            .unwrap();
+        }
+
+        _m.assert();
+        _m2.assert();
+        _m3.assert();
+        _m4.assert();
+        let result = db_client.get_sent_post_ids(subscription.id).unwrap();
+        assert_eq!(result, ["broken", "sfw"]);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn process_subscription_edit_in_place() {
+        let url = &server_url();
+        let text = format!("Weekly popular posts from: \"rust\"\n\nA half-hour to learn Rust\n{}/r/rust/comments/fbenua/a_halfhour_to_learn_rust/\n\n", url);
+        let expected_message = Message {
+            chat_id: USER_ID,
+            text: &text,
+            disable_web_page_preview: true,
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &expected_message);
+        let _m2 = mock("POST", format!("/bot{}/editMessageText", TOKEN).as_str())
+            .expect(0)
+            .create();
+        let _m3 = mock_reddit_success("rust");
+
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        db_client
+            .update_subscription(
+                USER_ID,
+                "rust",
+                SubscriptionChanges {
+                    edit_in_place: Some(true),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let user_subscription = db_client.get_user_subscriptions(USER_ID).unwrap().remove(0);
+
+        // Nothing to edit yet, the digest is sent and remembered.
+        process_subscription(
+            &db_client,
+            &telegram_client,
+            &reddit_client,
+            &user_subscription,
+            None,
+            false,
+        )
+        .await
+        .unwrap();
+        _m.assert();
+        _m2.assert();
+        _m3.assert();
+
+        let result = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(result[0].last_message_id.as_deref(), Some("691"));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn process_subscription_edit_in_place_not_modified() {
+        let url = &server_url();
+        let text = format!("Weekly popular posts from: \"rust\"\n\nA half-hour to learn Rust\n{}/r/rust/comments/fbenua/a_halfhour_to_learn_rust/\n\n", url);
+        let _m = mock("POST", format!("/bot{}/editMessageText", TOKEN).as_str())
+            .match_body(Matcher::Json(json!({
+                "chat_id": USER_ID,
+                "message_id": "691",
+                "text": text,
+                "disable_notification": false,
+                "disable_web_page_preview": true
+            })))
+            .with_status(400)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"ok":false,"error_code":400,"description":"Bad Request: message is not modified: specified new message content and reply markup are exactly the same as a current content and reply markup of the message"}"#)
+            .expect(1)
+            .create();
+        let _m2 = mock_send_message_not_called(TOKEN);
+        let _m3 = mock_reddit_success("rust");
+
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let subscription = db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        db_client
+            .update_subscription(
+                USER_ID,
+                "rust",
+                SubscriptionChanges {
+                    edit_in_place: Some(true),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        db_client
+            .set_last_message_id(subscription.id, Some("691"))
+            .unwrap();
+        db_client
+            .conn
+            .execute("UPDATE users_subscriptions SET last_sent_at = NULL")
+            .unwrap();
+        let user_subscription = db_client.get_user_subscriptions(USER_ID).unwrap().remove(0);
+
+        process_subscription(
+            &db_client,
+            &telegram_client,
+            &reddit_client,
+            &user_subscription,
+            None,
+            false,
+        )
+        .await
+        .unwrap();
+        _m.assert();
+        _m2.assert();
+        _m3.assert();
+
+        let result = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert!(result[0].last_sent_at.is_some());
+        assert_eq!(result[0].last_message_id.as_deref(), Some("691"));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn process_subscription_fetch_error() {
+        let url = &server_url();
+        let _m = mock_send_message_not_called(TOKEN);
+        let _m2 = mock("GET", "/r/rust/top.json?limit=10&t=week")
+            .with_status(503)
+            .with_body("<html>Service unavailable</html>")
+            .create();
+
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let user_subscription = db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+
+        let result = process_subscription(
+            &db_client,
+            &telegram_client,
+            &reddit_client,
+            &user_subscription,
+            None,
+            false,
+        )
+        .await;
+        assert!(result.is_err());
+        _m.assert();
+        _m2.assert();
+
+        let result = db_client.get_user_subscriptions(USER_ID).unwrap();
+        assert_eq!(result[0].last_sent_at, user_subscription.last_sent_at);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn process_cycle_fetch_error() {
+        let url = &server_url();
+        let message = Message {
+            chat_id: USER_ID,
+            text: "r/rust is down, back next week!",
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &message);
+        let _m2 = mock("GET", "/r/rust/top.json?limit=10&t=week")
+            .with_status(503)
+            .with_body("<html>Service unavailable</html>")
+            .expect(1)
+            .create();
+
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let send_on = Utc::now().weekday().num_days_from_monday() as i32;
+        db_client.subscribe(USER_ID, "rust", send_on, 0).unwrap();
+        db_client
+            .update_subscription(
+                USER_ID,
+                "rust",
+                SubscriptionChanges {
+                    error_text: Some(Some("r/{subreddit} is down, back next week!")),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        db_client
+            .conn
+            .execute("UPDATE users_subscriptions SET last_sent_at = NULL")
+            .unwrap();
+        let metrics = SchedulerMetrics::default();
+
+        // The user is told once, the next cycle doesn't retry.
+        for _ in 0..2 {
+            process_cycle(
+                &db_client,
+                &telegram_client,
+                &reddit_client,
+                &Mutex::new(ProcessingLog::default()),
+                &metrics,
+                None,
+                Duration::from_secs(0),
+            )
+            .await
+            .unwrap();
+        }
+
+        _m.assert();
+        _m2.assert();
+        assert!(metrics
+            .render()
+            .contains("\nreddit_bot_subscriptions_errored_total 1\n"));
+    }
+
+    #[test]
+    fn build_error_text_works() {
+        let subscription = Subscription {
+            subreddit: "rust".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            build_error_text(&subscription),
+            "Couldn't get popular posts from \"rust\" this week."
+        );
+
+        let subscription = Subscription {
+            error_text: Some("r/{subreddit} is down, back next week!".to_string()),
+            ..subscription
+        };
+        assert_eq!(
+            build_error_text(&subscription),
+            "r/rust is down, back next week!"
+        );
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn process_subscription_custom_empty_text() {
+        let url = &server_url();
+        let empty_message = Message {
+            chat_id: USER_ID,
+            text: "Weekly popular posts from: \"rust\"\n\nNothing new under the sun.\n",
+            disable_web_page_preview: true,
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &empty_message);
+        let _m2 = mock("GET", "/r/rust/top.json?limit=10&t=week")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"kind": "Listing", "data": {"children": []}}"#)
+            .expect(1)
+            .create();
+
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        db_client
+            .update_subscription(
+                USER_ID,
+                "rust",
+                SubscriptionChanges {
+                    empty_text: Some(Some("Nothing new under the sun.")),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let user_subscription = db_client.get_user_subscriptions(USER_ID).unwrap().remove(0);
+
+        process_subscription(
+            &db_client,
+            &telegram_client,
+            &reddit_client,
+            &user_subscription,
+            None,
+            false,
+        )
+        .await
+        .unwrap();
+        _m.assert();
+        _m2.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn process_combined_digest_success() {
+        let url = &server_url();
+        let post = format!(
+            "A half-hour to learn Rust\n{}/r/rust/comments/fbenua/a_halfhour_to_learn_rust/\n\n",
+            url
+        );
+        let expected_message = Message {
+            chat_id: USER_ID,
+            text: &format!(
+                "Your weekly digest\n\nWeekly popular posts from: \"golang\"\n\n{}Weekly popular posts from: \"rust\"\n\n{}",
+                post, post
+            ),
+            disable_web_page_preview: true,
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &expected_message);
+        let _m2 = mock_reddit_success("rust");
+        let _m3 = mock_reddit_success("golang");
+
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        db_client.subscribe(USER_ID, "golang", 0, 12).unwrap();
+        let user_subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+
+        process_combined_digest(
+            &db_client,
+            &telegram_client,
+            &reddit_client,
+            &user_subscriptions,
+            None,
+        )
+        .await
+        .unwrap();
+
+        _m.assert();
+        _m2.assert();
+        _m3.assert();
+        for user_subscription in user_subscriptions {
+            let result = db_client.get_sent_post_ids(user_subscription.id).unwrap();
+            assert_eq!(result, ["fbenua"]);
+        }
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn process_unknown_user() {
+        let url = &server_url();
+        let _m = mock_send_message_not_called(TOKEN);
+        let _m2 = mock("GET", Matcher::Any).expect(0).create();
+
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        db_client.subscribe(USER_ID, "golang", 0, 12).unwrap();
+        let user_subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+        // Removed while the cycle was running, e.g. after blocking the bot.
+        db_client.delete_user(USER_ID).unwrap();
+
+        let result = process_subscription(
+            &db_client,
+            &telegram_client,
+            &reddit_client,
+            &user_subscriptions[0],
+            None,
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result, Outcome::UnknownUser);
+        let result = process_combined_digest(
+            &db_client,
+            &telegram_client,
+            &reddit_client,
+            &user_subscriptions,
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result, Outcome::UnknownUser);
+
+        _m.assert();
+        _m2.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn process_combined_digest_later_message_fails() {
+        let url = &server_url();
+        let _m = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::Regex(r#""text":"Your weekly digest"#.to_string()))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .expect(1)
+            .create();
+        let _m2 = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::Regex(
+                r#""text":"Weekly popular posts"#.to_string(),
+            ))
+            .with_status(500)
+            .with_body(r#"{"ok":false,"error_code":500,"description":"Internal Server Error"}"#)
+            .expect(1)
+            .create();
+        let listing = |id: &str, title: String| {
+            format!(
+                r#"{{"kind": "Listing", "data": {{"children": [
+                    {{"kind": "t3", "data": {{"id": "{}", "title": "{}", "permalink": "/r/x/comments/{}/"}}}}
+                ]}}}}"#,
+                id, title, id
+            )
+        };
+        let _m3 = mock("GET", "/r/rust/top.json?limit=10&t=week")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(listing("1", "a".repeat(3000)))
+            .create();
+        let _m4 = mock("GET", "/r/golang/top.json?limit=10&t=week")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(listing("2", "b".repeat(3000)))
+            .create();
+
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let rust = db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        let golang = db_client.subscribe(USER_ID, "golang", 0, 12).unwrap();
+        let user_subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+
+        let result = process_combined_digest(
+            &db_client,
+            &telegram_client,
+            &reddit_client,
+            &user_subscriptions,
+            None,
+        )
+        .await;
+        assert!(result.is_err());
+
+        _m.assert();
+        _m2.assert();
+        // The first message was delivered, the digest isn't sent again next cycle.
+        assert_eq!(db_client.get_sent_post_ids(rust.id).unwrap(), ["1"]);
+        assert_eq!(db_client.get_sent_post_ids(golang.id).unwrap(), ["2"]);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn process_combined_digest_ignores_posts_per_message() {
+        let url = &server_url();
+        let _m = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::Regex(
+                r#"Your weekly digest\\n\\nWeekly popular posts from: \\"golang\\".*First.*Second"#
+                    .to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .expect(1)
+            .create();
+        let _m2 = mock("GET", "/r/rust/top.json?limit=10&t=week")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"kind": "Listing", "data": {"children": [
+                    {"kind": "t3", "data": {"id": "1", "title": "First", "permalink": "/r/rust/comments/1/"}},
+                    {"kind": "t3", "data": {"id": "2", "title": "Second", "permalink": "/r/rust/comments/2/"}}
+                ]}}"#,
+            )
+            .create();
+        let _m3 = mock_reddit_success("golang");
+
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        db_client.subscribe(USER_ID, "golang", 0, 12).unwrap();
+        db_client
+            .update_subscription(
+                USER_ID,
+                "rust",
+                SubscriptionChanges {
+                    posts_per_message: Some(Some(1)),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let user_subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+
+        process_combined_digest(
+            &db_client,
+            &telegram_client,
+            &reddit_client,
+            &user_subscriptions,
+            None,
+        )
+        .await
+        .unwrap();
+
+        _m.assert();
+        _m2.assert();
+        _m3.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn process_combined_digest_ignores_as_photos() {
+        let url = &server_url();
+        let _m = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::Regex(
+                r#"Your weekly digest\\n\\nWeekly popular posts from: \\"golang\\".*Ferris"#
+                    .to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .expect(1)
+            .create();
+        let _m2 = mock("POST", format!("/bot{}/sendPhoto", TOKEN).as_str())
+            .expect(0)
+            .create();
+        let _m3 = mock("GET", "/r/rust/top.json?limit=10&t=week")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"kind": "Listing", "data": {"children": [
+                    {"kind": "t3", "data": {"id": "sfw", "title": "Ferris", "permalink": "/r/rust/comments/sfw/", "post_hint": "image", "over_18": false, "url": "https://i.redd.it/ferris.png"}}
+                ]}}"#,
+            )
+            .create();
+        let _m4 = mock_reddit_success("golang");
+
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        db_client.subscribe(USER_ID, "golang", 0, 12).unwrap();
+        db_client
+            .update_subscription(
+                USER_ID,
+                "rust",
+                SubscriptionChanges {
+                    as_photos: Some(true),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let user_subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+
+        process_combined_digest(
+            &db_client,
+            &telegram_client,
+            &reddit_client,
+            &user_subscriptions,
+            None,
+        )
+        .await
+        .unwrap();
+
+        _m.assert();
+        _m2.assert();
+        _m3.assert();
+        _m4.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn process_combined_digest_ignores_edit_in_place() {
+        let url = &server_url();
+        let _m = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::Regex(r#"Your weekly digest"#.to_string()))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":692}}"#)
+            .expect(1)
+            .create();
+        let _m2 = mock("POST", format!("/bot{}/editMessageText", TOKEN).as_str())
+            .expect(0)
+            .create();
+        let _m3 = mock_reddit_success("rust");
+        let _m4 = mock_reddit_success("golang");
+
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let subscription = db_client.subscribe(USER_ID, "rust", 0, 12).unwrap();
+        db_client.subscribe(USER_ID, "golang", 0, 12).unwrap();
+        db_client
+            .update_subscription(
+                USER_ID,
+                "rust",
+                SubscriptionChanges {
+                    edit_in_place: Some(true),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        db_client
+            .set_last_message_id(subscription.id, Some("691"))
+            .unwrap();
+        let user_subscriptions = db_client.get_user_subscriptions(USER_ID).unwrap();
+
+        process_combined_digest(
+            &db_client,
+            &telegram_client,
+            &reddit_client,
+            &user_subscriptions,
+            None,
+        )
+        .await
+        .unwrap();
+
+        _m.assert();
+        _m2.assert();
+        _m3.assert();
+        _m4.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn process_cycle_combined_digest() {
+        let url = &server_url();
+        let _m = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::Regex(
+                r#"Your weekly digest\\n\\nWeekly popular posts from: \\"golang\\".*Weekly popular posts from: \\"rust\\""#.to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .expect(1)
+            .create();
+        let _m2 = mock_reddit_success("rust");
+        let _m3 = mock_reddit_success("golang");
+
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.set_combined_digest(USER_ID, true).unwrap();
+        let send_on = Utc::now().weekday().num_days_from_monday() as i32;
+        db_client.subscribe(USER_ID, "golang", send_on, 0).unwrap();
+        db_client.subscribe(USER_ID, "rust", send_on, 0).unwrap();
+        db_client
+            .conn
+            .execute("UPDATE users_subscriptions SET last_sent_at = NULL")
+            .unwrap();
+        let metrics = SchedulerMetrics::default();
+
+        process_cycle(
+            &db_client,
+            &telegram_client,
+            &reddit_client,
+            &Mutex::new(ProcessingLog::default()),
+            &metrics,
+            None,
+            Duration::from_secs(0),
+        )
+        .await
+        .unwrap();
+
+        _m.assert();
+        _m2.assert();
+        _m3.assert();
+        assert!(metrics
+            .render()
+            .contains("\nreddit_bot_subscriptions_sent_total 2\n"));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn process_cycle_skips_blocked_subreddit() {
+        let url = &server_url();
+        let _m = mock_send_message_not_called(TOKEN);
+        let _m2 = mock("GET", Matcher::Any).expect(0).create();
+
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let send_on = Utc::now().weekday().num_days_from_monday() as i32;
+        db_client
+            .subscribe(USER_ID, "rust+spam", send_on, 0)
+            .unwrap();
+        db_client.block_subreddit("spam").unwrap();
+        db_client
+            .conn
+            .execute("UPDATE users_subscriptions SET last_sent_at = NULL")
+            .unwrap();
+        let metrics = SchedulerMetrics::default();
+
+        process_cycle(
+            &db_client,
+            &telegram_client,
+            &reddit_client,
+            &Mutex::new(ProcessingLog::default()),
+            &metrics,
+            None,
+            Duration::from_secs(0),
+        )
+        .await
+        .unwrap();
+
+        _m.assert();
+        _m2.assert();
+        assert!(metrics
+            .render()
+            .contains("\nreddit_bot_subscriptions_skipped_total 1\n"));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn process_cycle_removes_one_off_subscription() {
+        let url = &server_url();
+        let expected_message = Message {
+            chat_id: USER_ID,
+            text: &format!("Weekly popular posts from: \"rust\"\n\nA half-hour to learn Rust\n{}/r/rust/comments/fbenua/a_halfhour_to_learn_rust/\n\n", url),
+            disable_web_page_preview: true,
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &expected_message);
+        let _m2 = mock_reddit_success("rust");
+
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let send_on = Utc::now().weekday().num_days_from_monday() as i32;
+        let subscription = db_client.subscribe(USER_ID, "rust", send_on, 0).unwrap();
+        db_client.set_recurring(subscription.id, false).unwrap();
+        db_client
+            .conn
+            .execute("UPDATE users_subscriptions SET last_sent_at = NULL")
+            .unwrap();
+
+        process_cycle(
+            &db_client,
+            &telegram_client,
+            &reddit_client,
+            &Mutex::new(ProcessingLog::default()),
+            &SchedulerMetrics::default(),
+            None,
+            Duration::from_secs(0),
+        )
+        .await
+        .unwrap();
+
+        _m.assert();
+        _m2.assert();
+        assert!(db_client
+            .get_user_subscriptions(USER_ID)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn process_post_subscription_success() {
+        let url = &server_url();
+        let permalink = "/r/rust/comments/fbenua/a_halfhour_to_learn_rust/";
+        let body = r#"[
+            {"kind": "Listing", "data": {"children": [{"kind": "t3", "data": {"id": "fbenua"}}]}},
+            {"kind": "Listing", "data": {"children": [
+                {"kind": "t1", "data": {"id": "c1", "author": "ferris", "score": 420, "created_utc": 1584878400.0, "body": "This is **great**"}},
+                {"kind": "t1", "data": {"id": "c2", "author": "koavf", "score": 12, "created_utc": 1584871200.0, "body": "Too old"}}
+            ]}}
+        ]"#;
+        let _m = mock(
+            "GET",
+            "/r/rust/comments/fbenua/a_halfhour_to_learn_rust.json?limit=50&sort=top&depth=1",
+        )
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(body)
+        .create();
+        let expected_message = Message {
+            chat_id: USER_ID,
+            text: &format!(
+                "New top comments in {}\n\nferris (420 points): This is great\n{}{}c1/\n\n",
+                permalink, url, permalink
+            ),
+            disable_web_page_preview: true,
+            ..Default::default()
+        };
+        let _m2 = mock_send_message_success(TOKEN, &expected_message);
+
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let post_subscription = db_client.watch_post(USER_ID, permalink).unwrap();
+        let since = Utc.with_ymd_and_hms(2020, 3, 22, 11, 0, 0).unwrap();
+        let now = Utc.with_ymd_and_hms(2020, 3, 22, 14, 0, 0).unwrap();
+
+        process_post_subscription(
+            &db_client,
+            &telegram_client,
+            &reddit_client,
+            &post_subscription,
+            since,
+            now,
+        )
+        .await
+        .unwrap();
+
+        _m.assert();
+        _m2.assert();
+        let result = db_client.get_post_subscriptions().unwrap();
+        assert_eq!(result[0].last_checked_at, now.to_rfc3339());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn process_post_subscriptions_blocked_and_expired() {
+        let url = &server_url();
+        let body = r#"[
+            {"kind": "Listing", "data": {"children": [{"kind": "t3", "data": {"id": "fbenua"}}]}},
+            {"kind": "Listing", "data": {"children": [
+                {"kind": "t1", "data": {"id": "c1", "author": "ferris", "score": 420, "created_utc": 4102444800.0, "body": "From the future"}}
+            ]}}
+        ]"#;
+        let _m = mock(
+            "GET",
+            "/r/rust/comments/fbenua/a_halfhour_to_learn_rust.json?limit=50&sort=top&depth=1",
+        )
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(body)
+        .expect(1)
+        .create();
+        let _m2 = mock(
+            "GET",
+            "/r/rust/comments/old/title.json?limit=50&sort=top&depth=1",
+        )
+        .expect(0)
+        .create();
+        let _m3 = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .with_status(403)
+            .with_body(r#"{"ok":false,"error_code":403,"description":"Forbidden: bot was blocked by the user"}"#)
+            .expect(1)
+            .create();
+
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        db_client.create_user("456").unwrap();
+        db_client
+            .watch_post(USER_ID, "/r/rust/comments/fbenua/a_halfhour_to_learn_rust/")
+            .unwrap();
+        db_client
+            .watch_post("456", "/r/rust/comments/old/title/")
+            .unwrap();
+        db_client
+            .conn
+            .execute("UPDATE post_subscriptions SET created_at = '2020-03-01T12:00:00+00:00', last_checked_at = '2020-03-01T12:00:00+00:00' WHERE user_id = '456'")
+            .unwrap();
+        db_client
+            .conn
+            .execute("UPDATE post_subscriptions SET last_checked_at = '2020-03-01T12:00:00+00:00'")
+            .unwrap();
+
+        process_post_subscriptions(&db_client, &telegram_client, &reddit_client, Utc::now())
+            .await
+            .unwrap();
+
+        _m.assert();
+        _m2.assert();
+        _m3.assert();
+        assert!(db_client.get_post_subscriptions().unwrap().is_empty());
+        assert!(!db_client.user_exists(USER_ID).unwrap());
+        assert!(db_client.user_exists("456").unwrap());
+    }
+
+    #[tokio::test]
+    async fn fetch_posts_retries_connect_errors() {
+        // Nothing listens on the port once the listener is dropped.
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let reddit_client =
+            RedditClient::new_with(&format!("http://{}", addr)).with_fetch_attempts(2);
+        let user_subscription = Subscription {
+            subreddit: "rust".to_string(),
+            post_limit: 10,
+            ..Default::default()
+        };
+
+        let result = fetch_posts(&reddit_client, &user_subscription, Duration::from_secs(0)).await;
+        let (err, attempts) = result.unwrap_err();
+        assert!(err.is_connect());
+        assert_eq!(attempts, 2);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn fetch_posts_since_last_sent() {
+        let url = &server_url();
+        let _m = mock("GET", "/r/rust/new.json?limit=100")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!({"kind": "Listing", "data": {"after": "t3_old", "children": [
+                    {"kind": "t3", "data": {"id": "new", "title": "New", "permalink": "/r/rust/comments/new/", "created_utc": 1584878500.0}},
+                    {"kind": "t3", "data": {"id": "old", "title": "Old", "permalink": "/r/rust/comments/old/", "created_utc": 1584878000.0}},
+                ]}})
+                .to_string(),
+            )
+            .create();
+        let reddit_client = RedditClient::new_with(url);
+        let user_subscription = Subscription {
+            subreddit: "rust".to_string(),
+            post_limit: 10,
+            last_sent_at: Some("2020-03-22T12:00:05+00:00".to_string()),
+            since_last_sent: true,
+            ..Default::default()
+        };
+
+        let result = fetch_posts(&reddit_client, &user_subscription, Duration::from_secs(0))
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].id, "new");
+        _m.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn fetch_posts_since_last_sent_post_limit() {
+        let url = &server_url();
+        let _m = mock("GET", "/r/rust/new.json?limit=100")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!({"kind": "Listing", "data": {"after": "t3_older", "children": [
+                    {"kind": "t3", "data": {"id": "newest", "title": "Newest", "permalink": "/r/rust/comments/newest/", "created_utc": 1584878600.0}},
+                    {"kind": "t3", "data": {"id": "newer", "title": "Newer", "permalink": "/r/rust/comments/newer/", "created_utc": 1584878500.0}},
+                ]}})
+                .to_string(),
+            )
+            .create();
+        let reddit_client = RedditClient::new_with(url);
+        let user_subscription = Subscription {
+            subreddit: "rust".to_string(),
+            post_limit: 1,
+            last_sent_at: Some("2020-03-22T12:00:05+00:00".to_string()),
+            since_last_sent: true,
+            ..Default::default()
+        };
+
+        let result = fetch_posts(&reddit_client, &user_subscription, Duration::from_secs(0))
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].id, "newest");
+        _m.assert();
+    }
+
+    #[test]
+    fn since_last_sent_works() {
+        let now = Utc.with_ymd_and_hms(2020, 3, 22, 12, 0, 0).unwrap();
+        let last_sent_at = Some("2020-03-20T08:00:00+00:00".to_string());
+        assert_eq!(
+            since_last_sent(&last_sent_at, None, now),
+            Utc.with_ymd_and_hms(2020, 3, 20, 8, 0, 0).unwrap()
+        );
+        assert_eq!(
+            since_last_sent(&None, None, now),
+            Utc.with_ymd_and_hms(2020, 3, 15, 12, 0, 0).unwrap()
+        );
+        assert_eq!(
+            since_last_sent(&Some("yesterday".to_string()), None, now),
+            Utc.with_ymd_and_hms(2020, 3, 15, 12, 0, 0).unwrap()
+        );
+        // The max age cuts off whichever starts later.
+        assert_eq!(
+            since_last_sent(&last_sent_at, Some(24), now),
+            Utc.with_ymd_and_hms(2020, 3, 21, 12, 0, 0).unwrap()
+        );
+        assert_eq!(
+            since_last_sent(&last_sent_at, Some(72), now),
+            Utc.with_ymd_and_hms(2020, 3, 20, 8, 0, 0).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn process_cycle_resumes_after_restart() {
+        let url = &server_url();
+        let expected_message = Message {
+            chat_id: USER_ID,
+            text: &format!("Weekly popular posts from: \"rust\"\n\nA half-hour to learn Rust\n{}/r/rust/comments/fbenua/a_halfhour_to_learn_rust/\n\n", url),
+            disable_web_page_preview: true,
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &expected_message);
+        let _m2 = mock_reddit_success("rust");
+        let _m3 = mock("GET", "/r/golang/top.json?limit=10&t=week")
+            .expect(0)
+            .create();
+
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let send_on = Utc::now().weekday().num_days_from_monday() as i32;
+        let completed = db_client.subscribe(USER_ID, "golang", send_on, 0).unwrap();
+        let pending = db_client.subscribe(USER_ID, "rust", send_on, 0).unwrap();
+        db_client
+            .conn
+            .execute("UPDATE users_subscriptions SET last_sent_at = NULL")
+            .unwrap();
+
+        // Simulates a restart after "golang" was sent but before it was marked in the database.
+        let processing_log = Mutex::new(ProcessingLog::default());
+        lock(&processing_log).mark_sent(completed.id, Utc::now().date_naive());
+        let metrics = SchedulerMetrics::default();
+
+        process_cycle(
+            &db_client,
+            &telegram_client,
+            &reddit_client,
+            &processing_log,
+            &metrics,
+            None,
+            Duration::from_secs(0),
+        )
+        .await
+        .unwrap();
+        assert!(lock(&processing_log).was_sent(pending.id, Utc::now().date_naive()));
+
+        // Neither is sent again by the next cycle, even though "rust" didn't get marked either.
+        db_client
+            .conn
+            .execute("UPDATE users_subscriptions SET last_sent_at = NULL")
+            .unwrap();
+        process_cycle(
+            &db_client,
+            &telegram_client,
+            &reddit_client,
+            &processing_log,
+            &metrics,
+            None,
+            Duration::from_secs(0),
+        )
+        .await
+        .unwrap();
+
+        _m.assert();
+        _m2.assert();
+        _m3.assert();
+
+        let result = metrics.render();
+        assert!(result.contains("\nreddit_bot_scheduler_cycles_total 2\n"));
+        assert!(result.contains("\nreddit_bot_subscriptions_evaluated_total 4\n"));
+        assert!(result.contains("\nreddit_bot_subscriptions_sent_total 1\n"));
+        assert!(result.contains("\nreddit_bot_subscriptions_skipped_total 3\n"));
+        assert!(result.contains("\nreddit_bot_subscriptions_errored_total 0\n"));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn process_cycle_in_user_timezone() {
+        let url = &server_url();
+        let expected_message = Message {
+            chat_id: USER_ID,
+            text: &format!("Weekly popular posts from: \"rust\"\n\nA half-hour to learn Rust\n{}/r/rust/comments/fbenua/a_halfhour_to_learn_rust/\n\n", url),
+            disable_web_page_preview: true,
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &expected_message);
+        let _m2 = mock_reddit_success("rust");
+        let _m3 = mock("GET", "/r/golang/top.json?limit=10&t=week")
+            .expect(0)
+            .create();
+
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+        // 26 hours apart, so it's never the same day in both timezones.
+        db_client.create_user(USER_ID).unwrap();
+        db_client
+            .set_timezone(USER_ID, "Pacific/Kiritimati")
+            .unwrap();
+        db_client.create_user("456").unwrap();
+        db_client.set_timezone("456", "Etc/GMT+12").unwrap();
+        let send_on = Utc::now()
+            .with_timezone(&Tz::Pacific__Kiritimati)
+            .weekday()
+            .num_days_from_monday() as i32;
+        db_client.subscribe(USER_ID, "rust", send_on, 0).unwrap();
+        db_client.subscribe("456", "golang", send_on, 0).unwrap();
+        db_client
+            .conn
+            .execute("UPDATE users_subscriptions SET last_sent_at = NULL")
+            .unwrap();
+
+        process_cycle(
+            &db_client,
+            &telegram_client,
+            &reddit_client,
+            &Mutex::new(ProcessingLog::default()),
+            &SchedulerMetrics::default(),
+            None,
+            Duration::from_secs(0),
+        )
+        .await
+        .unwrap();
+
+        _m.assert();
+        _m2.assert();
+        _m3.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn process_cycle_after_process_restart_relies_on_last_sent_at() {
+        let url = &server_url();
+        let expected_message = Message {
+            chat_id: USER_ID,
+            text: &format!("Weekly popular posts from: \"rust\"\n\nA half-hour to learn Rust\n{}/r/rust/comments/fbenua/a_halfhour_to_learn_rust/\n\n", url),
+            disable_web_page_preview: true,
+            ..Default::default()
+        };
+        let _m = mock_send_message_success(TOKEN, &expected_message);
+        let _m2 = mock_reddit_success("rust");
+        let _m3 = mock("GET", "/r/golang/top.json?limit=10&t=week")
+            .expect(0)
+            .create();
+
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+        db_client.create_user(USER_ID).unwrap();
+        let send_on = Utc::now().weekday().num_days_from_monday() as i32;
+        let sent = db_client.subscribe(USER_ID, "golang", send_on, 0).unwrap();
+        let pending = db_client.subscribe(USER_ID, "rust", send_on, 0).unwrap();
+        db_client
+            .conn
+            .execute(&format!(
+                "UPDATE users_subscriptions SET last_sent_at = NULL WHERE id = {}",
+                pending.id
+            ))
+            .unwrap();
+        db_client.update_last_sent(sent.id).unwrap();
+
+        // A fresh log, as after the whole process restarted: only last_sent_at skips "golang".
+        let processing_log = Mutex::new(ProcessingLog::default());
+
+        process_cycle(
+            &db_client,
+            &telegram_client,
+            &reddit_client,
+            &processing_log,
+            &SchedulerMetrics::default(),
+            None,
+            Duration::from_secs(0),
+        )
+        .await
+        .unwrap();
+
+        _m.assert();
+        _m2.assert();
+        _m3.assert();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn process_cycle_database_error() {
+        let url = &server_url();
+        let telegram_client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let reddit_client = RedditClient::new_with(url);
+        let db_client = setup_test_db();
+        db_client
+            .conn
+            .execute("DROP TABLE users_subscriptions")
+            .unwrap();
+
+        let result = process_cycle(
+            &db_client,
+            &telegram_client,
+            &reddit_client,
+            &Mutex::new(ProcessingLog::default()),
+            &SchedulerMetrics::default(),
+            None,
+            Duration::from_secs(0),
+        )
+        .await;
+        assert!(result.is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn reconnect_after_failure() {
+        std::fs::create_dir(".tmp").err();
+        std::fs::remove_dir_all(".tmp/reconnect").err();
+        std::fs::remove_dir_all(".tmp/reconnect_staging").err();
+        let database_url = ".tmp/reconnect/test.db";
+        assert!(DbClient::connect(database_url).is_err());
+
+        // The directory shows up after a few failed attempts, e.g. a volume being remounted. It's
+        // prepared elsewhere and moved in whole so reconnecting never races the migrations.
+        let remount = thread::spawn(|| {
+            thread::sleep(Duration::from_millis(50));
+            std::fs::create_dir(".tmp/reconnect_staging").unwrap();
+            let db = DbClient::new(".tmp/reconnect_staging/test.db");
+            db.run_migrations().unwrap();
+            drop(db);
+            std::fs::rename(".tmp/reconnect_staging", ".tmp/reconnect").unwrap();
+        });
+
+        // Gives up after about 2.5 seconds instead of hanging when the remount fails.
+        let result = reconnect(database_url, Duration::from_millis(10), 9);
+        remount.join().unwrap();
+        assert!(result.unwrap().get_subscriptions().is_ok());
+    }
+
+    #[test]
+    fn reconnect_gives_up() {
+        let result = reconnect(".tmp/missing/test.db", Duration::from_millis(1), 3);
+        assert!(result.is_err());
+    }
 }
@@ -1,9 +1,14 @@
+use log::{error, info};
+use reqwest::multipart::{Form, Part};
 use reqwest::{Client, Response};
-use serde_json::{from_str, Value};
+use serde_json::{from_str, from_value, json, Value};
 
 use super::error::TelegramError;
+use super::helpers::{build_inline_keyboard_pages, MAX_KEYBOARD_BUTTONS};
 use super::types::*;
 
+const KEYBOARD_CONTINUED_TEXT: &str = "More options";
+
 pub struct TelegramClient {
     token: String,
     domain: String,
@@ -36,7 +41,87 @@ impl TelegramClient {
         }
     }
 
-    #[allow(dead_code)]
+    /// Sends the text with an inline keyboard, large keyboards continue in follow-up messages.
+    pub async fn send_keyboard(
+        &self,
+        chat_id: &str,
+        text: &str,
+        buttons: Vec<InlineKeyboardButton>,
+        buttons_per_row: usize,
+    ) -> Result<(), TelegramError> {
+        let pages = build_inline_keyboard_pages(buttons, buttons_per_row, MAX_KEYBOARD_BUTTONS);
+        for (index, page) in pages.into_iter().enumerate() {
+            let reply_markup = ReplyMarkup::InlineKeyboardMarkup(page);
+            self.send_message(&Message {
+                chat_id,
+                text: if index == 0 {
+                    text
+                } else {
+                    KEYBOARD_CONTINUED_TEXT
+                },
+                reply_markup: Some(&reply_markup),
+                ..Default::default()
+            })
+            .await?;
+        }
+        Ok(())
+    }
+
+    pub async fn get_me(&self) -> Result<User, TelegramError> {
+        let url = format!("{}/bot{}/getMe", self.domain, self.token);
+        let resp: Response = Client::new().get(&url).send().await?;
+
+        if resp.status().is_success() {
+            let resp: Value = from_str(&resp.text().await?)?;
+            Ok(from_value(resp["result"].clone())?)
+        } else {
+            Err(resp.text().await?.into())
+        }
+    }
+
+    pub async fn get_chat_member(
+        &self,
+        chat_id: &str,
+        user_id: i64,
+    ) -> Result<ChatMember, TelegramError> {
+        let url = format!("{}/bot{}/getChatMember", self.domain, self.token);
+        let resp: Response = Client::new()
+            .post(&url)
+            .json(&json!({ "chat_id": chat_id, "user_id": user_id }))
+            .send()
+            .await?;
+
+        if resp.status().is_success() {
+            let resp: Value = from_str(&resp.text().await?)?;
+            Ok(from_value(resp["result"].clone())?)
+        } else {
+            Err(resp.text().await?.into())
+        }
+    }
+
+    /// Updates that fail to parse are logged and returned with only their id, so the offset still
+    /// moves past them instead of fetching them again on every poll.
+    pub async fn get_updates(
+        &self,
+        offset: i64,
+        timeout: u64,
+    ) -> Result<Vec<Update>, TelegramError> {
+        let url = format!("{}/bot{}/getUpdates", self.domain, self.token);
+        let resp: Response = Client::new()
+            .post(&url)
+            .json(&json!({ "offset": offset, "timeout": timeout }))
+            .send()
+            .await?;
+
+        if resp.status().is_success() {
+            let resp: Value = from_str(&resp.text().await?)?;
+            let updates: Vec<Value> = from_value(resp["result"].clone())?;
+            Ok(updates.into_iter().filter_map(parse_update).collect())
+        } else {
+            Err(resp.text().await?.into())
+        }
+    }
+
     pub async fn send_photo<'a>(&self, image: &Image<'a>) -> Result<String, TelegramError> {
         let url = format!("{}/bot{}/sendPhoto", self.domain, self.token);
         let resp: Response = Client::new().post(&url).json(&image).send().await?;
@@ -51,6 +136,55 @@ impl TelegramClient {
         }
     }
 
+    /// Uploads the file as a document, Telegram accepts up to 50 MB.
+    pub async fn send_document(
+        &self,
+        chat_id: &str,
+        file_name: &str,
+        content: Vec<u8>,
+    ) -> Result<String, TelegramError> {
+        let url = format!("{}/bot{}/sendDocument", self.domain, self.token);
+        let form = Form::new().text("chat_id", chat_id.to_string()).part(
+            "document",
+            Part::bytes(content).file_name(file_name.to_string()),
+        );
+        let resp: Response = Client::new().post(&url).multipart(form).send().await?;
+
+        if resp.status().is_success() {
+            let resp: Value = from_str(&resp.text().await?)?;
+            let resp = &resp["result"];
+            let resp = &resp["message_id"];
+            Ok(format!("{}", resp))
+        } else {
+            Err(resp.text().await?.into())
+        }
+    }
+
+    pub async fn send_poll(
+        &self,
+        chat_id: &str,
+        question: &str,
+        options: &[String],
+    ) -> Result<String, TelegramError> {
+        let url = format!("{}/bot{}/sendPoll", self.domain, self.token);
+        let poll = Poll {
+            chat_id,
+            question,
+            options,
+            ..Default::default()
+        };
+        let resp: Response = Client::new().post(&url).json(&poll).send().await?;
+
+        if resp.status().is_success() {
+            let resp: Value = from_str(&resp.text().await?)?;
+            let resp = &resp["result"];
+            let resp = &resp["message_id"];
+            Ok(format!("{}", resp))
+        } else {
+            Err(resp.text().await?.into())
+        }
+    }
+
     #[allow(dead_code)]
     pub async fn delete_message(
         &self,
@@ -74,7 +208,69 @@ impl TelegramClient {
         }
     }
 
-    #[allow(dead_code)]
+    pub async fn answer_callback_query(
+        &self,
+        callback_query_id: &str,
+        text: &str,
+        show_alert: bool,
+    ) -> Result<(), TelegramError> {
+        let url = format!("{}/bot{}/answerCallbackQuery", self.domain, self.token);
+        let resp: Response = Client::new()
+            .post(&url)
+            .form(&[
+                ("callback_query_id", &String::from(callback_query_id)),
+                ("text", &String::from(text)),
+                ("show_alert", &show_alert.to_string()),
+            ])
+            .send()
+            .await?;
+
+        if resp.status().is_success() {
+            Ok(())
+        } else {
+            Err(resp.text().await?.into())
+        }
+    }
+
+    pub async fn answer_inline_query(
+        &self,
+        inline_query_id: &str,
+        results: &[InlineQueryResultArticle],
+        cache_time: u32,
+    ) -> Result<(), TelegramError> {
+        let url = format!("{}/bot{}/answerInlineQuery", self.domain, self.token);
+        let answer = InlineQueryAnswer {
+            inline_query_id,
+            results,
+            cache_time,
+        };
+        let resp: Response = Client::new().post(&url).json(&answer).send().await?;
+
+        if resp.status().is_success() {
+            Ok(())
+        } else {
+            Err(resp.text().await?.into())
+        }
+    }
+
+    pub async fn send_chat_action(&self, chat_id: &str, action: &str) -> Result<(), TelegramError> {
+        let url = format!("{}/bot{}/sendChatAction", self.domain, self.token);
+        let resp: Response = Client::new()
+            .post(&url)
+            .form(&[
+                ("chat_id", &String::from(chat_id)),
+                ("action", &String::from(action)),
+            ])
+            .send()
+            .await?;
+
+        if resp.status().is_success() {
+            Ok(())
+        } else {
+            Err(resp.text().await?.into())
+        }
+    }
+
     pub async fn edit_message_text<'a>(
         &self,
         message: &EditMessage<'a>,
@@ -83,9 +279,14 @@ impl TelegramClient {
         let resp: Response = Client::new().post(&url).json(&message).send().await?;
 
         if resp.status().is_success() {
+            return Ok(());
+        }
+        let err: TelegramError = resp.text().await?.into();
+        if err.is_not_modified() {
+            info!("message {} is already up to date", message.message_id);
             Ok(())
         } else {
-            Err(resp.text().await?.into())
+            Err(err)
         }
     }
 
@@ -105,11 +306,30 @@ impl TelegramClient {
     }
 }
 
+fn parse_update(update: Value) -> Option<Update> {
+    match from_value::<Update>(update.clone()) {
+        Ok(update) => Some(update),
+        Err(err) => {
+            error!(
+                "failed to parse update, skipping it: {}, update: {}",
+                err, update
+            );
+            update["update_id"].as_i64().map(|update_id| Update {
+                update_id,
+                message: None,
+                channel_post: None,
+                callback_query: None,
+                inline_query: None,
+            })
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use mockito::{mock, server_url, Matcher};
-    use serde_json::json;
 
+    use crate::telegram::helpers::next_offset;
     use crate::telegram::test_helpers::mock_send_message_success;
 
     use super::*;
@@ -137,6 +357,9 @@ mod tests {
             text,
             disable_notification: true,
             disable_web_page_preview: false,
+            protect_content: false,
+            link_preview_options: None,
+            parse_mode: None,
             reply_markup: Some(&reply_markup),
         };
         let _m = mock_send_message_success(TOKEN, &message);
@@ -157,6 +380,9 @@ mod tests {
             text,
             disable_notification: true,
             disable_web_page_preview: false,
+            protect_content: false,
+            link_preview_options: None,
+            parse_mode: None,
             reply_markup: None,
         };
 
@@ -174,6 +400,189 @@ mod tests {
         _m.assert();
     }
 
+    #[tokio::test]
+    async fn send_keyboard_paginated() {
+        let url = &server_url();
+        let buttons = (0..MAX_KEYBOARD_BUTTONS + 1)
+            .map(|i| InlineKeyboardButton {
+                text: i.to_string(),
+                callback_data: i.to_string(),
+            })
+            .collect::<Vec<InlineKeyboardButton>>();
+        let _m1 = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::Regex(r#""text":"Select""#.to_string()))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .expect(1)
+            .create();
+        let _m2 = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::Regex(
+                r#""text":"More options".*"callback_data":"30""#.to_string(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":692}}"#)
+            .expect(1)
+            .create();
+
+        let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        client
+            .send_keyboard("123", "Select", buttons, 2)
+            .await
+            .unwrap();
+        _m1.assert();
+        _m2.assert();
+    }
+
+    #[tokio::test]
+    async fn get_me_success() {
+        let url = &server_url();
+        let resp = r#"{"ok":true,"result":{"id":414141,"is_bot":true,"first_name":"Bot","username":"reddit_bot","can_join_groups":true,"can_read_all_group_messages":false,"supports_inline_queries":false}}"#;
+
+        let _m = mock("GET", format!("/bot{}/getMe", TOKEN).as_str())
+            .with_status(200)
+            .with_body(resp)
+            .with_header("content-type", "application/json")
+            .create();
+
+        let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let result = client.get_me().await.unwrap();
+        assert_eq!(
+            result,
+            User {
+                id: 414141,
+                is_bot: true,
+                first_name: "Bot".to_string(),
+                username: Some("reddit_bot".to_string()),
+            }
+        );
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn get_me_error() {
+        let url = &server_url();
+        let error = r#"{"ok":false,"error_code":401,"description":"Unauthorized"}"#;
+
+        let _m = mock("GET", format!("/bot{}/getMe", TOKEN).as_str())
+            .with_status(401)
+            .with_header("content-type", "application/json")
+            .with_body(error)
+            .create();
+
+        let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let result = client.get_me().await.unwrap_err();
+        let result = format!("{}", result);
+        assert_eq!(result, error);
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn get_chat_member_success() {
+        let url = &server_url();
+        let resp = r#"{"ok":true,"result":{"user":{"id":414141,"is_bot":true,"first_name":"Bot","username":"reddit_bot"},"status":"administrator","can_be_edited":false,"can_post_messages":true}}"#;
+
+        let _m = mock("POST", format!("/bot{}/getChatMember", TOKEN).as_str())
+            .match_body(Matcher::Json(
+                json!({ "chat_id": "-100123", "user_id": 414141 }),
+            ))
+            .with_status(200)
+            .with_body(resp)
+            .with_header("content-type", "application/json")
+            .create();
+
+        let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let result = client.get_chat_member("-100123", 414141).await.unwrap();
+        assert_eq!(
+            result,
+            ChatMember {
+                status: "administrator".to_string(),
+                can_post_messages: Some(true),
+                can_send_messages: None,
+            }
+        );
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn get_chat_member_error() {
+        let url = &server_url();
+        let error = r#"{"ok":false,"error_code":400,"description":"Bad Request: chat not found"}"#;
+
+        let _m = mock("POST", format!("/bot{}/getChatMember", TOKEN).as_str())
+            .with_status(400)
+            .with_header("content-type", "application/json")
+            .with_body(error)
+            .create();
+
+        let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let result = client.get_chat_member("-100123", 414141).await.unwrap_err();
+        let result = format!("{}", result);
+        assert_eq!(result, error);
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn get_updates_success() {
+        let url = &server_url();
+        let resp = r#"{"ok":true,"result":[{"update_id":100,"message":{"message_id":691,"from":{"id":123,"is_bot":false,"first_name":"Name","username":"username"},"chat":{"id":123,"first_name":"Name","username":"username","type":"private"},"date":1581200384,"text":"/start"}}]}"#;
+
+        let _m = mock("POST", format!("/bot{}/getUpdates", TOKEN).as_str())
+            .match_body(Matcher::Json(json!({ "offset": 100, "timeout": 30 })))
+            .with_status(200)
+            .with_body(resp)
+            .with_header("content-type", "application/json")
+            .create();
+
+        let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let result = client.get_updates(100, 30).await.unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].update_id, 100);
+        let message = result[0].message.as_ref().unwrap();
+        assert_eq!(message.chat.id, 123);
+        assert_eq!(message.text.as_deref(), Some("/start"));
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn get_updates_skips_invalid() {
+        let url = &server_url();
+        let resp = r#"{"ok":true,"result":[{"update_id":100,"message":{"message_id":"bad"}},{"update_id":101,"message":{"message_id":691,"chat":{"id":123,"type":"private"},"date":1581200384,"text":"/start"}},{"message":{}}]}"#;
+
+        let _m = mock("POST", format!("/bot{}/getUpdates", TOKEN).as_str())
+            .with_status(200)
+            .with_body(resp)
+            .with_header("content-type", "application/json")
+            .create();
+
+        let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let result = client.get_updates(100, 30).await.unwrap();
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].update_id, 100);
+        assert_eq!(result[0].message, None);
+        assert_eq!(result[1].update_id, 101);
+        assert!(result[1].message.is_some());
+        assert_eq!(next_offset(&result, 100), 102);
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn get_updates_error() {
+        let url = &server_url();
+        let error = r#"{"ok":false,"error_code":401,"description":"Unauthorized"}"#;
+
+        let _m = mock("POST", format!("/bot{}/getUpdates", TOKEN).as_str())
+            .with_status(401)
+            .with_header("content-type", "application/json")
+            .with_body(error)
+            .create();
+
+        let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let result = client.get_updates(0, 30).await.unwrap_err();
+        let result = format!("{}", result);
+        assert_eq!(result, error);
+        _m.assert();
+    }
+
     #[tokio::test]
     async fn send_image_success() {
         let url = &server_url();
@@ -182,6 +591,7 @@ mod tests {
             chat_id: "123",
             photo: "image url",
             disable_notification: true,
+            ..Default::default()
         };
 
         let _m = mock("POST", format!("/bot{}/sendPhoto", TOKEN).as_str())
@@ -193,7 +603,6 @@ mod tests {
 
         let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
         let result = client.send_photo(&image).await.unwrap();
-        let result = format!("{}", result);
         assert_eq!(result, "691");
         _m.assert();
     }
@@ -206,6 +615,7 @@ mod tests {
             chat_id: "123",
             photo: "image url",
             disable_notification: true,
+            ..Default::default()
         };
 
         let _m = mock("POST", format!("/bot{}/sendPhoto", TOKEN).as_str())
@@ -222,6 +632,168 @@ mod tests {
         _m.assert();
     }
 
+    #[tokio::test]
+    async fn send_document_success() {
+        let url = &server_url();
+        let _m = mock("POST", format!("/bot{}/sendDocument", TOKEN).as_str())
+            .match_body(Matcher::AllOf(vec![
+                Matcher::Regex(r#"name="chat_id"\r\n\r\n123"#.to_string()),
+                Matcher::Regex(r#"name="document"; filename="backup.sqlite""#.to_string()),
+                Matcher::Regex("content".to_string()),
+            ]))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .with_header("content-type", "application/json")
+            .create();
+
+        let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let result = client
+            .send_document("123", "backup.sqlite", b"content".to_vec())
+            .await
+            .unwrap();
+        assert_eq!(result, "691");
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn send_poll_success() {
+        let url = &server_url();
+        let options = vec!["First".to_string(), "Second".to_string()];
+        let _m = mock("POST", format!("/bot{}/sendPoll", TOKEN).as_str())
+            .match_body(Matcher::Json(json!({
+                "chat_id": "123",
+                "question": "Which one?",
+                "options": ["First", "Second"],
+                "disable_notification": false
+            })))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":{"message_id":691}}"#)
+            .with_header("content-type", "application/json")
+            .create();
+
+        let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let result = client
+            .send_poll("123", "Which one?", &options)
+            .await
+            .unwrap();
+        assert_eq!(result, "691");
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn send_poll_error() {
+        let url = &server_url();
+        let error = r#"{"ok":false,"error_code":400,"description":"Bad Request: poll must have at least 2 option"}"#;
+        let options = vec!["Only".to_string()];
+        let _m = mock("POST", format!("/bot{}/sendPoll", TOKEN).as_str())
+            .with_status(400)
+            .with_header("content-type", "application/json")
+            .with_body(error)
+            .create();
+
+        let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let result = client
+            .send_poll("123", "Which one?", &options)
+            .await
+            .unwrap_err();
+        let result = format!("{}", result);
+        assert_eq!(result, error);
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn answer_callback_query_success() {
+        let url = &server_url();
+        let _m = mock(
+            "POST",
+            format!("/bot{}/answerCallbackQuery", TOKEN).as_str(),
+        )
+        .match_body("callback_query_id=9&text=Expired&show_alert=true")
+        .with_status(200)
+        .with_body(r#"{"ok":true,"result":true}"#)
+        .create();
+
+        let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        client
+            .answer_callback_query("9", "Expired", true)
+            .await
+            .unwrap();
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn answer_inline_query_success() {
+        let url = &server_url();
+        let results = vec![InlineQueryResultArticle::new(
+            "rust",
+            "r/rust",
+            "The Rust Programming Language",
+            "r/rust",
+        )];
+        let _m = mock("POST", format!("/bot{}/answerInlineQuery", TOKEN).as_str())
+            .match_body(Matcher::Json(json!({
+                "inline_query_id": "42",
+                "results": [{
+                    "type": "article",
+                    "id": "rust",
+                    "title": "r/rust",
+                    "description": "The Rust Programming Language",
+                    "input_message_content": {"message_text": "r/rust"}
+                }],
+                "cache_time": 300
+            })))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":true}"#)
+            .create();
+
+        let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        client
+            .answer_inline_query("42", &results, 300)
+            .await
+            .unwrap();
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn answer_inline_query_error() {
+        let url = &server_url();
+        let error = r#"{"ok":false,"error_code":400,"description":"Bad Request: query is too old and response timeout expired or query ID is invalid"}"#;
+        let _m = mock("POST", format!("/bot{}/answerInlineQuery", TOKEN).as_str())
+            .with_status(400)
+            .with_body(error)
+            .create();
+
+        let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let result = client
+            .answer_inline_query("42", &[], 300)
+            .await
+            .unwrap_err();
+        assert_eq!(result.to_string(), error);
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn answer_callback_query_error() {
+        let url = &server_url();
+        let error = r#"{"ok":false,"error_code":400,"description":"Bad Request: query is too old and response timeout expired or query ID is invalid"}"#;
+        let _m = mock(
+            "POST",
+            format!("/bot{}/answerCallbackQuery", TOKEN).as_str(),
+        )
+        .with_status(400)
+        .with_body(error)
+        .create();
+
+        let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let result = client
+            .answer_callback_query("9", "Expired", true)
+            .await
+            .unwrap_err();
+        let result = format!("{}", result);
+        assert_eq!(result, error);
+        _m.assert();
+    }
+
     #[tokio::test]
     async fn delete_message_success() {
         let url = &server_url();
@@ -238,8 +810,7 @@ mod tests {
             .create();
 
         let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
-        let result = client.delete_message(chat_id, message_id).await.unwrap();
-        assert_eq!(result, ());
+        client.delete_message(chat_id, message_id).await.unwrap();
         _m.assert();
     }
 
@@ -269,6 +840,47 @@ mod tests {
         _m.assert();
     }
 
+    #[tokio::test]
+    async fn send_chat_action_success() {
+        let url = &server_url();
+        let chat_id = "-100123";
+
+        let _m = mock("POST", format!("/bot{}/sendChatAction", TOKEN).as_str())
+            .match_body(Matcher::AllOf(vec![
+                Matcher::UrlEncoded(String::from("chat_id"), String::from(chat_id)),
+                Matcher::UrlEncoded(String::from("action"), String::from("typing")),
+            ]))
+            .with_status(200)
+            .with_body(r#"{"ok":true,"result":true}"#)
+            .with_header("content-type", "application/json")
+            .create();
+
+        let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        client.send_chat_action(chat_id, "typing").await.unwrap();
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn send_chat_action_error() {
+        let url = &server_url();
+        let chat_id = "-100123";
+        let error = r#"{"ok":false,"error_code":403,"description":"Forbidden: bot is not a member of the channel chat"}"#;
+        let _m = mock("POST", format!("/bot{}/sendChatAction", TOKEN).as_str())
+            .with_status(403)
+            .with_body(error)
+            .with_header("content-type", "application/json")
+            .create();
+
+        let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        let result = client
+            .send_chat_action(chat_id, "typing")
+            .await
+            .unwrap_err();
+        let result = format!("{}", result);
+        assert_eq!(result, error);
+        _m.assert();
+    }
+
     #[tokio::test]
     async fn edit_message_text_success() {
         let url = &server_url();
@@ -286,6 +898,7 @@ mod tests {
             disable_notification: true,
             disable_web_page_preview: false,
             reply_markup: Some(&reply_markup),
+            ..Default::default()
         };
 
         let _m = mock("POST", format!("/bot{}/editMessageText", TOKEN).as_str())
@@ -296,8 +909,7 @@ mod tests {
             .create();
 
         let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
-        let result = client.edit_message_text(&message).await.unwrap();
-        assert_eq!(result, ());
+        client.edit_message_text(&message).await.unwrap();
         _m.assert();
     }
 
@@ -319,6 +931,7 @@ mod tests {
             disable_notification: true,
             disable_web_page_preview: false,
             reply_markup: Some(&reply_markup),
+            ..Default::default()
         };
 
         let _m = mock("POST", format!("/bot{}/editMessageText", TOKEN).as_str())
@@ -335,6 +948,30 @@ mod tests {
         _m.assert();
     }
 
+    #[tokio::test]
+    async fn edit_message_text_not_modified() {
+        let url = &server_url();
+        let error = r#"{"ok":false,"error_code":400,"description":"Bad Request: message is not modified: specified new message content and reply markup are exactly the same as a current content and reply markup of the message"}"#;
+        let message = EditMessage {
+            chat_id: "123",
+            message_id: "456",
+            text: "message text",
+            disable_notification: false,
+            disable_web_page_preview: true,
+            ..Default::default()
+        };
+
+        let _m = mock("POST", format!("/bot{}/editMessageText", TOKEN).as_str())
+            .with_status(400)
+            .with_header("content-type", "application/json")
+            .with_body(error)
+            .create();
+
+        let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
+        client.edit_message_text(&message).await.unwrap();
+        _m.assert();
+    }
+
     #[tokio::test]
     async fn edit_message_image_success() {
         let url = &server_url();
@@ -355,8 +992,7 @@ mod tests {
             .create();
 
         let client = TelegramClient::new_with(String::from(TOKEN), String::from(url));
-        let result = client.edit_message_image(&edit_image).await.unwrap();
-        assert_eq!(result, ());
+        client.edit_message_image(&edit_image).await.unwrap();
         _m.assert();
     }
 
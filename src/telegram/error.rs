@@ -12,6 +12,13 @@ pub enum TelegramError {
     Unsuccessful(String),
 }
 
+impl TelegramError {
+    /// An edit that left the message as it was, Telegram rejects it although nothing is wrong.
+    pub fn is_not_modified(&self) -> bool {
+        matches!(self, TelegramError::Unsuccessful(err) if err.contains("message is not modified"))
+    }
+}
+
 impl From<ReqwestError> for TelegramError {
     fn from(error: ReqwestError) -> Self {
         TelegramError::NetworkError(error)
@@ -41,3 +48,22 @@ impl fmt::Display for TelegramError {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unsuccessful_error() {
+        let error = TelegramError::from(String::from("Forbidden: bot was blocked by the user"));
+        assert!(!error.is_not_modified());
+    }
+
+    #[test]
+    fn not_modified_error() {
+        let error = TelegramError::from(String::from(
+            r#"{"ok":false,"error_code":400,"description":"Bad Request: message is not modified: specified new message content and reply markup are exactly the same as a current content and reply markup of the message"}"#,
+        ));
+        assert!(error.is_not_modified());
+    }
+}
@@ -1,4 +1,7 @@
-use crate::telegram::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+use crate::telegram::types::{InlineKeyboardButton, InlineKeyboardMarkup, Update};
+
+/// Buttons per keyboard, some clients fail to render larger keyboards.
+pub const MAX_KEYBOARD_BUTTONS: usize = 30;
 
 pub fn build_inline_keyboard_markup(
     buttons: Vec<InlineKeyboardButton>,
@@ -6,9 +9,8 @@ pub fn build_inline_keyboard_markup(
 ) -> InlineKeyboardMarkup {
     let mut rows: Vec<Vec<InlineKeyboardButton>> = vec![];
     let mut row: Vec<InlineKeyboardButton> = vec![];
-    let mut buttons_iterator = buttons.into_iter();
 
-    while let Some(button) = buttons_iterator.next() {
+    for button in buttons {
         row.push(button);
         if row.len() == buttons_per_row {
             rows.push(row.clone());
@@ -16,7 +18,7 @@ pub fn build_inline_keyboard_markup(
         }
     }
 
-    if row.len() > 0 {
+    if !row.is_empty() {
         rows.push(row);
     }
 
@@ -24,3 +26,85 @@ pub fn build_inline_keyboard_markup(
         inline_keyboard: rows,
     }
 }
+
+/// Splits the buttons into keyboards of at most `max_buttons`, each sent with its own message.
+pub fn build_inline_keyboard_pages(
+    buttons: Vec<InlineKeyboardButton>,
+    buttons_per_row: usize,
+    max_buttons: usize,
+) -> Vec<InlineKeyboardMarkup> {
+    buttons
+        .chunks(max_buttons)
+        .map(|page| build_inline_keyboard_markup(page.to_vec(), buttons_per_row))
+        .collect()
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub fn escape_markdown(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "_*[]()~`>#+-=|{}.!\\".contains(c) {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result
+}
+
+/// Offset for the next `getUpdates` call, acknowledging every update in the batch.
+pub fn next_offset(updates: &[Update], offset: i64) -> i64 {
+    updates
+        .iter()
+        .map(|update| update.update_id + 1)
+        .max()
+        .map_or(offset, |next| next.max(offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buttons(count: usize) -> Vec<InlineKeyboardButton> {
+        (0..count)
+            .map(|i| InlineKeyboardButton {
+                text: i.to_string(),
+                callback_data: i.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn build_inline_keyboard_pages_within_limit() {
+        let result = build_inline_keyboard_pages(buttons(24), 4, 24);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].inline_keyboard.len(), 6);
+    }
+
+    #[test]
+    fn build_inline_keyboard_pages_over_limit() {
+        let result = build_inline_keyboard_pages(buttons(25), 2, 10);
+        assert_eq!(result.len(), 3);
+        let sizes = result
+            .iter()
+            .map(|page| page.inline_keyboard.iter().map(Vec::len).sum())
+            .collect::<Vec<usize>>();
+        assert_eq!(sizes, [10, 10, 5]);
+        assert_eq!(result[0].inline_keyboard[0][0].text, "0");
+        assert_eq!(result[2].inline_keyboard[2][0].text, "24");
+    }
+
+    #[test]
+    fn next_offset_works() {
+        let updates: Vec<Update> =
+            serde_json::from_str(r#"[{"update_id":5},{"update_id":7},{"update_id":6}]"#).unwrap();
+        assert_eq!(next_offset(&updates, 0), 8);
+        assert_eq!(next_offset(&[], 8), 8);
+        assert_eq!(next_offset(&updates[..1], 8), 8);
+    }
+}
@@ -0,0 +1,187 @@
+use futures::future::BoxFuture;
+
+use super::client::TelegramClient;
+use super::error::TelegramError;
+use super::types::*;
+
+/// The part of the Telegram API commands and dialogs talk back to users with. Implemented by
+/// `TelegramClient`, tests can swap in `RecordingMessenger` instead of a mocked HTTP server.
+pub trait Messenger: Sync {
+    fn get_me(&self) -> BoxFuture<'_, Result<User, TelegramError>>;
+
+    fn send_message<'a>(
+        &'a self,
+        message: &'a Message<'a>,
+    ) -> BoxFuture<'a, Result<String, TelegramError>>;
+
+    /// Sends the text with an inline keyboard, large keyboards continue in follow-up messages.
+    fn send_keyboard<'a>(
+        &'a self,
+        chat_id: &'a str,
+        text: &'a str,
+        buttons: Vec<InlineKeyboardButton>,
+        buttons_per_row: usize,
+    ) -> BoxFuture<'a, Result<(), TelegramError>>;
+
+    fn send_photo<'a>(
+        &'a self,
+        image: &'a Image<'a>,
+    ) -> BoxFuture<'a, Result<String, TelegramError>>;
+
+    fn send_document<'a>(
+        &'a self,
+        chat_id: &'a str,
+        file_name: &'a str,
+        content: Vec<u8>,
+    ) -> BoxFuture<'a, Result<String, TelegramError>>;
+
+    fn send_poll<'a>(
+        &'a self,
+        chat_id: &'a str,
+        question: &'a str,
+        options: &'a [String],
+    ) -> BoxFuture<'a, Result<String, TelegramError>>;
+
+    fn delete_message<'a>(
+        &'a self,
+        chat_id: &'a str,
+        message_id: &'a str,
+    ) -> BoxFuture<'a, Result<(), TelegramError>>;
+
+    fn answer_callback_query<'a>(
+        &'a self,
+        callback_query_id: &'a str,
+        text: &'a str,
+        show_alert: bool,
+    ) -> BoxFuture<'a, Result<(), TelegramError>>;
+
+    fn send_chat_action<'a>(
+        &'a self,
+        chat_id: &'a str,
+        action: &'a str,
+    ) -> BoxFuture<'a, Result<(), TelegramError>>;
+
+    fn edit_message_text<'a>(
+        &'a self,
+        message: &'a EditMessage<'a>,
+    ) -> BoxFuture<'a, Result<(), TelegramError>>;
+
+    fn edit_message_image<'a>(
+        &'a self,
+        edit_image: &'a EditImage<'a>,
+    ) -> BoxFuture<'a, Result<(), TelegramError>>;
+
+    fn get_chat_member<'a>(
+        &'a self,
+        chat_id: &'a str,
+        user_id: i64,
+    ) -> BoxFuture<'a, Result<ChatMember, TelegramError>>;
+}
+
+impl Messenger for TelegramClient {
+    fn get_me(&self) -> BoxFuture<'_, Result<User, TelegramError>> {
+        Box::pin(TelegramClient::get_me(self))
+    }
+
+    fn send_message<'a>(
+        &'a self,
+        message: &'a Message<'a>,
+    ) -> BoxFuture<'a, Result<String, TelegramError>> {
+        Box::pin(TelegramClient::send_message(self, message))
+    }
+
+    fn send_keyboard<'a>(
+        &'a self,
+        chat_id: &'a str,
+        text: &'a str,
+        buttons: Vec<InlineKeyboardButton>,
+        buttons_per_row: usize,
+    ) -> BoxFuture<'a, Result<(), TelegramError>> {
+        Box::pin(TelegramClient::send_keyboard(
+            self,
+            chat_id,
+            text,
+            buttons,
+            buttons_per_row,
+        ))
+    }
+
+    fn send_photo<'a>(
+        &'a self,
+        image: &'a Image<'a>,
+    ) -> BoxFuture<'a, Result<String, TelegramError>> {
+        Box::pin(TelegramClient::send_photo(self, image))
+    }
+
+    fn send_document<'a>(
+        &'a self,
+        chat_id: &'a str,
+        file_name: &'a str,
+        content: Vec<u8>,
+    ) -> BoxFuture<'a, Result<String, TelegramError>> {
+        Box::pin(TelegramClient::send_document(
+            self, chat_id, file_name, content,
+        ))
+    }
+
+    fn send_poll<'a>(
+        &'a self,
+        chat_id: &'a str,
+        question: &'a str,
+        options: &'a [String],
+    ) -> BoxFuture<'a, Result<String, TelegramError>> {
+        Box::pin(TelegramClient::send_poll(self, chat_id, question, options))
+    }
+
+    fn delete_message<'a>(
+        &'a self,
+        chat_id: &'a str,
+        message_id: &'a str,
+    ) -> BoxFuture<'a, Result<(), TelegramError>> {
+        Box::pin(TelegramClient::delete_message(self, chat_id, message_id))
+    }
+
+    fn answer_callback_query<'a>(
+        &'a self,
+        callback_query_id: &'a str,
+        text: &'a str,
+        show_alert: bool,
+    ) -> BoxFuture<'a, Result<(), TelegramError>> {
+        Box::pin(TelegramClient::answer_callback_query(
+            self,
+            callback_query_id,
+            text,
+            show_alert,
+        ))
+    }
+
+    fn send_chat_action<'a>(
+        &'a self,
+        chat_id: &'a str,
+        action: &'a str,
+    ) -> BoxFuture<'a, Result<(), TelegramError>> {
+        Box::pin(TelegramClient::send_chat_action(self, chat_id, action))
+    }
+
+    fn edit_message_text<'a>(
+        &'a self,
+        message: &'a EditMessage<'a>,
+    ) -> BoxFuture<'a, Result<(), TelegramError>> {
+        Box::pin(TelegramClient::edit_message_text(self, message))
+    }
+
+    fn edit_message_image<'a>(
+        &'a self,
+        edit_image: &'a EditImage<'a>,
+    ) -> BoxFuture<'a, Result<(), TelegramError>> {
+        Box::pin(TelegramClient::edit_message_image(self, edit_image))
+    }
+
+    fn get_chat_member<'a>(
+        &'a self,
+        chat_id: &'a str,
+        user_id: i64,
+    ) -> BoxFuture<'a, Result<ChatMember, TelegramError>> {
+        Box::pin(TelegramClient::get_chat_member(self, chat_id, user_id))
+    }
+}
@@ -1,6 +1,7 @@
 pub mod client;
 pub mod error;
 pub mod helpers;
+pub mod messenger;
 pub mod types;
 
 #[cfg(test)]
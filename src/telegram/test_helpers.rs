@@ -1,6 +1,11 @@
+use std::sync::Mutex;
+
+use futures::future::{self, BoxFuture};
 use serde_json::json;
 
-use crate::telegram::types::Message;
+use crate::telegram::error::TelegramError;
+use crate::telegram::messenger::Messenger;
+use crate::telegram::types::*;
 
 const SEND_MESSAGE_SUCCESS: &str = r#"{"ok":true,"result":{"message_id":691,"from":{"id":414141,"is_bot":true,"first_name":"Bot","username":"Bot"},"chat":{"id":123,"first_name":"Name","username":"username","type":"private"},"date":1581200384,"text":"This is a test message"}}"#;
 
@@ -21,3 +26,148 @@ pub fn mock_send_message_not_called(token: &str) -> Mock {
         .expect(0)
         .create()
 }
+
+/// What `RecordingMessenger` was asked to send, `text` is the message text, the caption of a
+/// keyboard, the question of a poll, the file name of a document or the link of a photo.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sent {
+    pub chat_id: String,
+    pub text: String,
+}
+
+/// Messenger that keeps everything sent instead of calling Telegram, for testing commands
+/// without a mocked HTTP server.
+#[derive(Default)]
+pub struct RecordingMessenger {
+    sent: Mutex<Vec<Sent>>,
+}
+
+impl RecordingMessenger {
+    pub fn new() -> Self {
+        RecordingMessenger::default()
+    }
+
+    pub fn sent(&self) -> Vec<Sent> {
+        self.sent.lock().unwrap().clone()
+    }
+
+    pub fn texts(&self) -> Vec<String> {
+        self.sent().into_iter().map(|sent| sent.text).collect()
+    }
+
+    /// Records the message and returns its id, ids count up from 1 like Telegram's do.
+    fn record(&self, chat_id: &str, text: &str) -> String {
+        let mut sent = self.sent.lock().unwrap();
+        sent.push(Sent {
+            chat_id: chat_id.to_string(),
+            text: text.to_string(),
+        });
+        sent.len().to_string()
+    }
+}
+
+impl Messenger for RecordingMessenger {
+    fn get_me(&self) -> BoxFuture<'_, Result<User, TelegramError>> {
+        Box::pin(future::ok(User {
+            id: 414141,
+            is_bot: true,
+            first_name: "Bot".to_string(),
+            username: Some("Bot".to_string()),
+        }))
+    }
+
+    fn send_message<'a>(
+        &'a self,
+        message: &'a Message<'a>,
+    ) -> BoxFuture<'a, Result<String, TelegramError>> {
+        Box::pin(future::ok(self.record(message.chat_id, message.text)))
+    }
+
+    fn send_keyboard<'a>(
+        &'a self,
+        chat_id: &'a str,
+        text: &'a str,
+        _buttons: Vec<InlineKeyboardButton>,
+        _buttons_per_row: usize,
+    ) -> BoxFuture<'a, Result<(), TelegramError>> {
+        self.record(chat_id, text);
+        Box::pin(future::ok(()))
+    }
+
+    fn send_photo<'a>(
+        &'a self,
+        image: &'a Image<'a>,
+    ) -> BoxFuture<'a, Result<String, TelegramError>> {
+        Box::pin(future::ok(self.record(image.chat_id, image.photo)))
+    }
+
+    fn send_document<'a>(
+        &'a self,
+        chat_id: &'a str,
+        file_name: &'a str,
+        _content: Vec<u8>,
+    ) -> BoxFuture<'a, Result<String, TelegramError>> {
+        Box::pin(future::ok(self.record(chat_id, file_name)))
+    }
+
+    fn send_poll<'a>(
+        &'a self,
+        chat_id: &'a str,
+        question: &'a str,
+        _options: &'a [String],
+    ) -> BoxFuture<'a, Result<String, TelegramError>> {
+        Box::pin(future::ok(self.record(chat_id, question)))
+    }
+
+    fn delete_message<'a>(
+        &'a self,
+        _chat_id: &'a str,
+        _message_id: &'a str,
+    ) -> BoxFuture<'a, Result<(), TelegramError>> {
+        Box::pin(future::ok(()))
+    }
+
+    fn answer_callback_query<'a>(
+        &'a self,
+        _callback_query_id: &'a str,
+        _text: &'a str,
+        _show_alert: bool,
+    ) -> BoxFuture<'a, Result<(), TelegramError>> {
+        Box::pin(future::ok(()))
+    }
+
+    fn send_chat_action<'a>(
+        &'a self,
+        _chat_id: &'a str,
+        _action: &'a str,
+    ) -> BoxFuture<'a, Result<(), TelegramError>> {
+        Box::pin(future::ok(()))
+    }
+
+    fn edit_message_text<'a>(
+        &'a self,
+        _message: &'a EditMessage<'a>,
+    ) -> BoxFuture<'a, Result<(), TelegramError>> {
+        Box::pin(future::ok(()))
+    }
+
+    fn edit_message_image<'a>(
+        &'a self,
+        _edit_image: &'a EditImage<'a>,
+    ) -> BoxFuture<'a, Result<(), TelegramError>> {
+        Box::pin(future::ok(()))
+    }
+
+    /// The bot is always allowed to post.
+    fn get_chat_member<'a>(
+        &'a self,
+        _chat_id: &'a str,
+        _user_id: i64,
+    ) -> BoxFuture<'a, Result<ChatMember, TelegramError>> {
+        Box::pin(future::ok(ChatMember {
+            status: "administrator".to_string(),
+            can_post_messages: Some(true),
+            can_send_messages: Some(true),
+        }))
+    }
+}
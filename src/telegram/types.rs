@@ -1,15 +1,41 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Default)]
 pub struct Message<'a> {
     pub chat_id: &'a str,
     pub text: &'a str,
     pub disable_notification: bool,
+    /// Deprecated by Telegram in favor of `link_preview_options`, still honored.
     pub disable_web_page_preview: bool,
+    /// Prevents forwarding and saving the message.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub protect_content: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub link_preview_options: Option<LinkPreviewOptions<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parse_mode: Option<ParseMode>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub reply_markup: Option<&'a ReplyMarkup>,
 }
 
+#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq)]
+pub struct LinkPreviewOptions<'a> {
+    pub is_disabled: bool,
+    /// Previews this URL instead of the first one in the text.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<&'a str>,
+    pub prefer_small_media: bool,
+    pub show_above_text: bool,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum ParseMode {
+    #[serde(rename = "HTML")]
+    Html,
+    #[allow(dead_code)]
+    MarkdownV2,
+}
+
 #[derive(Serialize, Default)]
 pub struct EditMessage<'a> {
     pub chat_id: &'a str,
@@ -18,6 +44,8 @@ pub struct EditMessage<'a> {
     pub disable_notification: bool,
     pub disable_web_page_preview: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub parse_mode: Option<ParseMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub reply_markup: Option<&'a ReplyMarkup>,
 }
 
@@ -25,6 +53,22 @@ pub struct EditMessage<'a> {
 pub struct Image<'a> {
     pub chat_id: &'a str,
     pub photo: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub caption: Option<&'a str>,
+    pub disable_notification: bool,
+    /// Prevents forwarding and saving the photo.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub protect_content: bool,
+    /// Blurs the photo until it's tapped, e.g. for NSFW posts.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub has_spoiler: bool,
+}
+
+#[derive(Serialize, Default)]
+pub struct Poll<'a> {
+    pub chat_id: &'a str,
+    pub question: &'a str,
+    pub options: &'a [String],
     pub disable_notification: bool,
 }
 
@@ -37,6 +81,43 @@ pub struct EditImage<'a> {
     pub media: Media<'a>,
 }
 
+#[derive(Serialize)]
+pub struct InlineQueryAnswer<'a> {
+    pub inline_query_id: &'a str,
+    pub results: &'a [InlineQueryResultArticle],
+    /// Seconds telegram may cache the results for, per query text.
+    pub cache_time: u32,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct InlineQueryResultArticle {
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub id: String,
+    pub title: String,
+    pub description: String,
+    pub input_message_content: InputTextMessageContent,
+}
+
+impl InlineQueryResultArticle {
+    pub fn new(id: &str, title: &str, description: &str, message_text: &str) -> Self {
+        InlineQueryResultArticle {
+            type_: "article".to_string(),
+            id: id.to_string(),
+            title: title.to_string(),
+            description: description.to_string(),
+            input_message_content: InputTextMessageContent {
+                message_text: message_text.to_string(),
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct InputTextMessageContent {
+    pub message_text: String,
+}
+
 #[derive(Serialize)]
 #[serde(untagged)]
 pub enum ReplyMarkup {
@@ -59,3 +140,260 @@ pub struct Media<'a> {
     #[serde(rename = "type")]
     pub type_: &'a str,
 }
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Update {
+    pub update_id: i64,
+    pub message: Option<IncomingMessage>,
+    pub channel_post: Option<IncomingMessage>,
+    pub callback_query: Option<CallbackQuery>,
+    pub inline_query: Option<InlineQuery>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct IncomingMessage {
+    pub message_id: i64,
+    pub from: Option<User>,
+    pub chat: Chat,
+    pub date: i64,
+    pub text: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct CallbackQuery {
+    pub id: String,
+    pub from: User,
+    pub message: Option<IncomingMessage>,
+    pub data: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct InlineQuery {
+    pub id: String,
+    pub from: User,
+    pub query: String,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct User {
+    pub id: i64,
+    pub is_bot: bool,
+    pub first_name: String,
+    pub username: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Chat {
+    pub id: i64,
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub title: Option<String>,
+    pub username: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ChatMember {
+    pub status: String,
+    pub can_post_messages: Option<bool>,
+    pub can_send_messages: Option<bool>,
+}
+
+impl ChatMember {
+    /// Whether the member is allowed to post messages in the chat.
+    pub fn can_send(&self) -> bool {
+        match self.status.as_str() {
+            "creator" | "member" => true,
+            "administrator" => self.can_post_messages.unwrap_or(true),
+            "restricted" => self.can_send_messages.unwrap_or(false),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_text_message() {
+        let json = r#"{"update_id":815212911,"message":{"message_id":1442,"from":{"id":123456789,"is_bot":false,"first_name":"Name","last_name":"Surname","username":"username","language_code":"en"},"chat":{"id":123456789,"first_name":"Name","last_name":"Surname","username":"username","type":"private"},"date":1584810262,"text":"/subscribe","entities":[{"offset":0,"length":10,"type":"bot_command"}]}}"#;
+        let update: Update = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            update,
+            Update {
+                update_id: 815212911,
+                message: Some(IncomingMessage {
+                    message_id: 1442,
+                    from: Some(User {
+                        id: 123456789,
+                        is_bot: false,
+                        first_name: "Name".to_string(),
+                        username: Some("username".to_string()),
+                    }),
+                    chat: Chat {
+                        id: 123456789,
+                        type_: "private".to_string(),
+                        title: None,
+                        username: Some("username".to_string()),
+                    },
+                    date: 1584810262,
+                    text: Some("/subscribe".to_string()),
+                }),
+                channel_post: None,
+                callback_query: None,
+                inline_query: None,
+            }
+        );
+    }
+
+    #[test]
+    fn deserialize_callback_query() {
+        let json = r#"{"update_id":815212912,"callback_query":{"id":"529485118465391234","from":{"id":123456789,"is_bot":false,"first_name":"Name","username":"username","language_code":"en"},"message":{"message_id":1443,"from":{"id":414141,"is_bot":true,"first_name":"Bot","username":"reddit_bot"},"chat":{"id":123456789,"first_name":"Name","username":"username","type":"private"},"date":1584810263,"text":"On which day do you want to receive the posts?","reply_markup":{"inline_keyboard":[[{"text":"Mon","callback_data":"0"},{"text":"Tue","callback_data":"1"}]]}},"chat_instance":"-1234567890","data":"1"}}"#;
+        let update: Update = serde_json::from_str(json).unwrap();
+        assert_eq!(update.update_id, 815212912);
+        assert!(update.message.is_none());
+
+        let query = update.callback_query.unwrap();
+        assert_eq!(query.id, "529485118465391234");
+        assert_eq!(query.from.id, 123456789);
+        assert_eq!(query.data, Some("1".to_string()));
+
+        let message = query.message.unwrap();
+        assert_eq!(message.message_id, 1443);
+        assert_eq!(message.chat.id, 123456789);
+        assert!(message.from.unwrap().is_bot);
+    }
+
+    #[test]
+    fn deserialize_channel_post() {
+        let json = r#"{"update_id":815212913,"channel_post":{"message_id":12,"sender_chat":{"id":-1001234567890,"title":"Channel","type":"channel"},"chat":{"id":-1001234567890,"title":"Channel","type":"channel"},"date":1584810264,"text":"/sendnow@reddit_bot","entities":[{"offset":0,"length":19,"type":"bot_command"}]}}"#;
+        let update: Update = serde_json::from_str(json).unwrap();
+        assert!(update.message.is_none());
+        assert!(update.callback_query.is_none());
+
+        let post = update.channel_post.unwrap();
+        assert!(post.from.is_none());
+        assert_eq!(
+            post.chat,
+            Chat {
+                id: -1001234567890,
+                type_: "channel".to_string(),
+                title: Some("Channel".to_string()),
+                username: None,
+            }
+        );
+        assert_eq!(post.text, Some("/sendnow@reddit_bot".to_string()));
+    }
+
+    #[test]
+    fn deserialize_inline_query() {
+        let json = r#"{"update_id":815212913,"inline_query":{"id":"4242","from":{"id":123456789,"is_bot":false,"first_name":"Name"},"query":"rust","offset":""}}"#;
+        let update: Update = serde_json::from_str(json).unwrap();
+        let query = update.inline_query.unwrap();
+        assert_eq!(query.id, "4242");
+        assert_eq!(query.from.id, 123456789);
+        assert_eq!(query.query, "rust");
+    }
+
+    #[test]
+    fn serialize_image_spoiler() {
+        let image = Image {
+            chat_id: "123",
+            photo: "https://i.redd.it/image.jpg",
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_string(&image).unwrap(),
+            r#"{"chat_id":"123","photo":"https://i.redd.it/image.jpg","disable_notification":false}"#
+        );
+
+        let image = Image {
+            has_spoiler: true,
+            ..image
+        };
+        assert_eq!(
+            serde_json::to_string(&image).unwrap(),
+            r#"{"chat_id":"123","photo":"https://i.redd.it/image.jpg","disable_notification":false,"has_spoiler":true}"#
+        );
+    }
+
+    #[test]
+    fn serialize_image_protect_content() {
+        let image = Image {
+            chat_id: "123",
+            photo: "https://i.redd.it/image.jpg",
+            protect_content: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_string(&image).unwrap(),
+            r#"{"chat_id":"123","photo":"https://i.redd.it/image.jpg","disable_notification":false,"protect_content":true}"#
+        );
+    }
+
+    #[test]
+    fn serialize_message_protect_content() {
+        let message = Message {
+            chat_id: "123",
+            text: "text",
+            protect_content: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_string(&message).unwrap(),
+            r#"{"chat_id":"123","text":"text","disable_notification":false,"disable_web_page_preview":false,"protect_content":true}"#
+        );
+    }
+
+    #[test]
+    fn serialize_message_link_preview_options() {
+        let message = Message {
+            chat_id: "123",
+            text: "text",
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_string(&message).unwrap(),
+            r#"{"chat_id":"123","text":"text","disable_notification":false,"disable_web_page_preview":false}"#
+        );
+
+        let message = Message {
+            link_preview_options: Some(LinkPreviewOptions {
+                is_disabled: true,
+                ..Default::default()
+            }),
+            ..message
+        };
+        assert_eq!(
+            serde_json::to_string(&message).unwrap(),
+            r#"{"chat_id":"123","text":"text","disable_notification":false,"disable_web_page_preview":false,"link_preview_options":{"is_disabled":true,"prefer_small_media":false,"show_above_text":false}}"#
+        );
+
+        let message = Message {
+            link_preview_options: Some(LinkPreviewOptions {
+                url: Some("https://example.com"),
+                prefer_small_media: true,
+                show_above_text: true,
+                ..Default::default()
+            }),
+            ..message
+        };
+        assert_eq!(
+            serde_json::to_string(&message).unwrap(),
+            r#"{"chat_id":"123","text":"text","disable_notification":false,"disable_web_page_preview":false,"link_preview_options":{"is_disabled":false,"url":"https://example.com","prefer_small_media":true,"show_above_text":true}}"#
+        );
+    }
+
+    #[test]
+    fn chat_member_can_send() {
+        let member = |json: &str| serde_json::from_str::<ChatMember>(json).unwrap();
+
+        assert!(member(r#"{"status":"member"}"#).can_send());
+        assert!(member(r#"{"status":"administrator"}"#).can_send());
+        assert!(!member(r#"{"status":"administrator","can_post_messages":false}"#).can_send());
+        assert!(member(r#"{"status":"restricted","can_send_messages":true}"#).can_send());
+        assert!(!member(r#"{"status":"restricted","can_send_messages":false}"#).can_send());
+        assert!(!member(r#"{"status":"left"}"#).can_send());
+        assert!(!member(r#"{"status":"kicked"}"#).can_send());
+    }
+}